@@ -0,0 +1,200 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::string::{String, ToString};
+use core::fmt::{self, Write};
+
+use span::LineCol;
+pub use span::{SourceId, SourceMap, Span};
+
+/// The code of every kind of diagnostic. Errors start with `E` and warnings with `W`.
+pub mod codes {
+    /// The source could not be split into tokens.
+    pub const LEX: &str = "E0001";
+    /// The tokens don't form a valid program.
+    pub const PARSE: &str = "E0002";
+    /// A macro was defined or used wrongly.
+    pub const MACRO: &str = "E0003";
+    /// A declaration uses the dynamic `_` type, which `--strict` forbids.
+    pub const DYNAMIC_TYPE: &str = "E0004";
+    /// Something went wrong while running.
+    pub const RUNTIME: &str = "E0005";
+    /// A value was thrown and never caught.
+    pub const UNCAUGHT: &str = "E0006";
+    /// A value's type doesn't match the type declared for it, or what an operator
+    /// expects.
+    pub const TYPE: &str = "E0007";
+    /// A file couldn't be imported, or imports itself through the files it imports.
+    pub const IMPORT: &str = "E0008";
+    /// `linus fmt` couldn't lay the file out without changing its tokens.
+    pub const FORMAT: &str = "E0009";
+    /// A `def` is missing the `->` after its type.
+    pub const MISSING_ASSIGN: &str = "W0001";
+    /// A global `def` is never used.
+    pub const UNUSED: &str = "W0002";
+    /// A `def` shadows an earlier one with the same name.
+    pub const SHADOWED: &str = "W0003";
+    /// `!=`, which isn't an operator: `not $ =` is what it means.
+    pub const NOT_EQUAL: &str = "W0004";
+    /// A `match` arm comes after one that fits any value, so it is never picked.
+    pub const UNREACHABLE: &str = "W0005";
+}
+
+/// Errors stop a program from running. Warnings are reported but only stop it when
+/// they are denied with `--deny-warnings` or `--strict`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found in a source by any stage, with where it is and how it might be
+/// fixed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// One of `codes`.
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(Severity::Error, code, message.into())
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, code, message.into())
+    }
+
+    fn new(severity: Severity, code: &'static str, message: String) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code,
+            message,
+            span: None,
+            hint: None,
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Diagnostic {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Diagnostic {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    /// Renders the diagnostic the way rustc does: the message, then where it is, then
+    /// the line it is on with the span underlined, then the hint.
+    pub fn render(&self, sources: &SourceMap) -> String {
+        let mut rendered = self.to_string();
+        let mut gutter = String::new();
+
+        if let Some(span) = self.span {
+            let LineCol { line, col } = sources.line_col(span);
+            let text = sources.text(span.source);
+            let range = sources
+                .lines(span.source)
+                .line_range(text, line)
+                .unwrap_or(text.len()..text.len());
+            let line_text = text[range.clone()].trim_end_matches('\r');
+
+            // keep tabs so the underline lines up with the text above it
+            let indent: String = line_text
+                .chars()
+                .take(col - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            // a span that runs past the end of the line is underlined up to the end
+            let end = span.end.clamp(span.start, range.start + line_text.len());
+            let width = text
+                .get(span.start..end)
+                .map_or(0, |underlined| underlined.chars().count())
+                .max(1);
+
+            gutter = " ".repeat(line.to_string().len());
+            let _ = write!(
+                rendered,
+                "\n{gutter}--> {}\n{gutter} |\n{line} | {line_text}\n{gutter} | {indent}{}",
+                sources.describe(span),
+                "^".repeat(width)
+            );
+        }
+        if let Some(hint) = &self.hint {
+            let _ = write!(rendered, "\n{gutter} = hint: {hint}");
+        }
+        rendered
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut sources = SourceMap::new();
+        let id = sources.add("a.lin", "def x: num -> 1\n\tdef 2: num -> 3\n".to_string());
+
+        let diagnostic = Diagnostic::error(codes::PARSE, "Invalid variable name.")
+            .with_span(Span::new(id, 21..22))
+            .with_hint("names start with a letter");
+        assert_eq!(
+            diagnostic.render(&sources),
+            "error[E0002]: Invalid variable name.\n \
+             --> a.lin:2:6\n  \
+             |\n\
+             2 | \tdef 2: num -> 3\n  \
+             | \t    ^\n  \
+             = hint: names start with a letter"
+        );
+    }
+
+    #[test]
+    fn test_render_underlines_the_span() {
+        let mut sources = SourceMap::new();
+        let id = sources.add("a.lin", "+ x \"é\"\n".to_string());
+
+        let diagnostic =
+            Diagnostic::warning(codes::UNUSED, "unused").with_span(Span::new(id, 4..8));
+        assert!(diagnostic
+            .render(&sources)
+            .ends_with("1 | + x \"é\"\n  |     ^^^"));
+
+        // past the end of the line, as for the newline a parse error stopped at
+        let diagnostic =
+            Diagnostic::error(codes::PARSE, "Unexpected").with_span(Span::new(id, 8..9));
+        assert!(diagnostic.render(&sources).ends_with("  |        ^"));
+    }
+
+    #[test]
+    fn test_render_without_span() {
+        let diagnostic =
+            Diagnostic::error(codes::RUNTIME, "Runtime Error: oops").with_hint("try again");
+        assert_eq!(
+            diagnostic.render(&SourceMap::new()),
+            "error[E0005]: Runtime Error: oops\n = hint: try again"
+        );
+    }
+}
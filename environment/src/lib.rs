@@ -1,30 +1,432 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 
+use lexer::Symbol;
+use parser::Expr;
+
+/// The pointer used for values that are shared rather than copied. It is an `Arc`
+/// with the `sync` feature, so that values and interpreters are `Send + Sync` and
+/// independent scripts can be evaluated on a thread pool, and an `Rc` otherwise.
+#[cfg(feature = "sync")]
+pub type Shared<T> = std::sync::Arc<T>;
+#[cfg(not(feature = "sync"))]
+pub type Shared<T> = std::rc::Rc<T>;
+
+/// A value. Strs, seqs, hashes, and bytes are behind a `Shared` pointer, so that
+/// looking a value up or passing it to a function copies a pointer rather than what
+/// it points to. Values never change: a function that "changes" one, like `put`,
+/// makes a changed copy, which `Shared::make_mut` skips when nothing else holds it.
 #[derive(Debug, Clone)]
 pub enum Type {
+    Int(i64),
     Num(f64),
-    Str(String),
+    Str(Shared<str>),
     Bool(bool),
     None,
-    Function(String),
+    Function(Shared<Function>),
+    Seq(Shared<Vec<Type>>),
+    Hash(Shared<BTreeMap<Key, Type>>),
+    Bytes(Shared<Vec<u8>>),
+}
+
+/// A function value: something that can be called with arguments.
+pub enum Function {
+    /// A builtin function or operator, called by name.
+    Builtin(String),
+    /// `comp f g`: calls the last function with the arguments, then each function
+    /// before it with the previous result.
+    Composed(Vec<Type>),
+    /// `partial f a`: calls the function with the bound arguments before any others.
+    Partial(Type, Vec<Type>),
+    /// A function defined in the program with `def`.
+    User(UserFunction),
+    /// A function the host wrote in Rust, see `Environment::define_native`.
+    Native(String, Box<NativeFn>),
+}
+
+/// The Rust function behind a native function, called with the evaluated arguments.
+/// With the `sync` feature it must be shareable between threads like every value.
+#[cfg(feature = "sync")]
+pub type NativeFn = dyn Fn(&[Type]) -> Result<Type, RuntimeError> + Send + Sync;
+#[cfg(not(feature = "sync"))]
+pub type NativeFn = dyn Fn(&[Type]) -> Result<Type, RuntimeError>;
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Function::Builtin(name) => f.debug_tuple("Builtin").field(name).finish(),
+            Function::Composed(functions) => f.debug_tuple("Composed").field(functions).finish(),
+            Function::Partial(function, bound) => f
+                .debug_tuple("Partial")
+                .field(function)
+                .field(bound)
+                .finish(),
+            Function::User(function) => f.debug_tuple("User").field(function).finish(),
+            Function::Native(name, _) => f.debug_tuple("Native").field(name).finish(),
+        }
+    }
+}
+
+/// Why running a program, or a native function, failed. It stops the program
+/// unless a `catch` handles it, which is given the message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    /// A name that nothing in scope defines.
+    UndefinedVariable(String),
+    /// A value of a type that the function or operator it was given to doesn't take,
+    /// with what it does take.
+    TypeMismatch(String),
+    /// A function called with a number of arguments it doesn't take.
+    ArityMismatch {
+        expected: usize,
+        found: usize,
+    },
+    DivisionByZero,
+    /// A value that was thrown and never caught, as it is printed.
+    Uncaught(String),
+    Message(String),
+}
+
+impl From<&str> for RuntimeError {
+    fn from(message: &str) -> RuntimeError {
+        RuntimeError::Message(message.to_string())
+    }
+}
+
+impl From<String> for RuntimeError {
+    fn from(message: String) -> RuntimeError {
+        RuntimeError::Message(message)
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::UndefinedVariable(name) => write!(f, "'{name}' is not defined"),
+            RuntimeError::TypeMismatch(message) => write!(f, "{message}"),
+            RuntimeError::ArityMismatch { expected, found } => write!(
+                f,
+                "Wrong number of arguments: expected {expected}, found {found}"
+            ),
+            RuntimeError::DivisionByZero => write!(f, "Cannot divide by zero"),
+            RuntimeError::Uncaught(value) => {
+                write!(f, "Runtime Error: uncaught exception: {value}")
+            }
+            RuntimeError::Message(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UserFunction {
+    pub name: Symbol,
+    pub params: Vec<Symbol>,
+    /// The default of each parameter, if it has one.
+    pub defaults: Vec<Option<Expr>>,
+    pub body: Expr,
+    /// The local bindings that were in scope where the function was defined.
+    pub captured: HashMap<Symbol, Type>,
+}
+
+/// The values that can be used as hash keys. Ints and nums are compared by value,
+/// so `1` and `1.0` are the same key, as are `-0` and `0`.
+#[derive(Debug, Clone)]
+pub enum Key {
+    Bool(bool),
+    Int(i64),
+    Num(f64),
+    Str(Shared<str>),
+}
+
+impl Key {
+    pub fn from_type(value: &Type) -> Option<Key> {
+        match value {
+            Type::Bool(bool) => Some(Key::Bool(*bool)),
+            Type::Int(int) => Some(Key::Int(*int)),
+            Type::Num(num) => Some(Key::Num(*num)),
+            Type::Str(string) => Some(Key::Str(string.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn to_type(&self) -> Type {
+        match self {
+            Key::Bool(bool) => Type::Bool(*bool),
+            Key::Int(int) => Type::Int(*int),
+            Key::Num(num) => Type::Num(*num),
+            Key::Str(string) => Type::Str(string.clone()),
+        }
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Key) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Key {}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Key) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Key) -> Ordering {
+        match (self, other) {
+            (Key::Bool(a), Key::Bool(b)) => a.cmp(b),
+            (Key::Int(a), Key::Int(b)) => a.cmp(b),
+            (Key::Num(a), Key::Num(b)) => (a + 0.0).total_cmp(&(b + 0.0)),
+            (Key::Int(a), Key::Num(b)) => {
+                compare_int_num(*a, *b).unwrap_or_else(|| (*a as f64).total_cmp(b))
+            }
+            (Key::Num(a), Key::Int(b)) => {
+                compare_int_num(*b, *a).map_or_else(|| a.total_cmp(&(*b as f64)), Ordering::reverse)
+            }
+            (Key::Str(a), Key::Str(b)) => a.cmp(b),
+            (Key::Bool(_), _) | (Key::Int(_) | Key::Num(_), Key::Str(_)) => Ordering::Less,
+            _ => Ordering::Greater,
+        }
+    }
+}
+
+/// Compares an int with a num exactly, rather than by converting the int to a num,
+/// which rounds ints too big to be represented. `None` if the num is NaN.
+pub fn compare_int_num(int: i64, num: f64) -> Option<Ordering> {
+    match (int as f64).partial_cmp(&num)? {
+        // the num is whole, and at most 2^63, so it converts exactly
+        Ordering::Equal => Some((int as i128).cmp(&(num as i128))),
+        ordering => Some(ordering),
+    }
+}
+
+impl Type {
+    pub fn str(string: impl Into<Shared<str>>) -> Type {
+        Type::Str(string.into())
+    }
+
+    pub fn seq(items: Vec<Type>) -> Type {
+        Type::Seq(Shared::new(items))
+    }
+
+    pub fn hash(entries: BTreeMap<Key, Type>) -> Type {
+        Type::Hash(Shared::new(entries))
+    }
+
+    pub fn bytes(bytes: Vec<u8>) -> Type {
+        Type::Bytes(Shared::new(bytes))
+    }
+
+    /// Approximate number of bytes the value occupies, including any heap data.
+    pub fn size(&self) -> usize {
+        std::mem::size_of::<Type>()
+            + match self {
+                Type::Str(string) => string.len(),
+                Type::Bytes(bytes) => bytes.capacity(),
+                Type::Seq(items) => items.iter().map(Type::size).sum(),
+                Type::Hash(entries) => entries
+                    .iter()
+                    .map(|(key, value)| key.to_type().size() + value.size())
+                    .sum(),
+                _ => 0,
+            }
+    }
 }
 
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int(int) => write!(f, "{}", int),
+            Type::Num(num) => write!(f, "{}", num),
+            Type::Str(string) => write!(f, "{}", string),
+            Type::Bool(bool) => write!(f, "{}", bool),
+            Type::None => write!(f, "none"),
+            Type::Function(function) => match function.as_ref() {
+                Function::Builtin(name) => write!(f, "<function {}>", name),
+                Function::User(function) => write!(f, "<function {}>", function.name),
+                Function::Native(name, _) => write!(f, "<function {}>", name),
+                _ => write!(f, "<function>"),
+            },
+            Type::Bytes(bytes) => {
+                write!(f, "<bytes")?;
+                for byte in bytes.iter() {
+                    write!(f, " {:02x}", byte)?;
+                }
+                write!(f, ">")
+            }
+            Type::Seq(items) => {
+                write!(f, "[")?;
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, " ")?;
+                    }
+                    write_nested(f, item)?;
+                }
+                write!(f, "]")
+            }
+            Type::Hash(entries) => {
+                write!(f, "{{")?;
+                for (idx, (key, value)) in entries.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, " ")?;
+                    }
+                    write_nested(f, &key.to_type())?;
+                    write!(f, " ")?;
+                    write_nested(f, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Strings inside collections are quoted so that their boundaries are visible.
+fn write_nested(f: &mut fmt::Formatter, value: &Type) -> fmt::Result {
+    match value {
+        Type::Str(string) => write!(f, "{:?}", string),
+        _ => write!(f, "{}", value),
+    }
+}
+
+/// The global definitions, and the stack of local scopes being evaluated in, e.g.
+/// the arguments of the function that is being called. Names are interned, so
+/// looking one up hashes and compares integers.
+#[derive(Default)]
 pub struct Environment {
-    values: HashMap<String, Type>,
+    values: HashMap<Symbol, Type>,
+    /// Globals that those in `values` can shadow, see `settle`.
+    base: HashMap<Symbol, Type>,
+    scopes: Vec<HashMap<Symbol, Type>>,
 }
 
 impl Environment {
     pub fn new() -> Environment {
         Environment {
             values: HashMap::new(),
+            base: HashMap::new(),
+            scopes: vec![],
+        }
+    }
+
+    /// Moves the globals defined so far beneath any defined from now on, which can
+    /// then shadow them rather than redefine them, e.g. for the prelude.
+    pub fn settle(&mut self) {
+        self.base.extend(self.values.drain());
+    }
+
+    /// Defines a global.
+    pub fn define(&mut self, name: Symbol, value: Type) {
+        self.values.insert(name, value);
+    }
+
+    /// Defines a global function that calls `call`, for a host to give scripts
+    /// functions of its own, such as a database lookup.
+    pub fn define_native(&mut self, name: &str, call: Box<NativeFn>) {
+        let function = Function::Native(name.to_string(), call);
+        self.define(Symbol::intern(name), Type::Function(Shared::new(function)));
+    }
+
+    /// Looks `name` up in the local scopes, innermost first, then the globals.
+    pub fn retrieve(&self, name: Symbol) -> Option<&Type> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name))
+            .or_else(|| self.values.get(&name))
+            .or_else(|| self.base.get(&name))
+    }
+
+    /// Whether `name` is already defined in the innermost local scope, or as a global
+    /// if there is none, so that defining it again would replace it.
+    pub fn defines(&self, name: Symbol) -> bool {
+        match self.scopes.last() {
+            Some(scope) => scope.contains_key(&name),
+            None => self.values.contains_key(&name),
         }
     }
 
-    pub fn define(&mut self, name: String, value: Type) {
+    /// Gives `name` a new value in the innermost scope that has it, or as a global,
+    /// returning the value it replaced. `None`, and nothing changes, if it isn't
+    /// defined anywhere.
+    pub fn assign(&mut self, name: Symbol, value: Type) -> Option<Type> {
+        if let Some(bound) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(&name))
+        {
+            return Some(std::mem::replace(bound, value));
+        }
+        let replaced = self
+            .values
+            .get(&name)
+            .or_else(|| self.base.get(&name))
+            .cloned()?;
         self.values.insert(name, value);
+        Some(replaced)
+    }
+
+    /// Whether there is a local scope, i.e. evaluation isn't at the top level.
+    pub fn is_local(&self) -> bool {
+        !self.scopes.is_empty()
+    }
+
+    /// Starts a child scope, e.g. for the bindings of a `let`.
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Binds `name` in the innermost local scope, or defines a global if there is
+    /// none.
+    pub fn bind(&mut self, name: Symbol, value: Type) {
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.insert(name, value);
+            }
+            None => self.define(name, value),
+        }
+    }
+
+    /// Every local binding currently in scope, with inner scopes taking precedence,
+    /// for a function defined here to capture.
+    pub fn locals(&self) -> HashMap<Symbol, Type> {
+        let mut locals = HashMap::new();
+        for scope in &self.scopes {
+            locals.extend(scope.iter().map(|(&name, value)| (name, value.clone())));
+        }
+        locals
+    }
+
+    /// Replaces the local scopes, returning the ones that were there, so that a
+    /// function body sees its own arguments rather than its caller's locals.
+    pub fn replace_scopes(
+        &mut self,
+        scopes: Vec<HashMap<Symbol, Type>>,
+    ) -> Vec<HashMap<Symbol, Type>> {
+        std::mem::replace(&mut self.scopes, scopes)
+    }
+
+    /// Every binding that can be looked up, innermost first and each name only
+    /// once, leaving out the globals defined before `settle`, such as the prelude.
+    pub fn bindings(&self) -> impl Iterator<Item = (Symbol, &Type)> {
+        let mut seen = HashSet::new();
+        self.scopes
+            .iter()
+            .rev()
+            .chain(std::iter::once(&self.values))
+            .flat_map(|scope| scope.iter())
+            .filter(move |(name, _)| seen.insert(**name))
+            .map(|(&name, value)| (name, value))
     }
 
-    pub fn retrieve(&self, name: &String) -> Option<&Type> {
-        self.values.get(name)
+    pub fn remove(&mut self, name: &str) -> Option<Type> {
+        self.values.remove(&Symbol::intern(name))
     }
 }
@@ -0,0 +1,106 @@
+//! Counts the heap allocations made while lexing, parsing, and interpreting large
+//! programs, to keep an eye on allocation churn. Run with
+//! `cargo bench -p integration-tests --bench allocations`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use environment::Type;
+use interpreter::{Capabilities, Interpreter, OutputSink};
+
+/// Discards everything a program prints.
+struct Discard;
+
+impl OutputSink for Discard {
+    fn print(&mut self, _: &Type) {}
+    fn eprint(&mut self, _: &Type) {}
+}
+
+/// Wraps the system allocator, counting every allocation and reallocation and the
+/// bytes they ask for.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// The number of allocations `f` makes.
+fn count<T>(f: impl FnOnce() -> T) -> usize {
+    count_bytes(f).0
+}
+
+/// The number of allocations `f` makes, and how many bytes they are for.
+fn count_bytes<T>(f: impl FnOnce() -> T) -> (usize, usize) {
+    let before = (
+        ALLOCATIONS.load(Ordering::Relaxed),
+        BYTES.load(Ordering::Relaxed),
+    );
+    let result = f();
+    let after = (
+        ALLOCATIONS.load(Ordering::Relaxed),
+        BYTES.load(Ordering::Relaxed),
+    );
+    drop(result);
+    (after.0 - before.0, after.1 - before.1)
+}
+
+fn main() {
+    let programs = [
+        (
+            "tokens",
+            "+ 1 2 3 4 5 6\n* 2.5 (- 10 4) (/ 9 3)\nprint \"some text\" true none\n".repeat(2000),
+        ),
+        (
+            "definitions",
+            (0..2000)
+                .map(|idx| format!("def x{idx}: num -> * {idx} 2\n"))
+                .collect(),
+        ),
+        (
+            "sharing",
+            format!(
+                "def text: str -> \"{}\"\ndef size: int coll: _ -> len coll\n{}",
+                "linus ".repeat(2000),
+                "size text\nfirst [text text text]\n".repeat(500)
+            ),
+        ),
+    ];
+
+    for (name, program) in programs {
+        let tokens = lexer::lex(program.clone()).expect("benchmark program should lex");
+        let lex = count(|| lexer::lex(program.clone()));
+        let parse = count(|| parser::parse(&tokens));
+        let exprs = parser::parse(&tokens).expect("benchmark program should parse");
+        let (interpret, bytes) = count_bytes(|| {
+            let mut interpreter = Interpreter::without_prelude(Capabilities::sandboxed());
+            interpreter.set_output(Box::new(Discard));
+            interpreter.execute(&exprs)
+        });
+        println!(
+            "{name}: {} bytes, {} tokens, lex {lex} allocations, parse {parse} allocations, \
+             interpret {interpret} allocations of {bytes} bytes",
+            program.len(),
+            tokens.len()
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! Benchmarks for each stage (lexing, parsing, interpreting) and the whole pipeline,
+//! over generated programs that stress different parts of the implementation.
+//! Run with `cargo bench -p integration-tests`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use environment::Type;
+use interpreter::{Capabilities, Interpreter, OutputSink};
+use linus::{Pipeline, Source};
+
+/// Discards everything a benchmarked program prints.
+struct Discard;
+
+impl OutputSink for Discard {
+    fn print(&mut self, _: &Type) {}
+    fn eprint(&mut self, _: &Type) {}
+}
+
+fn interpreter() -> Interpreter {
+    let mut interpreter = Interpreter::without_prelude(Capabilities::sandboxed());
+    interpreter.set_output(Box::new(Discard));
+    interpreter
+}
+
+/// The programs each stage is measured on, by name.
+fn programs() -> Vec<(&'static str, String)> {
+    let tokens = "+ 1 2 3 4 5 6\n* 2.5 (- 10 4) (/ 9 3)\n".repeat(2500);
+
+    let mut nested = "1".to_string();
+    for _ in 0..200 {
+        nested = format!("+ 1 ({nested})");
+    }
+    nested.push('\n');
+
+    let bytes = "00010203040506070809".repeat(100);
+    let arithmetic = format!("reduce (+) 0 (hex-decode \"{bytes}\")\n").repeat(50);
+
+    // large values looked up and passed to functions, which share rather than copy them
+    let text = "linus ".repeat(2000);
+    let mut sharing = format!(
+        "def text: str -> \"{text}\"\ndef bytes: _ -> hex-decode \"{bytes}\"\n\
+         def size: int coll: _ -> len coll\n"
+    );
+    sharing.push_str(&"size text\nsize bytes\nfirst [text text text]\n".repeat(500));
+
+    let definitions = (0..2000)
+        .map(|idx| format!("def x{idx}: num -> * {idx} 2\n"))
+        .collect();
+
+    vec![
+        ("tokens", tokens),
+        ("nested", nested),
+        ("arithmetic", arithmetic),
+        ("definitions", definitions),
+        ("sharing", sharing),
+    ]
+}
+
+fn stages(c: &mut Criterion) {
+    for (name, program) in programs() {
+        let tokens = lexer::lex(program.clone()).expect("benchmark program should lex");
+        let exprs = parser::parse(&tokens).expect("benchmark program should parse");
+        interpreter()
+            .execute(&exprs)
+            .expect("benchmark program should run");
+
+        c.bench_with_input(BenchmarkId::new("lex", name), &program, |b, program| {
+            b.iter(|| lexer::lex(black_box(program.clone())))
+        });
+        c.bench_with_input(BenchmarkId::new("parse", name), &tokens, |b, tokens| {
+            b.iter(|| parser::parse(black_box(tokens)))
+        });
+        c.bench_with_input(BenchmarkId::new("interpret", name), &exprs, |b, exprs| {
+            b.iter(|| interpreter().execute(black_box(exprs)))
+        });
+    }
+}
+
+fn pipeline(c: &mut Criterion) {
+    for (name, program) in programs() {
+        let source = Source {
+            path: format!("{name}.ln"),
+            string: program,
+        };
+        c.bench_with_input(BenchmarkId::new("pipeline", name), &source, |b, source| {
+            b.iter(|| Pipeline::with_interpreter(interpreter()).run(black_box(source)))
+        });
+    }
+}
+
+criterion_group!(benches, stages, pipeline);
+criterion_main!(benches);
@@ -0,0 +1,94 @@
+//! Golden-file tests: every `.ln` file under `fixtures/` is run through the whole
+//! pipeline and its output compared with the `.expected` file next to it. Run with
+//! `BLESS=1` to write the current output to the `.expected` files instead.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use interpreter::{Capabilities, CapturedOutput};
+use linus::{Pipeline, Source};
+
+/// The `.ln` files in `dir` and its subdirectories, sorted.
+pub fn fixtures(dir: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).expect("Could not read fixtures directory") {
+            let path = entry.expect("Could not read fixtures directory").path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "ln") {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    paths
+}
+
+/// Runs `source` sandboxed with a fixed seed and renders what it printed: stdout
+/// lines as they are, then stderr lines prefixed with `stderr: `, then the error
+/// that stopped it, if any.
+pub fn run(source: &str) -> String {
+    let output = CapturedOutput::new();
+    let mut pipeline = Pipeline::with_capabilities(Capabilities::sandboxed());
+    pipeline.interpreter().set_seed(0);
+    pipeline.interpreter().set_output(Box::new(output.clone()));
+
+    let result = pipeline.run(&Source {
+        path: "fixture.ln".to_string(),
+        string: source.to_string(),
+    });
+
+    let mut rendered = String::new();
+    for line in output.stdout() {
+        rendered.push_str(&format!("{line}\n"));
+    }
+    for line in output.stderr() {
+        rendered.push_str(&format!("stderr: {line}\n"));
+    }
+    if let Err(err) = result {
+        for diagnostic in err.diagnostics() {
+            let summary = linus::diagnostics::summary(diagnostic, pipeline.sources());
+            rendered.push_str(&format!("error ({}): {summary}\n", err.stage()));
+        }
+    }
+    rendered
+}
+
+/// Runs the fixture at `path` and compares the result with its `.expected` file,
+/// returning a description of the difference if they don't match.
+pub fn check(path: &Path, bless: bool) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let actual = run(&source);
+    let expected_path = path.with_extension("expected");
+
+    if bless {
+        return fs::write(&expected_path, &actual)
+            .map_err(|err| format!("{}: {err}", expected_path.display()));
+    }
+
+    let expected = fs::read_to_string(&expected_path)
+        .map_err(|err| format!("{}: {err}", expected_path.display()))?;
+    if actual == expected {
+        return Ok(());
+    }
+
+    let mut diff = format!("{}:\n", path.display());
+    let (actual, expected): (Vec<_>, Vec<_>) =
+        (actual.lines().collect(), expected.lines().collect());
+    for idx in 0..actual.len().max(expected.len()) {
+        match (expected.get(idx), actual.get(idx)) {
+            (Some(e), Some(a)) if e == a => diff.push_str(&format!("  {e}\n")),
+            (e, a) => {
+                if let Some(e) = e {
+                    diff.push_str(&format!("- {e}\n"));
+                }
+                if let Some(a) = a {
+                    diff.push_str(&format!("+ {a}\n"));
+                }
+            }
+        }
+    }
+    Err(diff)
+}
@@ -0,0 +1,29 @@
+use std::env;
+use std::path::Path;
+
+#[test]
+fn test_golden_files() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+    let bless = env::var_os("BLESS").is_some();
+
+    let fixtures = integration_tests::fixtures(&dir);
+    assert!(
+        !fixtures.is_empty(),
+        "no fixtures found in {}",
+        dir.display()
+    );
+
+    let failures: Vec<String> = fixtures
+        .iter()
+        .filter_map(|path| integration_tests::check(path, bless).err())
+        .collect();
+
+    if !failures.is_empty() {
+        panic!(
+            "{} of {} golden file(s) did not match (rerun with BLESS=1 to update):\n{}",
+            failures.len(),
+            fixtures.len(),
+            failures.join("\n")
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use arbitrary::{Arbitrary, Unstructured};
+use interpreter::{Capabilities, CapturedOutput, Interpreter};
+use parser::testing::print;
+use parser::Expr;
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+/// Builds a program from the fuzzer's bytes. Running out of bytes just ends the
+/// program early.
+fn program(bytes: &[u8]) -> Vec<Expr> {
+    let mut u = Unstructured::new(bytes);
+    let mut exprs = vec![];
+    while !u.is_empty() {
+        match Expr::arbitrary(&mut u) {
+            Ok(expr) => exprs.push(expr),
+            Err(_) => break,
+        }
+    }
+    exprs
+}
+
+proptest! {
+    #[test]
+    fn test_parse_print_round_trip(bytes in vec(any::<u8>(), 0..512)) {
+        let exprs = program(&bytes);
+        let source = print(&exprs);
+
+        let tokens = lexer::lex(source.clone()).unwrap();
+        let mut parsed = parser::parse(&tokens).unwrap_or_else(|err| panic!("{err}\n{source}"));
+        prop_assert_eq!(parsed.pop(), Some(Expr::Literal { token: lexer::Token::EOF }));
+        prop_assert_eq!(parsed, exprs, "{}", source);
+    }
+
+    #[test]
+    fn test_stream_parses_as_batch(bytes in vec(any::<u8>(), 0..512)) {
+        let source = print(&program(&bytes));
+
+        let tokens = lexer::lex(source.clone()).unwrap();
+        let batch = parser::parse(&tokens).unwrap();
+        let streamed: Result<Vec<_>, _> = parser::parse_stream(lexer::Lexer::new(&source)).collect();
+        prop_assert_eq!(streamed.unwrap(), batch, "{}", source);
+    }
+
+    #[test]
+    fn test_interpreter_does_not_panic(bytes in vec(any::<u8>(), 0..512)) {
+        let exprs = program(&bytes);
+
+        let mut interpreter = Interpreter::with_capabilities(Capabilities::sandboxed());
+        interpreter.set_seed(0);
+        // a generated function can call itself without end, and the default depth
+        // is deeper than a test thread's stack goes in a debug build
+        interpreter.set_max_depth(100);
+        interpreter.set_output(Box::new(CapturedOutput::new()));
+        for expr in &exprs {
+            // errors are fine, only a panic fails the property
+            let _ = interpreter.execute(std::slice::from_ref(expr));
+        }
+    }
+
+    #[test]
+    fn test_typechecker_does_not_panic(bytes in vec(any::<u8>(), 0..512)) {
+        // type errors are fine, only a panic fails the property
+        let _ = typechecker::check(&program(&bytes));
+    }
+}
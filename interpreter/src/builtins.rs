@@ -0,0 +1,1525 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use environment::{Function, Key, Shared, Type};
+use md5::Md5;
+use regex::Regex;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::{iter, Interpreter, Raised};
+
+mod assert;
+mod date;
+mod math;
+mod repr;
+mod text;
+
+/// A builtin function, called with already evaluated arguments.
+pub(crate) type Builtin = fn(&mut Interpreter, Vec<Type>) -> Result<Type, &'static str>;
+
+/// A builtin that raises more than an error: what the functions it is passed raise
+/// passes through it, or it throws a value of its own.
+pub(crate) type HigherOrder = fn(&mut Interpreter, Vec<Type>) -> Result<Type, Raised>;
+
+/// Whether there is a builtin function `name`.
+pub(crate) fn is_builtin(name: &str) -> bool {
+    lookup(name).is_some() || higher_order(name).is_some()
+}
+
+/// Finds the builtin function `name`, if there is one.
+pub(crate) fn lookup(name: &str) -> Option<Builtin> {
+    let builtin: Builtin = match name {
+        "print" => |interpreter, args| print(interpreter, args, false),
+        "eprint" => |interpreter, args| print(interpreter, args, true),
+        "println" => println,
+        "read-line" => read_line,
+        "read-num" => read_num,
+        "random" => random,
+        "comp" => |_, args| comp(args),
+        "partial" => |_, args| partial(args),
+        "first" => |_, args| match <[Type; 1]>::try_from(args) {
+            Ok([coll]) => iter::first(coll),
+            Err(_) => Err("Function 'first' takes a collection"),
+        },
+        "rest" => |_, args| match <[Type; 1]>::try_from(args) {
+            Ok([coll]) => iter::rest(coll),
+            Err(_) => Err("Function 'rest' takes a collection"),
+        },
+        "empty?" => |_, args| match <[Type; 1]>::try_from(args) {
+            Ok([coll]) => iter::is_empty(coll).map(Type::Bool),
+            Err(_) => Err("Function 'empty?' takes a collection"),
+        },
+        "cons" => |_, args| cons(args),
+        "len" => |_, args| match <[Type; 1]>::try_from(args) {
+            Ok([coll]) => Ok(Type::Int(iter::len(&coll)? as i64)),
+            Err(_) => Err("Function 'len' takes a collection"),
+        },
+        "nth" => |_, args| nth(args),
+        "abs" => |_, args| math::abs(args),
+        "min" => |_, args| math::extreme(args, false),
+        "max" => |_, args| math::extreme(args, true),
+        "mod" => |_, args| math::modulo(args),
+        "floor" => |_, args| math::floor(args),
+        "sqrt" => |_, args| math::sqrt(args),
+        "to-num" => |_, args| math::to_num(args),
+        "to-str" => |_, args| text::to_str(args),
+        "upper" => |_, args| text::case(args, true),
+        "lower" => |_, args| text::case(args, false),
+        "split" => |_, args| text::split(args),
+        "join" => |_, args| text::join(args),
+        "get" => |_, args| match args.as_slice() {
+            [Type::Hash(hash), key] => Ok(hash.get(&hash_key(key)?).cloned().unwrap_or(Type::None)),
+            _ => Err("Function 'get' takes a hash and a key"),
+        },
+        "put" => |_, args| put(args),
+        "keys" => |_, args| match args.as_slice() {
+            [Type::Hash(hash)] => Ok(Type::seq(hash.keys().map(Key::to_type).collect())),
+            _ => Err("Function 'keys' takes a hash"),
+        },
+        "contains" => |_, args| match args.as_slice() {
+            [Type::Hash(hash), key] => Ok(Type::Bool(hash.contains_key(&hash_key(key)?))),
+            _ => Err("Function 'contains' takes a hash and a key"),
+        },
+        "re-match" => re_match,
+        "re-find-all" => re_find_all,
+        "re-replace" => re_replace,
+        "re-split" => re_split,
+        "csv-parse" => |_, args| csv_parse(args),
+        "csv-write" => |_, args| csv_write(args),
+        #[cfg(feature = "http")]
+        "http-get" => http::get,
+        #[cfg(feature = "http")]
+        "http-post" => http::post,
+        "tcp-connect" => tcp_connect,
+        "tcp-listen" => tcp_listen,
+        "tcp-send" => tcp_send,
+        "tcp-recv" => tcp_recv,
+        "date-now" => |_, args| date::now(args),
+        "date-parse" => |_, args| date::parse(args),
+        "date-format" => |_, args| date::format(args),
+        "date-add" => |_, args| date::add(args),
+        "date-diff" => |_, args| date::diff(args),
+        "sha256" => |_, args| digest::<Sha256>(args),
+        "sha1" => |_, args| digest::<Sha1>(args),
+        "md5" => |_, args| digest::<Md5>(args),
+        "b64-encode" => |_, args| b64_encode(args),
+        "b64-decode" => |_, args| b64_decode(args),
+        "hex-encode" => |_, args| hex_encode(args),
+        "hex-decode" => |_, args| hex_decode(args),
+        "exec" => exec,
+        "list-dir" => list_dir,
+        "exists?" => exists,
+        "file-exists?" => file_exists,
+        "read-file" => read_file,
+        "write-file" => |interpreter, args| write_file(interpreter, args, false),
+        "append-file" => |interpreter, args| write_file(interpreter, args, true),
+        "is-dir?" => is_dir,
+        "mkdir" => mkdir,
+        "remove" => remove,
+        "path-join" => |_, args| path_join(args),
+        "basename" => |_, args| basename(args),
+        "repr" => |_, args| match args.as_slice() {
+            [value] => repr::repr(value).map(Type::str),
+            _ => Err("Function 'repr' takes a value"),
+        },
+        "write-repr" => write_repr,
+        "read-repr" => read_repr,
+        _ => return None,
+    };
+    Some(builtin)
+}
+
+fn higher_order(name: &str) -> Option<HigherOrder> {
+    let builtin: HigherOrder = match name {
+        "map" => map,
+        "filter" => filter,
+        "reduce" => reduce,
+        "assert" => assert::assert,
+        "assert-eq" => assert::assert_eq,
+        _ => return None,
+    };
+    Some(builtin)
+}
+
+/// Calls the builtin function `name`, or returns `None` if there is no builtin by
+/// that name.
+pub(crate) fn call(
+    interpreter: &mut Interpreter,
+    name: &str,
+    args: Vec<Type>,
+) -> Option<Result<Type, Raised>> {
+    if let Some(builtin) = higher_order(name) {
+        return Some(builtin(interpreter, args));
+    }
+    lookup(name).map(|builtin| builtin(interpreter, args).map_err(Raised::from))
+}
+
+/// Prints each argument on its own line, to the error stream for `eprint`.
+fn print(
+    interpreter: &mut Interpreter,
+    args: Vec<Type>,
+    error: bool,
+) -> Result<Type, &'static str> {
+    for arg in &args {
+        match error {
+            true => interpreter.output.eprint(arg),
+            false => interpreter.output.print(arg),
+        }
+    }
+    Ok(Type::None)
+}
+
+/// Prints the arguments on one line, separated by spaces.
+fn println(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    let line: Vec<String> = args.iter().map(Type::to_string).collect();
+    interpreter.output.print(&Type::str(line.join(" ")));
+    Ok(Type::None)
+}
+
+/// Shows `prompt`, unless it is empty, then reads the next line of input.
+fn prompted_line(
+    interpreter: &mut Interpreter,
+    args: Vec<Type>,
+    name: &'static str,
+) -> Result<Option<String>, &'static str> {
+    match args.as_slice() {
+        [Type::Str(prompt)] if prompt.is_empty() => (),
+        [Type::Str(prompt)] => interpreter.output.prompt(prompt),
+        _ => return Err(name),
+    }
+    interpreter
+        .input
+        .read_line()
+        .map_err(|_| "Could not read input")
+}
+
+/// The next line of input, or none once the input has ended.
+fn read_line(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    let line = prompted_line(interpreter, args, "Function 'read-line' takes a prompt")?;
+    Ok(line.map_or(Type::None, Type::str))
+}
+
+/// The next line of input read as an int or a num, or none once the input has ended.
+fn read_num(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    let line = match prompted_line(interpreter, args, "Function 'read-num' takes a prompt")? {
+        Some(line) => line,
+        None => return Ok(Type::None),
+    };
+    let line = line.trim();
+    match (line.parse(), line.parse()) {
+        (Ok(int), _) => Ok(Type::Int(int)),
+        (_, Ok(num)) => Ok(Type::Num(num)),
+        _ => Err("Function 'read-num' read a line that is not a number"),
+    }
+}
+
+/// `comp f g` is a function that calls `g`, then `f` with the result.
+fn comp(args: Vec<Type>) -> Result<Type, &'static str> {
+    if args.is_empty() {
+        return Err("Function 'comp' takes at least one function");
+    }
+    if !args.iter().all(|arg| matches!(arg, Type::Function(_))) {
+        return Err("Function 'comp' takes functions");
+    }
+    Ok(Type::Function(Shared::new(Function::Composed(args))))
+}
+
+/// `partial f a` is a function that calls `f` with `a` before its own arguments.
+fn partial(mut args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.first() {
+        Some(Type::Function(_)) => {
+            let function = args.remove(0);
+            Ok(Type::Function(Shared::new(Function::Partial(
+                function, args,
+            ))))
+        }
+        _ => Err("Function 'partial' takes a function and the arguments to bind"),
+    }
+}
+
+/// `cons x coll` is a seq of `x` followed by the values of `coll`.
+fn cons(args: Vec<Type>) -> Result<Type, &'static str> {
+    let Ok([value, coll]) = <[Type; 2]>::try_from(args) else {
+        return Err("Function 'cons' takes a value and a collection");
+    };
+    let mut items = vec![value];
+    items.extend(iter::items(coll)?);
+    Ok(Type::seq(items))
+}
+
+/// `nth coll idx` is the value of `coll` at index `idx`, counting from 0, or `none`
+/// if it is past the end.
+fn nth(args: Vec<Type>) -> Result<Type, &'static str> {
+    match <[Type; 2]>::try_from(args) {
+        Ok([coll, idx @ (Type::Int(_) | Type::Num(_))]) => match index(&idx) {
+            Some(idx) => Ok(iter::items(coll)?.nth(idx).unwrap_or(Type::None)),
+            None => Err("Function 'nth' takes an index that is a whole num"),
+        },
+        _ => Err("Function 'nth' takes a collection and an index"),
+    }
+}
+
+/// `put hash key value` is a copy of `hash` with `key` set to `value`.
+fn put(args: Vec<Type>) -> Result<Type, &'static str> {
+    match <[Type; 3]>::try_from(args) {
+        Ok([Type::Hash(mut hash), key, value]) => {
+            Shared::make_mut(&mut hash).insert(hash_key(&key)?, value);
+            Ok(Type::Hash(hash))
+        }
+        _ => Err("Function 'put' takes a hash, a key, and a value"),
+    }
+}
+
+/// The key a value is stored under in a hash.
+fn hash_key(key: &Type) -> Result<Key, &'static str> {
+    Key::from_type(key).ok_or("A hash key must be a str, num, or bool")
+}
+
+/// `map f coll` is a seq of `f` applied to each value of `coll`.
+fn map(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, Raised> {
+    let Ok([function, coll]) = <[Type; 2]>::try_from(args) else {
+        return Err("Function 'map' takes a function and a collection".into());
+    };
+    iter::items(coll)?
+        .map(|item| interpreter.apply(function.clone(), vec![item]))
+        .collect::<Result<_, _>>()
+        .map(Type::seq)
+}
+
+/// `filter f coll` is a seq of the values of `coll` for which `f` is true.
+fn filter(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, Raised> {
+    let Ok([function, coll]) = <[Type; 2]>::try_from(args) else {
+        return Err("Function 'filter' takes a function and a collection".into());
+    };
+    let mut kept = vec![];
+    for item in iter::items(coll)? {
+        match interpreter.apply(function.clone(), vec![item.clone()])? {
+            Type::Bool(true) => kept.push(item),
+            Type::Bool(false) => {}
+            _ => return Err("Function 'filter' takes a function that returns a bool".into()),
+        }
+    }
+    Ok(Type::seq(kept))
+}
+
+/// `reduce f init coll` calls `f` with the result so far and each value of `coll`.
+fn reduce(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, Raised> {
+    let Ok([function, init, coll]) = <[Type; 3]>::try_from(args) else {
+        return Err(
+            "Function 'reduce' takes a function, an initial value, and a collection".into(),
+        );
+    };
+    iter::items(coll)?.try_fold(init, |acc, item| {
+        interpreter.apply(function.clone(), vec![acc, item])
+    })
+}
+
+fn random(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [bound] => match num(bound) {
+            Some(bound) => Ok(Type::Num(interpreter.rng.next_f64() * bound)),
+            None => Err("Cannot apply function 'random' to a non-num"),
+        },
+        _ => Err("Function 'random' takes exactly one argument"),
+    }
+}
+
+fn re_match(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [Type::Str(pattern), Type::Str(string)] => {
+            Ok(Type::Bool(regex(interpreter, pattern)?.is_match(string)))
+        }
+        _ => Err("Function 're-match' takes a pattern and a str"),
+    }
+}
+
+fn re_find_all(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [Type::Str(pattern), Type::Str(string)] => Ok(Type::seq(
+            regex(interpreter, pattern)?
+                .find_iter(string)
+                .map(|found| Type::str(found.as_str()))
+                .collect(),
+        )),
+        _ => Err("Function 're-find-all' takes a pattern and a str"),
+    }
+}
+
+fn re_replace(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [Type::Str(pattern), Type::Str(string), Type::Str(replacement)] => Ok(Type::str(
+            regex(interpreter, pattern)?
+                .replace_all(string, &**replacement)
+                .into_owned(),
+        )),
+        _ => Err("Function 're-replace' takes a pattern, a str, and a replacement str"),
+    }
+}
+
+fn re_split(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [Type::Str(pattern), Type::Str(string)] => Ok(Type::seq(
+            regex(interpreter, pattern)?
+                .split(string)
+                .map(Type::str)
+                .collect(),
+        )),
+        _ => Err("Function 're-split' takes a pattern and a str"),
+    }
+}
+
+/// `csv-parse text` gives each row as a seq of strs. With `{"header" true}` after the
+/// text, the first row is a header, and each row after it is a hash from the header's
+/// fields to its own.
+fn csv_parse(args: Vec<Type>) -> Result<Type, &'static str> {
+    let (text, header) = match args.as_slice() {
+        [Type::Str(text)] => (text, None),
+        [Type::Str(text), Type::Hash(options)] => (text, csv_header(options)),
+        _ => return Err("Function 'csv-parse' takes a str and an optional hash of options"),
+    };
+
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => row.push(Type::str(std::mem::take(&mut field))),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                row.push(Type::str(std::mem::take(&mut field)));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err("Unterminated quoted field in csv");
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(Type::str(field));
+        rows.push(row);
+    }
+
+    match header {
+        None | Some(Type::Bool(false)) => Ok(Type::seq(rows.into_iter().map(Type::seq).collect())),
+        Some(Type::Bool(true)) => {
+            let mut rows = rows.into_iter();
+            let columns = match rows.next() {
+                Some(columns) => columns,
+                None => return Ok(Type::seq(vec![])),
+            };
+            let columns = columns
+                .iter()
+                .map(hash_key)
+                .collect::<Result<Vec<_>, _>>()?;
+            rows.map(|row| match row.len() == columns.len() {
+                true => Ok(Type::hash(columns.iter().cloned().zip(row).collect())),
+                false => Err("Each csv row must have a field for each column of the header"),
+            })
+            .collect::<Result<_, _>>()
+            .map(Type::seq)
+        }
+        Some(_) => Err("The csv option 'header' must be a bool"),
+    }
+}
+
+/// `csv-write rows` writes each row, a seq. With a header option after the rows each
+/// row is a hash instead, written as its values for the header's columns, in order,
+/// after a line of the columns: `{"header" ["name" "age"]}` names them, and
+/// `{"header" true}` takes them from the keys of the first row.
+fn csv_write(args: Vec<Type>) -> Result<Type, &'static str> {
+    let (rows, header) = match args.as_slice() {
+        [Type::Seq(rows)] => (rows, None),
+        [Type::Seq(rows), Type::Hash(options)] => (rows, csv_header(options)),
+        _ => {
+            return Err("Function 'csv-write' takes a seq of rows and an optional hash of options")
+        }
+    };
+    let columns: Option<Vec<Key>> = match header {
+        None | Some(Type::Bool(false)) => None,
+        Some(Type::Bool(true)) => Some(match rows.first() {
+            Some(Type::Hash(first)) => first.keys().cloned().collect(),
+            _ => vec![],
+        }),
+        Some(Type::Seq(columns)) => Some(columns.iter().map(hash_key).collect::<Result<_, _>>()?),
+        Some(_) => return Err("The csv option 'header' must be a bool or a seq of columns"),
+    };
+
+    let mut text = String::new();
+    if let Some(columns) = columns.as_ref().filter(|columns| !columns.is_empty()) {
+        let columns: Vec<Type> = columns.iter().map(Key::to_type).collect();
+        csv_line(&mut text, &columns);
+    }
+    for row in rows.iter() {
+        match (row, &columns) {
+            (Type::Seq(fields), None) => csv_line(&mut text, fields),
+            (Type::Hash(row), Some(columns)) => {
+                // a column the row doesn't have is left empty
+                let fields: Vec<Type> = columns
+                    .iter()
+                    .map(|column| row.get(column).cloned().unwrap_or(Type::str("")))
+                    .collect();
+                csv_line(&mut text, &fields);
+            }
+            (_, None) => return Err("Each csv row must be a seq"),
+            (_, Some(_)) => return Err("Each csv row must be a hash when there is a header"),
+        }
+    }
+    Ok(Type::str(text))
+}
+
+fn csv_header(options: &BTreeMap<Key, Type>) -> Option<&Type> {
+    options.get(&Key::Str("header".into()))
+}
+
+/// Writes `fields` as a line of csv, quoting those that need it.
+fn csv_line(text: &mut String, fields: &[Type]) {
+    let line: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            let field = field.to_string();
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect();
+    text.push_str(&line.join(","));
+    text.push('\n');
+}
+
+/// Hashes a str or bytes with the digest `D`, returning the result as lowercase hex.
+fn digest<D: Digest>(args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [data] => Ok(Type::str(hex(&D::digest(as_bytes(data).ok_or(
+            "Functions 'sha256', 'sha1', and 'md5' take a str or bytes",
+        )?)))),
+        _ => Err("Functions 'sha256', 'sha1', and 'md5' take a str or bytes"),
+    }
+}
+
+fn b64_encode(args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [data] => Ok(Type::str(BASE64_STANDARD.encode(
+            as_bytes(data).ok_or("Function 'b64-encode' takes a str or bytes")?,
+        ))),
+        _ => Err("Function 'b64-encode' takes a str or bytes"),
+    }
+}
+
+fn b64_decode(args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [Type::Str(encoded)] => BASE64_STANDARD
+            .decode(encoded.trim())
+            .map(Type::bytes)
+            .map_err(|_| "Invalid base64"),
+        _ => Err("Function 'b64-decode' takes a str"),
+    }
+}
+
+fn hex_encode(args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [data] => Ok(Type::str(hex(
+            as_bytes(data).ok_or("Function 'hex-encode' takes a str or bytes")?
+        ))),
+        _ => Err("Function 'hex-encode' takes a str or bytes"),
+    }
+}
+
+fn hex_decode(args: Vec<Type>) -> Result<Type, &'static str> {
+    let encoded = match args.as_slice() {
+        [Type::Str(encoded)] => encoded.trim(),
+        _ => return Err("Function 'hex-decode' takes a str"),
+    };
+    if encoded.len() % 2 != 0 || !encoded.is_ascii() {
+        return Err("Invalid hex");
+    }
+
+    (0..encoded.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(&encoded[idx..idx + 2], 16).map_err(|_| "Invalid hex"))
+        .collect::<Result<Vec<u8>, _>>()
+        .map(Type::bytes)
+}
+
+/// The raw bytes of a str (as UTF-8) or of bytes.
+fn as_bytes(value: &Type) -> Option<&[u8]> {
+    match value {
+        Type::Str(string) => Some(string.as_bytes()),
+        Type::Bytes(bytes) => Some(bytes),
+        _ => None,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// `exec cmd args` runs a program to completion, returning a hash of its exit code
+/// (none if it was killed by a signal), stdout, and stderr. The arguments can be
+/// given one by one or as a single seq. Without the `env` capability the program
+/// starts with an empty environment, rather than this one's.
+fn exec(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    require(interpreter.capabilities.process)?;
+    let (program, rest) = match args.split_first() {
+        Some((Type::Str(program), rest)) => (program, rest),
+        _ => return Err("Function 'exec' takes a command and its arguments"),
+    };
+    let rest = match rest {
+        [Type::Seq(items)] => items.as_slice(),
+        _ => rest,
+    };
+
+    let mut command = Command::new(&**program);
+    if !interpreter.capabilities.env {
+        command.env_clear();
+    }
+    for arg in rest {
+        match arg {
+            Type::Str(arg) => command.arg(&**arg),
+            _ => return Err("Arguments to 'exec' must be strs"),
+        };
+    }
+
+    let output = command.output().map_err(|_| "Could not run command")?;
+    Ok(Type::hash(BTreeMap::from([
+        (
+            Key::Str("code".into()),
+            output
+                .status
+                .code()
+                .map_or(Type::None, |code| Type::Int(code.into())),
+        ),
+        (
+            Key::Str("stdout".into()),
+            Type::str(String::from_utf8_lossy(&output.stdout).into_owned()),
+        ),
+        (
+            Key::Str("stderr".into()),
+            Type::str(String::from_utf8_lossy(&output.stderr).into_owned()),
+        ),
+    ])))
+}
+
+/// The names of the entries in a directory, sorted.
+fn list_dir(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    require(interpreter.capabilities.fs)?;
+    let path = match args.as_slice() {
+        [Type::Str(path)] => path,
+        _ => return Err("Function 'list-dir' takes a path"),
+    };
+
+    let mut names = fs::read_dir(&**path)
+        .map_err(|_| "Could not read directory")?
+        .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| "Could not read directory")?;
+    names.sort();
+    Ok(Type::seq(names.into_iter().map(Type::str).collect()))
+}
+
+fn exists(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    require(interpreter.capabilities.fs)?;
+    match args.as_slice() {
+        [Type::Str(path)] => Ok(Type::Bool(Path::new(&**path).exists())),
+        _ => Err("Function 'exists?' takes a path"),
+    }
+}
+
+fn file_exists(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    require(interpreter.capabilities.fs)?;
+    match args.as_slice() {
+        [Type::Str(path)] => Ok(Type::Bool(Path::new(&**path).is_file())),
+        _ => Err("Function 'file-exists?' takes a path"),
+    }
+}
+
+fn read_file(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    require(interpreter.capabilities.fs)?;
+    match args.as_slice() {
+        [Type::Str(path)] => fs::read_to_string(&**path)
+            .map(Type::str)
+            .map_err(|_| "Could not read file"),
+        _ => Err("Function 'read-file' takes a path"),
+    }
+}
+
+/// Writes a str to a file, creating it if it doesn't exist, and either replacing
+/// what is already there or adding to the end of it.
+fn write_file(
+    interpreter: &mut Interpreter,
+    args: Vec<Type>,
+    append: bool,
+) -> Result<Type, &'static str> {
+    require(interpreter.capabilities.fs)?;
+    let (path, text) = match args.as_slice() {
+        [Type::Str(path), Type::Str(text)] => (path, text),
+        _ if append => return Err("Function 'append-file' takes a path and a str"),
+        _ => return Err("Function 'write-file' takes a path and a str"),
+    };
+    fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(&**path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map(|_| Type::None)
+        .map_err(|_| "Could not write file")
+}
+
+fn is_dir(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    require(interpreter.capabilities.fs)?;
+    match args.as_slice() {
+        [Type::Str(path)] => Ok(Type::Bool(Path::new(&**path).is_dir())),
+        _ => Err("Function 'is-dir?' takes a path"),
+    }
+}
+
+/// Creates a directory along with any missing parents.
+fn mkdir(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    require(interpreter.capabilities.fs)?;
+    match args.as_slice() {
+        [Type::Str(path)] => fs::create_dir_all(&**path)
+            .map(|_| Type::None)
+            .map_err(|_| "Could not create directory"),
+        _ => Err("Function 'mkdir' takes a path"),
+    }
+}
+
+/// Removes a file, or a directory along with everything in it.
+fn remove(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    require(interpreter.capabilities.fs)?;
+    let path = match args.as_slice() {
+        [Type::Str(path)] => Path::new(&**path),
+        _ => return Err("Function 'remove' takes a path"),
+    };
+
+    let removed = match path.is_dir() {
+        true => fs::remove_dir_all(path),
+        false => fs::remove_file(path),
+    };
+    removed
+        .map(|_| Type::None)
+        .map_err(|_| "Could not remove path")
+}
+
+/// Writes the `repr` of a value to a file, replacing anything already there.
+fn write_repr(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    require(interpreter.capabilities.fs)?;
+    match args.as_slice() {
+        [Type::Str(path), value] => fs::write(&**path, repr::repr(value)? + "\n")
+            .map(|_| Type::None)
+            .map_err(|_| "Could not write file"),
+        _ => Err("Function 'write-repr' takes a path and a value"),
+    }
+}
+
+/// Reads back a value written by `write-repr`.
+fn read_repr(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    require(interpreter.capabilities.fs)?;
+    match args.as_slice() {
+        [Type::Str(path)] => {
+            repr::read(&fs::read_to_string(&**path).map_err(|_| "Could not read file")?)
+        }
+        _ => Err("Function 'read-repr' takes a path"),
+    }
+}
+
+fn path_join(args: Vec<Type>) -> Result<Type, &'static str> {
+    let mut path = PathBuf::new();
+    for part in &args {
+        match part {
+            Type::Str(part) => path.push(&**part),
+            _ => return Err("Function 'path-join' takes strs"),
+        }
+    }
+    Ok(Type::str(path.to_string_lossy().into_owned()))
+}
+
+fn basename(args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [Type::Str(path)] => Ok(Type::str(
+            Path::new(&**path)
+                .file_name()
+                .map_or(String::new(), |name| name.to_string_lossy().into_owned()),
+        )),
+        _ => Err("Function 'basename' takes a path"),
+    }
+}
+
+/// Connections are handed to scripts as a num indexing the interpreter's connections.
+fn tcp_connection<'a>(
+    interpreter: &'a mut Interpreter,
+    handle: &Type,
+) -> Result<&'a mut TcpStream, &'static str> {
+    index(handle)
+        .and_then(|handle| interpreter.connections.get_mut(handle))
+        .ok_or("Not a tcp connection")
+}
+
+fn add_connection(interpreter: &mut Interpreter, stream: TcpStream) -> Type {
+    interpreter.connections.push(stream);
+    Type::Int((interpreter.connections.len() - 1) as i64)
+}
+
+fn tcp_connect(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    require(interpreter.capabilities.net)?;
+    match args.as_slice() {
+        [Type::Str(address)] => {
+            let stream = TcpStream::connect(&**address).map_err(|_| "Could not connect")?;
+            Ok(add_connection(interpreter, stream))
+        }
+        _ => Err("Function 'tcp-connect' takes an address like \"host:port\""),
+    }
+}
+
+/// Waits for the next client on `address`, listening on it first if needed.
+fn tcp_listen(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    require(interpreter.capabilities.net)?;
+    let address = match args.as_slice() {
+        [Type::Str(address)] => address,
+        _ => return Err("Function 'tcp-listen' takes an address like \"host:port\""),
+    };
+
+    if !interpreter.listeners.contains_key(&**address) {
+        let listener = TcpListener::bind(&**address).map_err(|_| "Could not listen on address")?;
+        interpreter.listeners.insert(address.to_string(), listener);
+    }
+    let (stream, _) = interpreter.listeners[&**address]
+        .accept()
+        .map_err(|_| "Could not accept connection")?;
+    Ok(add_connection(interpreter, stream))
+}
+
+fn tcp_send(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    require(interpreter.capabilities.net)?;
+    match args.as_slice() {
+        [handle, Type::Str(data)] => {
+            tcp_connection(interpreter, handle)?
+                .write_all(data.as_bytes())
+                .map_err(|_| "Could not send on tcp connection")?;
+            Ok(Type::Int(data.len() as i64))
+        }
+        _ => Err("Function 'tcp-send' takes a connection and a str"),
+    }
+}
+
+/// Reads whatever is available (up to an optional maximum number of bytes), returning
+/// an empty str once the other side has closed the connection.
+fn tcp_recv(interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, &'static str> {
+    require(interpreter.capabilities.net)?;
+    let (handle, max) = match args.as_slice() {
+        [handle] => (handle, Some(4096)),
+        [handle, max] => (handle, index(max).filter(|max| *max >= 1)),
+        _ => (&Type::None, None),
+    };
+    let max = max.ok_or("Function 'tcp-recv' takes a connection and an optional byte count")?;
+
+    let mut buffer = vec![0; max];
+    let read = tcp_connection(interpreter, handle)?
+        .read(&mut buffer)
+        .map_err(|_| "Could not receive on tcp connection")?;
+    buffer.truncate(read);
+    Ok(Type::str(String::from_utf8_lossy(&buffer).into_owned()))
+}
+
+/// The value of an int or num argument as a num.
+fn num(value: &Type) -> Option<f64> {
+    match value {
+        Type::Int(int) => Some(*int as f64),
+        Type::Num(num) => Some(*num),
+        _ => None,
+    }
+}
+
+/// An argument used as an index or a count: a non-negative int, or a whole num.
+fn index(value: &Type) -> Option<usize> {
+    match value {
+        Type::Int(int) => usize::try_from(*int).ok(),
+        Type::Num(num) if *num >= 0.0 && num.fract() == 0.0 => Some(*num as usize),
+        _ => None,
+    }
+}
+
+/// Errors when a builtin needs a capability that has been taken away.
+fn require(allowed: bool) -> Result<(), &'static str> {
+    if allowed {
+        Ok(())
+    } else {
+        Err("Operation not permitted in sandbox")
+    }
+}
+
+#[cfg(feature = "http")]
+mod http {
+    use std::collections::BTreeMap;
+
+    use environment::{Key, Type};
+
+    use super::require;
+    use crate::Interpreter;
+
+    pub(super) fn get(
+        interpreter: &mut Interpreter,
+        args: Vec<Type>,
+    ) -> Result<Type, &'static str> {
+        require(interpreter.capabilities.net)?;
+        match args.as_slice() {
+            [Type::Str(url)] => response(ureq::get(url).call()),
+            _ => Err("Function 'http-get' takes a url"),
+        }
+    }
+
+    pub(super) fn post(
+        interpreter: &mut Interpreter,
+        args: Vec<Type>,
+    ) -> Result<Type, &'static str> {
+        require(interpreter.capabilities.net)?;
+        let (url, body, headers) = match args.as_slice() {
+            [Type::Str(url), Type::Str(body)] => (url, body, None),
+            [Type::Str(url), Type::Str(body), Type::Hash(headers)] => (url, body, Some(headers)),
+            _ => return Err("Function 'http-post' takes a url, a str body, and optional headers"),
+        };
+
+        let mut request = ureq::post(url);
+        for (name, value) in headers.iter().flat_map(|headers| headers.iter()) {
+            match (name, value) {
+                (Key::Str(name), Type::Str(value)) => request = request.set(name, value),
+                _ => return Err("HTTP headers must map strs to strs"),
+            }
+        }
+        response(request.send_string(body))
+    }
+
+    /// Turns a response (including 4xx/5xx responses) into a hash of its status and body.
+    fn response(result: Result<ureq::Response, ureq::Error>) -> Result<Type, &'static str> {
+        let response = match result {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(_) => return Err("HTTP request failed"),
+        };
+
+        let status = response.status();
+        let body = response
+            .into_string()
+            .map_err(|_| "Could not read HTTP response body")?;
+        Ok(Type::hash(BTreeMap::from([
+            (Key::Str("status".into()), Type::Int(status.into())),
+            (Key::Str("body".into()), Type::str(body)),
+        ])))
+    }
+}
+
+/// How many compiled regexes are kept, so that a program building patterns as it
+/// goes doesn't hold on to every one of them.
+const REGEX_CACHE: usize = 32;
+
+/// Compiles `pattern`, reusing the compiled form if the pattern has been used
+/// recently. The pattern used longest ago is dropped when there are too many.
+fn regex<'a>(interpreter: &'a mut Interpreter, pattern: &str) -> Result<&'a Regex, &'static str> {
+    let regexes = &mut interpreter.regexes;
+    let cached = match regexes.iter().position(|(cached, _)| cached == pattern) {
+        Some(idx) => regexes.remove(idx),
+        None => {
+            let regex = Regex::new(pattern).map_err(|_| "Invalid regular expression")?;
+            if regexes.len() == REGEX_CACHE {
+                regexes.remove(0);
+            }
+            (pattern.to_string(), regex)
+        }
+    };
+    regexes.push(cached);
+    Ok(&regexes[regexes.len() - 1].1)
+}
+
+/// A small xorshift generator backing the `random` builtin. It is not suitable for
+/// cryptography, but a given seed always produces the same sequence.
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        // scramble the seed (splitmix64) so small seeds don't start with small
+        // numbers, and avoid the zero state xorshift gets stuck on
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Rng {
+            state: (z ^ (z >> 31)).max(1),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn from_time() -> Rng {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        Rng::new(nanos)
+    }
+
+    /// There is no clock to seed from in wasm, so hosts there call `set_seed` with
+    /// a seed of their own, such as one from `Math.random`.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn from_time() -> Rng {
+        Rng::new(0)
+    }
+
+    /// A number in the range [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn str(string: &str) -> Type {
+        Type::str(string.to_string())
+    }
+
+    fn builtin(name: &str) -> Type {
+        Type::Function(Shared::new(Function::Builtin(name.to_string())))
+    }
+
+    #[test]
+    fn test_re_find_all_and_split() -> Result<(), Raised> {
+        let mut interpreter = Interpreter::new();
+
+        match call(
+            &mut interpreter,
+            "re-find-all",
+            vec![str("[0-9]+"), str("a1 b22")],
+        ) {
+            Some(Ok(Type::Seq(found))) => assert_eq!(found.len(), 2),
+            _ => panic!("re-find-all should return a seq"),
+        }
+        match call(
+            &mut interpreter,
+            "re-split",
+            vec![str(", *"), str("a, b,c")],
+        ) {
+            Some(Ok(value)) => assert_eq!(value.to_string(), "[\"a\" \"b\" \"c\"]"),
+            _ => panic!("re-split should return a seq"),
+        }
+        assert_eq!(interpreter.regexes.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_re_replace_reuses_compiled_pattern() {
+        let mut interpreter = Interpreter::new();
+
+        for _ in 0..3 {
+            match call(
+                &mut interpreter,
+                "re-replace",
+                vec![str("o"), str("foo"), str("0")],
+            ) {
+                Some(Ok(Type::Str(replaced))) => assert_eq!(&*replaced, "f00"),
+                _ => panic!("re-replace should return a str"),
+            }
+        }
+        assert_eq!(interpreter.regexes.len(), 1);
+    }
+
+    #[test]
+    fn test_regex_cache_is_bounded() {
+        let mut interpreter = Interpreter::new();
+        for n in 0..REGEX_CACHE + 8 {
+            regex(&mut interpreter, &format!("a{{{n}}}")).unwrap();
+            // the first pattern stays, since it is used again each time
+            regex(&mut interpreter, "a{0}").unwrap();
+        }
+        assert_eq!(interpreter.regexes.len(), REGEX_CACHE);
+        assert!(interpreter
+            .regexes
+            .iter()
+            .any(|(pattern, _)| pattern == "a{0}"));
+        assert!(!interpreter
+            .regexes
+            .iter()
+            .any(|(pattern, _)| pattern == "a{1}"));
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let text = "name,quote\nlinus,\"meow, \"\"purr\"\"\"\n";
+        let rows = match csv_parse(vec![str(text)]) {
+            Ok(rows) => rows,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(
+            rows.to_string(),
+            "[[\"name\" \"quote\"] [\"linus\" \"meow, \\\"purr\\\"\"]]"
+        );
+        match csv_write(vec![rows]) {
+            Ok(Type::Str(written)) => assert_eq!(&*written, text),
+            _ => panic!("csv-write should return a str"),
+        }
+    }
+
+    #[test]
+    fn test_csv_header_round_trip() {
+        let header = |value| Type::hash(BTreeMap::from([(Key::Str("header".into()), value)]));
+        let text = "age,name\n4,linus\n,\"a, b\"\n";
+        let rows = csv_parse(vec![str(text), header(Type::Bool(true))]).unwrap();
+        assert_eq!(
+            rows.to_string(),
+            "[{\"age\" \"4\" \"name\" \"linus\"} {\"age\" \"\" \"name\" \"a, b\"}]"
+        );
+        match csv_write(vec![rows.clone(), header(Type::Bool(true))]) {
+            Ok(Type::Str(written)) => assert_eq!(&*written, text),
+            _ => panic!("csv-write should return a str"),
+        }
+
+        // named columns are written in the order given, and missing ones left empty
+        let columns = Type::seq(vec![str("name"), str("nickname"), str("age")]);
+        match csv_write(vec![rows, header(columns)]) {
+            Ok(Type::Str(written)) => {
+                assert_eq!(&*written, "name,nickname,age\nlinus,,4\n\"a, b\",,\n")
+            }
+            _ => panic!("csv-write should return a str"),
+        }
+
+        assert!(csv_parse(vec![str("a,b\n1\n"), header(Type::Bool(true))]).is_err());
+        assert!(csv_write(vec![
+            Type::seq(vec![Type::seq(vec![])]),
+            header(Type::Bool(true))
+        ])
+        .is_err());
+    }
+
+    /// Serves a single canned HTTP response on a local port, returning its url.
+    #[cfg(feature = "http")]
+    fn serve_once(response: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        url
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http_get() {
+        let url = serve_once("HTTP/1.1 404 Not Found\r\nContent-Length: 4\r\n\r\nnope");
+        let mut interpreter = Interpreter::new();
+
+        match call(&mut interpreter, "http-get", vec![str(&url)]) {
+            Some(Ok(response)) => {
+                assert_eq!(response.to_string(), "{\"body\" \"nope\" \"status\" 404}")
+            }
+            _ => panic!("http-get should return a hash"),
+        }
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http_sandboxed() {
+        let mut interpreter = Interpreter::with_capabilities(crate::Capabilities::sandboxed());
+        assert!(matches!(
+            call(
+                &mut interpreter,
+                "http-get",
+                vec![str("http://127.0.0.1:1/")]
+            ),
+            Some(Err(Raised::Error("Operation not permitted in sandbox")))
+        ));
+    }
+
+    #[test]
+    fn test_tcp_round_trip() -> Result<(), Raised> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 4];
+            stream.read_exact(&mut request).unwrap();
+            stream.write_all(b"pong").unwrap();
+        });
+
+        let mut interpreter = Interpreter::new();
+        let connection = call(&mut interpreter, "tcp-connect", vec![str(&address)]).unwrap()?;
+        call(
+            &mut interpreter,
+            "tcp-send",
+            vec![connection.clone(), str("ping")],
+        )
+        .unwrap()?;
+        match call(&mut interpreter, "tcp-recv", vec![connection]) {
+            Some(Ok(Type::Str(reply))) => assert_eq!(&*reply, "pong"),
+            _ => panic!("tcp-recv should return a str"),
+        }
+        server.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_tcp_sandboxed() {
+        let mut interpreter = Interpreter::with_capabilities(crate::Capabilities::sandboxed());
+        assert!(matches!(
+            call(&mut interpreter, "tcp-listen", vec![str("127.0.0.1:0")]),
+            Some(Err(Raised::Error("Operation not permitted in sandbox")))
+        ));
+    }
+
+    #[test]
+    fn test_digests() {
+        let mut interpreter = Interpreter::new();
+        let cases = [
+            (
+                "sha256",
+                "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+            ),
+            ("sha1", "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"),
+            ("md5", "5d41402abc4b2a76b9719d911017c592"),
+        ];
+        for (name, expected) in cases {
+            match call(&mut interpreter, name, vec![str("hello")]) {
+                Some(Ok(Type::Str(hex))) => assert_eq!(&*hex, expected),
+                _ => panic!("{} should return a str", name),
+            }
+        }
+    }
+
+    #[test]
+    fn test_base64_and_hex() -> Result<(), Raised> {
+        let mut interpreter = Interpreter::new();
+
+        let encoded = call(&mut interpreter, "b64-encode", vec![str("linus")]).unwrap()?;
+        assert_eq!(encoded.to_string(), "bGludXM=");
+        let decoded = call(&mut interpreter, "b64-decode", vec![encoded]).unwrap()?;
+        assert!(matches!(&decoded, Type::Bytes(bytes) if **bytes == b"linus"));
+
+        let encoded = call(&mut interpreter, "hex-encode", vec![decoded]).unwrap()?;
+        assert_eq!(encoded.to_string(), "6c696e7573");
+        let decoded = call(&mut interpreter, "hex-decode", vec![encoded]).unwrap()?;
+        assert_eq!(decoded.to_string(), "<bytes 6c 69 6e 75 73>");
+
+        assert!(matches!(
+            call(&mut interpreter, "hex-decode", vec![str("abc")]),
+            Some(Err(Raised::Error("Invalid hex")))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_exec() -> Result<(), Raised> {
+        let mut interpreter = Interpreter::new();
+        let output = call(
+            &mut interpreter,
+            "exec",
+            vec![str("sh"), str("-c"), str("echo out; echo err >&2; exit 3")],
+        )
+        .unwrap()?;
+        assert_eq!(
+            output.to_string(),
+            "{\"code\" 3 \"stderr\" \"err\\n\" \"stdout\" \"out\\n\"}"
+        );
+
+        let mut interpreter = Interpreter::with_capabilities(crate::Capabilities::sandboxed());
+        assert!(matches!(
+            call(&mut interpreter, "exec", vec![str("true")]),
+            Some(Err(Raised::Error("Operation not permitted in sandbox")))
+        ));
+
+        // the environment is only passed on with the env capability
+        let home = vec![str("sh"), str("-c"), str("echo ${HOME-unset}")];
+        let mut interpreter = Interpreter::with_capabilities(crate::Capabilities {
+            env: false,
+            ..crate::Capabilities::all()
+        });
+        let output = call(&mut interpreter, "exec", home).unwrap()?;
+        assert!(output.to_string().contains("\"stdout\" \"unset\\n\""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_filesystem() -> Result<(), Raised> {
+        let mut interpreter = Interpreter::new();
+        let root = std::env::temp_dir().join(format!("linus-fs-test-{}", std::process::id()));
+        let root = root.to_string_lossy().into_owned();
+
+        let nested = call(
+            &mut interpreter,
+            "path-join",
+            vec![str(&root), str("a"), str("b")],
+        )
+        .unwrap()?;
+        call(&mut interpreter, "mkdir", vec![nested.clone()]).unwrap()?;
+        assert!(matches!(
+            call(&mut interpreter, "is-dir?", vec![nested.clone()]),
+            Some(Ok(Type::Bool(true)))
+        ));
+        assert_eq!(
+            call(&mut interpreter, "basename", vec![nested])
+                .unwrap()?
+                .to_string(),
+            "b"
+        );
+        assert_eq!(
+            call(&mut interpreter, "list-dir", vec![str(&root)])
+                .unwrap()?
+                .to_string(),
+            "[\"a\"]"
+        );
+
+        call(&mut interpreter, "remove", vec![str(&root)]).unwrap()?;
+        assert!(matches!(
+            call(&mut interpreter, "exists?", vec![str(&root)]),
+            Some(Ok(Type::Bool(false)))
+        ));
+
+        let mut interpreter = Interpreter::with_capabilities(crate::Capabilities::sandboxed());
+        assert!(matches!(
+            call(&mut interpreter, "exists?", vec![str(&root)]),
+            Some(Err(Raised::Error("Operation not permitted in sandbox")))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_files() -> Result<(), Raised> {
+        let mut interpreter = Interpreter::new();
+        let path = std::env::temp_dir().join(format!("linus-file-test-{}", std::process::id()));
+        let path = str(&path.to_string_lossy());
+
+        call(
+            &mut interpreter,
+            "write-file",
+            vec![path.clone(), str("a\n")],
+        )
+        .unwrap()?;
+        call(
+            &mut interpreter,
+            "append-file",
+            vec![path.clone(), str("b\n")],
+        )
+        .unwrap()?;
+        assert!(matches!(
+            call(&mut interpreter, "read-file", vec![path.clone()]),
+            Some(Ok(Type::Str(text))) if &*text == "a\nb\n"
+        ));
+        call(&mut interpreter, "write-file", vec![path.clone(), str("c")]).unwrap()?;
+        assert!(matches!(
+            call(&mut interpreter, "read-file", vec![path.clone()]),
+            Some(Ok(Type::Str(text))) if &*text == "c"
+        ));
+        assert!(matches!(
+            call(&mut interpreter, "file-exists?", vec![path.clone()]),
+            Some(Ok(Type::Bool(true)))
+        ));
+
+        call(&mut interpreter, "remove", vec![path.clone()]).unwrap()?;
+        assert!(matches!(
+            call(&mut interpreter, "file-exists?", vec![path.clone()]),
+            Some(Ok(Type::Bool(false)))
+        ));
+        assert!(matches!(
+            call(&mut interpreter, "read-file", vec![path]),
+            Some(Err(Raised::Error("Could not read file")))
+        ));
+        // a directory exists, but isn't a file
+        let dir = str(&std::env::temp_dir().to_string_lossy());
+        assert!(matches!(
+            call(&mut interpreter, "file-exists?", vec![dir]),
+            Some(Ok(Type::Bool(false)))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_iterating_hashes() -> Result<(), Raised> {
+        let mut interpreter = Interpreter::new();
+        let hash = Type::hash(BTreeMap::from([
+            (Key::Str("b".into()), Type::Num(2.0)),
+            (Key::Str("a".into()), Type::Num(1.0)),
+        ]));
+
+        let first = call(&mut interpreter, "first", vec![hash.clone()]).unwrap()?;
+        assert_eq!(first.to_string(), "[\"a\" 1]");
+        let rest = call(&mut interpreter, "rest", vec![hash.clone()]).unwrap()?;
+        assert_eq!(rest.to_string(), "{\"b\" 2}");
+        let keys = call(&mut interpreter, "map", vec![builtin("first"), hash]).unwrap()?;
+        assert_eq!(keys.to_string(), "[\"a\" \"b\"]");
+        Ok(())
+    }
+
+    #[test]
+    fn test_first_and_empty() -> Result<(), Raised> {
+        let mut interpreter = Interpreter::new();
+        let first = call(&mut interpreter, "first", vec![str("héllo")]).unwrap()?;
+        assert_eq!(first.to_string(), "h");
+        let first = call(&mut interpreter, "first", vec![Type::bytes(vec![7, 8])]).unwrap()?;
+        assert_eq!(first.to_string(), "7");
+        for (coll, empty) in [
+            (str(""), true),
+            (str("é"), false),
+            (Type::hash(BTreeMap::new()), true),
+            (Type::seq(vec![Type::None]), false),
+        ] {
+            let found = call(&mut interpreter, "empty?", vec![coll.clone()]).unwrap()?;
+            assert_eq!(found.to_string(), empty.to_string());
+            if empty {
+                let first = call(&mut interpreter, "first", vec![coll]).unwrap()?;
+                assert!(matches!(first, Type::None));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_repr_round_trip() -> Result<(), Raised> {
+        let mut interpreter = Interpreter::new();
+        let value = Type::seq(vec![
+            Type::Num(-1.5),
+            Type::Int(2),
+            Type::Num(2.0),
+            str("a b"),
+            Type::Bool(true),
+            Type::None,
+            Type::bytes(vec![0, 255]),
+            Type::hash(BTreeMap::from([(Key::Str("k".into()), Type::seq(vec![]))])),
+            Type::Function(Shared::new(Function::Builtin("+".to_string()))),
+            Type::Function(Shared::new(Function::Builtin("sha1".to_string()))),
+        ]);
+        let written = "[-1.5 2 2.0 \"a b\" true none (hex-decode \"00ff\") {\"k\" []} (+) sha1]";
+        assert_eq!(repr::repr(&value)?, written);
+        assert_eq!(repr::repr(&repr::read(written)?)?, written);
+
+        let path = std::env::temp_dir().join(format!("linus-repr-test-{}", std::process::id()));
+        let path = str(&path.to_string_lossy());
+        call(&mut interpreter, "write-repr", vec![path.clone(), value]).unwrap()?;
+        let read = call(&mut interpreter, "read-repr", vec![path.clone()]).unwrap()?;
+        assert_eq!(repr::repr(&read)?, written);
+        call(&mut interpreter, "remove", vec![path]).unwrap()?;
+
+        assert_eq!(
+            repr::repr(&str("say \"hi\"")),
+            Err("Cannot repr a str that contains '\"'")
+        );
+        assert!(repr::read("[1 2").is_err());
+        // a brace in a str is written so that it doesn't interpolate
+        assert_eq!(repr::repr(&str("{x}"))?, "\"\\{x\\}\"");
+        assert_eq!(repr::read("\"\\{x\\}\"")?.to_string(), "{x}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_assert() {
+        let mut interpreter = Interpreter::new();
+        let seq = |items: Vec<Type>| Type::seq(items);
+        assert!(matches!(
+            call(&mut interpreter, "assert", vec![Type::Bool(true)]),
+            Some(Ok(Type::None))
+        ));
+        assert!(matches!(
+            call(
+                &mut interpreter,
+                "assert-eq",
+                vec![seq(vec![Type::Int(1)]), seq(vec![Type::Num(1.0)])]
+            ),
+            Some(Ok(Type::None))
+        ));
+        let thrown = |result: Option<Result<Type, Raised>>| match result {
+            Some(Err(Raised::Thrown(value))) => value.to_string(),
+            _ => panic!("expected a thrown value"),
+        };
+        assert_eq!(
+            thrown(call(
+                &mut interpreter,
+                "assert",
+                vec![Type::Bool(false), str("why")]
+            )),
+            "Assertion failed: why"
+        );
+        assert_eq!(
+            thrown(call(
+                &mut interpreter,
+                "assert-eq",
+                vec![str("1"), Type::Int(1)]
+            )),
+            "Assertion failed: expected 1, got \"1\""
+        );
+        assert!(matches!(
+            call(&mut interpreter, "assert", vec![Type::Int(1)]),
+            Some(Err(Raised::Error(_)))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        let mut interpreter = Interpreter::new();
+        assert!(matches!(
+            call(&mut interpreter, "re-match", vec![str("("), str("")]),
+            Some(Err(Raised::Error("Invalid regular expression")))
+        ));
+    }
+
+    #[test]
+    fn test_math() {
+        assert!(matches!(
+            math::abs(vec![Type::Int(i64::MIN)]),
+            Ok(Type::Num(num)) if num == 2f64.powi(63)
+        ));
+        assert!(matches!(
+            math::extreme(vec![Type::Int(2), Type::Num(2.0), Type::Int(1)], true),
+            Ok(Type::Int(2))
+        ));
+        assert!(math::extreme(vec![], false).is_err());
+        assert!(matches!(
+            math::modulo(vec![Type::Int(i64::MIN), Type::Int(-1)]),
+            Ok(Type::Int(0))
+        ));
+        assert!(matches!(
+            math::floor(vec![Type::Num(-0.5)]),
+            Ok(Type::Int(-1))
+        ));
+        assert!(matches!(
+            math::floor(vec![Type::Num(1e300)]),
+            Ok(Type::Num(num)) if num == 1e300
+        ));
+        assert!(math::to_num(vec![str("two")]).is_err());
+    }
+
+    #[test]
+    fn test_text() -> Result<(), &'static str> {
+        assert_eq!(text::to_str(vec![Type::Num(2.0)])?.to_string(), "2");
+        assert_eq!(
+            text::split(vec![str(", "), str("a, b")])?.to_string(),
+            "[\"a\" \"b\"]"
+        );
+        // a str is a collection of its characters
+        assert_eq!(text::join(vec![str("-"), str("ab")])?.to_string(), "a-b");
+        assert!(text::join(vec![str(","), Type::seq(vec![Type::Int(1)])]).is_err());
+        Ok(())
+    }
+}
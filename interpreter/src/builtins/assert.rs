@@ -0,0 +1,69 @@
+use environment::Type;
+use lexer::Token;
+
+use crate::{operate, Interpreter, Raised};
+
+/// `assert cond` and `assert cond message` throw a str saying the assertion failed,
+/// which `catch` can handle, unless `cond` is true.
+pub(super) fn assert(_: &mut Interpreter, args: Vec<Type>) -> Result<Type, Raised> {
+    let (holds, message) = match args.as_slice() {
+        [Type::Bool(holds)] => (*holds, None),
+        [Type::Bool(holds), Type::Str(message)] => (*holds, Some(message)),
+        _ => return Err("Function 'assert' takes a bool and an optional message".into()),
+    };
+    match (holds, message) {
+        (true, _) => Ok(Type::None),
+        (false, None) => Err(failed("Assertion failed".to_string())),
+        (false, Some(message)) => Err(failed(format!("Assertion failed: {message}"))),
+    }
+}
+
+/// `assert-eq actual expected`, with an optional message after them, throws like
+/// `assert` unless the values are equal: scalars as `=` compares them, and seqs and
+/// hashes item by item.
+pub(super) fn assert_eq(_: &mut Interpreter, args: Vec<Type>) -> Result<Type, Raised> {
+    let (actual, expected, message) = match args.as_slice() {
+        [actual, expected] => (actual, expected, None),
+        [actual, expected, Type::Str(message)] => (actual, expected, Some(message)),
+        _ => return Err("Function 'assert-eq' takes two values and an optional message".into()),
+    };
+    if equal(actual, expected) {
+        return Ok(Type::None);
+    }
+    let differ = format!("expected {}, got {}", shown(expected), shown(actual));
+    match message {
+        None => Err(failed(format!("Assertion failed: {differ}"))),
+        Some(message) => Err(failed(format!("Assertion failed: {message}: {differ}"))),
+    }
+}
+
+fn failed(message: String) -> Raised {
+    Raised::Thrown(Type::str(message))
+}
+
+fn equal(a: &Type, b: &Type) -> bool {
+    match (a, b) {
+        (Type::Seq(a), Type::Seq(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| equal(a, b))
+        }
+        (Type::Hash(a), Type::Hash(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b.iter())
+                    .all(|((a_key, a), (b_key, b))| a_key == b_key && equal(a, b))
+        }
+        (Type::Bytes(a), Type::Bytes(b)) => a == b,
+        (a, b) => matches!(
+            operate(&Token::Equal, vec![a.clone(), b.clone()]),
+            Ok(Type::Bool(true))
+        ),
+    }
+}
+
+/// A value as it shows inside a seq, so that a str can be told from a num.
+fn shown(value: &Type) -> String {
+    match value {
+        Type::Str(string) => format!("{string:?}"),
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+
+use chrono::{
+    DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, Offset, Timelike, Utc,
+};
+use environment::{Key, Type};
+
+/// `date-now tz` is the current time in `tz`, which is "utc", "local", or an offset
+/// like "+05:30".
+pub(super) fn now(args: Vec<Type>) -> Result<Type, &'static str> {
+    let offset = match args.as_slice() {
+        [Type::Str(tz)] => parse_offset(tz)?,
+        _ => return Err("Function 'date-now' takes a timezone"),
+    };
+    Ok(to_hash(clock()?.with_timezone(&offset)))
+}
+
+/// The current time. A browser only lets JavaScript read its clock, so in wasm
+/// there is none.
+#[cfg(not(target_arch = "wasm32"))]
+fn clock() -> Result<DateTime<Utc>, &'static str> {
+    Ok(Utc::now())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn clock() -> Result<DateTime<Utc>, &'static str> {
+    Err("There is no clock to read the date from")
+}
+
+/// `date-parse s format` reads `s` using a strftime-style `format`. Formats without an
+/// offset (`%z`) are read as UTC, and formats without a time as midnight.
+pub(super) fn parse(args: Vec<Type>) -> Result<Type, &'static str> {
+    let (string, format) = match args.as_slice() {
+        [Type::Str(string), Type::Str(format)] => (string, format),
+        _ => return Err("Function 'date-parse' takes a str and a format"),
+    };
+
+    let date = DateTime::parse_from_str(string, format)
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(string, format).map(|date| date.and_utc().fixed_offset())
+        })
+        .or_else(|_| {
+            NaiveDate::parse_from_str(string, format)
+                .map(|date| date.and_time(Default::default()).and_utc().fixed_offset())
+        })
+        .map_err(|_| "Could not parse date")?;
+    Ok(to_hash(date))
+}
+
+/// `date-format date format` writes `date` using a strftime-style `format`.
+pub(super) fn format(args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [date, Type::Str(format)] => {
+            let date = from_hash(date)?;
+            let items = chrono::format::StrftimeItems::new(format)
+                .parse()
+                .map_err(|_| "Invalid date format")?;
+            Ok(Type::str(date.format_with_items(items.iter()).to_string()))
+        }
+        _ => Err("Function 'date-format' takes a date and a format"),
+    }
+}
+
+/// `date-add date seconds` moves `date` by a (possibly negative) number of seconds.
+pub(super) fn add(args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [date, seconds @ (Type::Int(_) | Type::Num(_))] => {
+            let seconds = super::num(seconds).unwrap_or_default();
+            let delta = chrono::Duration::try_milliseconds((seconds * 1000.0) as i64)
+                .ok_or("Date is out of range")?;
+            let date = from_hash(date)?
+                .checked_add_signed(delta)
+                .ok_or("Date is out of range")?;
+            Ok(to_hash(date))
+        }
+        _ => Err("Function 'date-add' takes a date and a number of seconds"),
+    }
+}
+
+/// `date-diff a b` is the number of seconds from `b` to `a`.
+pub(super) fn diff(args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [a, b] => {
+            let delta = from_hash(a)? - from_hash(b)?;
+            Ok(Type::Num(delta.num_milliseconds() as f64 / 1000.0))
+        }
+        _ => Err("Function 'date-diff' takes two dates"),
+    }
+}
+
+fn parse_offset(tz: &str) -> Result<FixedOffset, &'static str> {
+    match tz {
+        "utc" | "UTC" | "Z" => Ok(FixedOffset::east_opt(0).expect("zero offset is valid")),
+        "local" => Ok(clock()?.with_timezone(&Local).offset().fix()),
+        _ => {
+            let (sign, rest) = match tz.split_at_checked(1) {
+                Some(("+", rest)) => (1, rest),
+                Some(("-", rest)) => (-1, rest),
+                _ => return Err("Unknown timezone"),
+            };
+            let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+            match (hours.parse::<i32>(), minutes.parse::<i32>()) {
+                (Ok(hours), Ok(minutes)) => {
+                    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+                        .ok_or("Unknown timezone")
+                }
+                _ => Err("Unknown timezone"),
+            }
+        }
+    }
+}
+
+fn to_hash(date: DateTime<FixedOffset>) -> Type {
+    let fields = [
+        ("year", Type::Int(date.year().into())),
+        ("month", Type::Int(date.month().into())),
+        ("day", Type::Int(date.day().into())),
+        ("hour", Type::Int(date.hour().into())),
+        ("minute", Type::Int(date.minute().into())),
+        ("second", Type::Int(date.second().into())),
+        ("offset", Type::Int(date.offset().local_minus_utc().into())),
+        (
+            "timestamp",
+            Type::Num(date.timestamp_millis() as f64 / 1000.0),
+        ),
+    ];
+    Type::hash(
+        fields
+            .into_iter()
+            .map(|(key, value)| (Key::Str(key.into()), value))
+            .collect::<BTreeMap<_, _>>(),
+    )
+}
+
+/// Dates are rebuilt from their timestamp and offset; the other fields are for reading.
+fn from_hash(date: &Type) -> Result<DateTime<FixedOffset>, &'static str> {
+    let field = |name: &str| match date {
+        Type::Hash(entries) => match entries.get(&Key::Str(name.into())) {
+            Some(value) => super::num(value).ok_or("Not a date"),
+            None => Err("Not a date"),
+        },
+        _ => Err("Not a date"),
+    };
+
+    let offset = FixedOffset::east_opt(field("offset")? as i32).ok_or("Not a date")?;
+    DateTime::from_timestamp_millis((field("timestamp")? * 1000.0).round() as i64)
+        .map(|date| date.with_timezone(&offset))
+        .ok_or("Date is out of range")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn str(string: &str) -> Type {
+        Type::str(string.to_string())
+    }
+
+    #[test]
+    fn test_parse_and_format_keep_offset() -> Result<(), &'static str> {
+        let date = parse(vec![
+            str("2024-03-01 12:30:00 +0200"),
+            str("%Y-%m-%d %H:%M:%S %z"),
+        ])?;
+        match format(vec![date, str("%H:%M %z")])? {
+            Type::Str(formatted) => assert_eq!(&*formatted, "12:30 +0200"),
+            _ => panic!("date-format should return a str"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_and_diff() -> Result<(), &'static str> {
+        let start = parse(vec![str("2024-02-28"), str("%Y-%m-%d")])?;
+        let later = add(vec![start.clone(), Type::Num(86400.0 * 2.0)])?;
+
+        match format(vec![later.clone(), str("%Y-%m-%d")])? {
+            Type::Str(formatted) => assert_eq!(&*formatted, "2024-03-01"),
+            _ => panic!("date-format should return a str"),
+        }
+        assert!(matches!(diff(vec![later, start])?, Type::Num(seconds) if seconds == 172800.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_now_with_offset() -> Result<(), &'static str> {
+        match now(vec![str("-03:00")])? {
+            Type::Hash(entries) => assert!(matches!(
+                entries.get(&Key::Str("offset".into())),
+                Some(Type::Int(-10800))
+            )),
+            _ => panic!("date-now should return a hash"),
+        }
+        assert_eq!(now(vec![str("mars")]).unwrap_err(), "Unknown timezone");
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use std::cmp::Ordering;
+
+use environment::{compare_int_num, Type};
+
+use super::num;
+
+/// `abs x` is `x` without its sign, staying an int unless it is the one int whose
+/// absolute value doesn't fit.
+pub(super) fn abs(args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [Type::Int(int)] => Ok(int
+            .checked_abs()
+            .map_or(Type::Num((*int as f64).abs()), Type::Int)),
+        [Type::Num(num)] => Ok(Type::Num(num.abs())),
+        _ => Err("Function 'abs' takes an int or a num"),
+    }
+}
+
+/// `min a b ...` and `max a b ...` are the least and greatest of their arguments,
+/// which are compared exactly whether they are ints or nums. The first of any that
+/// are equal is kept.
+pub(super) fn extreme(args: Vec<Type>, greatest: bool) -> Result<Type, &'static str> {
+    let invalid = match greatest {
+        true => "Function 'max' takes one or more ints or nums",
+        false => "Function 'min' takes one or more ints or nums",
+    };
+    let mut args = args.into_iter();
+    let mut kept = args
+        .next()
+        .filter(|arg| num(arg).is_some())
+        .ok_or(invalid)?;
+    for arg in args {
+        let ordering = match (&arg, &kept) {
+            (Type::Int(a), Type::Int(b)) => Some(a.cmp(b)),
+            (Type::Int(a), Type::Num(b)) => compare_int_num(*a, *b),
+            (Type::Num(a), Type::Int(b)) => compare_int_num(*b, *a).map(Ordering::reverse),
+            (Type::Num(a), Type::Num(b)) => a.partial_cmp(b),
+            _ => return Err(invalid),
+        };
+        let wanted = match greatest {
+            true => Ordering::Greater,
+            false => Ordering::Less,
+        };
+        if ordering == Some(wanted) {
+            kept = arg;
+        }
+    }
+    Ok(kept)
+}
+
+/// `mod a b` is the remainder of dividing `a` by `b`, which is never negative:
+/// `mod -7 3` is 2.
+pub(super) fn modulo(args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [Type::Int(_), Type::Int(0)] => Err("Function 'mod' cannot divide by zero"),
+        [Type::Int(a), Type::Int(b)] => {
+            Ok(a.checked_rem_euclid(*b).map_or(Type::Int(0), Type::Int))
+        }
+        [a, b] => match (num(a), num(b)) {
+            (Some(a), Some(b)) => Ok(Type::Num(a.rem_euclid(b))),
+            _ => Err("Function 'mod' takes two ints or nums"),
+        },
+        _ => Err("Function 'mod' takes two ints or nums"),
+    }
+}
+
+/// `floor x` is the greatest int no greater than `x`. A num too big to be an int,
+/// or that isn't a number, stays a num.
+pub(super) fn floor(args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [Type::Int(int)] => Ok(Type::Int(*int)),
+        [Type::Num(num)] => {
+            let floor = num.floor();
+            // the range of an int as nums, where i64::MAX rounds up to 2^63
+            match floor >= -(2f64.powi(63)) && floor < 2f64.powi(63) {
+                true => Ok(Type::Int(floor as i64)),
+                false => Ok(Type::Num(floor)),
+            }
+        }
+        _ => Err("Function 'floor' takes an int or a num"),
+    }
+}
+
+/// `sqrt x` is the square root of `x` as a num, which isn't a number if `x` is
+/// negative.
+pub(super) fn sqrt(args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [value] => num(value)
+            .map(|num| Type::Num(num.sqrt()))
+            .ok_or("Function 'sqrt' takes an int or a num"),
+        _ => Err("Function 'sqrt' takes an int or a num"),
+    }
+}
+
+/// `to-num x` is an int or num as a num, or the num a str holds, ignoring
+/// whitespace around it.
+pub(super) fn to_num(args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [Type::Str(string)] => string
+            .trim()
+            .parse()
+            .map(Type::Num)
+            .map_err(|_| "Function 'to-num' could not read a num from the str"),
+        [value] => num(value)
+            .map(Type::Num)
+            .ok_or("Function 'to-num' takes an int, a num, or a str"),
+        _ => Err("Function 'to-num' takes an int, a num, or a str"),
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use environment::{Function, Key, Shared, Type};
+
+use super::is_builtin;
+use crate::operator_token;
+
+/// Writes `value` as source text that reads back as an equal value. Seqs are
+/// written `[a b]` and hashes `{k v}`, bytes as a call to `hex-decode`, and builtins
+/// by name. Other functions, and strs that contain a `"`, have no written form.
+pub(super) fn repr(value: &Type) -> Result<String, &'static str> {
+    let mut written = String::new();
+    write(&mut written, value, false)?;
+    Ok(written)
+}
+
+/// Reads a value written by `repr`.
+pub(super) fn read(text: &str) -> Result<Type, &'static str> {
+    let mut chars = text.chars().peekable();
+    let value = read_value(&mut chars)?;
+    skip_whitespace(&mut chars);
+    match chars.next() {
+        None => Ok(value),
+        Some(_) => Err("Unexpected text after value"),
+    }
+}
+
+fn write(written: &mut String, value: &Type, nested: bool) -> Result<(), &'static str> {
+    match value {
+        Type::Int(int) => written.push_str(&int.to_string()),
+        // with the fraction, so a whole num doesn't read back as an int
+        Type::Num(num) => written.push_str(&format!("{num:?}")),
+        Type::Bool(bool) => written.push_str(&bool.to_string()),
+        Type::None => written.push_str("none"),
+        Type::Str(string) if string.contains('"') => {
+            return Err("Cannot repr a str that contains '\"'")
+        }
+        Type::Str(string) => {
+            written.push('"');
+            // braces are escaped, so the str isn't read as interpolating
+            for c in string.chars() {
+                match c {
+                    '{' | '}' => {
+                        written.push('\\');
+                        written.push(c);
+                    }
+                    c => written.push(c),
+                }
+            }
+            written.push('"');
+        }
+        Type::Bytes(bytes) => {
+            let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+            match nested {
+                true => written.push_str(&format!("(hex-decode \"{hex}\")")),
+                false => written.push_str(&format!("hex-decode \"{hex}\"")),
+            }
+        }
+        Type::Function(function) => match function.as_ref() {
+            Function::Builtin(name) if operator_token(name).is_some() => {
+                written.push_str(&format!("({name})"))
+            }
+            Function::Builtin(name) => written.push_str(name),
+            _ => return Err("Cannot repr a function that is not a builtin"),
+        },
+        Type::Seq(items) => {
+            written.push('[');
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 {
+                    written.push(' ');
+                }
+                write(written, item, true)?;
+            }
+            written.push(']');
+        }
+        Type::Hash(entries) => {
+            written.push('{');
+            for (idx, (key, value)) in entries.iter().enumerate() {
+                if idx > 0 {
+                    written.push(' ');
+                }
+                write(written, &key.to_type(), true)?;
+                written.push(' ');
+                write(written, value, true)?;
+            }
+            written.push('}');
+        }
+    }
+    Ok(())
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn read_value(chars: &mut Peekable<Chars>) -> Result<Type, &'static str> {
+    skip_whitespace(chars);
+    match chars.peek() {
+        None => Err("Expected a value"),
+        Some('"') => {
+            chars.next();
+            let mut string = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => return Ok(Type::str(string)),
+                    Some('\\') if matches!(chars.peek(), Some('{' | '}')) => {
+                        string.extend(chars.next())
+                    }
+                    Some(c) => string.push(c),
+                    None => return Err("Unterminated str"),
+                }
+            }
+        }
+        Some('[') => {
+            chars.next();
+            let mut items = vec![];
+            while !close(chars, ']') {
+                items.push(read_value(chars)?);
+            }
+            Ok(Type::seq(items))
+        }
+        Some('{') => {
+            chars.next();
+            let mut entries = BTreeMap::new();
+            while !close(chars, '}') {
+                let key = Key::from_type(&read_value(chars)?).ok_or("Invalid hash key")?;
+                entries.insert(key, read_value(chars)?);
+            }
+            Ok(Type::hash(entries))
+        }
+        Some('(') => {
+            chars.next();
+            skip_whitespace(chars);
+            let value = read_word(chars)?;
+            match close(chars, ')') {
+                true => Ok(value),
+                false => Err("Expected ')'"),
+            }
+        }
+        Some(_) => read_word(chars),
+    }
+}
+
+/// Reads an int, num, bool, `none`, builtin name, or `hex-decode "..."` for bytes.
+fn read_word(chars: &mut Peekable<Chars>) -> Result<Type, &'static str> {
+    let word = word(chars);
+    match word.as_str() {
+        "true" => Ok(Type::Bool(true)),
+        "false" => Ok(Type::Bool(false)),
+        "none" => Ok(Type::None),
+        "hex-decode" => match read_value(chars)? {
+            Type::Str(hex) => super::hex_decode(vec![Type::Str(hex)]),
+            _ => Err("Expected a hex str"),
+        },
+        _ => match (word.parse::<i64>(), word.parse::<f64>()) {
+            (Ok(int), _) => Ok(Type::Int(int)),
+            (_, Ok(num)) => Ok(Type::Num(num)),
+            _ => builtin(&word),
+        },
+    }
+}
+
+/// Consumes the closing delimiter if it is next.
+fn close(chars: &mut Peekable<Chars>, delim: char) -> bool {
+    skip_whitespace(chars);
+    chars.next_if_eq(&delim).is_some()
+}
+
+fn word(chars: &mut Peekable<Chars>) -> String {
+    let mut word = String::new();
+    while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"[]{}()\"".contains(*c)) {
+        word.push(c);
+    }
+    word
+}
+
+fn builtin(name: &str) -> Result<Type, &'static str> {
+    match is_builtin(name) || operator_token(name).is_some() {
+        true => Ok(Type::Function(Shared::new(Function::Builtin(
+            name.to_string(),
+        )))),
+        false => Err("Unknown name in repr"),
+    }
+}
@@ -0,0 +1,48 @@
+use environment::Type;
+
+/// `to-str x` is `x` written as `print` writes it.
+pub(super) fn to_str(args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [value] => Ok(Type::str(value.to_string())),
+        _ => Err("Function 'to-str' takes a value"),
+    }
+}
+
+/// `upper s` and `lower s` are `s` in upper or lower case.
+pub(super) fn case(args: Vec<Type>, upper: bool) -> Result<Type, &'static str> {
+    match (args.as_slice(), upper) {
+        ([Type::Str(string)], true) => Ok(Type::str(string.to_uppercase())),
+        ([Type::Str(string)], false) => Ok(Type::str(string.to_lowercase())),
+        (_, true) => Err("Function 'upper' takes a str"),
+        (_, false) => Err("Function 'lower' takes a str"),
+    }
+}
+
+/// `split sep s` is a seq of the parts of `s` between each `sep`, or of its
+/// characters if `sep` is empty.
+pub(super) fn split(args: Vec<Type>) -> Result<Type, &'static str> {
+    match args.as_slice() {
+        [Type::Str(sep), Type::Str(string)] if sep.is_empty() => Ok(Type::seq(
+            string.chars().map(|c| Type::str(c.to_string())).collect(),
+        )),
+        [Type::Str(sep), Type::Str(string)] => {
+            Ok(Type::seq(string.split(&**sep).map(Type::str).collect()))
+        }
+        _ => Err("Function 'split' takes a separator and a str"),
+    }
+}
+
+/// `join sep coll` is the strs of `coll` one after another, with `sep` between
+/// each.
+pub(super) fn join(args: Vec<Type>) -> Result<Type, &'static str> {
+    let Ok([Type::Str(sep), coll]) = <[Type; 2]>::try_from(args) else {
+        return Err("Function 'join' takes a separator and a collection of strs");
+    };
+    let parts = crate::iter::items(coll)?
+        .map(|item| match item {
+            Type::Str(string) => Ok(string),
+            _ => Err("Function 'join' takes a separator and a collection of strs"),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Type::str(parts.join(&sep)))
+}
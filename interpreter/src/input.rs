@@ -0,0 +1,53 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::sync::{Arc, Mutex};
+
+/// Where the lines a script reads with `read-line` and `read-num` come from. Hosts
+/// implement this to feed a script input from their own widgets, and tests to feed
+/// it lines they wrote up front.
+pub trait InputSource {
+    /// The next line without its line ending, or `None` once the input has ended.
+    fn read_line(&mut self) -> io::Result<Option<String>>;
+}
+
+/// The interpreter's own source, reading from the process's stdin.
+pub struct StdInput;
+
+impl InputSource for StdInput {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line)? {
+            0 => Ok(None),
+            _ => {
+                let len = line.trim_end_matches(['\n', '\r']).len();
+                line.truncate(len);
+                Ok(Some(line))
+            }
+        }
+    }
+}
+
+/// Hands out lines that were given to it, then ends. Clones share the same lines, so
+/// a host can keep one clone to add lines to while the interpreter reads the other.
+#[derive(Clone, Default)]
+pub struct ScriptedInput {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl ScriptedInput {
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(lines: I) -> ScriptedInput {
+        ScriptedInput {
+            lines: Arc::new(Mutex::new(lines.into_iter().map(Into::into).collect())),
+        }
+    }
+
+    pub fn push(&self, line: impl Into<String>) {
+        self.lines.lock().unwrap().push_back(line.into());
+    }
+}
+
+impl InputSource for ScriptedInput {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        Ok(self.lines.lock().unwrap().pop_front())
+    }
+}
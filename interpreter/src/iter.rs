@@ -0,0 +1,87 @@
+//! The iteration protocol shared by every collection value: `first`, `rest`, and
+//! `empty?`. Seqs iterate over their items, strs over their characters, bytes over
+//! their bytes as nums, and hashes over `[key value]` seqs in key order. Builtins
+//! that walk a collection go through `items` rather than matching on `Type::Seq`.
+
+use std::collections::BTreeMap;
+
+use environment::{Key, Shared, Type};
+
+/// The values a collection yields, in order.
+pub(crate) fn items(value: Type) -> Result<Box<dyn Iterator<Item = Type>>, &'static str> {
+    match value {
+        Type::Seq(items) => Ok(Box::new(Shared::unwrap_or_clone(items).into_iter())),
+        Type::Str(string) => Ok(Box::new(
+            string
+                .chars()
+                .map(|c| Type::str(c.to_string()))
+                .collect::<Vec<_>>()
+                .into_iter(),
+        )),
+        Type::Bytes(bytes) => Ok(Box::new(
+            Shared::unwrap_or_clone(bytes)
+                .into_iter()
+                .map(|byte| Type::Int(byte.into())),
+        )),
+        Type::Hash(entries) => Ok(Box::new(
+            Shared::unwrap_or_clone(entries)
+                .into_iter()
+                .map(|(key, value)| Type::seq(vec![key.to_type(), value])),
+        )),
+        _ => Err("Cannot iterate over a value that is not a collection"),
+    }
+}
+
+/// How many values a collection yields, counted without making them.
+pub(crate) fn len(value: &Type) -> Result<usize, &'static str> {
+    match value {
+        Type::Seq(items) => Ok(items.len()),
+        Type::Str(string) => Ok(string.chars().count()),
+        Type::Bytes(bytes) => Ok(bytes.len()),
+        Type::Hash(entries) => Ok(entries.len()),
+        _ => Err("Cannot iterate over a value that is not a collection"),
+    }
+}
+
+/// The first value of a collection, or `none` if it is empty.
+pub(crate) fn first(value: Type) -> Result<Type, &'static str> {
+    let first = match value {
+        Type::Seq(items) => items.first().cloned(),
+        Type::Str(string) => string.chars().next().map(|c| Type::str(c.to_string())),
+        Type::Bytes(bytes) => bytes.first().map(|&byte| Type::Int(byte.into())),
+        Type::Hash(entries) => entries
+            .iter()
+            .next()
+            .map(|(key, value)| Type::seq(vec![key.to_type(), value.clone()])),
+        _ => return Err("Cannot iterate over a value that is not a collection"),
+    };
+    Ok(first.unwrap_or(Type::None))
+}
+
+/// Everything after the first value, as the same kind of collection.
+pub(crate) fn rest(value: Type) -> Result<Type, &'static str> {
+    match value {
+        Type::Seq(items) => Ok(Type::seq(items.iter().skip(1).cloned().collect())),
+        Type::Str(string) => Ok(Type::str(string.chars().skip(1).collect::<String>())),
+        Type::Bytes(bytes) => Ok(Type::bytes(bytes.iter().skip(1).copied().collect())),
+        Type::Hash(entries) => Ok(Type::hash(
+            entries
+                .iter()
+                .skip(1)
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect::<BTreeMap<Key, Type>>(),
+        )),
+        _ => Err("Cannot iterate over a value that is not a collection"),
+    }
+}
+
+/// Whether a collection yields no values, found without making any.
+pub(crate) fn is_empty(value: Type) -> Result<bool, &'static str> {
+    match value {
+        Type::Seq(items) => Ok(items.is_empty()),
+        Type::Str(string) => Ok(string.is_empty()),
+        Type::Bytes(bytes) => Ok(bytes.is_empty()),
+        Type::Hash(entries) => Ok(entries.is_empty()),
+        _ => Err("Cannot iterate over a value that is not a collection"),
+    }
+}
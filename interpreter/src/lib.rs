@@ -1,123 +1,1799 @@
-use environment::Environment;
-use environment::Type;
-use lexer::Token;
-use parser::Expr;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::Arc;
+
+use diagnostics::{codes, Diagnostic, SourceId, Span};
+use environment::{compare_int_num, Environment};
+use environment::{Function, Key, NativeFn, Shared, Type, UserFunction};
+use lexer::{Symbol, Token};
+use parser::{Arm, Binding, Catch, Expr, Param, Pattern};
+use regex::Regex;
+
+use builtins::Rng;
+
+pub use environment::RuntimeError;
+pub use input::{InputSource, ScriptedInput, StdInput};
+pub use observer::Observer;
+pub use optimize::optimize;
+pub use output::{CapturedOutput, OutputSink, StdOutput};
+pub use playground::run_source;
+pub use value::{Error, Value};
+
+mod builtins;
+mod input;
+mod iter;
+mod observer;
+mod optimize;
+mod output;
+mod playground;
+mod value;
+
+/// The boxed sink an interpreter prints through. With the `sync` feature it must be
+/// shareable between threads like the rest of the interpreter.
+#[cfg(feature = "sync")]
+pub type BoxedSink = Box<dyn OutputSink + Send + Sync>;
+#[cfg(not(feature = "sync"))]
+pub type BoxedSink = Box<dyn OutputSink>;
+
+/// The boxed source an interpreter reads input from, shareable between threads with
+/// the `sync` feature like the sink.
+#[cfg(feature = "sync")]
+pub type BoxedSource = Box<dyn InputSource + Send + Sync>;
+#[cfg(not(feature = "sync"))]
+pub type BoxedSource = Box<dyn InputSource>;
+
+/// The boxed observer an interpreter reports to, shareable between threads with
+/// the `sync` feature like the sink.
+#[cfg(feature = "sync")]
+pub type BoxedObserver = Box<dyn Observer + Send + Sync>;
+#[cfg(not(feature = "sync"))]
+pub type BoxedObserver = Box<dyn Observer>;
+
+/// What a script is allowed to reach outside of the interpreter. Builtins that touch
+/// the file system, environment, network, or other processes check these first.
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities {
+    pub fs: bool,
+    pub env: bool,
+    pub net: bool,
+    pub process: bool,
+}
+
+impl Capabilities {
+    /// Everything is allowed.
+    pub fn all() -> Capabilities {
+        Capabilities {
+            fs: true,
+            env: true,
+            net: true,
+            process: true,
+        }
+    }
+
+    /// Nothing outside of the interpreter is allowed, for running untrusted scripts.
+    pub fn sandboxed() -> Capabilities {
+        Capabilities {
+            fs: false,
+            env: false,
+            net: false,
+            process: false,
+        }
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Capabilities {
+        Capabilities::all()
+    }
+}
+
+/// Stops a running program from another thread, e.g. when Ctrl-C is pressed in the
+/// REPL. Loops check it on every iteration.
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt(Arc<AtomicBool>);
+
+impl Interrupt {
+    pub fn interrupt(&self) {
+        self.0.store(true, atomic::Ordering::Relaxed);
+    }
+
+    /// Whether an interrupt has been requested since the last check.
+    fn take(&self) -> bool {
+        self.0.swap(false, atomic::Ordering::Relaxed)
+    }
+}
+
+/// Why evaluation stopped: a runtime error, or a value passed to `throw` that no
+/// `catch` has intercepted yet.
+#[derive(Debug, Clone)]
+pub enum Raised {
+    Error(&'static str),
+    Thrown(Type),
+    /// An error of one of the kinds a host may want to tell apart, or the error a
+    /// native function returned.
+    Runtime(RuntimeError),
+}
+
+impl From<&'static str> for Raised {
+    fn from(err: &'static str) -> Raised {
+        Raised::Error(err)
+    }
+}
+
+impl From<RuntimeError> for Raised {
+    fn from(err: RuntimeError) -> Raised {
+        Raised::Runtime(err)
+    }
+}
+
+impl From<Raised> for RuntimeError {
+    fn from(raised: Raised) -> RuntimeError {
+        match raised {
+            Raised::Error(err) => RuntimeError::Message(err.to_string()),
+            Raised::Thrown(value) => RuntimeError::Uncaught(value.to_string()),
+            Raised::Runtime(err) => err,
+        }
+    }
+}
+
+impl Raised {
+    /// The error as a diagnostic, without a span, see `Interpreter::diagnostic`.
+    pub fn diagnostic(&self) -> Diagnostic {
+        match self {
+            Raised::Error(err) => Diagnostic::error(codes::RUNTIME, *err),
+            Raised::Runtime(err) => {
+                let diagnostic = Diagnostic::error(codes::RUNTIME, err.to_string());
+                match err {
+                    RuntimeError::UndefinedVariable(_) => diagnostic
+                        .with_hint("define it with `def` before it is used, or check the spelling"),
+                    RuntimeError::ArityMismatch { .. } => diagnostic
+                        .with_hint("give it as many arguments as it has parameters, or fewer to get a function waiting for the rest"),
+                    RuntimeError::DivisionByZero => diagnostic
+                        .with_hint("check the divisor first, or catch the error with `try`"),
+                    _ => diagnostic,
+                }
+            }
+            Raised::Thrown(_) => Diagnostic::error(codes::UNCAUGHT, self.to_string())
+                .with_hint("catch it with `try expression; catch err -> handler`"),
+        }
+    }
+}
+
+impl fmt::Display for Raised {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Raised::Error(err) => write!(f, "{err}"),
+            Raised::Thrown(value) => write!(f, "Runtime Error: uncaught exception: {value}"),
+            Raised::Runtime(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+/// Stops the program, and can't be caught, so that an interrupt always gets out.
+const INTERRUPTED: &str = "Runtime Error: interrupted";
+
+/// The name of every function made with `\`, which can't call itself by name.
+const LAMBDA: &str = "lambda";
+
+/// Raised for an argument passed by name to anything but a user function.
+const NAMED: &str = "Only functions defined with def or \\ take arguments by name";
+
+/// What an expression in tail position evaluates to: a value, or the call to a
+/// user function that it ends with, which is left for the caller to make.
+enum Tail {
+    Value(Type),
+    Call(String, Shared<Function>, Vec<Type>),
+}
+
+/// How deeply evaluation may nest before it is stopped, unless set otherwise.
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/// Definitions every interpreter starts with, see `prelude.ln`.
+const PRELUDE: &str = include_str!("prelude.ln");
 
 pub struct Interpreter {
     environment: environment::Environment,
+    capabilities: Capabilities,
+    memory_limit: Option<usize>,
+    allocated: usize,
+    max_depth: usize,
+    depth: usize,
+    calls: Vec<String>,
+    trace: Vec<String>,
+    rng: Rng,
+    /// Compiled regexes, the most recently used last, see `builtins::regex`.
+    regexes: Vec<(String, Regex)>,
+    listeners: HashMap<String, TcpListener>,
+    connections: Vec<TcpStream>,
+    output: BoxedSink,
+    input: BoxedSource,
+    observer: Option<BoxedObserver>,
+    interrupt: Interrupt,
+    /// Where the innermost call that the error being raised came out of is.
+    raised_at: Option<Span>,
 }
 
 impl Interpreter {
-    fn new() -> Interpreter {
+    /// An interpreter with the prelude loaded and every capability allowed.
+    pub fn new() -> Interpreter {
+        Interpreter::with_capabilities(Capabilities::all())
+    }
+
+    /// An interpreter with the prelude loaded.
+    pub fn with_capabilities(capabilities: Capabilities) -> Interpreter {
+        let mut interpreter = Interpreter::without_prelude(capabilities);
+        interpreter.load_prelude();
+        interpreter
+    }
+
+    pub fn without_prelude(capabilities: Capabilities) -> Interpreter {
         Interpreter {
             environment: Environment::new(),
+            capabilities,
+            memory_limit: None,
+            allocated: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            depth: 0,
+            calls: vec![],
+            trace: vec![],
+            rng: Rng::from_time(),
+            regexes: vec![],
+            listeners: HashMap::new(),
+            connections: vec![],
+            output: Box::new(StdOutput),
+            input: Box::new(StdInput),
+            observer: None,
+            interrupt: Interrupt::default(),
+            raised_at: None,
         }
     }
 
-    pub fn interpret(&mut self, exprs: &[Expr]) {
+    fn load_prelude(&mut self) {
+        let tokens = lexer::lex(PRELUDE.to_string()).expect("prelude should lex");
+        let exprs = parser::parse(&tokens).expect("prelude should parse");
+        self.execute(&exprs).expect("prelude should run");
+        // so that a program can define names of its own that the prelude has too
+        self.environment.settle();
+    }
+
+    /// Seeds the generator behind `random` so that runs can be replayed exactly.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Routes printed values to `output` instead of stdout and stderr.
+    pub fn set_output(&mut self, output: BoxedSink) {
+        self.output = output;
+    }
+
+    /// Reads the lines `read-line` and `read-num` return from `input` instead of
+    /// stdin.
+    pub fn set_input(&mut self, input: BoxedSource) {
+        self.input = input;
+    }
+
+    /// Reports evaluation to `observer`, e.g. a debugger, or to nothing with `None`.
+    pub fn set_observer(&mut self, observer: Option<BoxedObserver>) {
+        self.observer = observer;
+    }
+
+    /// Defines the global function `name`, which scripts call like any other and
+    /// which runs `call` with the arguments they pass. An error it returns can be
+    /// caught as its message.
+    pub fn define_native(&mut self, name: &str, call: Box<NativeFn>) {
+        self.undefine(name);
+        self.environment.define_native(name, call);
+    }
+
+    /// Removes the global `name`, e.g. when the file that defined it is reloaded
+    /// without it.
+    pub fn undefine(&mut self, name: &str) {
+        let Some(value) = self.environment.remove(name) else {
+            return;
+        };
+        // a native is the host's, so it was never counted
+        if !matches!(&value, Type::Function(function) if matches!(**function, Function::Native(..)))
+        {
+            self.allocated = self.allocated.saturating_sub(value.size());
+        }
+    }
+
+    /// The names the program has defined, sorted, with their values. The prelude's
+    /// are left out unless the program defined them again.
+    pub fn bindings(&self) -> Vec<(String, Value)> {
+        let mut bindings: Vec<_> = self
+            .environment
+            .bindings()
+            .map(|(name, value)| (name.to_string(), Value::from(value)))
+            .collect();
+        bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+        bindings
+    }
+
+    /// Caps the approximate number of bytes that values held by the program may use.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
+    }
+
+    /// Caps how deeply evaluation may nest, so that runaway recursion fails with an
+    /// error instead of overflowing the stack.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// The functions that were being called, outermost first, when the maximum depth
+    /// was last exceeded.
+    pub fn trace(&self) -> &[String] {
+        &self.trace
+    }
+
+    /// A handle that stops the running program at the next loop iteration.
+    pub fn interrupt_handle(&self) -> Interrupt {
+        self.interrupt.clone()
+    }
+
+    /// Runs the expressions, returning the value of each top-level one that has
+    /// one, rather than printing it, or the first error.
+    pub fn interpret(&mut self, exprs: &[Expr]) -> Result<Vec<Value>, RuntimeError> {
+        self.interrupt.take();
+        let mut values = vec![];
+        for expr in exprs.iter().filter(|expr| !is_eof(expr)) {
+            match self.evaluate_expression(expr)? {
+                Type::None => (),
+                value => values.push(Value::from(&value)),
+            }
+        }
+        Ok(values)
+    }
+
+    /// Runs linus source, returning the value of its last top-level expression
+    /// rather than printing it. What it defines stays defined, so a host can keep
+    /// one interpreter and run one snippet after another in it. Macros, imports,
+    /// and type checking are left to the pipeline.
+    pub fn eval_str(&mut self, source: &str) -> Result<Value, Error> {
+        let id = SourceId::default();
+        let tokens = lexer::lex_spanned(source, id).map_err(|err| Error::Syntax(vec![err]))?;
+        let exprs = parser::parse_spanned(&tokens, id).map_err(Error::Syntax)?;
+
+        self.interrupt.take();
+        let mut value = Type::None;
+        // the parser ends a source with its end, which isn't a value of its own
+        for expr in exprs.iter().filter(|expr| !is_eof(expr)) {
+            value = self.evaluate_expression(expr)?;
+        }
+        Ok(Value::from(&value))
+    }
+
+    /// Runs the expressions, printing the value of each top-level one that has one,
+    /// and stops at the first runtime error.
+    pub fn execute(&mut self, exprs: &[Expr]) -> Result<(), Raised> {
+        // an interrupt only stops what was running when it was requested
+        self.interrupt.take();
+        self.raised_at = None;
         for expr in exprs {
-            match self.evaluate_expression(expr) {
-                Ok(Type::Num(n)) => println!("{}", n),
-                Ok(Type::Str(str)) => println!("{}", str),
-                Ok(Type::Bool(bool)) => println!("{}", bool),
-                Ok(Type::None) => (),
-                Err(err) => panic!("{}", err),
-                _ => println!("error"),
+            match self.evaluate_expression(expr)? {
+                Type::None => (),
+                value => self.output.print(&value),
             }
         }
+        Ok(())
+    }
+
+    /// Where the innermost call that the last error `execute` returned came out of
+    /// is, if the calls it was in were parsed with their spans.
+    pub fn raised_at(&self) -> Option<Span> {
+        self.raised_at
+    }
+
+    /// `raised`, an error `execute` returned, as a diagnostic pointing at the call it
+    /// came out of.
+    pub fn diagnostic(&self, raised: &Raised) -> Diagnostic {
+        let diagnostic = raised.diagnostic();
+        match self.raised_at {
+            Some(span) => diagnostic.with_span(span),
+            None => diagnostic,
+        }
+    }
+
+    /// Notes that the call at `span` failed, unless a call inside it already has.
+    fn failed<T>(&mut self, span: Option<Span>, result: Result<T, Raised>) -> Result<T, Raised> {
+        if result.is_err() && self.raised_at.is_none() {
+            self.raised_at = span;
+        }
+        result
+    }
+
+    fn evaluate_expression(&mut self, expression: &Expr) -> Result<Type, Raised> {
+        self.enter()?;
+        let depth = self.depth;
+        self.observe(|observer, interpreter| observer.before(interpreter, expression, depth));
+        let value = self.evaluate(expression);
+        self.depth -= 1;
+        let value = value.and_then(|value| self.limit(value));
+        self.observe(|observer, interpreter| {
+            observer.after(interpreter, expression, depth, value.as_ref())
+        });
+        value
     }
 
-    fn evaluate_expression(&mut self, expression: &Expr) -> Result<Type, &'static str> {
+    /// Runs `hook` with the observer, if there is one.
+    fn observe(&mut self, hook: impl FnOnce(&mut dyn Observer, &Interpreter)) {
+        if let Some(mut observer) = self.observer.take() {
+            hook(observer.as_mut(), self);
+            self.observer = Some(observer);
+        }
+    }
+
+    /// Fails if holding on to `value` would go over the memory limit.
+    fn limit(&self, value: Type) -> Result<Type, Raised> {
+        match self.memory_limit {
+            Some(limit) if self.allocated + value.size() > limit => {
+                Err("Runtime Error: memory limit exceeded".into())
+            }
+            _ => Ok(value),
+        }
+    }
+
+    /// Evaluates an expression in tail position, where it is the last thing a
+    /// function body does. A call to a user function there is returned rather than
+    /// made, for `call_user` to make in place of the call it is in, so that recursion
+    /// in tail position runs in constant stack.
+    fn evaluate_tail(&mut self, expression: &Expr) -> Result<Tail, Raised> {
+        // the rest go through `evaluate_expression`, which reports them itself
+        if matches!(
+            expression,
+            Expr::FunctionCall {
+                operator: Token::Symbol(_),
+                ..
+            } | Expr::Do { .. }
+                | Expr::Let { .. }
+                | Expr::Match { .. }
+        ) {
+            self.observe(|observer, interpreter| {
+                observer.before(interpreter, expression, interpreter.depth)
+            });
+        }
+        match expression {
+            Expr::FunctionCall {
+                operator: Token::Symbol(name),
+                operand,
+                span,
+            } => {
+                let args = self.arguments(*name, operand);
+                let args = self.failed(*span, args)?;
+                match self.environment.retrieve(*name) {
+                    Some(Type::Function(function)) if matches!(**function, Function::User(_)) => {
+                        Ok(Tail::Call(name.to_string(), function.clone(), args))
+                    }
+                    _ => {
+                        let value = self.call(&Token::Symbol(*name), args);
+                        self.failed(*span, value).map(Tail::Value)
+                    }
+                }
+            }
+            Expr::Do { exprs } => match exprs.split_last() {
+                Some((last, exprs)) => {
+                    for expr in exprs {
+                        self.evaluate_expression(expr)?;
+                    }
+                    self.evaluate_tail(last)
+                }
+                None => Ok(Tail::Value(Type::None)),
+            },
+            Expr::Let { bindings, body } => {
+                self.environment.push_scope();
+                let result = self
+                    .bind_all(bindings)
+                    .and_then(|_| self.evaluate_tail(body));
+                self.environment.pop_scope();
+                result
+            }
+            Expr::Match { value, arms } => {
+                let value = self.evaluate_expression(value)?;
+                let arm = self.bind_arm(arms, value)?;
+                let result = self.evaluate_tail(&arm.body);
+                self.environment.pop_scope();
+                result
+            }
+            _ => self.evaluate_expression(expression).map(Tail::Value),
+        }
+    }
+
+    fn evaluate(&mut self, expression: &Expr) -> Result<Type, Raised> {
         match expression {
             Expr::Literal { token } => match token {
-                Token::Str(string) => Ok(Type::Str(string.clone())),
+                Token::Str(string) => Ok(Type::str(string.as_str())),
+                Token::Int(int) => Ok(Type::Int(*int)),
                 Token::Num(num) => Ok(Type::Num(*num)),
                 Token::True => Ok(Type::Bool(true)),
                 Token::False => Ok(Type::Bool(false)),
                 Token::None => Ok(Type::None),
-                Token::Symbol(sym) => Ok(Type::Str(sym.clone())),
+                Token::Symbol(sym) => Ok(Type::str(sym.as_str())),
                 Token::EOF => Ok(Type::None),
-                _ => Err("Not a literal"),
+                _ => Err("Not a literal".into()),
             },
-            Expr::FunctionCall { operator, operand } => match operator {
-                Token::Add
-                | Token::Subtract
-                | Token::Multiply
-                | Token::Divide
-                | Token::GreaterThan
-                | Token::LessThan
-                | Token::GreaterThanOrEqual
-                | Token::LessThanOrEqual
-                | Token::Equal
-                | Token::And
-                | Token::Or => operand
-                    .iter()
-                    .map(|operand| self.evaluate_expression(operand))
-                    .reduce(|a, b| match (a, b) {
-                        (Ok(Type::Num(a)), Ok(Type::Num(b))) => match operator {
-                            Token::Add => Ok(Type::Num(a + b)),
-                            Token::Subtract => Ok(Type::Num(a - b)),
-                            Token::Multiply => Ok(Type::Num(a * b)),
-                            Token::Divide => Ok(Type::Num(a / b)),
-                            Token::GreaterThan => Ok(Type::Bool(a > b)),
-                            Token::LessThan => Ok(Type::Bool(a < b)),
-                            Token::GreaterThanOrEqual => Ok(Type::Bool(a >= b)),
-                            Token::LessThanOrEqual => Ok(Type::Bool(a <= b)),
-                            Token::Equal => Ok(Type::Bool(a == b)),
-                            _ => Err("Unexpected operator"),
-                        },
-                        (Ok(Type::Bool(a)), Ok(Type::Bool(b))) => match operator {
-                            Token::And => Ok(Type::Bool(a && b)),
-                            Token::Or => Ok(Type::Bool(a || b)),
-                            Token::Equal => Ok(Type::Bool(a == b)),
-                            _ => Err("Unexpected operator"),
-                        },
-                        (Ok(Type::Bool(_)), Ok(Type::Num(_))) => Err("Cannot compare Bool and Num"),
-                        (Ok(Type::Num(_)), Ok(Type::Bool(_))) => Err("Cannot compare Num and Bool"),
-                        _ => Err("Runtime Error: something wrong with operands!"),
-                    })
-                    .unwrap(),
-                Token::Not => {
-                    match &operand
-                        .iter()
-                        .map(|operand| self.evaluate_expression(operand))
-                        .collect::<Vec<_>>()[0]
-                    {
-                        Ok(Type::Bool(a)) => Ok(Type::Bool(!a)),
-                        Ok(Type::None) => Ok(Type::Bool(true)),
-                        Ok(Type::Num(_)) => Err("Cannot apply function 'not' to type num"),
-                        Ok(Type::Str(_)) => Err("Cannot apply function 'not' to type str"),
-                        _ => Err("Not enough arguments to function 'not'"),
+            Expr::FunctionCall {
+                operator,
+                operand,
+                span,
+            } => {
+                let args = match operator {
+                    Token::Symbol(name) => self.arguments(*name, operand),
+                    _ => self.evaluate_operands(operand),
+                };
+                let value = args.and_then(|args| self.call(operator, args));
+                self.failed(*span, value)
+            }
+            Expr::Assignment { name, expr, .. } => {
+                let val = self.evaluate_expression(expr)?;
+                self.define(*name, val)?;
+                Ok(Type::None)
+            }
+            Expr::Let { bindings, body } => {
+                self.environment.push_scope();
+                let result = self.evaluate_let(bindings, body);
+                self.environment.pop_scope();
+                result
+            }
+            Expr::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                let mut result = self.evaluate_expression(body);
+                if let (Err(raised), Some(catch)) = (&result, catch) {
+                    if let Some(value) = caught(raised) {
+                        self.raised_at = None;
+                        result = self.evaluate_catch(catch, value);
                     }
                 }
-                _ => Err("Function does not exist"),
-            },
-            Expr::Assignment {
-                name,
-                type_decl,
-                expr,
+                if let Some(finally) = finally {
+                    self.evaluate_expression(finally)?;
+                }
+                result
+            }
+            Expr::Throw { value } => Err(Raised::Thrown(self.evaluate_expression(value)?)),
+            Expr::Match { value, arms } => {
+                let value = self.evaluate_expression(value)?;
+                let arm = self.bind_arm(arms, value)?;
+                let result = self.evaluate_expression(&arm.body);
+                self.environment.pop_scope();
+                result
+            }
+            // the pipeline replaces imports with what they import before running
+            Expr::Import { .. } => Err("Imports can only be run as part of a file".into()),
+            Expr::Named { .. } => Err(NAMED.into()),
+            Expr::Do { exprs } => {
+                let mut value = Type::None;
+                for expr in exprs {
+                    value = self.evaluate_expression(expr)?;
+                }
+                Ok(value)
+            }
+            Expr::Loop {
+                bindings,
+                condition,
+                recur,
+                result,
+            } => {
+                self.environment.push_scope();
+                let result = self.evaluate_loop(bindings, condition, recur, result);
+                self.environment.pop_scope();
+                result
+            }
+            Expr::Function {
+                name, params, body, ..
             } => {
-                let val: Type;
-                match self.evaluate_expression(expr) {
-                    Ok(x) => val = x,
-                    _ => return Err("Problem in assignment"),
+                let function = self.user_function(*name, params, body);
+                self.define(*name, function)?;
+                Ok(Type::None)
+            }
+            Expr::Set { name, expr } => {
+                let value = self.evaluate_expression(expr)?;
+                let size = value.size();
+                let replaced = self
+                    .environment
+                    .assign(*name, value)
+                    .ok_or("Cannot set a name that is not defined; use def to define it")?;
+                if !self.environment.is_local() {
+                    self.allocated = (self.allocated + size).saturating_sub(replaced.size());
                 }
-                self.environment.define(name.to_string(), val);
                 Ok(Type::None)
             }
+            // each value as `print` shows it, so strs are spliced in without quotes
+            Expr::Interpolation { parts } => {
+                let mut string = String::new();
+                for part in parts {
+                    string.push_str(&self.evaluate_expression(part)?.to_string());
+                }
+                Ok(Type::str(string))
+            }
+            Expr::Seq { items } => items
+                .iter()
+                .map(|item| self.evaluate_expression(item))
+                .collect::<Result<_, _>>()
+                .map(Type::seq),
+            Expr::Hash { entries } => {
+                let mut hash = BTreeMap::new();
+                for (key, value) in entries {
+                    let key = Key::from_type(&self.evaluate_expression(key)?)
+                        .ok_or("A hash key must be a str, num, or bool")?;
+                    hash.insert(key, self.evaluate_expression(value)?);
+                }
+                Ok(Type::hash(hash))
+            }
+            Expr::Lambda { params, body } => {
+                Ok(self.user_function(Symbol::intern(LAMBDA), params, body))
+            }
             Expr::Variable { name } => match name {
-                Token::Symbol(name) => match self.environment.retrieve(name) {
+                Token::Symbol(name) => match self.environment.retrieve(*name) {
                     Some(t) => Ok(t.clone()),
-                    None => Err("Variable name not found"),
+                    None if builtins::is_builtin(name) => Ok(Type::Function(Shared::new(
+                        Function::Builtin(name.to_string()),
+                    ))),
+                    None => Err(RuntimeError::UndefinedVariable(name.to_string()).into()),
                 },
-                _ => Err("Invalid variable name"),
+                _ => Err("Invalid variable name".into()),
+            },
+            Expr::Operator { token } => match operator_name(token) {
+                Some(name) => Ok(Type::Function(Shared::new(Function::Builtin(
+                    name.to_string(),
+                )))),
+                None => Err("Invalid expression".into()),
             },
-            _ => Err("Invalid expression"),
         }
     }
+
+    /// Binds each of a `let`'s bindings in turn, so that later ones can use earlier
+    /// ones, then evaluates its body.
+    fn evaluate_let(&mut self, bindings: &[Binding], body: &Expr) -> Result<Type, Raised> {
+        self.bind_all(bindings)?;
+        self.evaluate_expression(body)
+    }
+
+    fn bind_all(&mut self, bindings: &[Binding]) -> Result<(), Raised> {
+        for binding in bindings {
+            let value = self.evaluate_expression(&binding.expr)?;
+            self.environment.bind(binding.name, value);
+        }
+        Ok(())
+    }
+
+    fn evaluate_operands(&mut self, operand: &[Expr]) -> Result<Vec<Type>, Raised> {
+        operand
+            .iter()
+            .map(|operand| self.evaluate_expression(operand))
+            .collect()
+    }
+
+    /// The arguments of a call to `name`. When some are passed by name, they are put
+    /// in the order of the parameters of the user function it calls, with the ones
+    /// passed in order for the parameters left, and defaults for the rest.
+    fn arguments(&mut self, name: Symbol, operand: &[Expr]) -> Result<Vec<Type>, Raised> {
+        if !operand
+            .iter()
+            .any(|expr| matches!(expr, Expr::Named { .. }))
+        {
+            return self.evaluate_operands(operand);
+        }
+        let function = match self.environment.retrieve(name) {
+            Some(Type::Function(function)) if matches!(**function, Function::User(_)) => {
+                function.clone()
+            }
+            _ => return Err(NAMED.into()),
+        };
+        let Function::User(user) = &*function else {
+            return Err(NAMED.into());
+        };
+
+        // evaluated in the order they are written, before any is placed
+        let mut args = vec![None; user.params.len()];
+        let mut positional = vec![];
+        for expr in operand {
+            let Expr::Named { name: param, value } = expr else {
+                positional.push(self.evaluate_expression(expr)?);
+                continue;
+            };
+            let Some(idx) = user.params.iter().position(|name| name == param) else {
+                return Err(RuntimeError::Message(format!(
+                    "Function '{name}' has no parameter '{param}'"
+                ))
+                .into());
+            };
+            if args[idx].is_some() {
+                return Err(RuntimeError::Message(format!(
+                    "Function '{name}' is given '{param}' more than once"
+                ))
+                .into());
+            }
+            args[idx] = Some(self.evaluate_expression(value)?);
+        }
+        let mut positional = positional.into_iter();
+        for arg in args.iter_mut().filter(|arg| arg.is_none()) {
+            *arg = positional.next();
+        }
+        if positional.len() > 0 {
+            return Err(RuntimeError::ArityMismatch {
+                expected: user.params.len(),
+                found: operand.len(),
+            }
+            .into());
+        }
+        self.fill_defaults(&function, user, args)
+    }
+
+    /// The arguments, with each one that is missing, or left off the end, replaced by
+    /// its parameter's default. A default is evaluated where the function was defined, with the
+    /// parameters before it in scope.
+    fn fill_defaults(
+        &mut self,
+        function: &Shared<Function>,
+        user: &UserFunction,
+        mut args: Vec<Option<Type>>,
+    ) -> Result<Vec<Type>, Raised> {
+        args.resize(user.params.len(), None);
+        let caller = self
+            .environment
+            .replace_scopes(vec![captured(function, user), HashMap::new()]);
+        let mut filled = vec![];
+        let mut result = Ok(());
+        for ((param, default), arg) in user.params.iter().zip(&user.defaults).zip(args) {
+            let value = match (arg, default) {
+                (Some(value), _) => Ok(value),
+                (None, Some(default)) => self.evaluate_expression(default),
+                (None, None) => Err(RuntimeError::Message(format!(
+                    "Function '{}' is missing an argument for '{param}'",
+                    user.name
+                ))
+                .into()),
+            };
+            match value {
+                Ok(value) => {
+                    self.environment.bind(*param, value.clone());
+                    filled.push(value);
+                }
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        self.environment.replace_scopes(caller);
+        result.map(|()| filled)
+    }
+
+    /// Calls the function or operator `operator` with already evaluated arguments.
+    fn call(&mut self, operator: &Token, args: Vec<Type>) -> Result<Type, Raised> {
+        self.calls.push(match operator {
+            Token::Symbol(name) => name.to_string(),
+            _ => operator_name(operator).unwrap_or("?").to_string(),
+        });
+        let result = match operator {
+            Token::Symbol(name) => match self.environment.retrieve(*name) {
+                Some(function) => self.apply(function.clone(), args),
+                None => builtins::call(self, name, args).unwrap_or_else(|| {
+                    Err(RuntimeError::UndefinedVariable(name.to_string()).into())
+                }),
+            },
+            _ => operate(operator, args).map_err(Raised::from),
+        };
+        self.calls.pop();
+        result
+    }
+
+    /// A function value that captures the local bindings in scope where it is made.
+    fn user_function(&self, name: Symbol, params: &[Param], body: &Expr) -> Type {
+        Type::Function(Shared::new(Function::User(UserFunction {
+            name,
+            params: params.iter().map(|param| param.name).collect(),
+            defaults: params.iter().map(|param| param.default.clone()).collect(),
+            body: body.clone(),
+            captured: self.environment.locals(),
+        })))
+    }
+
+    /// Finds the first arm whose pattern `value` fits, and binds the names in the
+    /// pattern in a new scope, which the caller pops once the arm's body has run.
+    fn bind_arm<'e>(&mut self, arms: &'e [Arm], value: Type) -> Result<&'e Arm, Raised> {
+        for arm in arms {
+            let mut bound = vec![];
+            if fits(&arm.pattern, &value, &mut bound) {
+                self.environment.push_scope();
+                for (name, value) in bound {
+                    self.environment.bind(name, value);
+                }
+                return Ok(arm);
+            }
+        }
+        Err("No pattern in the match fits the value".into())
+    }
+
+    /// Runs a catch's handler with the caught value bound to its name.
+    fn evaluate_catch(&mut self, catch: &Catch, value: Type) -> Result<Type, Raised> {
+        self.environment.push_scope();
+        self.environment.bind(catch.name, value);
+        let result = self.evaluate_expression(&catch.body);
+        self.environment.pop_scope();
+        result
+    }
+
+    fn evaluate_loop(
+        &mut self,
+        bindings: &[Binding],
+        condition: &Expr,
+        recur: &[Expr],
+        result: &Expr,
+    ) -> Result<Type, Raised> {
+        for binding in bindings {
+            let value = self.evaluate_expression(&binding.expr)?;
+            self.environment.bind(binding.name, value);
+        }
+
+        loop {
+            if self.interrupt.take() {
+                return Err(INTERRUPTED.into());
+            }
+            match self.evaluate_expression(condition)? {
+                Type::Bool(true) => {}
+                Type::Bool(false) => break,
+                _ => return Err("A loop's condition must be a bool".into()),
+            }
+
+            // every new value is worked out from the old bindings before any changes
+            let values = recur
+                .iter()
+                .map(|value| self.evaluate_expression(value))
+                .collect::<Result<Vec<_>, _>>()?;
+            for (binding, value) in bindings.iter().zip(values) {
+                self.environment.bind(binding.name, value);
+            }
+        }
+
+        self.evaluate_expression(result)
+    }
+
+    /// Defines `name` in the innermost local scope, so that a `def` in a function body
+    /// or `let` stays there, or as a global at the top level, keeping count of the
+    /// memory held by globals. A name can only be defined once in each scope, after
+    /// which `set` changes it.
+    fn define(&mut self, name: Symbol, value: Type) -> Result<(), Raised> {
+        if self.environment.defines(name) {
+            return Err(
+                "Cannot define a name twice in the same scope; use set to change it".into(),
+            );
+        }
+        if self.environment.is_local() {
+            self.environment.bind(name, value);
+            return Ok(());
+        }
+        self.allocated += value.size();
+        self.environment.define(name, value);
+        Ok(())
+    }
+
+    /// Counts one more level of nesting, failing once there are too many. Each
+    /// successful call must be paired with `self.depth -= 1`.
+    fn enter(&mut self) -> Result<(), Raised> {
+        if self.depth >= self.max_depth {
+            self.trace = self.calls.clone();
+            return Err("Runtime Error: maximum recursion depth exceeded".into());
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Calls a function value with already evaluated arguments.
+    pub(crate) fn apply(&mut self, function: Type, args: Vec<Type>) -> Result<Type, Raised> {
+        self.enter()?;
+        let result = self.apply_function(function, args);
+        self.depth -= 1;
+        result
+    }
+
+    fn apply_function(&mut self, function: Type, args: Vec<Type>) -> Result<Type, Raised> {
+        let Type::Function(function) = function else {
+            return Err("Cannot call a value that is not a function".into());
+        };
+
+        match &*function {
+            Function::Builtin(name) => match operator_token(name) {
+                Some(operator) => operate(&operator, args).map_err(Raised::from),
+                None => builtins::call(self, name, args)
+                    .unwrap_or(Err("Function does not exist".into())),
+            },
+            Function::Composed(functions) => {
+                let mut functions = functions.iter().rev();
+                let mut result = match functions.next() {
+                    Some(innermost) => self.apply(innermost.clone(), args)?,
+                    None => return Err("Cannot call an empty composition".into()),
+                };
+                for function in functions {
+                    result = self.apply(function.clone(), vec![result])?;
+                }
+                Ok(result)
+            }
+            Function::Partial(function, bound) => {
+                let mut all = bound.clone();
+                all.extend(args);
+                self.apply(function.clone(), all)
+            }
+            Function::User(_) => self.call_user(function.clone(), args),
+            Function::Native(_, call) => {
+                let result = call(&args).map_err(Raised::Runtime)?;
+                self.limit(result)
+            }
+        }
+    }
+
+    /// Calls a user function, and then each function it calls in tail position in
+    /// turn, in place of the one before it.
+    fn call_user(&mut self, function: Shared<Function>, args: Vec<Type>) -> Result<Type, Raised> {
+        let calls = self.calls.len();
+        let result = self.tail_calls(function, args, calls);
+        self.calls.truncate(calls);
+        result
+    }
+
+    fn tail_calls(
+        &mut self,
+        mut function: Shared<Function>,
+        mut args: Vec<Type>,
+        calls: usize,
+    ) -> Result<Type, Raised> {
+        loop {
+            let Function::User(user) = &*function else {
+                return self.apply_function(Type::Function(function), args);
+            };
+            // the parameters after the arguments are given their defaults if they all
+            // have one, or else fewer arguments than it takes give a function waiting
+            // for the rest
+            if args.len() < user.params.len()
+                && user.defaults[args.len()..].iter().all(Option::is_some)
+            {
+                let given = args.into_iter().map(Some).collect();
+                args = self.fill_defaults(&function, user, given)?;
+            } else if !args.is_empty() && args.len() < user.params.len() {
+                return Ok(Type::Function(Shared::new(Function::Partial(
+                    Type::Function(function),
+                    args,
+                ))));
+            }
+            if args.len() != user.params.len() {
+                return Err(RuntimeError::ArityMismatch {
+                    expected: user.params.len(),
+                    found: args.len(),
+                }
+                .into());
+            }
+            if self.interrupt.take() {
+                return Err(INTERRUPTED.into());
+            }
+            let scope = user.params.iter().cloned().zip(args).collect();
+
+            // the body sees its arguments and what it captured, not the caller's locals,
+            // and can define names of its own that it captured
+            let caller = self
+                .environment
+                .replace_scopes(vec![captured(&function, user), scope]);
+            self.observe(|observer, interpreter| observer.call(interpreter, &user.name));
+            let result = self.evaluate_tail(&user.body);
+            self.environment.replace_scopes(caller);
+            match result? {
+                Tail::Value(value) => return self.limit(value),
+                // the call replaces this one in the trace too
+                Tail::Call(name, next, next_args) => {
+                    self.calls.truncate(calls);
+                    self.calls.push(name);
+                    function = next;
+                    args = next_args;
+                }
+            }
+        }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+/// What a user function's body sees besides its arguments: the bindings it captured,
+/// and, for a function defined with `def`, itself by its name wherever it is
+/// defined, so that it can call itself.
+fn captured(function: &Shared<Function>, user: &UserFunction) -> HashMap<Symbol, Type> {
+    let mut captured = user.captured.clone();
+    if user.name != LAMBDA {
+        captured.insert(user.name, Type::Function(function.clone()));
+    }
+    captured
+}
+
+fn is_eof(expr: &Expr) -> bool {
+    matches!(expr, Expr::Literal { token: Token::EOF })
+}
+
+/// The value a `catch` binds for what was raised: a thrown value as it is, or a
+/// runtime error's message as a str. Interrupts are never caught.
+fn caught(raised: &Raised) -> Option<Type> {
+    match raised {
+        Raised::Thrown(value) => Some(value.clone()),
+        Raised::Error(INTERRUPTED) => None,
+        Raised::Error(err) => Some(Type::str(err.to_string())),
+        Raised::Runtime(err) => Some(Type::str(err.to_string())),
+    }
+}
+
+/// Whether `value` fits `pattern`, adding what the names in it are bound to to
+/// `bound` if it does.
+fn fits(pattern: &Pattern, value: &Type, bound: &mut Vec<(Symbol, Type)>) -> bool {
+    match (pattern, value) {
+        (Pattern::Wildcard, _) => true,
+        (Pattern::Name(name), value) => {
+            bound.push((*name, value.clone()));
+            true
+        }
+        (Pattern::Literal(token), value) => match literal_value(token) {
+            Some(literal) if is_scalar(value) => matches!(
+                operate(&Token::Equal, vec![value.clone(), literal]),
+                Ok(Type::Bool(true))
+            ),
+            _ => false,
+        },
+        (Pattern::Seq(patterns), Type::Seq(items)) => {
+            patterns.len() == items.len()
+                && patterns
+                    .iter()
+                    .zip(items.iter())
+                    .all(|(pattern, item)| fits(pattern, item, bound))
+        }
+        (Pattern::Seq(_), _) => false,
+    }
+}
+
+/// Applies a built-in operator such as `+` or `and` to its evaluated operands.
+fn operate(operator: &Token, args: Vec<Type>) -> Result<Type, RuntimeError> {
+    if *operator == Token::Not {
+        return match args.first() {
+            Some(Type::Bool(a)) => Ok(Type::Bool(!a)),
+            Some(Type::None) => Ok(Type::Bool(true)),
+            Some(Type::Int(_)) => Err(type_mismatch("Cannot apply function 'not' to type int")),
+            Some(Type::Num(_)) => Err(type_mismatch("Cannot apply function 'not' to type num")),
+            Some(Type::Str(_)) => Err(type_mismatch("Cannot apply function 'not' to type str")),
+            Some(_) => Err(type_mismatch(
+                "Cannot apply function 'not' to a collection or function",
+            )),
+            None => Err("Not enough arguments to function 'not'".into()),
+        };
+    }
+
+    args.into_iter()
+        .map(Ok)
+        .reduce(|a, b| match (a, b) {
+            (Err(err), _) => Err(err),
+            // ints stay ints unless they would overflow, and any quotient is a num
+            (Ok(Type::Int(a)), Ok(Type::Int(b))) => match operator {
+                Token::Add => Ok(a
+                    .checked_add(b)
+                    .map_or(Type::Num(a as f64 + b as f64), Type::Int)),
+                Token::Subtract => Ok(a
+                    .checked_sub(b)
+                    .map_or(Type::Num(a as f64 - b as f64), Type::Int)),
+                Token::Multiply => Ok(a
+                    .checked_mul(b)
+                    .map_or(Type::Num(a as f64 * b as f64), Type::Int)),
+                Token::Divide if b == 0 => Err(RuntimeError::DivisionByZero),
+                Token::Divide => Ok(Type::Num(a as f64 / b as f64)),
+                _ => compare(operator, Some(a.cmp(&b))),
+            },
+            // an int and a num make a num, but are compared exactly
+            (Ok(Type::Int(a)), Ok(Type::Num(b))) if is_comparison(operator) => {
+                compare(operator, compare_int_num(a, b))
+            }
+            (Ok(Type::Num(a)), Ok(Type::Int(b))) if is_comparison(operator) => {
+                compare(operator, compare_int_num(b, a).map(Ordering::reverse))
+            }
+            (Ok(Type::Int(a)), Ok(Type::Num(b))) => arithmetic(operator, a as f64, b),
+            (Ok(Type::Num(a)), Ok(Type::Int(b))) => arithmetic(operator, a, b as f64),
+            (Ok(Type::Num(a)), Ok(Type::Num(b))) => arithmetic(operator, a, b),
+            (Ok(Type::Str(a)), Ok(Type::Str(b))) => match operator {
+                Token::Add => Ok(Type::str([&*a, &*b].concat())),
+                _ => compare(operator, Some(a.cmp(&b))),
+            },
+            (Ok(Type::Bool(a)), Ok(Type::Bool(b))) => match operator {
+                Token::And => Ok(Type::Bool(a && b)),
+                Token::Or => Ok(Type::Bool(a || b)),
+                Token::Equal => Ok(Type::Bool(a == b)),
+                _ => Err(mismatch(operator)),
+            },
+            (Ok(Type::None), Ok(Type::None)) if *operator == Token::Equal => Ok(Type::Bool(true)),
+            // values of different types are never equal
+            (Ok(a), Ok(b)) if *operator == Token::Equal => match is_scalar(&a) && is_scalar(&b) {
+                true => Ok(Type::Bool(false)),
+                false => Err(type_mismatch(
+                    "'=' can only compare ints, nums, strs, bools, and none",
+                )),
+            },
+            _ => Err(mismatch(operator)),
+        })
+        .unwrap_or(Err("Not enough arguments to operator".into()))
+}
+
+/// Applies an arithmetic or comparison operator to two nums.
+fn arithmetic(operator: &Token, a: f64, b: f64) -> Result<Type, RuntimeError> {
+    match operator {
+        Token::Add => Ok(Type::Num(a + b)),
+        Token::Subtract => Ok(Type::Num(a - b)),
+        Token::Multiply => Ok(Type::Num(a * b)),
+        Token::Divide if b == 0.0 => Err(RuntimeError::DivisionByZero),
+        Token::Divide => Ok(Type::Num(a / b)),
+        _ => compare(operator, a.partial_cmp(&b)),
+    }
+}
+
+fn type_mismatch(message: &str) -> RuntimeError {
+    RuntimeError::TypeMismatch(message.to_string())
+}
+
+/// What an operator can be applied to, for when it is given something else.
+fn mismatch(operator: &Token) -> RuntimeError {
+    type_mismatch(match operator {
+        Token::Add => "'+' can only add ints and nums, or strs to strs",
+        Token::Subtract => "'-' can only subtract ints and nums",
+        Token::Multiply => "'*' can only multiply ints and nums",
+        Token::Divide => "'/' can only divide ints and nums",
+        Token::And => "'and' can only combine bools",
+        Token::Or => "'or' can only combine bools",
+        _ => "Comparisons can only order ints and nums, or strs with strs",
+    })
+}
+
+/// The value of a literal such as `1` or `"a"`.
+fn literal_value(token: &Token) -> Option<Type> {
+    match token {
+        Token::Int(int) => Some(Type::Int(*int)),
+        Token::Num(num) => Some(Type::Num(*num)),
+        Token::Str(string) => Some(Type::str(string.as_str())),
+        Token::True => Some(Type::Bool(true)),
+        Token::False => Some(Type::Bool(false)),
+        Token::None => Some(Type::None),
+        _ => None,
+    }
+}
+
+fn is_scalar(value: &Type) -> bool {
+    matches!(
+        value,
+        Type::Int(_) | Type::Num(_) | Type::Str(_) | Type::Bool(_) | Type::None
+    )
+}
+
+fn is_comparison(operator: &Token) -> bool {
+    matches!(
+        operator,
+        Token::GreaterThan
+            | Token::LessThan
+            | Token::GreaterThanOrEqual
+            | Token::LessThanOrEqual
+            | Token::Equal
+    )
+}
+
+/// Applies a comparison operator to how two values are ordered, or `None` if they
+/// aren't, as when either is NaN, which makes every comparison false.
+fn compare(operator: &Token, ordering: Option<Ordering>) -> Result<Type, RuntimeError> {
+    let holds = match operator {
+        Token::GreaterThan => ordering == Some(Ordering::Greater),
+        Token::LessThan => ordering == Some(Ordering::Less),
+        Token::GreaterThanOrEqual => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        Token::LessThanOrEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        Token::Equal => ordering == Some(Ordering::Equal),
+        _ => return Err(mismatch(operator)),
+    };
+    Ok(Type::Bool(holds))
+}
+
+/// The name an operator goes by when it is used as a function value.
+fn operator_name(token: &Token) -> Option<&'static str> {
+    OPERATORS
+        .iter()
+        .find(|(operator, _)| operator == token)
+        .map(|(_, name)| *name)
+}
+
+fn operator_token(name: &str) -> Option<Token> {
+    OPERATORS
+        .iter()
+        .find(|(_, operator)| *operator == name)
+        .map(|(token, _)| token.clone())
+}
+
+const OPERATORS: &[(Token, &str)] = &[
+    (Token::Add, "+"),
+    (Token::Subtract, "-"),
+    (Token::Multiply, "*"),
+    (Token::Divide, "/"),
+    (Token::GreaterThan, ">"),
+    (Token::LessThan, "<"),
+    (Token::GreaterThanOrEqual, ">="),
+    (Token::LessThanOrEqual, "<="),
+    (Token::Equal, "="),
+    (Token::And, "and"),
+    (Token::Or, "or"),
+    (Token::Not, "not"),
+];
+
+/// Runs the expressions in a new interpreter, see `Interpreter::interpret`.
+pub fn interpret(exprs: &[Expr]) -> Result<Vec<Value>, RuntimeError> {
+    Interpreter::new().interpret(exprs)
 }
 
-pub fn interpret(exprs: &[Expr]) {
-    let mut interpreter = Interpreter::new();
-    interpreter.interpret(exprs)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_call(bound: f64) -> Expr {
+        Expr::FunctionCall {
+            operator: Token::Symbol("random".into()),
+            operand: vec![Expr::Literal {
+                token: Token::Num(bound),
+            }],
+            span: None,
+        }
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_sync_values_can_cross_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Type>();
+        assert_send_sync::<Environment>();
+        assert_send_sync::<Interpreter>();
+
+        let handles: Vec<_> = (0..4)
+            .map(|seed| {
+                std::thread::spawn(move || {
+                    let mut interpreter = Interpreter::new();
+                    interpreter.set_seed(seed);
+                    interpreter.evaluate_expression(&random_call(10.0))
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(matches!(handle.join().unwrap(), Ok(Type::Num(_))));
+        }
+    }
+
+    #[test]
+    fn test_output_sink() -> Result<(), Raised> {
+        let output = CapturedOutput::new();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(output.clone()));
+
+        let tokens = lexer::lex("+ 1 2\nprint \"a\"\neprint \"b\"\n".to_string())?;
+        let exprs = parser::parse(&tokens).map_err(|_| "parse error")?;
+        interpreter.execute(&exprs)?;
+
+        assert_eq!(output.stdout(), vec!["3", "a"]);
+        assert_eq!(output.stderr(), vec!["b"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_input_source() -> Result<(), Raised> {
+        let output = CapturedOutput::new();
+        let input = ScriptedInput::new(["linus", " 42 ", "2.5", "many"]);
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(output.clone()));
+        interpreter.set_input(Box::new(input.clone()));
+
+        let program = "print (+ \"hi \" (read-line \"name?\"))\nread-num \"\"\nread-num \"\"\n";
+        let tokens = lexer::lex(program.to_string())?;
+        interpreter.execute(&parser::parse(&tokens).map_err(|_| "parse error")?)?;
+        assert_eq!(output.stdout(), vec!["name?", "hi linus", "42", "2.5"]);
+
+        let tokens = lexer::lex("read-num \"\"\n".to_string())?;
+        let exprs = parser::parse(&tokens).map_err(|_| "parse error")?;
+        assert!(matches!(
+            interpreter.execute(&exprs),
+            Err(Raised::Error(
+                "Function 'read-num' read a line that is not a number"
+            ))
+        ));
+
+        // once the input has ended, reading gives none
+        let tokens = lexer::lex("read-line \"\"\n".to_string())?;
+        let exprs = parser::parse(&tokens).map_err(|_| "parse error")?;
+        interpreter.execute(&exprs)?;
+        input.push("more");
+        interpreter.execute(&exprs)?;
+        assert_eq!(
+            output.stdout(),
+            vec!["name?", "hi linus", "42", "2.5", "more"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_str() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.eval_str("+ 1 2"), Ok(Value::Int(3)));
+        assert_eq!(
+            interpreter.eval_str("def xs: _ -> [1 \"a\" {\"k\" 2.5}]\nxs"),
+            Ok(Value::Seq(vec![
+                Value::Int(1),
+                Value::Str("a".to_string()),
+                Value::Hash(vec![(Value::Str("k".to_string()), Value::Num(2.5))]),
+            ]))
+        );
+        // definitions carry over from one call to the next
+        assert_eq!(
+            interpreter.eval_str("xs").map(|xs| xs.to_string()),
+            Ok("[1 \"a\" {\"k\" 2.5}]".to_string())
+        );
+        assert_eq!(
+            interpreter.eval_str("inc"),
+            Ok(Value::Function(Some("inc".to_string())))
+        );
+        assert_eq!(interpreter.eval_str("def y: num -> 1"), Ok(Value::None));
+
+        assert!(matches!(
+            interpreter.eval_str("def 1"),
+            Err(Error::Syntax(_))
+        ));
+        assert_eq!(
+            interpreter.eval_str("missing"),
+            Err(Error::Runtime("'missing' is not defined".to_string()))
+        );
+        assert_eq!(
+            interpreter.eval_str("throw [1]"),
+            Err(Error::Thrown(Value::Seq(vec![Value::Int(1)])))
+        );
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_depth(50);
+        interpreter
+            .eval_str("def name: str -> \"ann\"; def xs: _ -> [1 \"a\"]")
+            .unwrap();
+        assert_eq!(
+            interpreter.eval_str("\"{name}: {+ 1 2}, {xs} {none}\""),
+            Ok(Value::Str("ann: 3, [1 \"a\"] none".to_string()))
+        );
+        assert_eq!(
+            interpreter.eval_str("\"{missing}\""),
+            Err(Error::Runtime("'missing' is not defined".to_string()))
+        );
+        let Err(Error::Syntax(errs)) = interpreter.eval_str("\"{name; name}\"") else {
+            panic!("two expressions in one interpolation should not parse");
+        };
+        assert_eq!(
+            errs[0].message,
+            "Expected one expression between '{' and '}' in a str"
+        );
+    }
+
+    #[test]
+    fn test_named_arguments() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_depth(50);
+        interpreter
+            .eval_str("def area: num width: num height: num -> * width height")
+            .unwrap();
+        interpreter
+            .eval_str("def box: num width: num = 1 height: num = width -> * width height")
+            .unwrap();
+        assert_eq!(
+            interpreter.eval_str("area height: 4 width: 3"),
+            Ok(Value::Int(12))
+        );
+        assert_eq!(interpreter.eval_str("area 2 height: 5"), Ok(Value::Int(10)));
+        // the arguments passed in order are for the parameters not named, so one can
+        // be piped in
+        assert_eq!(
+            interpreter.eval_str("3 |> area width: 2"),
+            Ok(Value::Int(6))
+        );
+        // defaults fill in what is left off, and can use the parameters before them
+        assert_eq!(interpreter.eval_str("box 3"), Ok(Value::Int(9)));
+        assert_eq!(interpreter.eval_str("box height: 2"), Ok(Value::Int(2)));
+        assert_eq!(
+            interpreter.eval_str("map (\\x: num y: num = 10 -> + x y) [1 2]"),
+            Ok(Value::Seq(vec![Value::Int(11), Value::Int(12)]))
+        );
+        // without a default, fewer arguments still give a function waiting for the rest
+        assert_eq!(
+            interpreter.eval_str("map (area 2) [3]"),
+            Ok(Value::Seq(vec![Value::Int(6)]))
+        );
+        assert_eq!(
+            interpreter.eval_str("area 1 depth: 2"),
+            Err(Error::Runtime(
+                "Function 'area' has no parameter 'depth'".to_string()
+            ))
+        );
+        assert_eq!(interpreter.eval_str("area 1 width: 2"), Ok(Value::Int(2)));
+        assert_eq!(
+            interpreter.eval_str("area 1 width: 2 width: 3"),
+            Err(Error::Runtime(
+                "Function 'area' is given 'width' more than once".to_string()
+            ))
+        );
+        assert_eq!(
+            interpreter.eval_str("area 1 2 width: 3"),
+            Err(Error::Runtime(
+                "Wrong number of arguments: expected 2, found 3".to_string()
+            ))
+        );
+        assert_eq!(
+            interpreter.eval_str("area width: 2"),
+            Err(Error::Runtime(
+                "Function 'area' is missing an argument for 'height'".to_string()
+            ))
+        );
+        assert_eq!(
+            interpreter.eval_str("+ x: 1"),
+            Err(Error::Runtime(NAMED.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_errors_point_at_the_failing_call() {
+        let source = "def f: _ x: num -> mod x 0\nf 1\ntry f 1; catch err -> err\nhelper 1\n";
+        let tokens = lexer::lex_spanned(source, SourceId::default()).unwrap();
+        let exprs = parser::parse_spanned(&tokens, SourceId::default()).unwrap();
+        let raised_at = |exprs: &[Expr]| {
+            let mut interpreter = Interpreter::new();
+            let raised = interpreter.execute(exprs).unwrap_err();
+            let span = interpreter.diagnostic(&raised).span.unwrap();
+            &source[span.range()]
+        };
+
+        // the innermost call, inside the function
+        assert_eq!(raised_at(&exprs[..2]), "mod");
+        // an error that was caught is forgotten
+        assert_eq!(raised_at(&[&exprs[..1], &exprs[2..]].concat()), "helper");
+    }
+
+    #[test]
+    fn test_set() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_depth(50);
+        assert_eq!(interpreter.eval_str("def n: int -> 1"), Ok(Value::None));
+        assert_eq!(
+            interpreter.eval_str("def n: int -> 2"),
+            Err(Error::Runtime(
+                "Cannot define a name twice in the same scope; use set to change it".to_string()
+            ))
+        );
+        assert_eq!(
+            interpreter.eval_str("do set n -> + n 1; n"),
+            Ok(Value::Int(2))
+        );
+        assert_eq!(
+            interpreter.eval_str("set m -> 1"),
+            Err(Error::Runtime(
+                "Cannot set a name that is not defined; use def to define it".to_string()
+            ))
+        );
+
+        // a set inside a function changes the global, and a let's binding is changed
+        // in the let, while a def there only shadows what is outside
+        assert_eq!(
+            interpreter.eval_str("def bump: int by: int -> do set n -> + n by; n"),
+            Ok(Value::None)
+        );
+        assert_eq!(interpreter.eval_str("bump 3"), Ok(Value::Int(5)));
+        assert_eq!(
+            interpreter.eval_str("let i: int -> 1; do set i -> 10; def n: int -> i; n"),
+            Ok(Value::Int(10))
+        );
+        assert_eq!(interpreter.eval_str("n"), Ok(Value::Int(5)));
+        // what the prelude defines can be defined again by a program
+        assert_eq!(
+            interpreter.eval_str("def inc: int x: int -> x"),
+            Ok(Value::None)
+        );
+        assert_eq!(interpreter.eval_str("inc 1"), Ok(Value::Int(1)));
+    }
+
+    #[test]
+    fn test_bindings() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.bindings(), vec![]);
+        assert_eq!(
+            interpreter.eval_str("def b: str -> \"x\"; def a: int -> 1; def inc: int x: int -> x"),
+            Ok(Value::None)
+        );
+        assert_eq!(
+            interpreter.bindings(),
+            vec![
+                ("a".to_string(), Value::Int(1)),
+                ("b".to_string(), Value::Str("x".to_string())),
+                ("inc".to_string(), Value::Function(Some("inc".to_string()))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_define_native() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_native(
+            "lookup",
+            Box::new(|args| match args {
+                [Type::Str(key)] if &**key == "answer" => Ok(Type::Int(42)),
+                [Type::Str(key)] => Err(format!("No entry for '{key}'").into()),
+                _ => Err("Function 'lookup' takes a str".into()),
+            }),
+        );
+
+        assert_eq!(
+            interpreter.eval_str("+ 1 (lookup \"answer\")"),
+            Ok(Value::Int(43))
+        );
+        assert_eq!(
+            interpreter.eval_str("lookup"),
+            Ok(Value::Function(Some("lookup".to_string())))
+        );
+        assert_eq!(
+            interpreter.eval_str("map lookup [\"answer\"]"),
+            Ok(Value::Seq(vec![Value::Int(42)]))
+        );
+        assert_eq!(
+            interpreter.eval_str("lookup \"question\""),
+            Err(Error::Runtime("No entry for 'question'".to_string()))
+        );
+        assert_eq!(
+            interpreter.eval_str("try lookup 1; catch err -> err"),
+            Ok(Value::Str("Function 'lookup' takes a str".to_string()))
+        );
+
+        // a native can be defined again, without changing the memory counted
+        let allocated = interpreter.allocated;
+        interpreter.define_native("lookup", Box::new(|_| Ok(Type::None)));
+        assert_eq!(interpreter.allocated, allocated);
+        assert_eq!(interpreter.eval_str("lookup 1"), Ok(Value::None));
+        interpreter.undefine("lookup");
+        assert_eq!(interpreter.allocated, allocated);
+    }
+
+    #[test]
+    fn test_comp_and_partial() -> Result<(), Raised> {
+        let output = CapturedOutput::new();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(output.clone()));
+
+        let program =
+            "def add2: _ -> partial (+) 2\nadd2 3\ndef add4: _ -> comp add2 add2\nadd4 1\n";
+        let tokens = lexer::lex(program.to_string())?;
+        interpreter.execute(&parser::parse(&tokens).map_err(|_| "parse error")?)?;
+
+        assert_eq!(output.stdout(), vec!["5", "5"]);
+        assert!(interpreter.apply(Type::Num(1.0), vec![]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_depth() -> Result<(), Raised> {
+        let output = CapturedOutput::new();
+        let mut program = "def f: _ -> partial (+) 1\n".to_string();
+        program.push_str(&"set f -> comp f f\n".repeat(12));
+        program.push_str("f 0\n");
+        let exprs = parser::parse(&lexer::lex(program)?).map_err(|_| "parse error")?;
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(output.clone()));
+        interpreter.execute(&exprs)?;
+        assert_eq!(output.stdout(), vec!["4096"]);
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_depth(10);
+        assert!(matches!(
+            interpreter.execute(&exprs),
+            Err(Raised::Error(
+                "Runtime Error: maximum recursion depth exceeded"
+            ))
+        ));
+        assert_eq!(interpreter.trace(), ["f"]);
+        assert_eq!(interpreter.depth, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_interrupt_stops_loop() -> Result<(), Raised> {
+        let program = "loop i: num -> 0; while true; recur (+ i 1); i\n".to_string();
+        let exprs = parser::parse(&lexer::lex(program)?).map_err(|_| "parse error")?;
+
+        let mut interpreter = Interpreter::new();
+        let interrupt = interpreter.interrupt_handle();
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            interrupt.interrupt();
+        });
+        assert!(matches!(
+            interpreter.execute(&exprs),
+            Err(Raised::Error(INTERRUPTED))
+        ));
+        interrupter.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_catch_does_not_stop_interrupts() -> Result<(), Raised> {
+        let program = "try loop i: num -> 0; while true; recur (+ i 1); i; catch err -> 0\n";
+        let exprs = parser::parse(&lexer::lex(program.to_string())?).map_err(|_| "parse error")?;
+
+        let mut interpreter = Interpreter::new();
+        interpreter.interrupt_handle().interrupt();
+        assert!(matches!(
+            interpreter.evaluate_expression(&exprs[0]),
+            Err(Raised::Error(INTERRUPTED))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tail_calls_run_in_constant_stack() -> Result<(), Raised> {
+        let output = CapturedOutput::new();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(output.clone()));
+
+        // a hash of functions picks what to call next, and a local `def` can call
+        // itself by name
+        let program = "def done: int n: int acc: int -> acc
+def sum: int n: int acc: int ->
+    let next: _ -> get {true done false sum} (= n 0); next (- n 1) (+ acc n)
+sum 20000 0
+def count: int n: int ->
+    do
+        def down: int k: int acc: int ->
+            let next: _ -> get {true done false down} (= k 0); next (- k 1) (+ acc k)
+        down n 0
+count 5000
+def countdown: int n: int acc: int -> match n; 0 -> acc; _ -> countdown (- n 1) (+ acc n)
+countdown 20000 0
+";
+        let exprs = parser::parse(&lexer::lex(program.to_string())?).map_err(|_| "parse error")?;
+        interpreter.execute(&exprs)?;
+        assert_eq!(output.stdout(), vec!["200010000", "12502500", "200010000"]);
+
+        // a call that isn't in tail position still nests
+        interpreter.set_max_depth(50);
+        let program = "def deep: int n: int -> + 1 (deep n)\ndeep 1\n";
+        let exprs = parser::parse(&lexer::lex(program.to_string())?).map_err(|_| "parse error")?;
+        assert!(matches!(
+            interpreter.execute(&exprs),
+            Err(Raised::Error(
+                "Runtime Error: maximum recursion depth exceeded"
+            ))
+        ));
+        assert_eq!(interpreter.trace().last().map(String::as_str), Some("deep"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_int_and_num_operators() {
+        let int = |operator: Token, args: [i64; 2]| {
+            operate(&operator, args.into_iter().map(Type::Int).collect())
+        };
+        assert!(matches!(int(Token::Add, [2, 3]), Ok(Type::Int(5))));
+        assert!(matches!(int(Token::Divide, [6, 4]), Ok(Type::Num(1.5))));
+        assert!(matches!(
+            int(Token::Divide, [1, 0]),
+            Err(RuntimeError::DivisionByZero)
+        ));
+        assert!(matches!(
+            int(Token::Multiply, [i64::MAX, 2]),
+            Ok(Type::Num(num)) if num == i64::MAX as f64 * 2.0
+        ));
+        assert!(matches!(
+            int(Token::LessThan, [-1, 0]),
+            Ok(Type::Bool(true))
+        ));
+
+        let mixed =
+            |operator: Token, a: i64, b: f64| operate(&operator, vec![Type::Int(a), Type::Num(b)]);
+        assert!(matches!(mixed(Token::Subtract, 1, 0.5), Ok(Type::Num(0.5))));
+        assert!(matches!(mixed(Token::Equal, 3, 3.0), Ok(Type::Bool(true))));
+        // 2^53 + 1 rounds to 2^53 as a num, but compares greater than it
+        assert!(matches!(
+            mixed(Token::GreaterThan, (1 << 53) + 1, 9007199254740992.0),
+            Ok(Type::Bool(true))
+        ));
+        assert!(matches!(
+            mixed(Token::Equal, 0, f64::NAN),
+            Ok(Type::Bool(false))
+        ));
+    }
+
+    #[test]
+    fn test_str_and_mixed_operators() {
+        let str = |string: &str| Type::str(string.to_string());
+        assert!(matches!(
+            operate(&Token::Add, vec![str("ab"), str("c"), str("d")]),
+            Ok(Type::Str(string)) if &*string == "abcd"
+        ));
+        assert!(matches!(
+            operate(&Token::LessThan, vec![str("apple"), str("banana")]),
+            Ok(Type::Bool(true))
+        ));
+        assert!(matches!(
+            operate(&Token::Equal, vec![str("a"), str("a")]),
+            Ok(Type::Bool(true))
+        ));
+        // values of different types are never equal
+        assert!(matches!(
+            operate(&Token::Equal, vec![str("1"), Type::Int(1)]),
+            Ok(Type::Bool(false))
+        ));
+        assert!(matches!(
+            operate(&Token::Equal, vec![Type::Bool(true), Type::Num(1.0)]),
+            Ok(Type::Bool(false))
+        ));
+        assert!(matches!(
+            operate(&Token::Equal, vec![Type::None, Type::None]),
+            Ok(Type::Bool(true))
+        ));
+
+        assert!(matches!(
+            operate(&Token::Add, vec![str("a"), Type::Int(1)]),
+            Err(RuntimeError::TypeMismatch(message)) if message == "'+' can only add ints and nums, or strs to strs"
+        ));
+        assert!(matches!(
+            operate(&Token::Multiply, vec![str("a"), str("b")]),
+            Err(RuntimeError::TypeMismatch(message)) if message == "'*' can only multiply ints and nums"
+        ));
+        assert!(matches!(
+            operate(&Token::And, vec![Type::Int(1), Type::Int(2)]),
+            Err(RuntimeError::TypeMismatch(message)) if message == "'and' can only combine bools"
+        ));
+        assert!(operate(&Token::Equal, vec![Type::seq(vec![]), Type::seq(vec![])]).is_err());
+    }
+
+    #[test]
+    fn test_prelude() -> Result<(), Raised> {
+        let pi = Expr::Variable {
+            name: Token::Symbol("pi".into()),
+        };
+        let mut interpreter = Interpreter::new();
+        assert!(matches!(
+            interpreter.evaluate_expression(&pi)?,
+            Type::Num(num) if num == std::f64::consts::PI
+        ));
+
+        let mut interpreter = Interpreter::without_prelude(Capabilities::all());
+        assert!(interpreter.evaluate_expression(&pi).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_seeded_random_is_repeatable() -> Result<(), Raised> {
+        let mut first = Interpreter::new();
+        let mut second = Interpreter::new();
+        first.set_seed(42);
+        second.set_seed(42);
+
+        for _ in 0..10 {
+            match (
+                first.evaluate_expression(&random_call(100.0))?,
+                second.evaluate_expression(&random_call(100.0))?,
+            ) {
+                (Type::Num(a), Type::Num(b)) => {
+                    assert_eq!(a, b);
+                    assert!((0.0..100.0).contains(&a));
+                }
+                _ => panic!("random should return a num"),
+            }
+        }
+        Ok(())
+    }
 }
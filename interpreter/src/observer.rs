@@ -0,0 +1,28 @@
+use environment::Type;
+use parser::Expr;
+
+use crate::{Interpreter, Raised};
+
+/// Watches a program as it runs, for tools such as a debugger. Each hook is given
+/// the interpreter, to look at what is in scope, and does nothing unless it is
+/// implemented.
+pub trait Observer {
+    /// Called before `expr` is evaluated, `depth` levels of nesting deep.
+    fn before(&mut self, _interpreter: &Interpreter, _expr: &Expr, _depth: usize) {}
+
+    /// Called after `expr` was evaluated, with its value or what it raised. An
+    /// expression that ends a function body, and whose value is the value of the
+    /// call, is only reported before.
+    fn after(
+        &mut self,
+        _interpreter: &Interpreter,
+        _expr: &Expr,
+        _depth: usize,
+        _result: Result<&Type, &Raised>,
+    ) {
+    }
+
+    /// Called when the user function `name` is called, once its arguments are bound
+    /// and before its body runs.
+    fn call(&mut self, _interpreter: &Interpreter, _name: &str) {}
+}
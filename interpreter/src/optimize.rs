@@ -0,0 +1,279 @@
+//! Constant folding, run between type checking and interpretation with `-O`.
+//!
+//! An operator applied to literals, such as `+ 1 (* 2 3)`, is replaced by the
+//! literal it evaluates to, working from the innermost calls out. Folding uses the
+//! same `operate` as running does, so a folded program prints exactly what it would
+//! have. An operation that fails is left as it is, to fail when it runs.
+//!
+//! A `match` on a literal is replaced by the body of the arm it picks, inside a
+//! `let` binding the value when that arm's pattern is a name.
+
+use diagnostics::Span;
+use environment::Type;
+use lexer::Token;
+use parser::{Arm, Binding, Catch, Expr, Param};
+
+use crate::{fits, literal_value, operate};
+
+/// Folds the constant operations in each expression.
+pub fn optimize(exprs: Vec<Expr>) -> Vec<Expr> {
+    exprs.into_iter().map(fold).collect()
+}
+
+fn fold(expr: Expr) -> Expr {
+    match expr {
+        Expr::FunctionCall {
+            operator,
+            operand,
+            span,
+        } => {
+            let operand: Vec<Expr> = operand.into_iter().map(fold).collect();
+            match operator {
+                Token::Symbol(_) => Expr::FunctionCall {
+                    operator,
+                    operand,
+                    span,
+                },
+                operator => fold_operation(operator, operand, span),
+            }
+        }
+        Expr::Assignment {
+            name,
+            type_decl,
+            expr,
+        } => Expr::Assignment {
+            name,
+            type_decl,
+            expr: Box::new(fold(*expr)),
+        },
+        Expr::Function {
+            name,
+            type_decl,
+            params,
+            body,
+        } => Expr::Function {
+            name,
+            type_decl,
+            params: fold_params(params),
+            body: Box::new(fold(*body)),
+        },
+        Expr::Let { bindings, body } => Expr::Let {
+            bindings: fold_bindings(bindings),
+            body: Box::new(fold(*body)),
+        },
+        Expr::Do { exprs } => Expr::Do {
+            exprs: optimize(exprs),
+        },
+        Expr::Loop {
+            bindings,
+            condition,
+            recur,
+            result,
+        } => Expr::Loop {
+            bindings: fold_bindings(bindings),
+            condition: Box::new(fold(*condition)),
+            recur: optimize(recur),
+            result: Box::new(fold(*result)),
+        },
+        Expr::Try {
+            body,
+            catch,
+            finally,
+        } => Expr::Try {
+            body: Box::new(fold(*body)),
+            catch: catch.map(|catch| {
+                Box::new(Catch {
+                    name: catch.name,
+                    body: fold(catch.body),
+                })
+            }),
+            finally: finally.map(|finally| Box::new(fold(*finally))),
+        },
+        Expr::Throw { value } => Expr::Throw {
+            value: Box::new(fold(*value)),
+        },
+        Expr::Match { value, arms } => fold_match(fold(*value), arms),
+        Expr::Seq { items } => Expr::Seq {
+            items: optimize(items),
+        },
+        Expr::Interpolation { parts } => Expr::Interpolation {
+            parts: optimize(parts),
+        },
+        Expr::Named { name, value } => Expr::Named {
+            name,
+            value: Box::new(fold(*value)),
+        },
+        Expr::Hash { entries } => Expr::Hash {
+            entries: entries
+                .into_iter()
+                .map(|(key, value)| (fold(key), fold(value)))
+                .collect(),
+        },
+        Expr::Lambda { params, body } => Expr::Lambda {
+            params: fold_params(params),
+            body: Box::new(fold(*body)),
+        },
+        expr => expr,
+    }
+}
+
+fn fold_bindings(bindings: Vec<Binding>) -> Vec<Binding> {
+    bindings
+        .into_iter()
+        .map(|binding| Binding {
+            expr: fold(binding.expr),
+            ..binding
+        })
+        .collect()
+}
+
+fn fold_params(params: Vec<Param>) -> Vec<Param> {
+    params
+        .into_iter()
+        .map(|param| Param {
+            default: param.default.map(fold),
+            ..param
+        })
+        .collect()
+}
+
+/// The body of the arm a literal `value` picks, with the value bound to the arm's
+/// name if it has one. A match on anything else, or that no arm fits, is kept.
+fn fold_match(value: Expr, arms: Vec<Arm>) -> Expr {
+    if let (Expr::Literal { token }, Some(literal)) = (&value, self::value(&value)) {
+        let mut bound = vec![];
+        if let Some(arm) = arms.iter().position(|arm| {
+            bound.clear();
+            fits(&arm.pattern, &literal, &mut bound)
+        }) {
+            let type_decl = type_decl(token).to_string();
+            let body = fold(arms.into_iter().nth(arm).expect("the arm is there").body);
+            return match bound.first() {
+                Some(&(name, _)) => Expr::Let {
+                    bindings: vec![Binding {
+                        name,
+                        type_decl,
+                        expr: value,
+                    }],
+                    body: Box::new(body),
+                },
+                None => body,
+            };
+        }
+    }
+    Expr::Match {
+        value: Box::new(value),
+        arms: arms
+            .into_iter()
+            .map(|arm| Arm {
+                body: fold(arm.body),
+                ..arm
+            })
+            .collect(),
+    }
+}
+
+/// The declared type of a literal, as a `let` would write it.
+fn type_decl(token: &Token) -> &'static str {
+    match token {
+        Token::Int(_) => "int",
+        Token::Num(_) => "num",
+        Token::Str(_) => "str",
+        Token::True | Token::False => "bool",
+        _ => "_",
+    }
+}
+
+/// The literal an operator applied to `operand` evaluates to, if every operand is
+/// a literal and the operation succeeds.
+fn fold_operation(operator: Token, operand: Vec<Expr>, span: Option<Span>) -> Expr {
+    let values: Option<Vec<Type>> = operand.iter().map(value).collect();
+    let folded = values
+        .and_then(|values| operate(&operator, values).ok())
+        .and_then(|value| literal(&value));
+    match folded {
+        Some(token) => Expr::Literal { token },
+        None => Expr::FunctionCall {
+            operator,
+            operand,
+            span,
+        },
+    }
+}
+
+fn value(expr: &Expr) -> Option<Type> {
+    match expr {
+        Expr::Literal { token } => literal_value(token),
+        _ => None,
+    }
+}
+
+fn literal(value: &Type) -> Option<Token> {
+    match value {
+        Type::Int(int) => Some(Token::Int(*int)),
+        Type::Num(num) => Some(Token::Num(*num)),
+        Type::Str(string) => Some(Token::Str(string.to_string())),
+        Type::Bool(true) => Some(Token::True),
+        Type::Bool(false) => Some(Token::False),
+        Type::None => Some(Token::None),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn optimized(source: &str) -> String {
+        let tokens = lexer::lex(source.to_string()).unwrap();
+        let exprs = optimize(parser::parse(&tokens).unwrap());
+        parser::dump::ast(&exprs, parser::dump::Format::Sexpr)
+    }
+
+    #[test]
+    fn test_folds_constants() {
+        assert_eq!(optimized("+ 1 / 2 * 3 - 4 5\n"), "0.33333333333333337\n");
+        assert_eq!(optimized("def x: num -> * 2 (+ 1 2)\n"), "(def x num 6)\n");
+        assert_eq!(
+            optimized("and (< 1 2) (not false)\n+ \"a\" \"b\"\n"),
+            "true\n\"ab\"\n"
+        );
+        assert_eq!(
+            optimized("def f: num x: num -> + x (* 2 3)\n"),
+            "(def f num ((x num)) (+ x 6))\n"
+        );
+    }
+
+    #[test]
+    fn test_leaves_failures_and_names() {
+        // an error is left to happen when the program runs
+        assert_eq!(optimized("+ 1 true\n"), "(+ 1 true)\n");
+        assert_eq!(optimized("inc (+ 1 1)\n"), "(inc 2)\n");
+    }
+
+    #[test]
+    fn test_folds_matches_on_literals() {
+        assert_eq!(
+            optimized("match (+ 1 1); 1 -> \"one\"; 2 -> \"two\"; _ -> \"many\"\n"),
+            "\"two\"\n"
+        );
+        assert_eq!(
+            optimized("match 3; 1 -> 0; n -> * n 2\n"),
+            "(let ((n int 3)) (* n 2))\n"
+        );
+        // no arm fits, which is left to fail when it runs
+        assert_eq!(optimized("match 3; 1 -> 0\n"), "(match 3 (1 0))\n");
+        assert_eq!(
+            optimized("match x; 1 -> + 1 1; _ -> 0\n"),
+            "(match x (1 2) (_ 0))\n"
+        );
+    }
+
+    #[test]
+    fn test_folds_param_defaults() {
+        assert_eq!(
+            optimized("def f: int x: int = (+ 1 2) -> x\n"),
+            "(def f int ((x int 3)) x)\n"
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use environment::Type;
+
+/// Where the values a script prints end up. Hosts such as GUIs or the playground
+/// implement this to route output into their own widgets or logs.
+pub trait OutputSink {
+    /// Output: the value of each top-level expression and anything passed to `print`.
+    fn print(&mut self, value: &Type);
+
+    /// Diagnostic output, from `eprint`.
+    fn eprint(&mut self, value: &Type);
+
+    /// Asks for input, from `read-line` and `read-num`. Sinks that can leave the
+    /// cursor after the prompt should, but by default it is printed as a line.
+    fn prompt(&mut self, text: &str) {
+        self.print(&Type::str(text));
+    }
+}
+
+/// The interpreter's own sink, writing to the process's stdout and stderr.
+pub struct StdOutput;
+
+impl OutputSink for StdOutput {
+    fn print(&mut self, value: &Type) {
+        println!("{}", value);
+    }
+
+    fn eprint(&mut self, value: &Type) {
+        eprintln!("{}", value);
+    }
+
+    fn prompt(&mut self, text: &str) {
+        print!("{text}");
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Collects printed values as lines of text. Clones share the same buffer, so a
+/// host can keep one clone and hand the other to the interpreter.
+#[derive(Clone, Default)]
+pub struct CapturedOutput {
+    lines: Arc<Mutex<Captured>>,
+}
+
+#[derive(Default)]
+struct Captured {
+    stdout: Vec<String>,
+    stderr: Vec<String>,
+}
+
+impl CapturedOutput {
+    pub fn new() -> CapturedOutput {
+        CapturedOutput::default()
+    }
+
+    pub fn stdout(&self) -> Vec<String> {
+        self.lines.lock().unwrap().stdout.clone()
+    }
+
+    pub fn stderr(&self) -> Vec<String> {
+        self.lines.lock().unwrap().stderr.clone()
+    }
+}
+
+impl OutputSink for CapturedOutput {
+    fn print(&mut self, value: &Type) {
+        self.lines.lock().unwrap().stdout.push(value.to_string());
+    }
+
+    fn eprint(&mut self, value: &Type) {
+        self.lines.lock().unwrap().stderr.push(value.to_string());
+    }
+}
@@ -0,0 +1,76 @@
+//! The entry point for running linus in a browser. It takes source and gives back
+//! text, so that it can be exported with `#[wasm_bindgen]` as it is:
+//!
+//! ```text
+//! cargo build -p interpreter --target wasm32-unknown-unknown --no-default-features
+//! ```
+//!
+//! `http` is left out because its client doesn't build for wasm.
+
+use std::sync::{Arc, Mutex};
+
+use diagnostics::SourceMap;
+use environment::Type;
+
+use crate::{Capabilities, Interpreter, OutputSink, ScriptedInput};
+
+/// Runs `source` in a sandboxed interpreter and returns everything it printed, one
+/// line each, followed by the error that stopped it, if one did.
+pub fn run_source(source: &str) -> String {
+    let mut sources = SourceMap::new();
+    let id = sources.add("playground", source.to_string());
+    let transcript = Transcript::default();
+
+    let mut interpreter = Interpreter::with_capabilities(Capabilities::sandboxed());
+    interpreter.set_output(Box::new(transcript.clone()));
+    interpreter.set_input(Box::new(ScriptedInput::default()));
+
+    let result = lexer::lex_spanned(source, id)
+        .map_err(|err| vec![err])
+        .and_then(|tokens| parser::parse_spanned(&tokens, id))
+        .map_err(|errs| errs.iter().map(|err| err.render(&sources)).collect())
+        .and_then(|exprs| {
+            interpreter
+                .execute(&exprs)
+                .map_err(|raised| vec![interpreter.diagnostic(&raised).render(&sources)])
+        });
+
+    let mut output = transcript.0.lock().unwrap().clone();
+    if let Err(errs) = result {
+        output.extend(errs);
+    }
+    output.join("\n")
+}
+
+/// Keeps what is printed and what is printed to stderr together, in the order it
+/// was printed, the way a terminal shows them.
+#[derive(Clone, Default)]
+struct Transcript(Arc<Mutex<Vec<String>>>);
+
+impl OutputSink for Transcript {
+    fn print(&mut self, value: &Type) {
+        self.0.lock().unwrap().push(value.to_string());
+    }
+
+    fn eprint(&mut self, value: &Type) {
+        self.print(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_source() {
+        assert_eq!(run_source("print \"hi\"\n+ 1 2\n"), "hi\n3");
+        assert_eq!(run_source("eprint 1\nprint 2\n"), "1\n2");
+        assert_eq!(run_source("read-line \"name? \"\n"), "name? ");
+
+        let failed = run_source("print 1\nmissing\n");
+        assert!(failed.starts_with("1\nerror"), "{failed}");
+        assert!(run_source("def 1").starts_with("error"));
+        // the playground can't reach outside of the page
+        assert!(run_source("read-file \"/etc/passwd\"").contains("sandbox"));
+    }
+}
@@ -0,0 +1,138 @@
+use std::fmt;
+
+use diagnostics::Diagnostic;
+use environment::{Function, Type};
+
+use crate::Raised;
+
+/// A value handed back to the host by `Interpreter::eval_str`. Unlike the
+/// interpreter's own values it doesn't hold on to anything of the interpreter's,
+/// so it can be kept, compared, and sent anywhere.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    None,
+    Bool(bool),
+    Int(i64),
+    Num(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Seq(Vec<Value>),
+    /// The entries of a hash, in key order.
+    Hash(Vec<(Value, Value)>),
+    /// A function, with its name if it has one.
+    Function(Option<String>),
+}
+
+impl From<&Type> for Value {
+    fn from(value: &Type) -> Value {
+        match value {
+            Type::None => Value::None,
+            Type::Bool(bool) => Value::Bool(*bool),
+            Type::Int(int) => Value::Int(*int),
+            Type::Num(num) => Value::Num(*num),
+            Type::Str(string) => Value::Str(string.to_string()),
+            Type::Bytes(bytes) => Value::Bytes(bytes.to_vec()),
+            Type::Seq(items) => Value::Seq(items.iter().map(Value::from).collect()),
+            Type::Hash(entries) => Value::Hash(
+                entries
+                    .iter()
+                    .map(|(key, value)| (Value::from(&key.to_type()), Value::from(value)))
+                    .collect(),
+            ),
+            Type::Function(function) => Value::Function(match function.as_ref() {
+                Function::Builtin(name) => Some(name.clone()),
+                Function::User(function) => Some(function.name.to_string()),
+                Function::Native(name, _) => Some(name.clone()),
+                _ => None,
+            }),
+        }
+    }
+}
+
+/// Written the way `print` writes the value it came from.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::None => write!(f, "none"),
+            Value::Bool(bool) => write!(f, "{bool}"),
+            Value::Int(int) => write!(f, "{int}"),
+            Value::Num(num) => write!(f, "{num}"),
+            Value::Str(string) => write!(f, "{string}"),
+            Value::Bytes(bytes) => {
+                write!(f, "<bytes")?;
+                for byte in bytes {
+                    write!(f, " {byte:02x}")?;
+                }
+                write!(f, ">")
+            }
+            Value::Seq(items) => {
+                write!(f, "[")?;
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, " ")?;
+                    }
+                    write_nested(f, item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Hash(entries) => {
+                write!(f, "{{")?;
+                for (idx, (key, value)) in entries.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, " ")?;
+                    }
+                    write_nested(f, key)?;
+                    write!(f, " ")?;
+                    write_nested(f, value)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Function(Some(name)) => write!(f, "<function {name}>"),
+            Value::Function(None) => write!(f, "<function>"),
+        }
+    }
+}
+
+/// Strings inside collections are quoted so that their boundaries are visible.
+fn write_nested(f: &mut fmt::Formatter, value: &Value) -> fmt::Result {
+    match value {
+        Value::Str(string) => write!(f, "{string:?}"),
+        _ => write!(f, "{value}"),
+    }
+}
+
+/// Why `Interpreter::eval_str` failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The source couldn't be lexed or parsed.
+    Syntax(Vec<Diagnostic>),
+    /// Running it failed.
+    Runtime(String),
+    /// Running it threw a value that nothing caught.
+    Thrown(Value),
+}
+
+impl From<Raised> for Error {
+    fn from(raised: Raised) -> Error {
+        match raised {
+            Raised::Error(err) => Error::Runtime(err.to_string()),
+            Raised::Thrown(value) => Error::Thrown(Value::from(&value)),
+            Raised::Runtime(err) => Error::Runtime(err.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Syntax(errs) => {
+                let messages: Vec<&str> = errs.iter().map(|err| err.message.as_str()).collect();
+                write!(f, "{}", messages.join("\n"))
+            }
+            Error::Runtime(err) => write!(f, "{err}"),
+            Error::Thrown(value) => write!(f, "Runtime Error: uncaught exception: {value}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -1,18 +1,44 @@
-use std::iter::Peekable;
-use std::str::Chars;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::Peekable;
+use core::ops::Range;
+use core::str::CharIndices;
+
+use diagnostics::{codes, Diagnostic, SourceId, Span};
+
+mod symbol;
+
+pub use symbol::Symbol;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     // Literals
-    Symbol(String),
+    /// A name, interned.
+    Symbol(Symbol),
     Str(String),
+    /// A str with expressions in braces, e.g. `"{name} scored {+ a b}"`.
+    Interpolated(Vec<Piece>),
+    Int(i64),
     Num(f64),
     True,
     False,
     None,
     // Collections
+    /// `[`, which opens a seq literal.
     Seq,
+    /// `]`
+    RightBracket,
+    /// `{`, which opens a hash literal.
     Hash,
+    /// `}`
+    RightBrace,
     Group,
     Choice,
     // Operators
@@ -33,6 +59,8 @@ pub enum Token {
     TypeDelim,
     // Variables/Functions
     Def,
+    /// `set`, which changes a binding that already exists.
+    Set,
     Assign,
     AnonFn,
     // Special Expressions
@@ -40,13 +68,24 @@ pub enum Token {
     Let,
     If,
     Loop,
+    /// `match`, which picks the arm whose pattern fits a value.
+    Match,
     // Blocks
+    /// A line indented deeper than the one before it, which opens a block that the
+    /// matching `Dedent` closes.
     Indent,
     Dedent,
     LeftParen,
     RightParen,
     Appl,
+    /// `|>`, which passes the value before it to the call after it as its last
+    /// argument.
+    Pipe,
     Newline,
+    /// `;`, which separates expressions on the same line like a newline does.
+    Semicolon,
+    /// `import`, which brings in the definitions of another file.
+    Import,
     // Exception handling
     Try,
     Catch,
@@ -56,165 +95,574 @@ pub enum Token {
     EOF,
 }
 
+/// A part of an interpolated str, in order: text as it reads, or the tokens of the
+/// expression between a `{` and its `}`, ending with an `EOF` like any source.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Piece {
+    Text(String),
+    Code(Vec<Token>),
+}
+
+const UNTERMINATED_STR: &str = "Unterminated str";
+const UNTERMINATED_COMMENT: &str = "Unterminated block comment";
+const UNKNOWN_ESCAPE: &str = "Unknown escape sequence in str";
+const EMPTY_INTERPOLATION: &str = "Expected an expression between '{' and '}' in str";
+const MALFORMED_NUM: &str = "Malformed num";
+const INCONSISTENT_INDENT: &str = "Inconsistent indentation";
+
+/// Where a token was found in the source: the bytes it covers, and the line and
+/// column it starts at. Lines and columns count from 1, and columns count chars.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Location {
+    pub range: Range<usize>,
+    pub line: usize,
+    pub col: usize,
+}
+
+/// A token together with where it was found.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Spanned {
+    pub token: Token,
+    pub location: Location,
+}
+
+/// Why lexing failed, and the bytes of the source it is about.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LexError {
+    pub message: &'static str,
+    pub range: Range<usize>,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message)
+    }
+}
+
+/// Yields the characters of the source, with their byte offsets, with `\r\n` and
+/// lone `\r` line endings turned into `\n`, so that files written on Windows lex
+/// the same as everywhere else.
+struct LineEndings<'a> {
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl Iterator for LineEndings<'_> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<(usize, char)> {
+        match self.chars.next()? {
+            (idx, '\r') => {
+                self.chars.next_if(|&(_, c)| c == '\n');
+                Some((idx, '\n'))
+            }
+            next => Some(next),
+        }
+    }
+}
+
+/// Splits a source into tokens as they are asked for, each with the bytes it
+/// covers. Lexing stops at the first error.
 pub struct Lexer<'a> {
-    stream: Peekable<Chars<'a>>,
-    tokens: Vec<Token>,
-    indented: bool,
+    source: &'a str,
+    stream: Peekable<LineEndings<'a>>,
+    /// The length of the source, which is where the stream ends.
+    end: usize,
+    /// Tokens lexed but not yet taken, since a line break can end several blocks.
+    pending: VecDeque<(Token, Range<usize>)>,
+    /// Whether the `EOF` token, or an error, has been lexed.
+    finished: bool,
+    /// The indentation of each open block, innermost last, starting with the
+    /// unindented top level.
+    indents: Vec<&'a str>,
+    /// For each open bracket, how many blocks were open before it. The blocks opened
+    /// inside a bracket are closed with it.
+    brackets: Vec<usize>,
+    /// Reused for building every lexeme, so that only the ones kept in a token are
+    /// copied into an allocation of their own.
+    scratch: String,
 }
 
-impl Lexer<'_> {
-    fn new(input: &String) -> Lexer {
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Lexer<'a> {
+        let mut chars = input.char_indices().peekable();
+        // a leading byte order mark is an encoding detail, not part of the program
+        chars.next_if(|&(_, c)| c == '\u{feff}');
+
         Lexer {
-            stream: input.chars().peekable(),
-            tokens: vec![],
-            indented: false,
+            source: input,
+            stream: LineEndings { chars }.peekable(),
+            end: input.len(),
+            pending: VecDeque::new(),
+            finished: false,
+            indents: vec![""],
+            brackets: vec![],
+            scratch: String::new(),
         }
     }
 
-    fn split_tokens(&mut self) -> Result<(), &'static str> {
-        while let Some(c) = self.stream.next() {
-            match c {
-                '\n' if self.stream.peek() == Some(&' ') || self.stream.peek() == Some(&'\t') => {
-                    self.indented = true;
-                    self.tokens.push(Token::Indent);
-                }
-                '\n' if self.stream.peek() != Some(&' ')
-                    && self.stream.peek() != Some(&'\t')
-                    && self.indented =>
-                {
-                    self.indented = false;
-                    self.tokens.push(Token::Dedent);
-                }
-                '\n' => {
-                    self.tokens.push(Token::Newline);
+    fn next_char(&mut self) -> Option<char> {
+        self.stream.next().map(|(_, c)| c)
+    }
+
+    fn peek_char(&mut self) -> Option<char> {
+        self.stream.peek().map(|&(_, c)| c)
+    }
+
+    /// The byte offset of the next character.
+    fn offset(&mut self) -> usize {
+        self.stream.peek().map_or(self.end, |&(idx, _)| idx)
+    }
+
+    /// Adds a token that started at `start` and ends before the next character.
+    fn push(&mut self, token: Token, start: usize) {
+        let end = self.offset();
+        self.push_range(token, start..end);
+    }
+
+    fn push_range(&mut self, token: Token, range: Range<usize>) {
+        self.pending.push_back((token, range));
+    }
+
+    /// The next token and the bytes it covers, or why the source can't be lexed.
+    pub fn next_spanned(&mut self) -> Option<Result<(Token, Range<usize>), LexError>> {
+        loop {
+            if let Some(next) = self.pending.pop_front() {
+                return Some(Ok(next));
+            }
+            if self.finished {
+                return None;
+            }
+            if let Err((message, range)) = self.step() {
+                self.finished = true;
+                return Some(Err(LexError { message, range }));
+            }
+        }
+    }
+
+    /// Lexes the line break at `start` by the indentation of the next line that isn't
+    /// blank or only a comment: a `Newline` if it is at the same level, an `Indent` if
+    /// it is deeper, or a `Dedent` for each block it goes back out of. The end of the
+    /// source is at the top level.
+    fn line_break(&mut self, start: usize) -> Result<(), (&'static str, Range<usize>)> {
+        let range = start..self.offset();
+        let (line, indent) = loop {
+            let line = self.offset();
+            while let Some(' ' | '\t') = self.peek_char() {
+                self.next_char();
+            }
+            match self.peek_char() {
+                Some('\n') => {
+                    self.next_char();
                 }
-                ' ' | '\t' | '\r' => {}
-                '"' => {
-                    let mut str_lexeme = String::new();
-                    str_lexeme.push(c);
-                    while let Some(&x) = self.stream.peek() {
-                        match self.stream.next() {
-                            Some(nxt) => str_lexeme.push(nxt),
-                            None => break,
-                        };
-                        if x == '"' {
-                            break;
+                // a line that starts with block comments is indented as far as
+                // they are, unless nothing follows them
+                Some('#') if self.source[self.offset()..].starts_with("#|") => {
+                    let indent = self.offset();
+                    while self.source[self.offset()..].starts_with("#|") {
+                        let start = self.offset();
+                        self.next_char();
+                        self.next_char();
+                        self.block_comment(start)?;
+                        while let Some(' ' | '\t') = self.peek_char() {
+                            self.next_char();
                         }
                     }
-                    self.tokens.push(Token::Str(str_lexeme));
-                }
-                '#' => {
-                    while let Some(x) = self.stream.next() {
-                        if x == '\n' {
-                            break;
-                        }
+                    if !matches!(self.peek_char(), None | Some('\n' | '#')) {
+                        break (line, &self.source[line..indent]);
                     }
                 }
-                ':' => {
-                    self.tokens.push(Token::TypeDelim);
-                }
-                '(' => {
-                    self.tokens.push(Token::LeftParen);
-                }
-                ')' => {
-                    self.tokens.push(Token::RightParen);
-                }
-                '$' => {
-                    self.tokens.push(Token::Appl);
-                }
-                '\\' => {
-                    self.tokens.push(Token::AnonFn);
-                }
-                '-' if self.stream.peek() == Some(&'>') => {
-                    self.tokens.push(Token::Assign);
-                    self.stream.next();
-                }
-                '+' => {
-                    self.tokens.push(Token::Add);
-                }
-                '-' => {
-                    self.tokens.push(Token::Subtract);
-                }
-                '/' => {
-                    self.tokens.push(Token::Divide);
-                }
-                '*' => {
-                    self.tokens.push(Token::Multiply);
-                }
-                '>' if self.stream.peek() == Some(&'=') => {
-                    self.tokens.push(Token::GreaterThanOrEqual);
-                }
-                '<' if self.stream.peek() == Some(&'=') => {
-                    self.tokens.push(Token::LessThanOrEqual);
+                Some('#') => {
+                    while self.peek_char().is_some_and(|x| x != '\n') {
+                        self.next_char();
+                    }
                 }
-                '>' => {
-                    self.tokens.push(Token::GreaterThan);
+                None => break (line, ""),
+                Some(_) => break (line, &self.source[line..self.offset()]),
+            }
+        };
+
+        let current = self.indents.last().copied().unwrap_or_default();
+        if indent == current {
+            self.push_range(Token::Newline, range);
+        } else if indent.starts_with(current) {
+            self.indents.push(indent);
+            self.push_range(Token::Indent, range);
+        } else {
+            // going back out, to the indentation of one of the enclosing blocks
+            let level = self
+                .indents
+                .iter()
+                .position(|&outer| outer == indent)
+                .ok_or((INCONSISTENT_INDENT, line..self.offset()))?;
+            // but not out of a bracket that is still open
+            let floor = self.brackets.last().copied().unwrap_or(1);
+            if !self.dedent((level + 1).max(floor), range.clone()) {
+                self.push_range(Token::Newline, range);
+            }
+        }
+        Ok(())
+    }
+
+    /// Lexes the expression after a `{` in the str that starts at `start`, up to the
+    /// `}` that matches it, which is taken too.
+    fn interpolation(&mut self, start: usize) -> Result<Vec<Token>, (&'static str, Range<usize>)> {
+        let code = self.offset();
+        self.skip_code(start)?;
+        let end = self.offset() - '}'.len_utf8();
+        let mut lexer = Lexer::new(&self.source[code..end]);
+        let mut tokens = vec![];
+        while let Some(next) = lexer.next_spanned() {
+            match next {
+                Ok((token, _)) => tokens.push(token),
+                Err(err) => {
+                    return Err((err.message, code + err.range.start..code + err.range.end))
                 }
-                '<' => {
-                    self.tokens.push(Token::LessThan);
+            }
+        }
+        match tokens == [Token::EOF] {
+            true => Err((EMPTY_INTERPOLATION, code - 1..end + 1)),
+            false => Ok(tokens),
+        }
+    }
+
+    /// Skips code in a str up to the `}` that ends it, past any strs in it, which
+    /// may have code in them in turn.
+    fn skip_code(&mut self, start: usize) -> Result<(), (&'static str, Range<usize>)> {
+        loop {
+            match self.next_char() {
+                Some('}') => return Ok(()),
+                Some('{') => self.skip_code(start)?,
+                Some('"') => loop {
+                    match self.next_char() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            self.next_char();
+                        }
+                        Some('{') => self.skip_code(start)?,
+                        Some(_) => {}
+                        None => return Err((UNTERMINATED_STR, start..self.end)),
+                    }
+                },
+                Some(_) => {}
+                None => return Err((UNTERMINATED_STR, start..self.end)),
+            }
+        }
+    }
+
+    /// Skips the rest of the block comment whose `#|` was at `start`, up to the `|#`
+    /// that matches it. Block comments nest, and the lines they span don't count as
+    /// lines of the program.
+    fn block_comment(&mut self, start: usize) -> Result<(), (&'static str, Range<usize>)> {
+        let mut open = 1;
+        while open > 0 {
+            match self.next_char() {
+                Some('#') if self.peek_char() == Some('|') => {
+                    self.next_char();
+                    open += 1;
                 }
-                '=' => {
-                    self.tokens.push(Token::Equal);
+                Some('|') if self.peek_char() == Some('#') => {
+                    self.next_char();
+                    open -= 1;
                 }
-                '0'..='9' => {
-                    let mut num_lexeme = String::new();
-                    num_lexeme.push(c);
+                Some(_) => {}
+                None => return Err((UNTERMINATED_COMMENT, start..self.end)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes blocks until there are only `open` left, returning whether any were.
+    fn dedent(&mut self, open: usize, range: Range<usize>) -> bool {
+        let closed = self.indents.len() > open;
+        while self.indents.len() > open {
+            self.indents.pop();
+            self.push_range(Token::Dedent, range.clone());
+        }
+        closed
+    }
+
+    fn open_bracket(&mut self, token: Token, start: usize) {
+        self.brackets.push(self.indents.len());
+        self.push(token, start);
+    }
+
+    /// Closes the blocks opened inside the bracket, then the bracket.
+    fn close_bracket(&mut self, token: Token, start: usize) {
+        if let Some(open) = self.brackets.pop() {
+            self.dedent(open, start..start);
+        }
+        self.push(token, start);
+    }
+
+    /// Lexes a num that starts with `first`: digits, with an optional fraction and
+    /// exponent, where `_` may separate digits, as in `-1_000.5e-3`. Without a
+    /// fraction or exponent it is an int, unless it is too big for one.
+    fn number(&mut self, first: char, start: usize) -> Result<(), (&'static str, Range<usize>)> {
+        self.scratch.clear();
+        self.scratch.push(first);
+        // take everything that could belong to the num, so `1.2.3` or `12ab` is an
+        // error rather than a num followed by something else
+        while let Some(x) = self.peek_char() {
+            let exponent_sign = matches!(x, '+' | '-') && self.scratch.ends_with(['e', 'E']);
+            if !x.is_ascii_alphanumeric() && !matches!(x, '_' | '.') && !exponent_sign {
+                break;
+            }
+            self.next_char();
+            self.scratch.push(x);
+        }
+        let malformed = (MALFORMED_NUM, start..self.offset());
+
+        let bytes = self.scratch.as_bytes();
+        let separates_digits = |idx: usize| {
+            idx > 0
+                && bytes[idx - 1].is_ascii_digit()
+                && bytes.get(idx + 1).is_some_and(u8::is_ascii_digit)
+        };
+        if (0..bytes.len()).any(|idx| bytes[idx] == b'_' && !separates_digits(idx)) {
+            return Err(malformed);
+        }
+        self.scratch.retain(|c| c != '_');
+
+        if let Ok(int) = self.scratch.parse::<i64>() {
+            self.push(Token::Int(int), start);
+            return Ok(());
+        }
+        match self.scratch.parse::<f64>() {
+            Ok(num) => {
+                self.push(Token::Num(num), start);
+                Ok(())
+            }
+            Err(_) => Err(malformed),
+        }
+    }
 
-                    while let Some(&x) = self.stream.peek() {
-                        if !x.is_digit(10) && x != '.' {
-                            break;
+    /// Lexes the next character and what follows it that belongs to the same token,
+    /// or the end of the source, or fails with an error and the bytes it is about.
+    fn step(&mut self) -> Result<(), (&'static str, Range<usize>)> {
+        let start = self.offset();
+        let Some(c) = self.next_char() else {
+            // a source that doesn't end with a line break may still be in blocks
+            self.dedent(1, self.end..self.end);
+            self.push(Token::EOF, self.end);
+            self.finished = true;
+            return Ok(());
+        };
+        match c {
+            '\n' => self.line_break(start)?,
+            ';' => {
+                self.push(Token::Semicolon, start);
+            }
+            ' ' | '\t' => {}
+            '"' => {
+                self.scratch.clear();
+                let mut pieces = vec![];
+                loop {
+                    let escape = self.offset();
+                    match self.next_char() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            let escaped = match self.next_char() {
+                                Some('"') => '"',
+                                Some('\\') => '\\',
+                                Some('n') => '\n',
+                                Some('t') => '\t',
+                                Some('{') => '{',
+                                Some('}') => '}',
+                                Some(_) => return Err((UNKNOWN_ESCAPE, escape..self.offset())),
+                                None => return Err((UNTERMINATED_STR, start..self.end)),
+                            };
+                            self.scratch.push(escaped);
+                        }
+                        Some('{') => {
+                            if !self.scratch.is_empty() {
+                                pieces.push(Piece::Text(self.scratch.as_str().into()));
+                                self.scratch.clear();
+                            }
+                            pieces.push(Piece::Code(self.interpolation(start)?));
                         }
-                        match self.stream.next() {
-                            Some(nxt) => num_lexeme.push(nxt),
-                            None => break,
-                        };
+                        Some(x) => self.scratch.push(x),
+                        None => return Err((UNTERMINATED_STR, start..self.end)),
                     }
-                    self.tokens
-                        .push(Token::Num(num_lexeme.parse::<f64>().unwrap()));
                 }
-                _ => {
-                    let mut lexeme = String::new();
-                    lexeme.push(c);
+                if pieces.is_empty() {
+                    self.push(Token::Str(self.scratch.as_str().into()), start);
+                } else {
+                    if !self.scratch.is_empty() {
+                        pieces.push(Piece::Text(self.scratch.as_str().into()));
+                    }
+                    self.push(Token::Interpolated(pieces), start);
+                }
+            }
+            '#' if self.peek_char() == Some('|') => {
+                self.next_char();
+                self.block_comment(start)?;
+            }
+            // the line break after a comment is still lexed
+            '#' => {
+                while self.peek_char().is_some_and(|x| x != '\n') {
+                    self.next_char();
+                }
+            }
+            ':' => {
+                self.push(Token::TypeDelim, start);
+            }
+            '(' => self.open_bracket(Token::LeftParen, start),
+            ')' => self.close_bracket(Token::RightParen, start),
+            '[' => self.open_bracket(Token::Seq, start),
+            ']' => self.close_bracket(Token::RightBracket, start),
+            '{' => self.open_bracket(Token::Hash, start),
+            '}' => self.close_bracket(Token::RightBrace, start),
+            '$' => {
+                self.push(Token::Appl, start);
+            }
+            '\\' => {
+                self.push(Token::AnonFn, start);
+            }
+            '|' if self.peek_char() == Some('>') => {
+                self.next_char();
+                self.push(Token::Pipe, start);
+            }
+            '-' if self.peek_char() == Some('>') => {
+                self.next_char();
+                self.push(Token::Assign, start);
+            }
+            '+' => {
+                self.push(Token::Add, start);
+            }
+            // a '-' right before a digit is part of a negative num
+            '-' if self.peek_char().is_some_and(|x| x.is_ascii_digit()) => self.number(c, start)?,
+            '-' => {
+                self.push(Token::Subtract, start);
+            }
+            '/' => {
+                self.push(Token::Divide, start);
+            }
+            '*' => {
+                self.push(Token::Multiply, start);
+            }
+            '>' if self.peek_char() == Some('=') => {
+                self.next_char();
+                self.push(Token::GreaterThanOrEqual, start);
+            }
+            '<' if self.peek_char() == Some('=') => {
+                self.next_char();
+                self.push(Token::LessThanOrEqual, start);
+            }
+            '>' => {
+                self.push(Token::GreaterThan, start);
+            }
+            '<' => {
+                self.push(Token::LessThan, start);
+            }
+            '=' => {
+                self.push(Token::Equal, start);
+            }
+            '0'..='9' => self.number(c, start)?,
+            _ => {
+                self.scratch.clear();
+                self.scratch.push(c);
 
-                    while let Some(&x) = self.stream.peek() {
-                        if x.is_whitespace() || x == '#' || x == ':' || x == ')' {
-                            break;
-                        }
-                        match self.stream.next() {
-                            Some(nxt) => lexeme.push(nxt),
-                            None => break,
-                        };
+                while let Some(x) = self.peek_char() {
+                    if x.is_whitespace() || matches!(x, '#' | ':' | ')' | ']' | '}' | ';') {
+                        break;
                     }
-                    match lexeme.as_str() {
-                        "true" => self.tokens.push(Token::True),
-                        "false" => self.tokens.push(Token::False),
-                        "none" => self.tokens.push(Token::None),
-                        "and" => self.tokens.push(Token::And),
-                        "or" => self.tokens.push(Token::Or),
-                        "not" => self.tokens.push(Token::Not),
-                        "def" => self.tokens.push(Token::Def),
-                        "let" => self.tokens.push(Token::Let),
-                        "try" => self.tokens.push(Token::Try),
-                        "catch" => self.tokens.push(Token::Catch),
-                        "finally" => self.tokens.push(Token::Finally),
-                        "throw" => self.tokens.push(Token::Throw),
-                        "loop" => self.tokens.push(Token::Loop),
-                        "do" => self.tokens.push(Token::Do),
-                        "num" | "str" | "_" | "bool" => self.tokens.push(Token::TypeDecl(lexeme)),
-                        _ => self.tokens.push(Token::Symbol(lexeme)),
+                    match self.next_char() {
+                        Some(nxt) => self.scratch.push(nxt),
+                        None => break,
+                    };
+                }
+                let lexeme = self.scratch.as_str();
+                match lexeme {
+                    "true" => self.push(Token::True, start),
+                    "false" => self.push(Token::False, start),
+                    "none" => self.push(Token::None, start),
+                    "and" => self.push(Token::And, start),
+                    "or" => self.push(Token::Or, start),
+                    "not" => self.push(Token::Not, start),
+                    "def" => self.push(Token::Def, start),
+                    "set" => self.push(Token::Set, start),
+                    "let" => self.push(Token::Let, start),
+                    "try" => self.push(Token::Try, start),
+                    "catch" => self.push(Token::Catch, start),
+                    "finally" => self.push(Token::Finally, start),
+                    "throw" => self.push(Token::Throw, start),
+                    "loop" => self.push(Token::Loop, start),
+                    "do" => self.push(Token::Do, start),
+                    "import" => self.push(Token::Import, start),
+                    "match" => self.push(Token::Match, start),
+                    "int" | "num" | "str" | "_" | "bool" => {
+                        self.push(Token::TypeDecl(lexeme.into()), start)
                     }
+                    _ => self.push(Token::Symbol(Symbol::intern(lexeme)), start),
                 }
             }
         }
-        self.tokens.push(Token::EOF);
         Ok(())
     }
 }
 
+impl Iterator for Lexer<'_> {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Result<Token, LexError>> {
+        self.next_spanned().map(|next| next.map(|(token, _)| token))
+    }
+}
+
+/// Every token in `source`, see `Lexer` for them one at a time.
 pub fn lex(source: String) -> Result<Vec<Token>, &'static str> {
-    let mut lexer = Lexer::new(&source);
-    lexer.split_tokens()?;
-    Ok(lexer.tokens)
+    Lexer::new(&source)
+        .collect::<Result<_, _>>()
+        .map_err(|err| err.message)
+}
+
+/// Like `lex`, but with where each token was found in `source`, which is `id` in
+/// the `SourceMap`.
+pub fn lex_spanned(source: &str, id: SourceId) -> Result<Vec<Spanned>, Diagnostic> {
+    let mut lexer = Lexer::new(source);
+    let mut tokens = vec![];
+    while let Some(next) = lexer.next_spanned() {
+        tokens.push(next.map_err(|err| diagnostic(err, id))?);
+    }
+
+    // walk the source once, since the tokens are in order
+    let (mut line, mut col) = (1, 1);
+    let mut chars = source.char_indices().peekable();
+    let spanned = tokens
+        .into_iter()
+        .map(|(token, range)| {
+            while let Some((_, c)) = chars.next_if(|&(idx, _)| idx < range.start) {
+                match c {
+                    '\r' if chars.peek().map(|&(_, c)| c) == Some('\n') => {}
+                    '\n' | '\r' => {
+                        line += 1;
+                        col = 1;
+                    }
+                    '\u{feff}' => {}
+                    _ => col += 1,
+                }
+            }
+            Spanned {
+                token,
+                location: Location { range, line, col },
+            }
+        })
+        .collect();
+    Ok(spanned)
+}
+
+fn diagnostic(err: LexError, id: SourceId) -> Diagnostic {
+    let hint = match err.message {
+        UNKNOWN_ESCAPE => "the escapes are \\\", \\n, \\t, \\\\, \\{, and \\}",
+        EMPTY_INTERPOLATION => "write '\\{' for a brace that is part of the text",
+        MALFORMED_NUM => "a num is written like `-12`, `1_000`, `2.5`, or `6.02e23`",
+        UNTERMINATED_COMMENT => "end the block comment with a '|#' for each '#|' in it",
+        INCONSISTENT_INDENT => "line it up with a block it is in, or indent it further than the line above, with the same mix of spaces and tabs",
+        _ => "end the str with a '\"'",
+    };
+    Diagnostic::error(codes::LEX, err.message)
+        .with_span(Span::new(id, err.range))
+        .with_hint(hint)
 }
 
 #[cfg(test)]
@@ -231,13 +679,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_num_assignment"),
-            &Token::Symbol("def".to_string())
+            &Token::Def
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_num_assignment"),
-            &Token::Symbol("first_num".to_string())
+            &Token::Symbol("first_num".into())
         );
         assert_eq!(
             result_iter
@@ -261,7 +709,7 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_num_assignment"),
-            &Token::Num(1.0)
+            &Token::Int(1)
         );
         assert_eq!(
             result_iter
@@ -283,13 +731,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_str_assignment"),
-            &Token::Symbol("def".to_string())
+            &Token::Def
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_str_assignment"),
-            &Token::Symbol("test_string".to_string())
+            &Token::Symbol("test_string".into())
         );
         assert_eq!(
             result_iter
@@ -313,7 +761,7 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_str_assignment"),
-            &Token::Str("\"this is a test\"".to_string())
+            &Token::Str("this is a test".to_string())
         );
         assert_eq!(
             result_iter
@@ -334,13 +782,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_bool_assignment"),
-            &Token::Symbol("def".to_string())
+            &Token::Def
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_bool_assignment"),
-            &Token::Symbol("is_bool".to_string())
+            &Token::Symbol("is_bool".into())
         );
         assert_eq!(
             result_iter
@@ -385,13 +833,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_none_assignment"),
-            &Token::Symbol("def".to_string())
+            &Token::Def
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_none_assignment"),
-            &Token::Symbol("is_none".to_string())
+            &Token::Symbol("is_none".into())
         );
         assert_eq!(
             result_iter
@@ -439,13 +887,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_none_assignment"),
-            &Token::Symbol("def".to_string())
+            &Token::Def
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_func_assignment"),
-            &Token::Symbol("no_args_func".to_string())
+            &Token::Symbol("no_args_func".into())
         );
         assert_eq!(
             result_iter
@@ -469,13 +917,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_func_assignment"),
-            &Token::Symbol("print".to_string())
+            &Token::Symbol("print".into())
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_func_assignment"),
-            &Token::Str("\"Hello, world!\"".to_string())
+            &Token::Str("Hello, world!".to_string())
         );
         assert_eq!(
             result_iter
@@ -496,13 +944,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_func_call"),
-            &Token::Symbol("print".to_string())
+            &Token::Symbol("print".into())
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_func_call"),
-            &Token::Symbol("test_string".to_string())
+            &Token::Symbol("test_string".into())
         );
         assert_eq!(
             result_iter
@@ -523,13 +971,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_func_call"),
-            &Token::Symbol("def".to_string())
+            &Token::Def
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_func_call"),
-            &Token::Symbol("sum".to_string())
+            &Token::Symbol("sum".into())
         );
         assert_eq!(
             result_iter
@@ -553,7 +1001,7 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_func_call"),
-            &Token::Symbol("x".to_string())
+            &Token::Symbol("x".into())
         );
         assert_eq!(
             result_iter
@@ -571,13 +1019,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_function_def"),
-            &Token::Indent
+            &Token::Newline
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_func_call"),
-            &Token::Symbol("y".to_string())
+            &Token::Symbol("y".into())
         );
         assert_eq!(
             result_iter
@@ -601,25 +1049,31 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_function_def"),
-            &Token::Indent
+            &Token::Newline
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_func_call"),
-            &Token::Symbol("+".to_string())
+            &Token::Add
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_func_call"),
-            &Token::Symbol("x".to_string())
+            &Token::Symbol("x".into())
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_func_call"),
-            &Token::Symbol("y".to_string())
+            &Token::Symbol("y".into())
+        );
+        assert_eq!(
+            result_iter
+                .next()
+                .expect("Error reading test: test_multiline_func_call"),
+            &Token::Dedent
         );
         assert_eq!(
             result_iter
@@ -676,6 +1130,40 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_block_comment() -> Result<(), String> {
+        let result = lex("a #| one #| two |# still one\n|# b".to_string())?;
+        assert_eq!(
+            result,
+            vec![
+                Token::Symbol("a".into()),
+                Token::Symbol("b".into()),
+                Token::EOF
+            ]
+        );
+
+        // a commented out body doesn't open or close a block
+        let source = "def f: int x: int ->\n    #| let\nold stuff\n  |#\n    x\n#| gone |#\nf 1";
+        assert_eq!(
+            lex(source.to_string())?,
+            lex("def f: int x: int ->\n    x\nf 1".to_string())?
+        );
+        let source = "def f: int ->\n    #| note |# 1\nf";
+        assert_eq!(
+            lex(source.to_string())?,
+            lex("def f: int ->\n    1\nf".to_string())?
+        );
+
+        assert_eq!(
+            lex("a #| #| b |#".to_string()),
+            Err("Unterminated block comment")
+        );
+        let err = lex_spanned("a\n  #| b", SourceId(0)).unwrap_err();
+        assert_eq!(err.message, "Unterminated block comment");
+        assert_eq!(err.span.map(|span| span.range()), Some(4..8));
+        Ok(())
+    }
+
     #[test]
     fn test_inline_comment() -> Result<(), String> {
         let result = lex("symbol sym#comment".to_string())?;
@@ -686,13 +1174,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_inline_comment"),
-            &Token::Symbol("symbol".to_string())
+            &Token::Symbol("symbol".into())
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_inline_comment"),
-            &Token::Symbol("sym".to_string())
+            &Token::Symbol("sym".into())
         );
         assert_eq!(
             result_iter
@@ -713,7 +1201,7 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_string_with_comment_symbol"),
-            &Token::Str("\"this is # not a comment\"".to_string())
+            &Token::Str("this is # not a comment".to_string())
         );
         assert_eq!(
             result_iter
@@ -734,13 +1222,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_long_function_def"),
-            &Token::Symbol("def".to_string())
+            &Token::Def
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_long_function_def"),
-            &Token::Symbol("x".to_string())
+            &Token::Symbol("x".into())
         );
         assert_eq!(
             result_iter
@@ -764,55 +1252,55 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_long_function_def"),
-            &Token::Symbol("+".to_string())
+            &Token::Add
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_long_function_def"),
-            &Token::Num(1.0)
+            &Token::Int(1)
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_long_function_def"),
-            &Token::Symbol("/".to_string())
+            &Token::Divide
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_long_function_def"),
-            &Token::Num(2.0)
+            &Token::Int(2)
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_long_function_def"),
-            &Token::Symbol("*".to_string())
+            &Token::Multiply
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_long_function_def"),
-            &Token::Num(3.0)
+            &Token::Int(3)
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_long_function_def"),
-            &Token::Symbol("-".to_string())
+            &Token::Subtract
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_long_function_def"),
-            &Token::Num(4.0)
+            &Token::Int(4)
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_long_function_def"),
-            &Token::Num(5.0)
+            &Token::Int(5)
         );
         assert_eq!(
             result_iter
@@ -837,13 +1325,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_function_def"),
-            &Token::Symbol("def".to_string())
+            &Token::Def
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_function_def"),
-            &Token::Symbol("x".to_string())
+            &Token::Symbol("x".into())
         );
         assert_eq!(
             result_iter
@@ -867,13 +1355,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_function_def"),
-            &Token::Symbol("sum".to_string())
+            &Token::Symbol("sum".into())
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_function_def"),
-            &Token::Num(1.0)
+            &Token::Int(1)
         );
         assert_eq!(
             result_iter
@@ -885,13 +1373,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_function_def"),
-            &Token::Symbol("divide".to_string())
+            &Token::Symbol("divide".into())
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_function_def"),
-            &Token::Num(2.0)
+            &Token::Int(2)
         );
         assert_eq!(
             result_iter
@@ -903,13 +1391,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_function_def"),
-            &Token::Symbol("multiply".to_string())
+            &Token::Symbol("multiply".into())
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_function_def"),
-            &Token::Num(3.0)
+            &Token::Int(3)
         );
         assert_eq!(
             result_iter
@@ -921,19 +1409,37 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_function_def"),
-            &Token::Symbol("subtract".to_string())
+            &Token::Symbol("subtract".into())
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_function_def"),
-            &Token::Num(4.0)
+            &Token::Int(4)
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_multiline_function_def"),
-            &Token::Num(5.0)
+            &Token::Int(5)
+        );
+        assert_eq!(
+            result_iter
+                .next()
+                .expect("Error reading test: test_multiline_func_def"),
+            &Token::Dedent
+        );
+        assert_eq!(
+            result_iter
+                .next()
+                .expect("Error reading test: test_multiline_func_def"),
+            &Token::Dedent
+        );
+        assert_eq!(
+            result_iter
+                .next()
+                .expect("Error reading test: test_multiline_func_def"),
+            &Token::Dedent
         );
         assert_eq!(
             result_iter
@@ -957,13 +1463,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Symbol("def".to_string())
+            &Token::Def
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Symbol("func_1".to_string())
+            &Token::Symbol("func_1".into())
         );
         assert_eq!(
             result_iter
@@ -993,13 +1499,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Symbol("print".to_string())
+            &Token::Symbol("print".into())
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Str("\"Hello, world!\"".to_string())
+            &Token::Str("Hello, world!".to_string())
         );
         assert_eq!(
             result_iter
@@ -1011,13 +1517,13 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Symbol("def".to_string())
+            &Token::Def
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Symbol("func_2".to_string())
+            &Token::Symbol("func_2".into())
         );
         assert_eq!(
             result_iter
@@ -1047,19 +1553,25 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Symbol("+".to_string())
+            &Token::Add
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Num(2.0)
+            &Token::Int(2)
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Num(2.0)
+            &Token::Int(2)
+        );
+        assert_eq!(
+            result_iter
+                .next()
+                .expect("Error reading test: test_two_func_decls"),
+            &Token::Dedent
         );
         assert_eq!(
             result_iter
@@ -1081,19 +1593,19 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Symbol("plus".to_string())
+            &Token::Symbol("plus".into())
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Num(1.0)
+            &Token::Int(1)
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Num(2.0)
+            &Token::Int(2)
         );
         assert_eq!(
             result_iter
@@ -1105,19 +1617,19 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Symbol("divide".to_string())
+            &Token::Symbol("divide".into())
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Num(3.0)
+            &Token::Int(3)
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Num(4.0)
+            &Token::Int(4)
         );
 
         Ok(())
@@ -1133,19 +1645,19 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Symbol("plus".to_string())
+            &Token::Symbol("plus".into())
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Num(1.0)
+            &Token::Int(1)
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Num(2.0)
+            &Token::Int(2)
         );
         assert_eq!(
             result_iter
@@ -1157,19 +1669,19 @@ mod tests {
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Symbol("divide".to_string())
+            &Token::Symbol("divide".into())
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Num(3.0)
+            &Token::Int(3)
         );
         assert_eq!(
             result_iter
                 .next()
                 .expect("Error reading test: test_two_func_decls"),
-            &Token::Num(4.0)
+            &Token::Int(4)
         );
         assert_eq!(
             result_iter
@@ -1180,4 +1692,353 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_crlf_and_bom() -> Result<(), String> {
+        let unix = "def sum: num\n    x: num ->\n    + x 1\n\"a\nb\"\nsum 2\n";
+        let windows = format!("\u{feff}{}", unix.replace('\n', "\r\n"));
+
+        assert_eq!(lex(windows)?, lex(unix.to_string())?);
+        assert_eq!(lex("1\r2\r".to_string())?, lex("1\n2\n".to_string())?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spans() -> Result<(), String> {
+        let result = lex_spanned(
+            "\u{feff}def x: str -> \"é\"\r\n  x # a comment\nx",
+            SourceId(0),
+        )
+        .map_err(|err| err.message)?;
+        let locations: Vec<(Range<usize>, usize, usize)> = result
+            .iter()
+            .map(|spanned| {
+                let Location { range, line, col } = spanned.location.clone();
+                (range, line, col)
+            })
+            .collect();
+
+        assert_eq!(
+            result
+                .iter()
+                .map(|spanned| &spanned.token)
+                .collect::<Vec<_>>(),
+            lex("def x: str -> \"é\"\n  x # a comment\nx".to_string())?
+                .iter()
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            locations,
+            vec![
+                (3..6, 1, 1),
+                (7..8, 1, 5),
+                (8..9, 1, 6),
+                (10..13, 1, 8),
+                (14..16, 1, 12),
+                (17..21, 1, 15),
+                (21..23, 1, 18),
+                (25..26, 2, 3),
+                (38..39, 2, 16),
+                (39..40, 3, 1),
+                (40..40, 3, 2),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_str_escapes() -> Result<(), String> {
+        let result = lex(r#""say \"hi\"\n\tand \\ go" "two
+lines""#
+            .to_string())?;
+        assert_eq!(
+            result,
+            vec![
+                Token::Str("say \"hi\"\n\tand \\ go".to_string()),
+                Token::Str("two\nlines".to_string()),
+                Token::EOF,
+            ]
+        );
+
+        assert_eq!(lex(r#"print "oops"#.to_string()), Err("Unterminated str"));
+        assert_eq!(lex(r#""trailing \"#.to_string()), Err("Unterminated str"));
+        let err = lex_spanned(r#"+ "a\qb""#, SourceId(0)).unwrap_err();
+        assert_eq!(err.message, "Unknown escape sequence in str");
+        assert_eq!(err.span.map(|span| span.range()), Some(4..6));
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpolation() -> Result<(), String> {
+        let result = lex(r#""{name} scored {+ a "}"} \{ok\}""#.to_string())?;
+        assert_eq!(
+            result,
+            vec![
+                Token::Interpolated(vec![
+                    Piece::Code(vec![Token::Symbol("name".into()), Token::EOF]),
+                    Piece::Text(" scored ".to_string()),
+                    Piece::Code(vec![
+                        Token::Add,
+                        Token::Symbol("a".into()),
+                        Token::Str("}".to_string()),
+                        Token::EOF,
+                    ]),
+                    Piece::Text(" {ok}".to_string()),
+                ]),
+                Token::EOF,
+            ]
+        );
+
+        assert_eq!(lex(r#""a {+ 1""#.to_string()), Err("Unterminated str"));
+        let err = lex_spanned(r#"print "a { }""#, SourceId(0)).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Expected an expression between '{' and '}' in str"
+        );
+        assert_eq!(err.span.map(|span| span.range()), Some(9..12));
+        let err = lex_spanned(r#""{1.2.3}""#, SourceId(0)).unwrap_err();
+        assert_eq!(err.span.map(|span| span.range()), Some(2..7));
+        Ok(())
+    }
+
+    #[test]
+    fn test_num_literals() -> Result<(), String> {
+        let result = lex("- 5 -5 1_000_000 5.0 2.5e3 1E-2 -0.5e+1 (-3)".to_string())?;
+        assert_eq!(
+            result,
+            vec![
+                Token::Subtract,
+                Token::Int(5),
+                Token::Int(-5),
+                Token::Int(1_000_000),
+                Token::Num(5.0),
+                Token::Num(2500.0),
+                Token::Num(0.01),
+                Token::Num(-5.0),
+                Token::LeftParen,
+                Token::Int(-3),
+                Token::RightParen,
+                Token::EOF,
+            ]
+        );
+
+        for malformed in ["1.2.3", "1e", "1_", "1__0", "1_.5", "12ab", "-1e+"] {
+            assert_eq!(
+                lex(malformed.to_string()),
+                Err("Malformed num"),
+                "{malformed}"
+            );
+        }
+        // too big for an int
+        assert_eq!(
+            lex("9223372036854775808".to_string())?[0],
+            Token::Num(9223372036854775808.0)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_indentation() -> Result<(), String> {
+        let result = lex("a\n  b\n\n    # note\n    c\n  d\ne\n".to_string())?;
+        let symbol = |name: &str| Token::Symbol(name.into());
+        assert_eq!(
+            result,
+            vec![
+                symbol("a"),
+                Token::Indent,
+                symbol("b"),
+                Token::Indent,
+                symbol("c"),
+                Token::Dedent,
+                symbol("d"),
+                Token::Dedent,
+                symbol("e"),
+                Token::Newline,
+                Token::EOF,
+            ]
+        );
+
+        // blocks opened inside a bracket close with it
+        let result = lex("(a\n  (b\n    c))\nd".to_string())?;
+        assert_eq!(
+            result,
+            vec![
+                Token::LeftParen,
+                symbol("a"),
+                Token::Indent,
+                Token::LeftParen,
+                symbol("b"),
+                Token::Indent,
+                symbol("c"),
+                Token::Dedent,
+                Token::RightParen,
+                Token::Dedent,
+                Token::RightParen,
+                Token::Newline,
+                symbol("d"),
+                Token::EOF,
+            ]
+        );
+
+        for inconsistent in ["a\n    b\n  c", "a\n\tb\n    c", "a\n  b\n \tc"] {
+            assert_eq!(
+                lex(inconsistent.to_string()),
+                Err("Inconsistent indentation"),
+                "{inconsistent:?}"
+            );
+        }
+        let err = lex_spanned("a\n    b\n  c", SourceId(0)).unwrap_err();
+        assert_eq!(err.span.map(|span| span.range()), Some(8..10));
+        Ok(())
+    }
+
+    #[test]
+    fn test_comparison_operators() -> Result<(), String> {
+        let result = lex(">= 1 <= 2 > 3 < 4".to_string())?;
+        assert_eq!(
+            result,
+            vec![
+                Token::GreaterThanOrEqual,
+                Token::Int(1),
+                Token::LessThanOrEqual,
+                Token::Int(2),
+                Token::GreaterThan,
+                Token::Int(3),
+                Token::LessThan,
+                Token::Int(4),
+                Token::EOF,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_semicolon() -> Result<(), String> {
+        let result = lex("x; \"a;b\";1".to_string())?;
+        assert_eq!(
+            result,
+            vec![
+                Token::Symbol("x".into()),
+                Token::Semicolon,
+                Token::Str("a;b".to_string()),
+                Token::Semicolon,
+                Token::Int(1),
+                Token::EOF,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash() -> Result<(), String> {
+        let result = lex("{k v}".to_string())?;
+        assert_eq!(
+            result,
+            vec![
+                Token::Hash,
+                Token::Symbol("k".into()),
+                Token::Symbol("v".into()),
+                Token::RightBrace,
+                Token::EOF,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_seq() -> Result<(), String> {
+        let result = lex("[x [1]]".to_string())?;
+        assert_eq!(
+            result,
+            vec![
+                Token::Seq,
+                Token::Symbol("x".into()),
+                Token::Seq,
+                Token::Int(1),
+                Token::RightBracket,
+                Token::RightBracket,
+                Token::EOF,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_import() -> Result<(), String> {
+        let result = lex("import \"lib.ln\"".to_string())?;
+        assert_eq!(
+            result,
+            vec![Token::Import, Token::Str("lib.ln".to_string()), Token::EOF]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_match() -> Result<(), String> {
+        let result = lex("match x; _ -> 1".to_string())?;
+        assert_eq!(
+            result,
+            vec![
+                Token::Match,
+                Token::Symbol("x".into()),
+                Token::Semicolon,
+                Token::TypeDecl("_".to_string()),
+                Token::Assign,
+                Token::Int(1),
+                Token::EOF
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipe() -> Result<(), String> {
+        let result = lex("x |> f 1 |>g".to_string())?;
+        assert_eq!(
+            result,
+            vec![
+                Token::Symbol("x".into()),
+                Token::Pipe,
+                Token::Symbol("f".into()),
+                Token::Int(1),
+                Token::Pipe,
+                Token::Symbol("g".into()),
+                Token::EOF
+            ]
+        );
+        assert_eq!(lex("set x -> 1".to_string())?[0], Token::Set);
+        // a '|' on its own is part of a symbol
+        assert_eq!(lex("a|b".to_string())?[0], Token::Symbol("a|b".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexer_iterator() {
+        let mut lexer = Lexer::new("a\n  b\nc");
+        assert_eq!(lexer.next(), Some(Ok(Token::Symbol("a".into()))));
+        assert_eq!(lexer.next_spanned(), Some(Ok((Token::Indent, 1..2))));
+        let rest: Vec<_> = lexer.collect();
+        assert_eq!(
+            rest,
+            vec![
+                Ok(Token::Symbol("b".into())),
+                Ok(Token::Dedent),
+                Ok(Token::Symbol("c".into())),
+                Ok(Token::EOF)
+            ]
+        );
+
+        // the tokens before an error are still given, and nothing after it
+        let lexed: Vec<_> = Lexer::new("1 \"a\\q\" 2").collect();
+        assert_eq!(
+            lexed,
+            vec![
+                Ok(Token::Int(1)),
+                Err(LexError {
+                    message: UNKNOWN_ESCAPE,
+                    range: 4..6
+                })
+            ]
+        );
+    }
 }
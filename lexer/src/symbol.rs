@@ -0,0 +1,147 @@
+//! Interned names. Each distinct name is stored once, in a table shared by the
+//! whole program, and a `Symbol` is its index in that table, so that comparing or
+//! hashing names, e.g. to look a variable up, works on an integer rather than on the
+//! characters of the name.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::cmp::Ordering;
+use core::fmt;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicBool, Ordering as Atomic};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// The symbol for `name`, which is added to the table the first time it is seen.
+    pub fn intern(name: &str) -> Symbol {
+        TABLE.with(|table| {
+            if let Some(&id) = table.ids.get(name) {
+                return Symbol(id);
+            }
+            // names are never removed, so they can be handed out for as long as the
+            // program runs
+            let name: &'static str = Box::leak(name.into());
+            let id = table.names.len() as u32;
+            table.names.push(name);
+            table.ids.insert(name, id);
+            Symbol(id)
+        })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        TABLE.with(|table| table.names[self.0 as usize])
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+/// Symbols are ordered by their names, so that anything sorted by name comes out the
+/// same however the names were interned.
+impl Ord for Symbol {
+    fn cmp(&self, other: &Symbol) -> Ordering {
+        match self.0 == other.0 {
+            true => Ordering::Equal,
+            false => self.as_str().cmp(other.as_str()),
+        }
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Symbol) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+struct Table {
+    names: Vec<&'static str>,
+    ids: BTreeMap<&'static str, u32>,
+}
+
+/// The table behind a spin lock, which works without `std`. It is only held for a
+/// lookup or an insertion.
+struct Locked {
+    locked: AtomicBool,
+    table: UnsafeCell<Table>,
+}
+
+// the table is only reached through `with`, which holds the lock
+unsafe impl Sync for Locked {}
+
+impl Locked {
+    fn with<T>(&self, f: impl FnOnce(&mut Table) -> T) -> T {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Atomic::Acquire, Atomic::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        // SAFETY: the lock is held, so nothing else has the table
+        let result = f(unsafe { &mut *self.table.get() });
+        self.locked.store(false, Atomic::Release);
+        result
+    }
+}
+
+static TABLE: Locked = Locked {
+    locked: AtomicBool::new(false),
+    table: UnsafeCell::new(Table {
+        names: Vec::new(),
+        ids: BTreeMap::new(),
+    }),
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let a = Symbol::intern("interned-name");
+        assert_eq!(a, Symbol::intern("interned-name"));
+        assert_ne!(a, Symbol::intern("another-name"));
+        assert_eq!(a.as_str(), "interned-name");
+        assert_eq!(a, "interned-name");
+        assert!(Symbol::intern("b") > Symbol::intern("a"));
+    }
+}
@@ -0,0 +1,106 @@
+//! A debugger that steps through a program as the interpreter runs it, see
+//! `linus --debug`. Before each expression it shows the expression and what is in
+//! scope, and waits for a command:
+//!
+//! - `step` (or `s`, or an empty line) runs to the next expression
+//! - `next` (or `n`) runs to the next expression that isn't nested in this one
+//! - `continue` (or `c`) runs until a breakpoint, or the end
+//! - `break name` (or `b name`) stops whenever the function `name` is called, and
+//!   `delete name` stops doing so
+//!
+//! Once the commands run out, the program runs to the end.
+
+use std::collections::BTreeSet;
+
+use environment::Type;
+use interpreter::{BoxedSink, BoxedSource, Interpreter, Observer};
+use parser::{dump, Expr};
+
+pub struct Debugger {
+    commands: BoxedSource,
+    output: BoxedSink,
+    /// Where to stop next: at any expression, at one nested no deeper than a depth,
+    /// or only at breakpoints.
+    stop: Stop,
+    breakpoints: BTreeSet<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stop {
+    Step,
+    Next(usize),
+    Continue,
+}
+
+impl Debugger {
+    /// A debugger that reads commands from `commands` and shows where it stopped in
+    /// `output`, as diagnostic output so that it stays apart from the program's own.
+    pub fn new(commands: BoxedSource, output: BoxedSink) -> Debugger {
+        Debugger {
+            commands,
+            output,
+            stop: Stop::Step,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    fn show(&mut self, line: &str) {
+        self.output.eprint(&Type::str(line));
+    }
+
+    /// Shows where the program stopped, then takes commands until one resumes it.
+    fn pause(&mut self, interpreter: &Interpreter, at: &str, depth: usize) {
+        self.show(at);
+        for (name, value) in interpreter.bindings() {
+            self.show(&format!("  {name} = {value}"));
+        }
+        loop {
+            self.output.prompt("(debug) ");
+            let Ok(Some(line)) = self.commands.read_line() else {
+                self.stop = Stop::Continue;
+                self.breakpoints.clear();
+                return;
+            };
+            let (command, arg) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            match (command, arg.trim()) {
+                ("" | "s" | "step", _) => self.stop = Stop::Step,
+                ("n" | "next", _) => self.stop = Stop::Next(depth),
+                ("c" | "continue", _) => self.stop = Stop::Continue,
+                ("b" | "break", name) if !name.is_empty() => {
+                    self.breakpoints.insert(name.to_string());
+                    self.show(&format!("Stopping whenever {name} is called"));
+                    continue;
+                }
+                ("d" | "delete", name) if self.breakpoints.remove(name) => {
+                    self.show(&format!("No longer stopping when {name} is called"));
+                    continue;
+                }
+                _ => {
+                    self.show("Commands: step, next, continue, break name, delete name");
+                    continue;
+                }
+            }
+            return;
+        }
+    }
+}
+
+impl Observer for Debugger {
+    fn before(&mut self, interpreter: &Interpreter, expr: &Expr, depth: usize) {
+        let stops = match self.stop {
+            Stop::Step => true,
+            Stop::Next(below) => depth <= below,
+            Stop::Continue => false,
+        };
+        if stops {
+            self.pause(interpreter, &dump::expr_sexpr(expr), depth);
+        }
+    }
+
+    fn call(&mut self, interpreter: &Interpreter, name: &str) {
+        if self.breakpoints.contains(name) {
+            // there is no expression to go past yet, so `next` is the same as `step`
+            self.pause(interpreter, &format!("Called {name}"), usize::MAX);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+pub use ::diagnostics::{codes, Diagnostic, Severity};
+use parser::{Expr, Pattern};
+use span::{SourceId, SourceMap, Span};
+
+use crate::fixes;
+use crate::incremental::Document;
+use crate::symbols::occurrences;
+
+/// `file:line:col: message`, or just the message if the diagnostic has no span.
+pub fn summary(diagnostic: &Diagnostic, sources: &SourceMap) -> String {
+    match diagnostic.span {
+        Some(span) => format!("{}: {}", sources.describe(span), diagnostic.message),
+        None => diagnostic.message.clone(),
+    }
+}
+
+/// Warnings for code that runs but is probably not what was meant: anything with a
+/// suggested fix (such as an unused `def`), a `def` that shadows a name already bound
+/// where it is, and a `match` arm that comes after one that fits any value. Their
+/// spans are in `id`, which `source` was added to the `SourceMap` as.
+pub fn lint(source: &str, id: SourceId) -> Vec<Diagnostic> {
+    let mut warnings: Vec<Diagnostic> = fixes::suggest(source)
+        .into_iter()
+        .map(|fix| {
+            Diagnostic::warning(fix.code, fix.message)
+                .with_span(Span::new(id, fix.span))
+                .with_hint("fix it with `linus check --apply-fixes`")
+        })
+        .collect();
+
+    let document = Document::new(source.to_string());
+    for occurrence in occurrences(&document) {
+        if occurrence.shadows {
+            warnings.push(
+                Diagnostic::warning(
+                    codes::SHADOWED,
+                    format!("'{}' shadows an earlier definition", occurrence.name),
+                )
+                .with_span(Span::new(id, occurrence.span)),
+            );
+        }
+    }
+
+    let exprs = lexer::lex_spanned(source, id)
+        .ok()
+        .and_then(|tokens| parser::parse_spanned(&tokens, id).ok())
+        .unwrap_or_default();
+    let mut unreachable = vec![];
+    for expr in &exprs {
+        unreachable_arms(expr, &mut unreachable);
+    }
+    for span in unreachable {
+        warnings.push(
+            Diagnostic::warning(
+                codes::UNREACHABLE,
+                "this arm is never picked, since an arm before it fits any value",
+            )
+            .with_span(span)
+            .with_hint("remove it, or move it above the arm that fits any value"),
+        );
+    }
+
+    warnings
+}
+
+/// The spans of the `match` arms in `expr` that come after an arm whose pattern is
+/// `_` or a name.
+fn unreachable_arms(expr: &Expr, found: &mut Vec<Span>) {
+    match expr {
+        Expr::Match { value, arms } => {
+            unreachable_arms(value, found);
+            let mut reached = true;
+            for arm in arms {
+                if !reached {
+                    found.extend(arm.span);
+                }
+                reached &= !matches!(arm.pattern, Pattern::Wildcard | Pattern::Name(_));
+                unreachable_arms(&arm.body, found);
+            }
+        }
+        Expr::Assignment { expr, .. } | Expr::Set { expr, .. } => unreachable_arms(expr, found),
+        Expr::Function { params, body, .. } | Expr::Lambda { params, body } => {
+            for default in params.iter().filter_map(|param| param.default.as_ref()) {
+                unreachable_arms(default, found);
+            }
+            unreachable_arms(body, found);
+        }
+        Expr::FunctionCall { operand: exprs, .. }
+        | Expr::Do { exprs }
+        | Expr::Seq { items: exprs }
+        | Expr::Interpolation { parts: exprs } => {
+            for expr in exprs {
+                unreachable_arms(expr, found);
+            }
+        }
+        Expr::Let { bindings, body } => {
+            for binding in bindings {
+                unreachable_arms(&binding.expr, found);
+            }
+            unreachable_arms(body, found);
+        }
+        Expr::Loop {
+            bindings,
+            condition,
+            recur,
+            result,
+        } => {
+            for binding in bindings {
+                unreachable_arms(&binding.expr, found);
+            }
+            unreachable_arms(condition, found);
+            for expr in recur {
+                unreachable_arms(expr, found);
+            }
+            unreachable_arms(result, found);
+        }
+        Expr::Try {
+            body,
+            catch,
+            finally,
+        } => {
+            unreachable_arms(body, found);
+            if let Some(catch) = catch {
+                unreachable_arms(&catch.body, found);
+            }
+            if let Some(finally) = finally {
+                unreachable_arms(finally, found);
+            }
+        }
+        Expr::Hash { entries } => {
+            for (key, value) in entries {
+                unreachable_arms(key, found);
+                unreachable_arms(value, found);
+            }
+        }
+        Expr::Throw { value } | Expr::Named { value, .. } => unreachable_arms(value, found),
+        Expr::Literal { .. }
+        | Expr::Operator { .. }
+        | Expr::Variable { .. }
+        | Expr::Import { .. } => {}
+    }
+}
@@ -0,0 +1,225 @@
+use std::ops::Range;
+
+use lexer::{Piece, Token};
+use parser::Expr;
+
+use crate::diagnostics::codes;
+use crate::incremental::{split_forms, Document};
+use crate::symbols::{occurrences, symbol_spans};
+
+/// A suggested edit for a diagnostic: replace the byte range `span` with `replacement`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    /// The code of the warning the fix is for, one of `codes`.
+    pub code: &'static str,
+    pub message: String,
+    pub span: Range<usize>,
+    pub replacement: String,
+}
+
+/// Looks for problems in `source` that have a mechanical fix.
+pub fn suggest(source: &str) -> Vec<Fix> {
+    let mut fixes = missing_assign(source);
+    fixes.extend(unused_definitions(source));
+    fixes.extend(not_equal(source));
+    fixes.sort_by_key(|fix| fix.span.start);
+    fixes
+}
+
+/// Applies `fixes` to `source`, skipping any fix that overlaps one already applied.
+pub fn apply(source: &str, fixes: &[Fix]) -> String {
+    let mut fixes: Vec<&Fix> = fixes.iter().collect();
+    fixes.sort_by_key(|fix| fix.span.start);
+
+    let mut fixed = String::new();
+    let mut idx = 0;
+    for fix in fixes {
+        if fix.span.start < idx {
+            continue;
+        }
+        fixed.push_str(&source[idx..fix.span.start]);
+        fixed.push_str(&fix.replacement);
+        idx = fix.span.end;
+    }
+    fixed.push_str(&source[idx..]);
+    fixed
+}
+
+/// `def x: num 1` is missing the `->` between the type and the expression.
+fn missing_assign(source: &str) -> Vec<Fix> {
+    let mut fixes = vec![];
+    let mut line_start = 0;
+
+    for line in source.split_inclusive('\n') {
+        let line_end = line_start + line.trim_end().len();
+        let indent = line.len() - line.trim_start().len();
+
+        if let Some(delim) = line[indent..]
+            .strip_prefix("def ")
+            .and_then(|rest| rest.find(':'))
+        {
+            let after_delim = line_start + indent + "def ".len() + delim + 1;
+            let type_start = line_end - source[after_delim..line_end].trim_start().len();
+            let type_end = source[type_start..line_end]
+                .find(char::is_whitespace)
+                .map_or(line_end, |idx| type_start + idx);
+            let after_type = source[type_end..line_end].trim_start();
+
+            if type_end > type_start
+                && !after_type.is_empty()
+                && !after_type.starts_with("->")
+                && !after_type.starts_with('#')
+                && !declares_params(after_type)
+            {
+                fixes.push(Fix {
+                    code: codes::MISSING_ASSIGN,
+                    message: "missing '->' after the declared type".to_string(),
+                    span: type_end..type_end,
+                    replacement: " ->".to_string(),
+                });
+            }
+        }
+        line_start += line.len();
+    }
+
+    fixes
+}
+
+/// Whether `text` after a def's type is a list of `name: type` parameters, each
+/// with an optional `= default`, either ending in `->` or continuing on the next
+/// line.
+fn declares_params(text: &str) -> bool {
+    let params = text.split("->").next().unwrap_or_default();
+    let params = params
+        .split('#')
+        .next()
+        .unwrap_or_default()
+        .replace(':', " : ")
+        .replace('=', " = ");
+    let words: Vec<&str> = params.split_whitespace().collect();
+    let mut idx = 0;
+    while idx < words.len() {
+        match words.get(idx..idx + 3) {
+            Some([name, ":", type_decl]) if *name != ":" && *type_decl != ":" => idx += 3,
+            _ => return false,
+        }
+        // a default runs up to the name of the next parameter
+        if words.get(idx) == Some(&"=") {
+            idx += 2;
+            while idx < words.len() && words.get(idx + 1) != Some(&":") {
+                idx += 1;
+            }
+        }
+    }
+    !words.is_empty()
+}
+
+/// A global `def` whose name is never referenced outside of it, and isn't a test,
+/// can be removed along with its body. The comments and blank lines after it stay.
+fn unused_definitions(source: &str) -> Vec<Fix> {
+    let document = Document::new(source.to_string());
+    // a use in a form that doesn't parse can't be told apart, so nothing is removed
+    if document.errors().next().is_some() {
+        return vec![];
+    }
+    let occurrences = occurrences(&document);
+    let interpolated = match lexer::lex(source.to_string()) {
+        Ok(tokens) => interpolated_names(&tokens),
+        Err(_) => return vec![],
+    };
+    let mut fixes = vec![];
+
+    for form in split_forms(source, 0..source.len()).0 {
+        let Some(name) = defined_name(&source[form.clone()]) else {
+            continue;
+        };
+        // `linus test` is what uses a test
+        if name.starts_with("test_") {
+            continue;
+        }
+        let used = interpolated.contains(&name)
+            || occurrences.iter().any(|occurrence| {
+                occurrence.name == name
+                    && occurrence.local.is_none()
+                    && !occurrence.defines
+                    && !form.contains(&occurrence.span.start)
+            });
+
+        if !used {
+            fixes.push(Fix {
+                code: codes::UNUSED,
+                message: format!("'{name}' is defined but never used"),
+                span: form.start..code_end(source, form),
+                replacement: String::new(),
+            });
+        }
+    }
+
+    fixes
+}
+
+/// The name a top-level form defines, if it parses as a `def`.
+fn defined_name(form: &str) -> Option<String> {
+    let tokens = lexer::lex(form.to_string()).ok()?;
+    match parser::parse(&tokens).ok()?.first()? {
+        Expr::Assignment { name, .. } | Expr::Function { name, .. } => Some(name.to_string()),
+        _ => None,
+    }
+}
+
+/// The names in the code of the interpolated strs in `tokens`, which have no spans
+/// of their own, so they count as used wherever they are.
+fn interpolated_names(tokens: &[Token]) -> Vec<String> {
+    let mut names = vec![];
+    for token in tokens {
+        if let Token::Interpolated(pieces) = token {
+            for piece in pieces {
+                if let Piece::Code(code) = piece {
+                    names.extend(code.iter().filter_map(|token| match token {
+                        Token::Symbol(name) => Some(name.to_string()),
+                        _ => None,
+                    }));
+                    names.extend(interpolated_names(code));
+                }
+            }
+        }
+    }
+    names
+}
+
+/// The end of the last line of `form` with code on it.
+fn code_end(source: &str, form: Range<usize>) -> usize {
+    let mut end = form.start;
+    let mut line_start = form.start;
+    for line in source[form].split_inclusive('\n') {
+        let code = line.trim_start();
+        if !code.is_empty() && !code.starts_with('#') {
+            end = line_start + line.len();
+        }
+        line_start += line.len();
+    }
+    end
+}
+
+/// `!= a b`: there is no `!=`, so it is read as a name that isn't defined, unless
+/// the program defines it. `not $ = a b` is what it means.
+fn not_equal(source: &str) -> Vec<Fix> {
+    let spans = symbol_spans(source);
+    if spans
+        .windows(2)
+        .any(|pair| pair[0].0 == "def" && pair[1].0 == "!=")
+    {
+        return vec![];
+    }
+    spans
+        .into_iter()
+        // only where it is called, rather than passed as `(!=)`
+        .filter(|(symbol, span)| symbol == "!=" && source[span.end..].starts_with([' ', '\t']))
+        .map(|(_, span)| Fix {
+            code: codes::NOT_EQUAL,
+            message: "there is no '!=' operator; use 'not' with '='".to_string(),
+            span,
+            replacement: "not $ =".to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,166 @@
+//! `linus fmt`: rewrites source in one canonical layout.
+//!
+//! Each line is indented four spaces for every block it is in, tokens are separated
+//! by one space (none inside brackets, before `:` or `;`, or after `\`), comments
+//! start with `# ` and go one space after the code they follow, or on their own line
+//! at the indentation of the code below them, and runs of blank lines become one.
+//! Block comments, and the space around them, are left as they are.
+//! Which lines tokens are on is left as written, as is how each token is spelled.
+
+use lexer::{Spanned, Token};
+use span::SourceId;
+
+use crate::diagnostics::{codes, Diagnostic};
+
+const INDENT: &str = "    ";
+
+/// `source` formatted, or why it can't be: it must lex and parse, so that the
+/// formatter never has to guess what a broken program meant. Spans are in `id`.
+pub fn format(source: &str, id: SourceId) -> Result<String, Vec<Diagnostic>> {
+    let tokens = lexer::lex_spanned(source, id).map_err(|err| vec![err])?;
+    parser::parse_spanned(&tokens, id)?;
+
+    let mut formatted = String::new();
+    let mut depth = 0;
+    let mut end = 0;
+    let mut previous: Option<&Token> = None;
+    for Spanned { token, location } in &tokens {
+        match token {
+            Token::Indent => depth += 1,
+            Token::Dedent => depth -= 1,
+            Token::Newline | Token::EOF => {}
+            token => {
+                let gap = &source[end..location.range.start];
+                match previous {
+                    _ if gap.contains("#|") => keep_block_comments(&mut formatted, gap, depth),
+                    Some(previous) if !gap.contains('\n') => {
+                        if spaced(previous, token) {
+                            formatted.push(' ');
+                        }
+                    }
+                    _ => {
+                        between_lines(&mut formatted, gap, depth, false);
+                        push_indent(&mut formatted, depth);
+                    }
+                }
+                formatted.push_str(&source[location.range.clone()]);
+                end = location.range.end;
+                previous = Some(token);
+            }
+        }
+    }
+    match &source[end..] {
+        rest if rest.contains("#|") => {
+            formatted.push_str(rest.trim_end());
+            end_line(&mut formatted);
+        }
+        rest => between_lines(&mut formatted, rest, 0, true),
+    }
+
+    // the formatted source must be the same program, token for token
+    let relexed = lexer::lex_spanned(&formatted, id).map_err(|err| vec![err])?;
+    let unchanged = tokens
+        .iter()
+        .map(|spanned| &spanned.token)
+        .eq(relexed.iter().map(|spanned| &spanned.token));
+    if !unchanged {
+        return Err(vec![Diagnostic::error(
+            codes::FORMAT,
+            "Could not format the file without changing what it means",
+        )]);
+    }
+    Ok(formatted)
+}
+
+/// Adds what was between the last token of one line and the first of a later one,
+/// or the end of the source: the comment at the end of the first line, and the
+/// comments and blank lines in between, with comments indented `depth` blocks deep.
+fn between_lines(formatted: &mut String, gap: &str, depth: usize, at_end: bool) {
+    let mut lines: Vec<&str> = gap.split('\n').collect();
+    if !at_end {
+        // the indentation of the next line, which is replaced
+        lines.pop();
+    }
+    let mut lines = lines.into_iter();
+    if !formatted.is_empty() {
+        if let Some(comment) = lines.next().and_then(comment) {
+            formatted.push(' ');
+            formatted.push_str(&comment);
+        }
+    }
+
+    // blank lines at the start and the end of the source are dropped
+    let mut blank = false;
+    for line in lines {
+        match comment(line) {
+            Some(comment) => {
+                end_line(formatted);
+                if blank {
+                    formatted.push('\n');
+                }
+                push_indent(formatted, depth);
+                formatted.push_str(&comment);
+                blank = false;
+            }
+            None => blank = !formatted.is_empty(),
+        }
+    }
+    end_line(formatted);
+    if blank && !at_end {
+        formatted.push('\n');
+    }
+}
+
+/// Adds a gap between two tokens that has block comments in it as it was written,
+/// since a block comment may be laid out by hand, apart from the indentation of the
+/// line after it.
+fn keep_block_comments(formatted: &mut String, gap: &str, depth: usize) {
+    let gap = gap.trim_end_matches([' ', '\t']);
+    formatted.push_str(gap);
+    match gap.ends_with('\n') {
+        true => push_indent(formatted, depth),
+        false => formatted.push(' '),
+    }
+}
+
+fn end_line(formatted: &mut String) {
+    if !formatted.is_empty() && !formatted.ends_with('\n') {
+        formatted.push('\n');
+    }
+}
+
+fn push_indent(formatted: &mut String, depth: usize) {
+    for _ in 0..depth {
+        formatted.push_str(INDENT);
+    }
+}
+
+/// The comment on `line`, which holds nothing else, as `# text`. A `#!` line is
+/// left as it is, so that a script can still say what runs it.
+fn comment(line: &str) -> Option<String> {
+    let line = line.trim();
+    let text = line.strip_prefix('#')?;
+    if text.starts_with('!') {
+        return Some(line.to_string());
+    }
+    // a comment that is indented past its `#` stays that way, e.g. to show code
+    match text.trim_end() {
+        "" => Some("#".to_string()),
+        text if text.starts_with([' ', '\t']) => Some(format!("#{text}")),
+        text => Some(format!("# {text}")),
+    }
+}
+
+/// Whether a space goes between two tokens on the same line.
+fn spaced(previous: &Token, next: &Token) -> bool {
+    let opens = matches!(
+        previous,
+        Token::LeftParen | Token::Seq | Token::Hash | Token::AnonFn
+    );
+    let closes = matches!(
+        next,
+        Token::RightParen | Token::RightBracket | Token::RightBrace
+    );
+    let attaches = matches!(next, Token::TypeDelim | Token::Semicolon);
+    !opens && !closes && !attaches
+}
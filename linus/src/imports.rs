@@ -0,0 +1,160 @@
+//! Imports are resolved in a pass of their own between parsing and macro expansion.
+//!
+//! `import "lib.ln"` is replaced by the top-level definitions of `lib.ln`, found
+//! relative to the file that imports it: its `def`s, `defmacro`s, and whatever it
+//! imports in turn. Everything else it does at the top level is left out, so a
+//! library can try itself out without the files that import it doing the same.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use interpreter::Capabilities;
+use lexer::{Spanned, Token};
+use parser::Expr;
+use span::{SourceId, SourceMap, Span};
+
+use crate::diagnostics::{codes, Diagnostic};
+use crate::pipeline::LinusError;
+
+/// The files being imported and those that already have been during one run.
+pub(crate) struct Importer<'a> {
+    sources: &'a mut SourceMap,
+    /// The file being resolved and the files that import it, outermost first, each
+    /// as found on disk and as written.
+    importing: Vec<(PathBuf, String)>,
+    /// A file imported by two others is only brought in once.
+    imported: HashSet<PathBuf>,
+    /// Without file system access nothing can be imported, as in the sandbox.
+    capabilities: Capabilities,
+}
+
+impl<'a> Importer<'a> {
+    /// An importer for the file at `path`, adding each file it imports to `sources`,
+    /// which only reads files when `capabilities` allow it.
+    pub(crate) fn new(
+        sources: &'a mut SourceMap,
+        path: &str,
+        capabilities: Capabilities,
+    ) -> Importer<'a> {
+        let importing = match capabilities.fs.then(|| fs::canonicalize(path)) {
+            Some(Ok(canonical)) => vec![(canonical, path.to_string())],
+            // there is no file behind a REPL input, so it can't be imported again
+            _ => vec![],
+        };
+        Importer {
+            sources,
+            importing,
+            imported: HashSet::new(),
+            capabilities,
+        }
+    }
+
+    /// Replaces each import in `ast`, which was parsed from `tokens` of the file at
+    /// `path`, with the definitions of the file it names.
+    pub(crate) fn resolve(
+        &mut self,
+        ast: Vec<Expr>,
+        tokens: &[Spanned],
+        path: &str,
+        id: SourceId,
+    ) -> Result<Vec<Expr>, LinusError> {
+        let mut spans = import_spans(tokens, id).into_iter();
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+        let mut resolved = vec![];
+        for expr in ast {
+            match expr {
+                Expr::Import { path: import } => {
+                    let span = spans.next();
+                    let defs = self.import(&dir.join(&import), span)?;
+                    resolved.extend(defs);
+                }
+                expr => resolved.push(expr),
+            }
+        }
+        Ok(resolved)
+    }
+
+    fn import(&mut self, path: &Path, span: Option<Span>) -> Result<Vec<Expr>, LinusError> {
+        let shown = path.to_string_lossy().into_owned();
+        let diagnostic = |message: String| {
+            let diagnostic = Diagnostic::error(codes::IMPORT, message);
+            match span {
+                Some(span) => diagnostic.with_span(span),
+                None => diagnostic,
+            }
+        };
+        let unreadable = |err: io::Error| {
+            LinusError::Import(diagnostic(format!("Could not import '{shown}': {err}")))
+        };
+
+        if !self.capabilities.fs {
+            return Err(LinusError::Import(diagnostic(format!(
+                "Could not import '{shown}': {SANDBOXED}"
+            ))));
+        }
+        let canonical = fs::canonicalize(path).map_err(unreadable)?;
+        if let Some(start) = self
+            .importing
+            .iter()
+            .position(|(file, _)| *file == canonical)
+        {
+            let mut cycle: Vec<&str> = self.importing[start..]
+                .iter()
+                .map(|(_, shown)| shown.as_str())
+                .collect();
+            cycle.push(&shown);
+            return Err(LinusError::Import(
+                diagnostic(format!("Import cycle: {}", cycle.join(" -> "))).with_hint(
+                    "move what the files share into another file that each of them imports",
+                ),
+            ));
+        }
+        if !self.imported.insert(canonical.clone()) {
+            return Ok(vec![]);
+        }
+
+        let string = fs::read_to_string(&canonical).map_err(unreadable)?;
+        let id = self.sources.add(&shown, string.clone());
+        let tokens = lexer::lex_spanned(&string, id).map_err(LinusError::Lex)?;
+        let ast = parser::parse_spanned(&tokens, id).map_err(LinusError::Parse)?;
+
+        self.importing.push((canonical, shown.clone()));
+        let ast = self.resolve(ast, &tokens, &shown, id)?;
+        self.importing.pop();
+        Ok(ast.into_iter().filter(is_definition).collect())
+    }
+}
+
+/// The same as the builtins that need the file system say in the sandbox.
+const SANDBOXED: &str = "Operation not permitted in sandbox";
+
+/// The spans of the paths of the imports in `tokens`, in order.
+fn import_spans(tokens: &[Spanned], id: SourceId) -> Vec<Span> {
+    tokens
+        .windows(2)
+        .filter_map(|window| match window {
+            [Spanned {
+                token: Token::Import,
+                ..
+            }, Spanned {
+                token: Token::Str(_),
+                location,
+            }] => Some(Span::new(id, location.range.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_definition(expr: &Expr) -> bool {
+    match expr {
+        Expr::Assignment { .. } | Expr::Function { .. } => true,
+        Expr::FunctionCall {
+            operator: Token::Symbol(name),
+            ..
+        } => name == "defmacro",
+        _ => false,
+    }
+}
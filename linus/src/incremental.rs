@@ -0,0 +1,219 @@
+use std::ops::Range;
+
+use lexer::Token;
+use parser::Expr;
+
+/// A top-level form (a line starting in the first column plus any indented,
+/// blank, or comment lines that follow it) along with its lexed and parsed state.
+struct Form {
+    start: usize,
+    len: usize,
+    tokens: Vec<Token>,
+    exprs: Vec<Expr>,
+    errors: Vec<String>,
+}
+
+impl Form {
+    fn build(source: &str, start: usize, len: usize) -> Form {
+        let mut form = Form {
+            start,
+            len,
+            tokens: vec![],
+            exprs: vec![],
+            errors: vec![],
+        };
+
+        match lexer::lex(source[start..start + len].to_string()) {
+            Ok(tokens) => form.tokens = tokens,
+            Err(err) => {
+                form.errors.push(err.to_string());
+                return form;
+            }
+        }
+
+        match parser::parse(&form.tokens) {
+            Ok(mut exprs) => {
+                // every form ends in EOF, which the parser turns into a literal
+                if let Some(Expr::Literal { token: Token::EOF }) = exprs.last() {
+                    exprs.pop();
+                }
+                form.exprs = exprs
+            }
+            Err(errs) => form.errors = errs.lines().map(String::from).collect(),
+        }
+
+        form
+    }
+
+    fn end(&self) -> usize {
+        self.start + self.len
+    }
+}
+
+/// Source text kept split into top-level forms so that an edit only re-lexes and
+/// re-parses the forms it touches rather than the whole file.
+pub struct Document {
+    text: String,
+    forms: Vec<Form>,
+}
+
+impl Document {
+    pub fn new(text: String) -> Document {
+        let forms = split_forms(&text, 0..text.len())
+            .0
+            .into_iter()
+            .map(|range| Form::build(&text, range.start, range.len()))
+            .collect();
+
+        Document { text, forms }
+    }
+
+    /// Replaces the byte `range` of the text with `replacement`, re-processing the
+    /// forms around the edit. Returns the indices of the forms that were rebuilt.
+    pub fn edit(
+        &mut self,
+        range: Range<usize>,
+        replacement: &str,
+    ) -> Result<Range<usize>, &'static str> {
+        if range.start > range.end
+            || range.end > self.text.len()
+            || !self.text.is_char_boundary(range.start)
+            || !self.text.is_char_boundary(range.end)
+        {
+            return Err("Edit range is outside of the document");
+        }
+
+        // an edit can join a form to its neighbours (e.g. indenting the first line of a
+        // form or deleting the newline before one), so rebuild one form on either side
+        let first = self
+            .forms
+            .iter()
+            .position(|form| form.end() >= range.start)
+            .unwrap_or(self.forms.len())
+            .saturating_sub(1);
+        let last = self
+            .forms
+            .iter()
+            .rposition(|form| form.start <= range.end)
+            .map_or(self.forms.len(), |idx| (idx + 2).min(self.forms.len()));
+
+        let region_start = self.forms.get(first).map_or(0, |form| form.start);
+        let region_end = match last {
+            0 => 0,
+            n => self.forms[n - 1].end(),
+        }
+        .max(range.end);
+
+        self.text.replace_range(range.clone(), replacement);
+
+        let delta = replacement.len() as isize - range.len() as isize;
+        let mut region_end = (region_end as isize + delta) as usize;
+        let (mut ranges, unterminated) = split_forms(&self.text, region_start..region_end);
+
+        // an unterminated string or block comment now runs past the region, so every
+        // later form changed
+        let last = if unterminated {
+            region_end = self.text.len();
+            ranges = split_forms(&self.text, region_start..region_end).0;
+            self.forms.len()
+        } else {
+            last
+        };
+
+        for form in &mut self.forms[last..] {
+            form.start = (form.start as isize + delta) as usize;
+        }
+
+        let rebuilt: Vec<Form> = ranges
+            .into_iter()
+            .map(|range| Form::build(&self.text, range.start, range.len()))
+            .collect();
+        let rebuilt_len = rebuilt.len();
+
+        self.forms.splice(first..last, rebuilt);
+
+        Ok(first..first + rebuilt_len)
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The tokens of the whole document, in the same order `lexer::lex` would produce.
+    pub fn tokens(&self) -> Vec<&Token> {
+        self.forms
+            .iter()
+            .flat_map(|form| form.tokens.iter().filter(|token| **token != Token::EOF))
+            .chain(std::iter::once(&Token::EOF))
+            .collect()
+    }
+
+    /// The byte range of each form that parses, along with what it parses to.
+    pub(crate) fn parsed_forms(&self) -> impl Iterator<Item = (Range<usize>, &[Expr])> {
+        self.forms
+            .iter()
+            .filter(|form| form.errors.is_empty())
+            .map(|form| (form.start..form.end(), form.exprs.as_slice()))
+    }
+
+    pub fn exprs(&self) -> impl Iterator<Item = &Expr> {
+        self.forms.iter().flat_map(|form| form.exprs.iter())
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &str> {
+        self.forms
+            .iter()
+            .flat_map(|form| form.errors.iter().map(String::as_str))
+    }
+}
+
+/// Splits `range` of `source` into the byte ranges of its top-level forms. Text
+/// before the first top-level line (blank lines, comments) is kept as its own form.
+/// Also returns whether the range ends inside an unterminated string or block
+/// comment.
+pub(crate) fn split_forms(source: &str, range: Range<usize>) -> (Vec<Range<usize>>, bool) {
+    let mut forms = vec![];
+    let mut form_start = range.start;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut in_comment = false;
+    // how many block comments are open; a line in one doesn't start a form
+    let mut in_block = 0;
+    let mut line_start = true;
+
+    for (idx, c) in source[range.clone()].char_indices() {
+        let idx = idx + range.start;
+
+        if line_start && !in_string && !c.is_whitespace() && c != '#' && idx > form_start {
+            forms.push(form_start..idx);
+            form_start = idx;
+        }
+        line_start = false;
+
+        match c {
+            _ if escaped => escaped = false,
+            '\n' => {
+                in_comment = false;
+                line_start = !in_string && in_block == 0;
+            }
+            '\\' if in_string => escaped = true,
+            '"' if !in_comment && in_block == 0 => in_string = !in_string,
+            // the character after `#|` or `|#` is skipped like an escaped one
+            '#' if !in_string && !in_comment && source[idx..].starts_with("#|") => {
+                in_block += 1;
+                escaped = true;
+            }
+            '|' if in_block > 0 && source[idx..].starts_with("|#") => {
+                in_block -= 1;
+                escaped = true;
+            }
+            '#' if !in_string && in_block == 0 => in_comment = true,
+            _ => {}
+        }
+    }
+
+    if form_start < range.end {
+        forms.push(form_start..range.end);
+    }
+    (forms, in_string || in_block > 0)
+}
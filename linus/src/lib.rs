@@ -1,22 +1,145 @@
 use std::fs;
 
+use lexer::{Spanned, Token};
+use span::{SourceId, SourceMap, Span};
+
+pub mod debugger;
+pub mod diagnostics;
+pub mod fixes;
+pub mod format;
+mod imports;
+pub mod incremental;
+mod pipeline;
+pub mod repl;
+pub mod symbols;
+pub mod testing;
+pub mod tracer;
+
+pub use self::diagnostics::{codes, Diagnostic, Severity};
+pub use pipeline::{run, LinusError, Pipeline};
+
 pub struct Source {
+    /// The file the source was read from, used to label diagnostics.
+    pub path: String,
     pub string: String,
 }
 
 impl Source {
-    pub fn build(mut args: impl Iterator<Item = String>) -> Result<Source, &'static str> {
+    pub fn build(mut args: impl Iterator<Item = String>) -> Result<Source, String> {
         args.next();
 
         // the file path is the first argument
         let file_path = match args.next() {
             Some(arg) => arg,
-            None => return Err("No source file"),
+            None => return Err("No source file".to_string()),
         };
 
+        Source::from_path(&file_path)
+    }
+
+    pub fn from_path(file_path: &str) -> Result<Source, String> {
         // open and read the file into a string
-        let string = fs::read_to_string(file_path).expect("Could not read file");
+        let string = fs::read_to_string(file_path)
+            .map_err(|err| format!("Could not read file '{file_path}': {err}"))?;
+
+        Ok(Source {
+            path: file_path.to_string(),
+            string,
+        })
+    }
+}
+
+/// Expands each of `args` that is a glob, such as `src/**/*.ln`, into the paths it
+/// matches, in order, and keeps the others as they are. A glob that matches nothing
+/// is an error, so that a typo doesn't check no files and pass.
+pub fn expand_paths(args: &[&String]) -> Result<Vec<String>, String> {
+    let mut paths = vec![];
+    for arg in args {
+        if !arg.contains(['*', '?', '[']) {
+            paths.push(arg.to_string());
+            continue;
+        }
+        let matches = glob::glob(arg).map_err(|err| format!("Invalid glob '{arg}': {err}"))?;
+        let before = paths.len();
+        for path in matches {
+            let path = path.map_err(|err| format!("Could not read '{arg}': {err}"))?;
+            paths.push(path.to_string_lossy().into_owned());
+        }
+        if paths.len() == before {
+            return Err(format!("No files match '{arg}'"));
+        }
+    }
+    Ok(paths)
+}
+
+/// Runs the front end (lexing, parsing, and type checking) and the lints over a source without
+/// executing it, returning every error and warning that was produced. In `strict`
+/// mode the dynamic `_` type is an error. Spans are in `id`, which the source was
+/// added to the `SourceMap` as.
+pub fn diagnose(source: &Source, id: SourceId, strict: bool) -> Vec<Diagnostic> {
+    let tokens = match lexer::lex_spanned(&source.string, id) {
+        Ok(tokens) => tokens,
+        Err(err) => return vec![err],
+    };
+
+    let mut diagnostics: Vec<Diagnostic> = match parser::parse_spanned(&tokens, id) {
+        Ok(ast) => typechecker::check(&ast).err().unwrap_or_default(),
+        Err(errs) => errs,
+    };
+
+    if strict {
+        for window in tokens.windows(3) {
+            if let [Spanned {
+                token: Token::Symbol(name),
+                ..
+            }, Spanned {
+                token: Token::TypeDelim,
+                ..
+            }, Spanned {
+                token: Token::TypeDecl(type_decl),
+                location,
+            }] = window
+            {
+                if type_decl == "_" {
+                    diagnostics.push(
+                        Diagnostic::error(
+                            codes::DYNAMIC_TYPE,
+                            format!("'{name}' is declared with the dynamic type '_', which is not allowed in strict mode"),
+                        )
+                        .with_span(Span::new(id, location.range.clone()))
+                        .with_hint("declare a type such as num, str, or bool"),
+                    );
+                }
+            }
+        }
+    }
+
+    diagnostics.extend(diagnostics::lint(&source.string, id));
+    diagnostics
+}
+
+/// The diagnostics that fail a check: the errors, and in `strict` mode the warnings
+/// too.
+pub(crate) fn failures(source: &Source, id: SourceId, strict: bool) -> Vec<Diagnostic> {
+    diagnose(source, id, strict)
+        .into_iter()
+        .filter(|diagnostic| strict || diagnostic.severity == Severity::Error)
+        .collect()
+}
+
+/// Like `diagnose`, but only succeeds if there are no errors. In `strict` mode
+/// warnings count as errors too.
+pub fn check(source: &Source, strict: bool) -> Result<(), Vec<String>> {
+    let mut sources = SourceMap::new();
+    let id = sources.add(&source.path, source.string.clone());
+    let errs: Vec<String> = failures(source, id, strict)
+        .iter()
+        .map(|diagnostic| diagnostics::summary(diagnostic, &sources))
+        .collect();
 
-        Ok(Source { string })
+    if errs.is_empty() {
+        Ok(())
+    } else {
+        Err(errs)
     }
 }
@@ -1,27 +1,457 @@
 use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
 use std::process;
 
-use linus::Source;
+use interpreter::{Capabilities, Interpreter, StdInput, StdOutput};
+use linus::debugger::Debugger;
+use linus::fixes;
+use linus::format;
+use linus::repl::{self, Repl};
+use linus::symbols;
+use linus::testing;
+use linus::tracer::Tracer;
+use linus::{Diagnostic, Pipeline, Severity, Source};
+use parser::dump::{self, Format};
+use span::SourceMap;
 
-use interpreter;
-use lexer;
-use parser;
+/// How many of the innermost calls to show when the maximum depth is exceeded.
+const TRACE_LENGTH: usize = 10;
 
 fn main() {
-    let source: Source = Source::build(env::args()).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments {err}");
+    let args: Vec<String> = env::args().collect();
+
+    match args.get(1).map(String::as_str) {
+        Some("check") => check(&args[2..]),
+        Some("fmt") => fmt(&args[2..]),
+        Some("test") => test(&args[2..]),
+        Some("repl") | None => repl(),
+        _ => {}
+    }
+
+    let mut strict = false;
+    let mut deny_warnings = false;
+    let mut keep_going = false;
+    let mut prelude = true;
+    let mut type_check = true;
+    let mut optimize = false;
+    let mut sandbox = false;
+    let mut debug = false;
+    let mut trace = false;
+    let mut max_memory = None;
+    let mut max_depth = None;
+    let mut seed = None;
+    let mut dump_tokens = false;
+    let mut dump_ast = false;
+    let mut format = Format::Sexpr;
+    let mut rest = vec![];
+    let mut options = args.into_iter();
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--strict" => strict = true,
+            "--deny-warnings" => deny_warnings = true,
+            "--keep-going" => keep_going = true,
+            "--no-prelude" => prelude = false,
+            "--no-check" => type_check = false,
+            "-O" => optimize = true,
+            "--sandbox" => sandbox = true,
+            "--debug" => debug = true,
+            "--trace" => trace = true,
+            "--tokens" => dump_tokens = true,
+            "--ast" => dump_ast = true,
+            "--format" => {
+                format = match options.next().map(|format| format.parse::<Format>()) {
+                    Some(Ok(format)) => format,
+                    _ => {
+                        eprintln!("Problem parsing arguments --format expects json or sexpr");
+                        process::exit(1);
+                    }
+                }
+            }
+            "--seed" => {
+                seed = match options.next().map(|seed| seed.parse::<u64>()) {
+                    Some(Ok(seed)) => Some(seed),
+                    _ => {
+                        eprintln!("Problem parsing arguments --seed expects a number");
+                        process::exit(1);
+                    }
+                }
+            }
+            "--max-memory" => {
+                max_memory = match options.next().map(|bytes| bytes.parse::<usize>()) {
+                    Some(Ok(bytes)) => Some(bytes),
+                    _ => {
+                        eprintln!(
+                            "Problem parsing arguments --max-memory expects a number of bytes"
+                        );
+                        process::exit(1);
+                    }
+                }
+            }
+            "--max-depth" => {
+                max_depth = match options.next().map(|depth| depth.parse::<usize>()) {
+                    Some(Ok(depth)) => Some(depth),
+                    _ => {
+                        eprintln!("Problem parsing arguments --max-depth expects a number");
+                        process::exit(1);
+                    }
+                }
+            }
+            _ => rest.push(arg),
+        }
+    }
+
+    if debug && trace {
+        eprintln!("Problem parsing arguments --debug and --trace can't be used together");
+        process::exit(1);
+    }
+
+    let source: Source = Source::build(rest.into_iter()).unwrap_or_else(|err| {
+        eprintln!("{err}");
         process::exit(1);
     });
+    if dump_tokens || dump_ast {
+        dump(&source, dump_tokens, dump_ast, optimize, format);
+    }
+    let capabilities = match sandbox {
+        true => Capabilities::sandboxed(),
+        false => Capabilities::all(),
+    };
+    let interpreter = match prelude {
+        true => Interpreter::with_capabilities(capabilities),
+        false => Interpreter::without_prelude(capabilities),
+    };
+    let mut pipeline = Pipeline::with_interpreter(interpreter);
+    pipeline.set_strict(strict);
+    pipeline.set_deny_warnings(deny_warnings);
+    pipeline.set_keep_going(keep_going);
+    pipeline.set_type_check(type_check);
+    pipeline.set_optimize(optimize);
+    pipeline.interpreter().set_memory_limit(max_memory);
+    if let Some(max_depth) = max_depth {
+        pipeline.interpreter().set_max_depth(max_depth);
+    }
+    if let Some(seed) = seed {
+        pipeline.interpreter().set_seed(seed);
+    }
+    if debug {
+        let debugger = Debugger::new(Box::new(StdInput), Box::new(StdOutput));
+        pipeline
+            .interpreter()
+            .set_observer(Some(Box::new(debugger)));
+    }
+    if trace {
+        let tracer = Tracer::new(Box::new(StdOutput));
+        pipeline.interpreter().set_observer(Some(Box::new(tracer)));
+    }
+
+    let result = pipeline.run(&source);
+    for warning in pipeline.warnings() {
+        report(warning, pipeline.sources(), &source.path);
+    }
+    if let Err(err) = result {
+        eprintln!("Could not complete {}", err.stage());
+        for diagnostic in err.diagnostics() {
+            report(diagnostic, pipeline.sources(), &source.path);
+        }
+        let trace = pipeline.interpreter().trace();
+        for call in trace.iter().rev().take(TRACE_LENGTH) {
+            eprintln!("  in {call}");
+        }
+        if trace.len() > TRACE_LENGTH {
+            eprintln!("  ... and {} more", trace.len() - TRACE_LENGTH);
+        }
+        process::exit(1);
+    }
+}
+
+/// Prints a diagnostic with the line it points at, or labelled with the file it is
+/// about when it doesn't point anywhere.
+fn report(diagnostic: &Diagnostic, sources: &SourceMap, path: &str) {
+    match diagnostic.span {
+        Some(_) => eprintln!("{}", diagnostic.render(sources)),
+        None => eprintln!("{path}: {}", diagnostic.render(sources)),
+    }
+}
 
-    let lexed_source = lexer::lex(source.string).unwrap_or_else(|err| {
-        eprintln!("Could not complete lexing\n{err}.");
-        process::exit(1)
+/// Prints what the lexer and the parser make of the source, without running it,
+/// with the expressions as `-O` leaves them if `optimize` is set.
+fn dump(source: &Source, tokens: bool, ast: bool, optimize: bool, format: Format) -> ! {
+    let mut sources = SourceMap::new();
+    let id = sources.add(&source.path, source.string.clone());
+    let spanned = lexer::lex_spanned(&source.string, id).unwrap_or_else(|err| {
+        report(&err, &sources, &source.path);
+        process::exit(1);
     });
+    if tokens {
+        print!("{}", dump::tokens(&spanned, format));
+    }
+    if ast {
+        let exprs = parser::parse_spanned(&spanned, id).unwrap_or_else(|errs| {
+            for err in &errs {
+                report(err, &sources, &source.path);
+            }
+            process::exit(1);
+        });
+        let exprs = match optimize {
+            true => interpreter::optimize(exprs),
+            false => exprs,
+        };
+        print!("{}", dump::ast(&exprs, format));
+    }
+    process::exit(0);
+}
+
+fn repl() -> ! {
+    let mut repl = Repl::new();
+    // Ctrl-C stops a runaway loop rather than ending the session
+    let interrupt = repl.pipeline().interpreter().interrupt_handle();
+    if let Err(err) = ctrlc::set_handler(move || interrupt.interrupt()) {
+        eprintln!("Could not handle Ctrl-C: {err}");
+    }
+    let mut stdin = io::stdin().lock();
+    loop {
+        // keep reading while the input so far is only the start of an expression
+        let mut input = String::new();
+        loop {
+            print!("{}", if input.is_empty() { "> " } else { "... " });
+            io::stdout().flush().ok();
+            match stdin.read_line(&mut input) {
+                Ok(0) | Err(_) => process::exit(0),
+                Ok(_) => {}
+            }
+            if !repl::incomplete(&input) {
+                break;
+            }
+        }
+        if input.trim() == ":quit" {
+            process::exit(0);
+        }
+
+        match repl.eval(&input) {
+            Ok(Some(shown)) => println!("{shown}"),
+            Ok(None) => {}
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+}
+
+fn check(args: &[String]) -> ! {
+    let mut paths = vec![];
+    let mut query = None;
+    let mut apply_fixes = false;
+    let mut strict = false;
+    let mut deny_warnings = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--query" => query = args.next(),
+            "--apply-fixes" => apply_fixes = true,
+            "--strict" => strict = true,
+            "--deny-warnings" => deny_warnings = true,
+            _ => paths.push(arg),
+        }
+    }
 
-    let ast = parser::parse(&lexed_source).unwrap_or_else(|err| {
-        eprintln!("Could not complete parsing\n{err}");
-        process::exit(1)
+    if paths.is_empty() {
+        eprintln!("Problem parsing arguments No source file");
+        process::exit(1);
+    }
+    let paths = linus::expand_paths(&paths).unwrap_or_else(|err| {
+        eprintln!("Problem parsing arguments {err}");
+        process::exit(1);
     });
 
-    interpreter::interpret(&ast);
+    if let Some(query) = query {
+        let source = Source::from_path(&paths[0]).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(1);
+        });
+
+        let offset = query
+            .split_once(':')
+            .and_then(|(line, col)| Some((line.parse().ok()?, col.parse().ok()?)))
+            .and_then(|(line, col)| symbols::offset(&source.string, line, col))
+            .unwrap_or_else(|| {
+                eprintln!("Problem parsing arguments Invalid query position {query}");
+                process::exit(1);
+            });
+
+        match symbols::type_at(&source.string, offset) {
+            Some(type_decl) => println!("{type_decl}"),
+            None => println!("unknown"),
+        }
+        process::exit(0);
+    }
+
+    let mut failed = 0;
+    let mut errors = 0;
+    let mut warnings = 0;
+    for path in &paths {
+        // a file that can't be read fails, but the others are still checked
+        let mut source = match Source::from_path(path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("{err}");
+                failed += 1;
+                continue;
+            }
+        };
+
+        let suggested = fixes::suggest(&source.string);
+        if apply_fixes && !suggested.is_empty() {
+            source.string = fixes::apply(&source.string, &suggested);
+            if let Err(err) = fs::write(path, &source.string) {
+                eprintln!("{path}: Could not apply fixes: {err}");
+                failed += 1;
+                continue;
+            }
+            eprintln!("{path}: applied {} fix(es)", suggested.len());
+        }
+
+        let mut sources = SourceMap::new();
+        let id = sources.add(path, source.string.clone());
+        let mut ok = true;
+        for mut diagnostic in linus::diagnose(&source, id, strict) {
+            if strict || deny_warnings {
+                diagnostic.severity = Severity::Error;
+            }
+            match diagnostic.severity {
+                Severity::Error => errors += 1,
+                _ => warnings += 1,
+            }
+            ok &= diagnostic.severity != Severity::Error;
+            report(&diagnostic, &sources, path);
+        }
+        if !ok {
+            failed += 1;
+        }
+    }
+
+    eprintln!(
+        "checked {} file(s): {errors} error(s), {warnings} warning(s)",
+        paths.len()
+    );
+    if failed > 0 {
+        eprintln!("{failed} of {} file(s) failed checking", paths.len());
+        process::exit(1);
+    }
+    process::exit(0);
+}
+
+fn fmt(args: &[String]) -> ! {
+    let mut paths = vec![];
+    let mut check = false;
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ => paths.push(arg),
+        }
+    }
+
+    if paths.is_empty() {
+        eprintln!("Problem parsing arguments No source file");
+        process::exit(1);
+    }
+    let paths = linus::expand_paths(&paths).unwrap_or_else(|err| {
+        eprintln!("Problem parsing arguments {err}");
+        process::exit(1);
+    });
+
+    let mut failed = 0;
+    let mut unformatted = 0;
+    for path in &paths {
+        let source = match Source::from_path(path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("{err}");
+                failed += 1;
+                continue;
+            }
+        };
+
+        let mut sources = SourceMap::new();
+        let id = sources.add(path, source.string.clone());
+        let formatted = match format::format(&source.string, id) {
+            Ok(formatted) => formatted,
+            Err(errs) => {
+                for err in &errs {
+                    report(err, &sources, path);
+                }
+                failed += 1;
+                continue;
+            }
+        };
+        if formatted == source.string {
+            continue;
+        }
+
+        if check {
+            eprintln!("{path}: would be reformatted");
+            unformatted += 1;
+        } else if let Err(err) = fs::write(path, &formatted) {
+            eprintln!("{path}: Could not write the formatted file: {err}");
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        eprintln!("{failed} of {} file(s) could not be formatted", paths.len());
+    }
+    if unformatted > 0 {
+        eprintln!(
+            "{unformatted} of {} file(s) would be reformatted",
+            paths.len()
+        );
+    }
+    process::exit(if failed + unformatted > 0 { 1 } else { 0 });
+}
+
+fn test(args: &[String]) -> ! {
+    let mut paths: Vec<&String> = args.iter().collect();
+    let here = ".".to_string();
+    if paths.is_empty() {
+        paths.push(&here);
+    }
+    let paths = linus::expand_paths(&paths)
+        .and_then(|paths| testing::discover(&paths))
+        .unwrap_or_else(|err| {
+            eprintln!("Problem parsing arguments {err}");
+            process::exit(1);
+        });
+    if paths.is_empty() {
+        eprintln!("No test files, which are named '*{}'", testing::SUFFIX);
+        process::exit(1);
+    }
+
+    let mut passed = 0;
+    let mut failed = 0;
+    for path in &paths {
+        let source = match Source::from_path(path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("{err}");
+                failed += 1;
+                continue;
+            }
+        };
+        for outcome in testing::run(&source, Pipeline::new) {
+            match outcome.passed() {
+                true => {
+                    eprintln!("test {} ... ok", outcome.name);
+                    passed += 1;
+                }
+                false => {
+                    eprintln!("test {} ... FAILED", outcome.name);
+                    for failure in &outcome.failures {
+                        eprintln!("{failure}");
+                    }
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    eprintln!("{passed} passed, {failed} failed");
+    process::exit(if failed > 0 { 1 } else { 0 });
 }
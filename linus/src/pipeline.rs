@@ -0,0 +1,231 @@
+use std::error::Error;
+use std::fmt;
+use std::slice;
+
+use interpreter::{Capabilities, Interpreter};
+use parser::macros::Macros;
+use span::SourceMap;
+use typechecker::Checker;
+
+use crate::diagnostics::{self, codes, Diagnostic};
+use crate::imports::Importer;
+use crate::Source;
+
+/// An error from one of the stages a source goes through.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinusError {
+    /// Problems found by a strict check before running.
+    Check(Vec<Diagnostic>),
+    Lex(Diagnostic),
+    Parse(Vec<Diagnostic>),
+    /// A file that couldn't be imported, or imports that form a cycle.
+    Import(Diagnostic),
+    /// Every mismatch between the types a source declares and those of its
+    /// expressions.
+    Type(Vec<Diagnostic>),
+    /// One error, or with `keep_going` one for each top-level expression that failed.
+    Runtime(Vec<Diagnostic>),
+}
+
+impl LinusError {
+    /// The stage that failed, e.g. for "Could not complete lexing".
+    pub fn stage(&self) -> &'static str {
+        match self {
+            LinusError::Check(_) => "checking",
+            LinusError::Lex(_) => "lexing",
+            LinusError::Parse(_) => "parsing",
+            LinusError::Import(_) => "importing",
+            LinusError::Type(_) => "type checking",
+            LinusError::Runtime(_) => "running",
+        }
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        match self {
+            LinusError::Check(errs)
+            | LinusError::Parse(errs)
+            | LinusError::Type(errs)
+            | LinusError::Runtime(errs) => errs,
+            LinusError::Lex(err) | LinusError::Import(err) => slice::from_ref(err),
+        }
+    }
+
+    pub fn messages(&self) -> Vec<&str> {
+        self.diagnostics()
+            .iter()
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect()
+    }
+}
+
+impl fmt::Display for LinusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.messages().join("\n"))
+    }
+}
+
+impl Error for LinusError {}
+
+/// Lexes, parses, and interprets sources with a single interpreter, so that state
+/// carries over from one `run` to the next.
+pub struct Pipeline {
+    strict: bool,
+    deny_warnings: bool,
+    keep_going: bool,
+    type_check: bool,
+    optimize: bool,
+    warnings: Vec<Diagnostic>,
+    /// Every source that has been run, so diagnostics can be rendered against them.
+    sources: SourceMap,
+    macros: Macros,
+    /// The types of the globals defined so far, for checking the next source.
+    checker: Checker,
+    interpreter: Interpreter,
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::with_capabilities(Capabilities::all())
+    }
+
+    pub fn with_capabilities(capabilities: Capabilities) -> Pipeline {
+        Pipeline::with_interpreter(Interpreter::with_capabilities(capabilities))
+    }
+
+    /// A pipeline that runs sources with an interpreter that has already been set up.
+    pub fn with_interpreter(interpreter: Interpreter) -> Pipeline {
+        Pipeline {
+            strict: false,
+            deny_warnings: false,
+            keep_going: false,
+            type_check: true,
+            optimize: false,
+            warnings: vec![],
+            sources: SourceMap::new(),
+            macros: Macros::new(),
+            checker: Checker::new(),
+            interpreter,
+        }
+    }
+
+    /// Runs a strict `check` over each source before executing it.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Refuses to run sources that have warnings, as `strict` does.
+    pub fn set_deny_warnings(&mut self, deny_warnings: bool) {
+        self.deny_warnings = deny_warnings;
+    }
+
+    /// Keeps running the rest of a source's top-level expressions after one fails,
+    /// reporting every failure at the end.
+    pub fn set_keep_going(&mut self, keep_going: bool) {
+        self.keep_going = keep_going;
+    }
+
+    /// Checks each source's types before running it, which is on by default. Without
+    /// it a value that doesn't match its declared type is only noticed, if at all,
+    /// when it is used.
+    pub fn set_type_check(&mut self, type_check: bool) {
+        self.type_check = type_check;
+    }
+
+    /// Folds constant operations, such as `+ 1 2`, into the values they evaluate to
+    /// before running each source.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// The warnings from the last `run`.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    /// The sources that have been run, which the spans of diagnostics point into.
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    /// Removes the global `name` from the interpreter and the type checker, e.g. when
+    /// the file that defined it is reloaded without it.
+    pub fn undefine(&mut self, name: &str) {
+        self.interpreter.undefine(name);
+        self.checker.undefine(name);
+    }
+
+    /// The type checker sources are checked with, which knows the types of the
+    /// globals they have defined.
+    pub fn checker(&mut self) -> &mut Checker {
+        &mut self.checker
+    }
+
+    /// The interpreter sources are run with, for setting limits or a seed.
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
+    pub fn run(&mut self, source: &Source) -> Result<(), LinusError> {
+        self.warnings = vec![];
+        let id = self.sources.add(&source.path, source.string.clone());
+        if self.strict {
+            let errs = crate::failures(source, id, true);
+            if !errs.is_empty() {
+                return Err(LinusError::Check(errs));
+            }
+        }
+
+        let warnings = diagnostics::lint(&source.string, id);
+        if self.deny_warnings && !warnings.is_empty() {
+            return Err(LinusError::Check(warnings));
+        }
+        self.warnings = warnings;
+
+        let tokens = lexer::lex_spanned(&source.string, id).map_err(LinusError::Lex)?;
+        let ast = parser::parse_spanned(&tokens, id).map_err(LinusError::Parse)?;
+        let capabilities = self.interpreter.capabilities();
+        let ast = Importer::new(&mut self.sources, &source.path, capabilities).resolve(
+            ast,
+            &tokens,
+            &source.path,
+            id,
+        )?;
+        let ast = self
+            .macros
+            .expand(ast)
+            .map_err(|err| LinusError::Parse(vec![Diagnostic::error(codes::MACRO, err)]))?;
+        if self.type_check {
+            self.checker.check(&ast).map_err(LinusError::Type)?;
+        }
+        let ast = match self.optimize {
+            true => interpreter::optimize(ast),
+            false => ast,
+        };
+
+        let mut errs = vec![];
+        for expr in &ast {
+            if let Err(err) = self.interpreter.execute(slice::from_ref(expr)) {
+                errs.push(self.interpreter.diagnostic(&err));
+                if !self.keep_going {
+                    break;
+                }
+            }
+        }
+
+        match errs.is_empty() {
+            true => Ok(()),
+            false => Err(LinusError::Runtime(errs)),
+        }
+    }
+}
+
+impl Default for Pipeline {
+    fn default() -> Pipeline {
+        Pipeline::new()
+    }
+}
+
+/// Runs `source` with a fresh pipeline and every capability allowed.
+pub fn run(source: &Source) -> Result<(), LinusError> {
+    Pipeline::new().run(source)
+}
@@ -0,0 +1,265 @@
+use interpreter::Value;
+use lexer::Token;
+use parser::dump::{self, Format};
+use parser::Expr;
+
+use crate::{LinusError, Pipeline, Source};
+
+/// An interactive session: each input runs in the same pipeline, so definitions
+/// carry over, and a failing expression doesn't end the session. Inputs starting
+/// with `:` are meta-commands that inspect code instead of running it.
+pub struct Repl {
+    pipeline: Pipeline,
+    /// Files brought in with `:load`, with the names each one defined.
+    loaded: Vec<(String, Vec<String>)>,
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        Repl::with_pipeline(Pipeline::new())
+    }
+
+    pub fn with_pipeline(mut pipeline: Pipeline) -> Repl {
+        pipeline.set_keep_going(true);
+        Repl {
+            pipeline,
+            loaded: vec![],
+        }
+    }
+
+    pub fn pipeline(&mut self) -> &mut Pipeline {
+        &mut self.pipeline
+    }
+
+    /// Runs `input`, or the meta-command it holds. Code prints through the
+    /// interpreter's output sink; meta-commands return what they show.
+    pub fn eval(&mut self, input: &str) -> Result<Option<String>, String> {
+        if let Some(command) = input.trim_start().strip_prefix(':') {
+            let (name, arg) = command
+                .split_once(char::is_whitespace)
+                .unwrap_or((command, ""));
+            let (name, arg) = (name.trim(), arg.trim());
+            return match name {
+                "tokens" => self.tokens(arg).map(Some),
+                "ast" => self.ast(arg).map(Some),
+                "type" => self.type_of(arg).map(Some),
+                "env" => Ok(Some(self.env())),
+                "load" => self.load(arg).map(Some),
+                "reload" => self.reload().map(Some),
+                _ => Err(format!("Unknown command ':{name}'")),
+            };
+        }
+
+        let source = Source {
+            path: "<repl>".to_string(),
+            string: input.to_string(),
+        };
+        self.run(&source).1.map(|_| None)
+    }
+
+    /// Runs `source`, returning the names it defines along with the outcome.
+    fn run(&mut self, source: &Source) -> (Vec<String>, Result<(), String>) {
+        let result = self.pipeline.run(source);
+
+        let defined = match self.parse(&source.string) {
+            Ok(exprs) => exprs
+                .iter()
+                .filter_map(|expr| match expr {
+                    Expr::Assignment { name, .. } | Expr::Function { name, .. } => {
+                        Some(name.to_string())
+                    }
+                    _ => None,
+                })
+                .collect(),
+            Err(_) => vec![],
+        };
+        (defined, result.map_err(|err| render(&err)))
+    }
+
+    /// Runs the file at `path` in the session, and remembers it for `:reload`.
+    fn load(&mut self, path: &str) -> Result<String, String> {
+        let source = Source::from_path(path)?;
+        // what an earlier load of the file defined is defined again, not twice
+        for (_, previous) in self.loaded.iter().filter(|(loaded, _)| loaded == path) {
+            for name in previous {
+                self.pipeline.undefine(name);
+            }
+        }
+        self.loaded.retain(|(loaded, _)| loaded != path);
+        let (defined, result) = self.run(&source);
+        let shown = format!("Loaded {path} ({} definition(s))", defined.len());
+        self.loaded.push((path.to_string(), defined));
+        result.map(|_| shown)
+    }
+
+    /// Runs every loaded file again. Names a file no longer defines are removed, so
+    /// nothing stale from an earlier version of it is left behind.
+    fn reload(&mut self) -> Result<String, String> {
+        if self.loaded.is_empty() {
+            return Err("Nothing has been loaded".to_string());
+        }
+
+        let mut shown = vec![];
+        let mut errs = vec![];
+        for (path, previous) in std::mem::take(&mut self.loaded) {
+            for name in &previous {
+                self.pipeline.undefine(name);
+            }
+            match self.load(&path) {
+                Ok(loaded) => shown.push(loaded),
+                Err(err) => errs.push(format!("{path}: {err}")),
+            }
+            // a file that can no longer be read is still reloaded next time
+            if !self.loaded.iter().any(|(loaded, _)| *loaded == path) {
+                self.loaded.push((path, previous));
+            }
+        }
+
+        match errs.is_empty() {
+            true => Ok(shown.join("\n")),
+            false => Err(errs.join("\n")),
+        }
+    }
+
+    fn tokens(&self, input: &str) -> Result<String, String> {
+        let tokens = lexer::lex(input.to_string()).map_err(String::from)?;
+        Ok(tokens
+            .iter()
+            .filter(|token| **token != Token::EOF)
+            .map(|token| format!("{token:?}"))
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+
+    fn ast(&self, input: &str) -> Result<String, String> {
+        let written = dump::ast(&self.parse(input)?, Format::Sexpr);
+        Ok(written.trim_end().to_string())
+    }
+
+    /// Each name the session has defined, with its type and value, one per line.
+    fn env(&mut self) -> String {
+        let bindings = self.pipeline.interpreter().bindings();
+        let checker = self.pipeline.checker();
+        bindings
+            .into_iter()
+            .map(|(name, value)| {
+                let type_decl = match checker.global(&name) {
+                    Some(type_decl) => type_decl.to_string(),
+                    None => kind(&value).to_string(),
+                };
+                match value {
+                    Value::Str(string) => format!("{name}: {type_decl} = {string:?}"),
+                    value => format!("{name}: {type_decl} = {value}"),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The type `input` would evaluate to, worked out by the type checker from the
+    /// types of the globals defined so far, without running it.
+    fn type_of(&mut self, input: &str) -> Result<String, String> {
+        match self.parse(input)?.as_slice() {
+            [expr] => Ok(self.pipeline.checker().type_of(expr).to_string()),
+            _ => Err("':type' takes a single expression".to_string()),
+        }
+    }
+
+    /// Parses `input`, without the `EOF` literal the parser ends with.
+    fn parse(&self, input: &str) -> Result<Vec<Expr>, String> {
+        let tokens = lexer::lex(input.to_string()).map_err(String::from)?;
+        let mut exprs = parser::parse(&tokens)?;
+        if let Some(Expr::Literal { token: Token::EOF }) = exprs.last() {
+            exprs.pop();
+        }
+        Ok(exprs)
+    }
+}
+
+impl Default for Repl {
+    fn default() -> Repl {
+        Repl::new()
+    }
+}
+
+/// Whether `input` is the start of something longer, so the REPL should read more
+/// lines before running it: a def without its `->` yet, a line ending in `->`, a
+/// bare `let`, `loop`, `try`, or `finally`, a line ending in `do`, unclosed
+/// parentheses, brackets, braces, strs, or block comments, or an indented block that hasn't been ended
+/// with a blank line.
+pub fn incomplete(input: &str) -> bool {
+    if input.trim_start().starts_with(':') {
+        return false;
+    }
+    if unterminated(input) {
+        return true;
+    }
+
+    let lines: Vec<&str> = input.lines().collect();
+    let Some(last) = lines.last() else {
+        return false;
+    };
+    if last.trim().is_empty() {
+        return false;
+    }
+
+    let opened = input.matches('(').count() > input.matches(')').count()
+        || input.matches('[').count() > input.matches(']').count()
+        || input.matches('{').count() > input.matches('}').count();
+    let defining = input.trim_start().starts_with("def ") && !input.contains("->");
+    let indented = lines.len() > 1 && last.starts_with([' ', '\t']);
+    let block = matches!(last.trim(), "let" | "loop" | "try" | "finally")
+        || last.split_whitespace().next_back() == Some("do")
+        || last.split_whitespace().next() == Some("match") && !last.contains("->");
+    opened || defining || indented || block || last.trim_end().ends_with("->")
+}
+
+/// Whether `input` ends inside a str or a block comment, which may go on over
+/// several lines.
+fn unterminated(input: &str) -> bool {
+    let mut chars = input.chars();
+    let mut in_str = false;
+    let mut in_block = 0;
+    while let Some(c) = chars.next() {
+        let next = chars.clone().next();
+        match c {
+            '#' if !in_str && next == Some('|') => {
+                chars.next();
+                in_block += 1;
+            }
+            '|' if in_block > 0 && next == Some('#') => {
+                chars.next();
+                in_block -= 1;
+            }
+            _ if in_block > 0 => {}
+            '"' => in_str = !in_str,
+            '\\' if in_str => {
+                chars.next();
+            }
+            '#' if !in_str => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            _ => {}
+        }
+    }
+    in_str || in_block > 0
+}
+
+/// The type of a value with no declared type, such as one a host defined.
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::None => "none",
+        Value::Bool(_) => "bool",
+        Value::Int(_) => "int",
+        Value::Num(_) => "num",
+        Value::Str(_) => "str",
+        Value::Bytes(_) => "bytes",
+        Value::Seq(_) => "seq",
+        Value::Hash(_) => "hash",
+        Value::Function(_) => "_",
+    }
+}
+
+fn render(err: &LinusError) -> String {
+    err.messages().join("\n")
+}
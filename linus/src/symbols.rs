@@ -0,0 +1,496 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use lexer::{Symbol, Token};
+use parser::names::NameSpans;
+use parser::{Binding, Expr, Param, Pattern};
+use span::{LineIndex, SourceId};
+use typechecker::Checker;
+
+use crate::incremental::Document;
+
+/// Where a symbol occurs: the file it is in and its byte range within that file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub file: String,
+    pub span: Range<usize>,
+}
+
+/// Definition and reference locations for every global symbol across a set of files.
+#[derive(Default)]
+pub struct SymbolIndex {
+    definitions: HashMap<String, Location>,
+    references: HashMap<String, Vec<Location>>,
+}
+
+impl SymbolIndex {
+    pub fn new() -> SymbolIndex {
+        SymbolIndex::default()
+    }
+
+    /// Indexes `document` under `file`, replacing anything previously indexed for it.
+    /// Only globals are indexed: a local binding, such as a parameter or a `def` in a
+    /// function body, and the references bound to it are left out.
+    pub fn add_document(&mut self, file: &str, document: &Document) {
+        self.remove_file(file);
+
+        for occurrence in occurrences(document) {
+            if occurrence.local.is_some() {
+                continue;
+            }
+            let location = Location {
+                file: file.to_string(),
+                span: occurrence.span,
+            };
+            match occurrence.defines {
+                true => {
+                    self.definitions.insert(occurrence.name, location);
+                }
+                false => self
+                    .references
+                    .entry(occurrence.name)
+                    .or_default()
+                    .push(location),
+            }
+        }
+    }
+
+    pub fn remove_file(&mut self, file: &str) {
+        self.definitions.retain(|_, location| location.file != file);
+        for locations in self.references.values_mut() {
+            locations.retain(|location| location.file != file);
+        }
+    }
+
+    pub fn definition(&self, name: &str) -> Option<&Location> {
+        self.definitions.get(name)
+    }
+
+    pub fn references(&self, name: &str) -> &[Location] {
+        self.references.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// Every location of `name`, its definition first, e.g. for a cross-file rename.
+    pub fn occurrences(&self, name: &str) -> Vec<&Location> {
+        self.definition(name)
+            .into_iter()
+            .chain(self.references(name))
+            .collect()
+    }
+
+    /// Finds the symbol whose definition or reference covers `offset` in `file`.
+    pub fn symbol_at(&self, file: &str, offset: usize) -> Option<&str> {
+        let covers = |location: &Location| location.file == file && location.span.contains(&offset);
+
+        self.definitions
+            .iter()
+            .find(|(_, location)| covers(location))
+            .or_else(|| {
+                self.references.iter().find_map(|(name, locations)| {
+                    locations.iter().find(|l| covers(l)).map(|l| (name, l))
+                })
+            })
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// The type of the name at byte `offset` in `source`, as the type checker works it
+/// out where the name is written: a global or local's declared type, or a
+/// function's type at its definition.
+pub fn type_at(source: &str, offset: usize) -> Option<String> {
+    let tokens = lexer::lex_spanned(source, SourceId::default()).ok()?;
+    let exprs = parser::parse_spanned(&tokens, SourceId::default()).ok()?;
+    let mut checker = Checker::new();
+    // a program with type errors still has types for its names
+    let _ = checker.check_spanned(&exprs, &tokens);
+    checker.type_at(offset).map(ToString::to_string)
+}
+
+/// Converts a 1-based `line` and `col` into a byte offset into `source`.
+pub fn offset(source: &str, line: usize, col: usize) -> Option<usize> {
+    LineIndex::new(source).offset(source, line, col)
+}
+
+/// Renames the global `name` in `source` to `new_name`: its definition and every
+/// reference bound to it, but not a local binding of the same name or a reference
+/// to one, nor strings, comments, and symbols that merely contain `name`.
+pub fn rename(source: &str, name: &str, new_name: &str) -> Result<String, &'static str> {
+    match lexer::lex(new_name.to_string())?.as_slice() {
+        [Token::Symbol(_), Token::EOF] => {}
+        _ => return Err("New name is not a valid symbol"),
+    }
+
+    let document = Document::new(source.to_string());
+    if document.errors().next().is_some() {
+        return Err("Source does not parse");
+    }
+    let occurrences = occurrences(&document);
+    if occurrences.iter().any(|occurrence| {
+        occurrence.defines && occurrence.local.is_none() && occurrence.name == new_name
+    }) {
+        return Err("New name is already defined");
+    }
+
+    let mut targets: Vec<&Occurrence> = occurrences
+        .iter()
+        .filter(|occurrence| occurrence.name == name && occurrence.local.is_none())
+        .collect();
+    targets.sort_by_key(|occurrence| occurrence.span.start);
+
+    let mut renamed = String::with_capacity(source.len());
+    let mut spans = vec![];
+    let mut idx = 0;
+    for occurrence in targets {
+        renamed.push_str(&source[idx..occurrence.span.start]);
+        spans.push(renamed.len()..renamed.len() + new_name.len());
+        renamed.push_str(new_name);
+        idx = occurrence.span.end;
+    }
+    renamed.push_str(&source[idx..]);
+
+    // a local binding of the new name would take over the references in its scope
+    let captured = occurrences_of(&renamed)
+        .into_iter()
+        .any(|occurrence| occurrence.local.is_some() && spans.contains(&occurrence.span));
+    if captured {
+        return Err("New name is already bound where the symbol is used");
+    }
+    Ok(renamed)
+}
+
+/// A symbol where it occurs in a source, and the binding it is or refers to.
+pub(crate) struct Occurrence {
+    pub name: String,
+    pub span: Range<usize>,
+    /// Which local binding it is, numbered in the order they are made, or `None`
+    /// for a global.
+    pub local: Option<usize>,
+    /// Whether this is where it is bound, rather than used.
+    pub defines: bool,
+    /// Whether this is a `def` of a name that is already bound where it is, which
+    /// it hides from then on.
+    pub shadows: bool,
+}
+
+/// The symbols in the forms of `document` that parse, in order, each resolved
+/// through the scopes of the AST to the binding it refers to. The symbols in
+/// interpolated strs, and names that are not bound or used, such as the name of
+/// an argument passed by name, are left out.
+pub(crate) fn occurrences(document: &Document) -> Vec<Occurrence> {
+    let mut resolver = Resolver::default();
+    for (range, exprs) in document.parsed_forms() {
+        let Ok(tokens) = lexer::lex_spanned(&document.text()[range.clone()], SourceId::default())
+        else {
+            continue;
+        };
+        resolver.names = NameSpans::new(&tokens, range.start);
+        for expr in exprs {
+            resolver.expr(expr);
+        }
+    }
+    resolver.found
+}
+
+fn occurrences_of(source: &str) -> Vec<Occurrence> {
+    occurrences(&Document::new(source.to_string()))
+}
+
+/// Walks a form's AST, matching each name in it to the symbol token it was parsed
+/// from.
+#[derive(Default)]
+struct Resolver {
+    /// The symbol tokens of the form.
+    names: NameSpans,
+    /// The local bindings in scope, innermost last.
+    scopes: Vec<Vec<(Symbol, usize)>>,
+    locals: usize,
+    /// The globals defined so far.
+    globals: HashSet<Symbol>,
+    found: Vec<Occurrence>,
+}
+
+impl Resolver {
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assignment { name, expr, .. } => {
+                let span = self.claim(*name);
+                self.expr(expr);
+                self.define(*name, span);
+            }
+            Expr::Function {
+                name, params, body, ..
+            } => {
+                // it can call itself
+                let span = self.claim(*name);
+                self.define(*name, span);
+                self.function(params, body);
+            }
+            Expr::Lambda { params, body } => self.function(params, body),
+            Expr::FunctionCall {
+                operator, operand, ..
+            } => {
+                if let Token::Symbol(name) = operator {
+                    self.reference(*name);
+                }
+                for operand in operand {
+                    self.expr(operand);
+                }
+            }
+            Expr::Variable {
+                name: Token::Symbol(name),
+            } => self.reference(*name),
+            Expr::Named { name, value } => {
+                self.claim(*name);
+                self.expr(value);
+            }
+            Expr::Let { bindings, body } => {
+                self.scopes.push(vec![]);
+                self.bindings(bindings);
+                self.expr(body);
+                self.scopes.pop();
+            }
+            Expr::Loop {
+                bindings,
+                condition,
+                recur,
+                result,
+            } => {
+                self.scopes.push(vec![]);
+                self.bindings(bindings);
+                self.expr(condition);
+                for value in recur {
+                    self.expr(value);
+                }
+                self.expr(result);
+                self.scopes.pop();
+            }
+            Expr::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.expr(body);
+                if let Some(catch) = catch {
+                    self.scopes.push(vec![]);
+                    let span = self.claim(catch.name);
+                    self.bind(catch.name, span);
+                    self.expr(&catch.body);
+                    self.scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    self.expr(finally);
+                }
+            }
+            Expr::Match { value, arms } => {
+                self.expr(value);
+                for arm in arms {
+                    self.scopes.push(vec![]);
+                    self.pattern(&arm.pattern);
+                    self.expr(&arm.body);
+                    self.scopes.pop();
+                }
+            }
+            Expr::Set { name, expr } => {
+                self.reference(*name);
+                self.expr(expr);
+            }
+            Expr::Do { exprs } | Expr::Seq { items: exprs } => {
+                for expr in exprs {
+                    self.expr(expr);
+                }
+            }
+            Expr::Hash { entries } => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            Expr::Throw { value } => self.expr(value),
+            // the tokens in an interpolated str have no spans of their own
+            Expr::Interpolation { .. }
+            | Expr::Literal { .. }
+            | Expr::Operator { .. }
+            | Expr::Variable { .. }
+            | Expr::Import { .. } => {}
+        }
+    }
+
+    /// Each parameter is bound after its default, which can use the ones before it.
+    fn function(&mut self, params: &[Param], body: &Expr) {
+        self.scopes.push(vec![]);
+        for param in params {
+            let span = self.claim(param.name);
+            if let Some(default) = &param.default {
+                self.expr(default);
+            }
+            self.bind(param.name, span);
+        }
+        self.expr(body);
+        self.scopes.pop();
+    }
+
+    fn bindings(&mut self, bindings: &[Binding]) {
+        for binding in bindings {
+            let span = self.claim(binding.name);
+            self.expr(&binding.expr);
+            self.bind(binding.name, span);
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Name(name) => {
+                let span = self.claim(*name);
+                self.bind(*name, span);
+            }
+            Pattern::Seq(patterns) => {
+                for pattern in patterns {
+                    self.pattern(pattern);
+                }
+            }
+            Pattern::Literal(_) | Pattern::Wildcard => {}
+        }
+    }
+
+    /// The span of the token `name` was parsed from, marking it matched.
+    fn claim(&mut self, name: Symbol) -> Option<Range<usize>> {
+        self.names.claim(name)
+    }
+
+    /// Binds the name a `def` defines, like `bind`, noting whether it shadows one.
+    fn define(&mut self, name: Symbol, span: Option<Range<usize>>) {
+        let shadows = self.globals.contains(&name)
+            || self
+                .scopes
+                .iter()
+                .flatten()
+                .any(|(bound, _)| *bound == name);
+        let found = self.found.len();
+        self.bind(name, span);
+        if let Some(occurrence) = self.found.get_mut(found) {
+            occurrence.shadows = shadows;
+        }
+    }
+
+    /// Binds `name` in the innermost scope, or as a global at the top level.
+    fn bind(&mut self, name: Symbol, span: Option<Range<usize>>) {
+        let local = self.scopes.last_mut().map(|scope| {
+            self.locals += 1;
+            scope.push((name, self.locals));
+            self.locals
+        });
+        if local.is_none() {
+            self.globals.insert(name);
+        }
+        if let Some(span) = span {
+            self.found.push(Occurrence {
+                name: name.to_string(),
+                span,
+                local,
+                defines: true,
+                shadows: false,
+            });
+        }
+    }
+
+    fn reference(&mut self, name: Symbol) {
+        let local = self
+            .scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(bound, _)| *bound == name)
+            .map(|(_, local)| *local);
+        if let Some(span) = self.claim(name) {
+            self.found.push(Occurrence {
+                name: name.to_string(),
+                span,
+                local,
+                defines: false,
+                shadows: false,
+            });
+        }
+    }
+}
+
+/// Byte ranges of the symbols (and the `def` keyword) in `source`, skipping strings and
+/// comments. Lexemes are split the same way the lexer splits them.
+pub(crate) fn symbol_spans(source: &str) -> Vec<(String, Range<usize>)> {
+    let mut spans = vec![];
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '"' => {
+                while let Some((_, x)) = chars.next() {
+                    match x {
+                        '"' => break,
+                        // the escaped character can't end the string
+                        '\\' => {
+                            chars.next();
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            '#' if source[start..].starts_with("#|") => {
+                // to the `|#` that closes it, past any nested in it
+                chars.next();
+                let mut open = 1;
+                while open > 0 {
+                    let Some((idx, _)) = chars.next() else {
+                        break;
+                    };
+                    if source[idx..].starts_with("#|") {
+                        chars.next();
+                        open += 1;
+                    } else if source[idx..].starts_with("|#") {
+                        chars.next();
+                        open -= 1;
+                    }
+                }
+            }
+            '#' => {
+                for (_, x) in chars.by_ref() {
+                    if x == '\n' {
+                        break;
+                    }
+                }
+            }
+            '0'..='9' => {
+                // the rest of a num, including separators and an exponent's sign
+                let mut prev = c;
+                while let Some(&(_, x)) = chars.peek() {
+                    let exponent_sign = matches!(x, '+' | '-') && matches!(prev, 'e' | 'E');
+                    if !x.is_ascii_alphanumeric() && !matches!(x, '_' | '.') && !exponent_sign {
+                        break;
+                    }
+                    chars.next();
+                    prev = x;
+                }
+            }
+            c if c.is_whitespace() || "()[]{};:$\\+-/*<>=".contains(c) => {}
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(idx, x)) = chars.peek() {
+                    if x.is_whitespace() || matches!(x, '#' | ':' | ')' | ']' | '}' | ';') {
+                        break;
+                    }
+                    chars.next();
+                    end = idx + x.len_utf8();
+                }
+
+                let lexeme = &source[start..end];
+                match lexer::lex(lexeme.to_string()).as_deref() {
+                    Ok([Token::Symbol(name), Token::EOF]) => {
+                        spans.push((name.to_string(), start..end))
+                    }
+                    Ok([Token::Def, Token::EOF]) => spans.push((lexeme.to_string(), start..end)),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    spans
+}
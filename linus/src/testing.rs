@@ -0,0 +1,114 @@
+//! `linus test`: finds tests written in linus and runs them.
+//!
+//! A test file is one named `*_test.ln`. Each of its `def test_*` definitions is a
+//! test, which passes if evaluating it raises nothing, e.g. with `assert` or
+//! `assert-eq`. A test file without any is one test, of running the whole file.
+//! Every test runs in a fresh pipeline, along with the rest of its file but not the
+//! other tests in it.
+
+use std::fs;
+use std::path::Path;
+
+use crate::incremental::split_forms;
+use crate::{Pipeline, Source};
+
+/// The suffix of the files that hold tests.
+pub const SUFFIX: &str = "_test.ln";
+
+/// A test that was run, and the diagnostics it failed with, rendered, if it did.
+pub struct Outcome {
+    pub name: String,
+    pub failures: Vec<String>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// The test files in `paths`: each file as it is, and for each directory the test
+/// files anywhere below it, in order of their paths.
+pub fn discover(paths: &[String]) -> Result<Vec<String>, String> {
+    let mut found = vec![];
+    for path in paths {
+        match Path::new(path).is_dir() {
+            true => walk(Path::new(path), &mut found)?,
+            false => found.push(path.clone()),
+        }
+    }
+    Ok(found)
+}
+
+fn walk(dir: &Path, found: &mut Vec<String>) -> Result<(), String> {
+    let entries = fs::read_dir(dir)
+        .map_err(|err| format!("Could not read directory '{}': {err}", dir.display()))?;
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            walk(&path, found)?;
+        } else if path.to_string_lossy().ends_with(SUFFIX) {
+            found.push(path.to_string_lossy().into_owned());
+        }
+    }
+    Ok(())
+}
+
+/// Runs each test in `source`, in the order they are defined, with a pipeline from
+/// `pipeline` for each.
+pub fn run(source: &Source, pipeline: impl Fn() -> Pipeline) -> Vec<Outcome> {
+    let forms = split_forms(&source.string, 0..source.string.len()).0;
+    let tests: Vec<(usize, String)> = forms
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, form)| Some((idx, test_name(&source.string[form.clone()])?)))
+        .collect();
+    if tests.is_empty() {
+        return vec![outcome(source.path.clone(), source, pipeline())];
+    }
+
+    tests
+        .into_iter()
+        .map(|(test, name)| {
+            // the other tests are blanked out, so lines are where they were written
+            let mut string = String::with_capacity(source.string.len());
+            for (idx, form) in forms.iter().enumerate() {
+                let text = &source.string[form.clone()];
+                match idx != test && test_name(text).is_some() {
+                    true => string.extend(text.matches('\n')),
+                    false => string.push_str(text),
+                }
+            }
+            let only = Source {
+                path: source.path.clone(),
+                string,
+            };
+            outcome(format!("{}::{name}", source.path), &only, pipeline())
+        })
+        .collect()
+}
+
+fn outcome(name: String, source: &Source, mut pipeline: Pipeline) -> Outcome {
+    let failures = match pipeline.run(source) {
+        Ok(()) => vec![],
+        Err(err) => err
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| diagnostic.render(pipeline.sources()))
+            .collect(),
+    };
+    Outcome { name, failures }
+}
+
+/// The name of the test a top-level form defines, if it defines one.
+fn test_name(form: &str) -> Option<String> {
+    let rest = form.strip_prefix("def")?;
+    let name = rest.trim_start().split([':', ' ', '\t']).next()?;
+    match rest.starts_with([' ', '\t']) && name.starts_with("test_") {
+        true => Some(name.to_string()),
+        false => None,
+    }
+}
@@ -0,0 +1,43 @@
+//! Logs each expression the interpreter evaluates together with its value, see
+//! `linus --trace`. An expression is logged once it is done, indented by how deeply
+//! it is nested, so the expressions it was made of come just before it, one level
+//! further in.
+
+use environment::Type;
+use interpreter::{BoxedSink, Interpreter, Observer, Raised};
+use lexer::Token;
+use parser::{dump, Expr};
+
+pub struct Tracer {
+    output: BoxedSink,
+}
+
+impl Tracer {
+    /// A tracer that writes to `output` as diagnostic output, so that it stays apart
+    /// from the program's own.
+    pub fn new(output: BoxedSink) -> Tracer {
+        Tracer { output }
+    }
+}
+
+impl Observer for Tracer {
+    fn after(
+        &mut self,
+        _interpreter: &Interpreter,
+        expr: &Expr,
+        depth: usize,
+        result: Result<&Type, &Raised>,
+    ) {
+        // the end of the input isn't part of the program
+        if let Expr::Literal { token: Token::EOF } = expr {
+            return;
+        }
+        let indent = "  ".repeat(depth.saturating_sub(1));
+        let expr = dump::expr_sexpr(expr);
+        let line = match result {
+            Ok(value) => format!("{indent}{expr} => {value}"),
+            Err(err) => format!("{indent}{expr} raised {err}"),
+        };
+        self.output.eprint(&Type::str(line));
+    }
+}
@@ -0,0 +1,927 @@
+use interpreter::{
+    Capabilities, CapturedOutput, Interpreter, Raised, RuntimeError, ScriptedInput, Value,
+};
+use linus::debugger::Debugger;
+use linus::fixes::{self, Fix};
+use linus::format;
+use linus::incremental::Document;
+use linus::repl::{self, Repl};
+use linus::symbols::{self, Location, SymbolIndex};
+use linus::testing;
+use linus::tracer::Tracer;
+use linus::{codes, Diagnostic, LinusError, Pipeline, Source};
+use span::SourceId;
+
+/// The error for using `name` where nothing defines it.
+fn undefined(name: &str) -> Diagnostic {
+    Raised::from(RuntimeError::UndefinedVariable(name.to_string())).diagnostic()
+}
+
+fn source(string: &str) -> Source {
+    Source {
+        path: "test.lin".to_string(),
+        string: string.to_string(),
+    }
+}
+
+#[test]
+fn test_missing_file_reports_path() {
+    let err = Source::from_path("does/not/exist.lin").err().unwrap();
+    assert!(err.starts_with("Could not read file 'does/not/exist.lin': "));
+}
+
+#[test]
+fn test_pipeline_keeps_state_between_runs() {
+    let mut pipeline = Pipeline::new();
+    assert_eq!(pipeline.run(&source("def x: num -> 1\n")), Ok(()));
+    assert_eq!(pipeline.run(&source("+ x 2\n")), Ok(()));
+
+    assert_eq!(
+        linus::run(&source("x\n")),
+        Err(LinusError::Runtime(vec![undefined("x")]))
+    );
+}
+
+#[test]
+fn test_pipeline_keep_going() {
+    let program = source("a\ndef x: num -> 1\nb\nx\n");
+    let mut pipeline = Pipeline::new();
+    assert_eq!(
+        pipeline.run(&program),
+        Err(LinusError::Runtime(vec![undefined("a")]))
+    );
+
+    let mut pipeline = Pipeline::new();
+    pipeline.set_keep_going(true);
+    assert_eq!(
+        pipeline.run(&program),
+        Err(LinusError::Runtime(vec![undefined("a"), undefined("b"),]))
+    );
+    // the def after the first failure still ran
+    assert_eq!(pipeline.run(&source("x\n")), Ok(()));
+}
+
+#[test]
+fn test_pipeline_reports_stage() {
+    let err = linus::run(&source("def x num -> 1\n")).unwrap_err();
+    assert_eq!(err.stage(), "parsing");
+
+    let mut pipeline = Pipeline::new();
+    pipeline.set_strict(true);
+    let err = pipeline.run(&source("def x: _ -> 1\nx\n")).unwrap_err();
+    assert!(matches!(err, LinusError::Check(_)));
+}
+
+#[test]
+fn test_pipeline_type_check() {
+    let program = source("print \"ran\"\ndef x: num -> \"hello\"\n");
+    let mut pipeline = Pipeline::new();
+    let err = pipeline.run(&program).unwrap_err();
+    assert_eq!(err.stage(), "type checking");
+    assert_eq!(
+        err.messages(),
+        vec!["'x' is declared num but its value is str"]
+    );
+
+    // the checker remembers the globals of earlier runs, unless it is turned off
+    assert_eq!(pipeline.run(&source("def y: str -> \"a\"\n")), Ok(()));
+    assert!(pipeline.run(&source("+ y 1\n")).is_err());
+    pipeline.set_type_check(false);
+    assert_eq!(pipeline.run(&program), Ok(()));
+
+    let result = linus::check(&source("+ 1 true\n"), false);
+    assert_eq!(
+        result.unwrap_err(),
+        vec!["'+' expects int, num, or str operands, but one is bool".to_string()]
+    );
+}
+
+#[test]
+fn test_check_valid_source() {
+    let result = linus::check(&source("def x: num -> 1\n+ x 2\n"), false);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_check_reports_parse_errors() {
+    let result = linus::check(&source("def x num -> 1\n"), false);
+    assert_eq!(
+        result.unwrap_err(),
+        vec![
+            "test.lin:1:11: Error in global variable declaration: invalid syntax after \"def\""
+                .to_string()
+        ]
+    );
+}
+
+#[test]
+fn test_parse_errors_point_at_source() {
+    let mut pipeline = Pipeline::new();
+    let err = pipeline
+        .run(&source("def x: num -> 1\n+ x 1\ndef 2: num -> 3\n"))
+        .unwrap_err();
+    assert_eq!(
+        err.diagnostics()[0].render(pipeline.sources()),
+        "error[E0002]: Invalid variable name.\n \
+         --> test.lin:3:5\n  \
+         |\n\
+         3 | def 2: num -> 3\n  \
+         |     ^\n  \
+         = hint: a name is a word such as `total`, not a literal or a keyword"
+    );
+}
+
+#[test]
+fn test_incremental_edit_matches_full_lex() {
+    let text = "def x: num -> 1\n# comment\ndef y: num -> 2\n+ x\n    * y 3\n- y x\n";
+    let mut document = Document::new(text.to_string());
+
+    let rebuilt = document.edit(26..26, "def z: str -> \"a\"\nz\n").unwrap();
+    assert_eq!(rebuilt, 0..5);
+
+    let full = lexer::lex(document.text().to_string()).unwrap();
+    assert_eq!(document.tokens(), full.iter().collect::<Vec<_>>());
+    assert_eq!(document.errors().count(), 0);
+}
+
+#[test]
+fn test_incremental_edit_block_comment() {
+    let text = "+ 1 2\n#| old\n+ 3 4\n|#\n+ 5 6\n";
+    let mut document = Document::new(text.to_string());
+    // the commented out line isn't a form of its own
+    assert_eq!(document.exprs().count(), 2);
+
+    // without its start, what was commented out is code again
+    document.edit(6..8, "").unwrap();
+    assert_eq!(document.exprs().count(), 4);
+    let full = lexer::lex(document.text().to_string()).unwrap();
+    assert_eq!(document.tokens(), full.iter().collect::<Vec<_>>());
+    assert_eq!(document.errors().count(), 0);
+}
+
+#[test]
+fn test_incremental_edit_only_rebuilds_neighbouring_forms() {
+    let text = "+ 1 2\n+ 3 4\n+ 5 6\n+ 7 8\n+ 9 10\n";
+    let mut document = Document::new(text.to_string());
+
+    let rebuilt = document.edit(20..21, "70").unwrap();
+    assert_eq!(rebuilt, 2..5);
+    assert_eq!(document.text(), "+ 1 2\n+ 3 4\n+ 5 6\n+ 70 8\n+ 9 10\n");
+
+    let full = lexer::lex(document.text().to_string()).unwrap();
+    assert_eq!(document.tokens(), full.iter().collect::<Vec<_>>());
+    assert_eq!(document.exprs().count(), 5);
+}
+
+#[test]
+fn test_incremental_edit_unterminated_string() {
+    let mut document = Document::new("+ 1 2\n+ 3 4\n+ 5 6\n+ 7 8\n".to_string());
+
+    document.edit(0..0, "\"").unwrap();
+
+    // the string runs to the end of the text, so the whole of it is one bad form
+    assert_eq!(
+        lexer::lex(document.text().to_string()),
+        Err("Unterminated str")
+    );
+    assert_eq!(
+        document.errors().collect::<Vec<_>>(),
+        vec!["Unterminated str"]
+    );
+
+    document.edit(0..1, "").unwrap();
+    assert_eq!(document.errors().count(), 0);
+    assert_eq!(document.exprs().count(), 4);
+}
+
+#[test]
+fn test_symbol_index_definitions_and_references() {
+    let mut index = SymbolIndex::new();
+    index.add_document(
+        "costs.ln",
+        &Document::new("def total-cost: num -> 10\n# total-cost in a comment\n".to_string()),
+    );
+    index.add_document(
+        "main.ln",
+        &Document::new("+ total-cost 1.5\n\"total-cost\"\n* 2 total-cost\n".to_string()),
+    );
+
+    assert_eq!(
+        index.definition("total-cost"),
+        Some(&Location {
+            file: "costs.ln".to_string(),
+            span: 4..14,
+        })
+    );
+    assert_eq!(
+        index.references("total-cost"),
+        &[
+            Location {
+                file: "main.ln".to_string(),
+                span: 2..12,
+            },
+            Location {
+                file: "main.ln".to_string(),
+                span: 34..44,
+            },
+        ]
+    );
+    assert_eq!(index.symbol_at("main.ln", 40), Some("total-cost"));
+    assert_eq!(index.symbol_at("main.ln", 20), None);
+
+    index.remove_file("main.ln");
+    assert!(index.references("total-cost").is_empty());
+}
+
+#[test]
+fn test_symbol_index_scopes() {
+    let mut index = SymbolIndex::new();
+    index.add_document(
+        "main.ln",
+        &Document::new(
+            "def cost: num -> 10\ndef f: num cost: num -> do def g: num -> cost; g\nlet cost: num -> 1; cost\n+ cost 1\n"
+                .to_string(),
+        ),
+    );
+
+    // neither the parameter nor the let binding is the global, and the def in the
+    // body stays local to it
+    assert_eq!(
+        index.definition("cost").map(|location| &location.span),
+        Some(&(4..8))
+    );
+    assert_eq!(
+        index
+            .references("cost")
+            .iter()
+            .map(|location| &location.span)
+            .collect::<Vec<_>>(),
+        [&(96..100)]
+    );
+    assert_eq!(index.definition("g"), None);
+    assert_eq!(
+        index.definition("f").map(|location| &location.span),
+        Some(&(24..25))
+    );
+}
+
+#[test]
+fn test_rename_symbol() {
+    let source = "def cost: num -> 1\n# cost of things\n+ cost cost-total\n\"cost\"\n";
+    assert_eq!(
+        symbols::rename(source, "cost", "price"),
+        Ok("def price: num -> 1\n# cost of things\n+ price cost-total\n\"cost\"\n".to_string())
+    );
+}
+
+#[test]
+fn test_rename_respects_scopes() {
+    // parameters and let bindings of the same name shadow the global, so they stay
+    let source = "def cost: num -> 1\ndef total: num cost: num -> + cost 1\nlet cost: num -> 2; + cost 1\ncost |> total\n";
+    assert_eq!(
+        symbols::rename(source, "cost", "price"),
+        Ok("def price: num -> 1\ndef total: num cost: num -> + cost 1\nlet cost: num -> 2; + cost 1\nprice |> total\n".to_string())
+    );
+    // a reference to the global can't be renamed to a local's name
+    let source = "def cost: num -> 1\ndef total: num price: num -> + price cost\n";
+    assert_eq!(
+        symbols::rename(source, "cost", "price"),
+        Err("New name is already bound where the symbol is used")
+    );
+}
+
+#[test]
+fn test_rename_rejects_invalid_names() {
+    let source = "def cost: num -> 1\ndef price: num -> 2\n";
+    assert_eq!(
+        symbols::rename(source, "cost", "def"),
+        Err("New name is not a valid symbol")
+    );
+    assert_eq!(
+        symbols::rename(source, "cost", "price"),
+        Err("New name is already defined")
+    );
+}
+
+#[test]
+fn test_expand_paths() {
+    let dir = std::env::temp_dir().join(format!("linus-glob-test-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    for file in ["a.ln", "b.ln", "notes.txt", "lib/c.ln"] {
+        std::fs::write(dir.join(file), "").unwrap();
+    }
+    let dir = dir.to_string_lossy();
+
+    let glob = format!("{dir}/**/*.ln");
+    let plain = "plain.ln".to_string();
+    let expanded = linus::expand_paths(&[&glob, &plain]).unwrap();
+    assert_eq!(
+        expanded,
+        vec![
+            format!("{dir}/a.ln"),
+            format!("{dir}/b.ln"),
+            format!("{dir}/lib/c.ln"),
+            plain.clone(),
+        ]
+    );
+
+    let missing = format!("{dir}/*.lin");
+    assert_eq!(
+        linus::expand_paths(&[&missing]),
+        Err(format!("No files match '{missing}'"))
+    );
+    std::fs::remove_dir_all(&*dir).unwrap();
+}
+
+#[test]
+fn test_format() {
+    let source = "#header\n\n\ndef  x :num ->  1   #trailing\ndef f: num a: num -> do\n  print  a\n\n\n  # inside\n  ( + a  1 )\n[ 1  2 ]\n\n";
+    let formatted = "# header\n\ndef x: num -> 1 # trailing\ndef f: num a: num -> do\n    print a\n\n    # inside\n    (+ a 1)\n[1 2]\n";
+    assert_eq!(
+        format::format(source, SourceId::default()),
+        Ok(formatted.to_string())
+    );
+    // formatting is already done on formatted source
+    assert_eq!(
+        format::format(formatted, SourceId::default()),
+        Ok(formatted.to_string())
+    );
+    assert_eq!(
+        format::format("map (\\x:int -> x) [1];+ 1 2\n", SourceId::default()),
+        Ok("map (\\x: int -> x) [1]; + 1 2\n".to_string())
+    );
+    assert!(format::format("def x num -> 1\n", SourceId::default()).is_err());
+
+    // block comments stay where they were, as they were written
+    let source =
+        "def f: num a: num -> do\n  #| print a\n     print a |#\n  +  a #|one|# 1\n#| end |#\n";
+    let formatted =
+        "def f: num a: num -> do\n  #| print a\n     print a |#\n    + a #|one|# 1\n#| end |#\n";
+    assert_eq!(
+        format::format(source, SourceId::default()),
+        Ok(formatted.to_string())
+    );
+}
+
+#[test]
+fn test_type_at_symbol() {
+    let source = "def name: str -> \"linus\"\ndef age: num -> 4\n+ age 1\n";
+
+    let offset = symbols::offset(source, 3, 3).unwrap();
+    assert_eq!(symbols::type_at(source, offset), Some("num".to_string()));
+
+    let offset = symbols::offset(source, 1, 6).unwrap();
+    assert_eq!(symbols::type_at(source, offset), Some("str".to_string()));
+
+    let offset = symbols::offset(source, 3, 7).unwrap();
+    assert_eq!(symbols::type_at(source, offset), None);
+    assert_eq!(symbols::offset(source, 9, 1), None);
+
+    let source = "def add: num -> num -> num -> \\x: num y: num -> + x y\n";
+    assert_eq!(
+        symbols::type_at(source, 5),
+        Some("num -> num -> num".to_string())
+    );
+
+    // names are typed by what they are bound to where they are written
+    let source = "def x: str -> \"x\"\ndef area: num x: num -> let y: int -> 2; * x y\nx\n";
+    let at = |line, col| symbols::type_at(source, symbols::offset(source, line, col).unwrap());
+    assert_eq!(at(2, 6), Some("num -> num".to_string()));
+    assert_eq!(at(2, 15), Some("num".to_string()));
+    assert_eq!(at(2, 29), Some("int".to_string()));
+    assert_eq!(at(2, 44), Some("num".to_string()));
+    assert_eq!(at(2, 46), Some("int".to_string()));
+    assert_eq!(at(3, 1), Some("str".to_string()));
+}
+
+#[test]
+fn test_fix_missing_assign() {
+    let source = "def x: num 1\n+ x 2\n";
+    let suggested = fixes::suggest(source);
+    assert_eq!(
+        suggested,
+        vec![Fix {
+            code: codes::MISSING_ASSIGN,
+            message: "missing '->' after the declared type".to_string(),
+            span: 10..10,
+            replacement: " ->".to_string(),
+        }]
+    );
+    assert_eq!(fixes::apply(source, &suggested), "def x: num -> 1\n+ x 2\n");
+
+    // parameters come between the type and the '->'
+    let function = "def add: num x: num y:num -> + x y\ndef inc: num\n    n: num ->\n    + n 1\n";
+    assert_eq!(
+        fixes::suggest(&format!("{function}add 1 (inc 2)\n")),
+        vec![]
+    );
+}
+
+#[test]
+fn test_fix_unused_definition() {
+    let source = "def x: num -> 1\ndef unused: str -> \"x\"\n+ x 2\n";
+    let suggested = fixes::suggest(source);
+    assert_eq!(suggested.len(), 1);
+    assert_eq!(suggested[0].message, "'unused' is defined but never used");
+    assert_eq!(fixes::apply(source, &suggested), "def x: num -> 1\n+ x 2\n");
+
+    // a function goes with its indented body, but not the comment after it, and one
+    // that only calls itself is still unused
+    let source = "def x: num -> 1\ndef count: int\n    n: int ->\n    match n\n        0 -> 0\n        _ -> count (- n 1)\n\n# uses x\n+ x 2\n";
+    let suggested = fixes::suggest(source);
+    assert_eq!(suggested.len(), 1);
+    assert_eq!(suggested[0].message, "'count' is defined but never used");
+    let fixed = fixes::apply(source, &suggested);
+    assert_eq!(fixed, "def x: num -> 1\n\n# uses x\n+ x 2\n");
+    let tokens = lexer::lex(fixed).unwrap();
+    assert_eq!(parser::parse(&tokens).unwrap().len(), 3);
+
+    // a use inside a seq, a hash, before a `;`, or in an interpolated str is a use
+    for used in [
+        "print [x]",
+        "print {\"k\" x}",
+        "print x; print 2",
+        "print \"{x}\"",
+    ] {
+        assert_eq!(
+            fixes::suggest(&format!("def x: int -> 1\n{used}\n")),
+            vec![]
+        );
+    }
+    // but a local of the same name isn't
+    let suggested = fixes::suggest("def x: int -> 1\nlet x: int -> 2; print x\n");
+    assert_eq!(suggested.len(), 1);
+    assert_eq!(suggested[0].message, "'x' is defined but never used");
+}
+
+#[test]
+fn test_fix_not_equal() {
+    let program = "def x: num -> 1\nprint (!= x 2)\nmap (!=) [x]\n";
+    let suggested = fixes::suggest(program);
+    assert_eq!(
+        suggested,
+        vec![Fix {
+            code: codes::NOT_EQUAL,
+            message: "there is no '!=' operator; use 'not' with '='".to_string(),
+            span: 23..25,
+            replacement: "not $ =".to_string(),
+        }]
+    );
+    let mut pipeline = Pipeline::new();
+    let output = CapturedOutput::new();
+    pipeline.interpreter().set_output(Box::new(output.clone()));
+    let fixed = fixes::apply("def x: num -> 1\nprint (!= x 2)\n", &suggested);
+    assert_eq!(pipeline.run(&source(&fixed)), Ok(()));
+    assert_eq!(output.stdout(), ["true"]);
+
+    // unless the program defines it
+    assert_eq!(
+        fixes::suggest("def !=: bool a: _ b: _ -> false\n!= 1 2\n"),
+        vec![]
+    );
+}
+
+#[test]
+fn test_check_strict() {
+    let program = source("def x: _ -> 1\ndef unused: num -> 2\n+ x 2\n");
+    assert!(linus::check(&program, false).is_ok());
+    assert_eq!(
+        linus::check(&program, true).unwrap_err(),
+        vec![
+            "test.lin:1:8: 'x' is declared with the dynamic type '_', which is not allowed in strict mode"
+                .to_string(),
+            "test.lin:2:1: 'unused' is defined but never used".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn test_warnings_do_not_stop_running() {
+    let program =
+        source("def x: num -> 1\ndef g: num n: num -> do def x: num -> n; + x 1\ndef unused: num -> 3\ng x\n");
+    assert_eq!(
+        linus::diagnose(&program, SourceId::default(), false)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.span.map(|span| span.range())))
+            .collect::<Vec<_>>(),
+        vec![
+            (codes::UNUSED, Some(63..84)),
+            (codes::SHADOWED, Some(44..45)),
+        ]
+    );
+
+    let mut pipeline = Pipeline::new();
+    assert_eq!(pipeline.run(&program), Ok(()));
+    assert_eq!(pipeline.warnings().len(), 2);
+
+    pipeline.set_deny_warnings(true);
+    assert!(matches!(pipeline.run(&program), Err(LinusError::Check(_))));
+}
+
+#[test]
+fn test_lint_scopes_and_match_arms() {
+    let warnings = |program: &str| {
+        linus::diagnose(&source(program), SourceId::default(), false)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.span.map(|span| span.range())))
+            .collect::<Vec<_>>()
+    };
+    // Each `def t` is local to its own function, so neither shadows the other.
+    assert_eq!(
+        warnings("def f: int a: int -> do def t: int -> a; t\ndef g: int b: int -> do def t: int -> b; t\nprint f 1\nprint g 2\n"),
+        vec![]
+    );
+    assert_eq!(
+        warnings("def f: int n: int -> match n; 1 -> 10; _ -> 20; 2 -> 30; m -> 40\nprint f 2\n"),
+        vec![
+            (codes::UNREACHABLE, Some(48..49)),
+            (codes::UNREACHABLE, Some(57..58))
+        ]
+    );
+}
+
+#[test]
+fn test_repl_meta_commands() {
+    let mut repl = Repl::new();
+    assert_eq!(repl.eval("def name: str -> \"linus\"\n"), Ok(None));
+    assert_eq!(repl.eval(":type name\n"), Ok(Some("str".to_string())));
+    assert_eq!(repl.eval(":type <= 1 2"), Ok(Some("bool".to_string())));
+    assert_eq!(repl.eval(":type random 1"), Ok(Some("_".to_string())));
+    assert_eq!(repl.eval(":type * 2 3"), Ok(Some("int".to_string())));
+    assert_eq!(repl.eval(":type / 2 3"), Ok(Some("num".to_string())));
+    repl.eval("def adder: (int -> int) n: int -> \\x: int -> + x n\n")
+        .unwrap();
+    assert_eq!(
+        repl.eval(":type adder"),
+        Ok(Some("int -> (int -> int)".to_string()))
+    );
+    assert_eq!(
+        repl.eval(":type adder 1"),
+        Ok(Some("int -> int".to_string()))
+    );
+    assert_eq!(
+        repl.eval(":type let n: int -> 1; adder n"),
+        Ok(Some("int -> int".to_string()))
+    );
+    assert_eq!(repl.eval(":type [name]"), Ok(Some("seq".to_string())));
+    assert_eq!(
+        repl.eval(":tokens + 1 x\n"),
+        Ok(Some("Add Int(1) Symbol(\"x\")".to_string()))
+    );
+    assert_eq!(repl.eval(":ast + 1 x"), Ok(Some("(+ 1 x)".to_string())));
+    assert_eq!(
+        repl.eval(":ast def y: num -> 1\ny"),
+        Ok(Some("(def y num 1)\ny".to_string()))
+    );
+    assert_eq!(
+        repl.eval(":env"),
+        Ok(Some(
+            "adder: int -> (int -> int) = <function adder>\nname: str = \"linus\"".to_string()
+        ))
+    );
+    assert_eq!(
+        repl.eval(":nope"),
+        Err("Unknown command ':nope'".to_string())
+    );
+
+    // errors don't end the session
+    assert_eq!(
+        repl.eval("missing"),
+        Err("'missing' is not defined".to_string())
+    );
+    assert_eq!(repl.eval("name"), Ok(None));
+}
+
+#[test]
+fn test_repl_multi_line_input() {
+    assert!(!repl::incomplete("+ 1 2\n"));
+    assert!(!repl::incomplete(":type x\n"));
+    assert!(repl::incomplete("def sum: num\n"));
+    assert!(repl::incomplete("def x: num ->\n"));
+    assert!(repl::incomplete("+ 1 (* 2\n"));
+    assert!(repl::incomplete("[1 2\n"));
+    assert!(repl::incomplete("{\"a\" 1\n"));
+    assert!(repl::incomplete("let\n    x: num -> 1\n"));
+    assert!(repl::incomplete("def greet: _ -> do\n"));
+    assert!(repl::incomplete("try\n    / 1 0\n"));
+    assert!(repl::incomplete("match n\n"));
+    assert!(!repl::incomplete("match n; _ -> 1\n"));
+    assert!(repl::incomplete("def x: num ->\n    + 1 2\n"));
+    assert!(!repl::incomplete("def x: num ->\n    + 1 2\n\n"));
+    assert!(repl::incomplete("print \"two\n\n"));
+    assert!(!repl::incomplete("print \"say \\\"hi\\\"\" # \"\n"));
+    assert!(repl::incomplete("#| a #| b |#\n"));
+    assert!(!repl::incomplete("#| \" |# + 1 2\n"));
+}
+
+#[test]
+fn test_repl_load_and_reload() {
+    let path = std::env::temp_dir().join(format!("linus-repl-{}.ln", std::process::id()));
+    let path_str = path.to_str().unwrap();
+    std::fs::write(&path, "def a: num -> 1\ndef b: num -> 2\n").unwrap();
+
+    let mut repl = Repl::new();
+    assert_eq!(
+        repl.eval(&format!(":load {path_str}")),
+        Ok(Some(format!("Loaded {path_str} (2 definition(s))")))
+    );
+    assert_eq!(repl.eval("b"), Ok(None));
+
+    std::fs::write(&path, "def a: str -> \"one\"\n").unwrap();
+    assert!(repl.eval(":reload").is_ok());
+    assert_eq!(repl.eval(":type a"), Ok(Some("str".to_string())));
+    assert_eq!(repl.eval("b"), Err("'b' is not defined".to_string()));
+
+    std::fs::remove_file(&path).unwrap();
+    assert!(repl.eval(":reload").is_err());
+}
+
+fn run(string: &str, interpreter: &mut Interpreter) -> Result<Vec<Value>, RuntimeError> {
+    let tokens = lexer::lex(string.to_string()).unwrap();
+    let ast = parser::parse(&tokens).unwrap();
+    interpreter.interpret(&ast)
+}
+
+#[test]
+fn test_memory_limit_allows_small_programs() {
+    let mut interpreter = Interpreter::with_capabilities(Capabilities::all());
+    interpreter.set_memory_limit(Some(1024));
+    assert_eq!(
+        run("def x: str -> \"small\"\n", &mut interpreter),
+        Ok(vec![])
+    );
+}
+
+#[test]
+fn test_memory_limit_exceeded() {
+    let mut interpreter = Interpreter::with_capabilities(Capabilities::all());
+    interpreter.set_memory_limit(Some(256));
+    let long = "a".repeat(300);
+    assert_eq!(
+        run(&format!("def x: str -> \"{long}\"\n"), &mut interpreter),
+        Err(RuntimeError::Message(
+            "Runtime Error: memory limit exceeded".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_interpret_returns_values_and_errors() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_max_depth(50);
+    assert_eq!(
+        run("def x: int -> 2\n+ x 1\n\"a\"\n", &mut interpreter),
+        Ok(vec![Value::Int(3), Value::Str("a".to_string())])
+    );
+    assert_eq!(
+        run("missing\n", &mut interpreter),
+        Err(RuntimeError::UndefinedVariable("missing".to_string()))
+    );
+    assert_eq!(
+        run("+ 1 \"a\"\n", &mut interpreter),
+        Err(RuntimeError::TypeMismatch(
+            "'+' can only add ints and nums, or strs to strs".to_string()
+        ))
+    );
+    assert_eq!(
+        run(
+            "def add: int a: int b: int -> + a b\nadd 1 2 3\n",
+            &mut interpreter
+        ),
+        Err(RuntimeError::ArityMismatch {
+            expected: 2,
+            found: 3
+        })
+    );
+    assert_eq!(
+        run("/ 1 0\n", &mut interpreter),
+        Err(RuntimeError::DivisionByZero)
+    );
+    assert_eq!(
+        run("throw \"oops\"\n", &mut interpreter),
+        Err(RuntimeError::Uncaught("oops".to_string()))
+    );
+}
+
+#[test]
+fn test_macros() {
+    let mut pipeline = Pipeline::new();
+    let defined = "defmacro twice x (quote (+ (unquote x) (unquote x)))\n";
+    assert_eq!(pipeline.run(&source(defined)), Ok(()));
+    assert_eq!(pipeline.run(&source("def y: num -> twice 4\n")), Ok(()));
+    assert_eq!(pipeline.run(&source("twice y\n")), Ok(()));
+
+    assert_eq!(
+        pipeline.run(&source("twice 1 2\n")),
+        Err(LinusError::Parse(vec![Diagnostic::error(
+            codes::MACRO,
+            "Wrong number of arguments to macro"
+        )]))
+    );
+    assert_eq!(
+        linus::run(&source("defmacro bad x (+ x x)\n")),
+        Err(LinusError::Parse(vec![Diagnostic::error(
+            codes::MACRO,
+            "A macro's body must be a quoted template"
+        )]))
+    );
+}
+
+#[test]
+fn test_imports() {
+    let dir = std::env::temp_dir().join(format!("linus-imports-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(
+        dir.join("lib/math.ln"),
+        "import \"shared.ln\"\ndef double: num x: num -> twice x\nprint \"not run\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lib/shared.ln"),
+        "defmacro twice x (quote (* 2 (unquote x)))\ndef one: int -> 1\n",
+    )
+    .unwrap();
+    let main = dir.join("main.ln");
+    let program = |string: &str| Source {
+        path: main.to_string_lossy().into_owned(),
+        string: string.to_string(),
+    };
+
+    let mut pipeline = Pipeline::new();
+    let imported = "import \"lib/math.ln\"\nimport \"lib/shared.ln\"\ndef y: num -> double one\n";
+    assert_eq!(pipeline.run(&program(imported)), Ok(()));
+    assert_eq!(pipeline.run(&source("twice y\n")), Ok(()));
+
+    // what is imported is type checked like the rest of the file
+    let err = pipeline
+        .run(&program("import \"lib/math.ln\"\ndouble \"a\"\n"))
+        .unwrap_err();
+    assert_eq!(err.stage(), "type checking");
+
+    let err = pipeline
+        .run(&program("import \"missing.ln\"\n"))
+        .unwrap_err();
+    assert_eq!(err.stage(), "importing");
+    let summary = linus::diagnostics::summary(&err.diagnostics()[0], pipeline.sources());
+    assert!(summary.contains("main.ln:1:8: Could not import "));
+
+    std::fs::write(dir.join("lib/shared.ln"), "import \"math.ln\"\n").unwrap();
+    let err = pipeline
+        .run(&program("import \"lib/math.ln\"\n"))
+        .unwrap_err();
+    let lib = dir.join("lib").to_string_lossy().into_owned();
+    assert_eq!(
+        err.messages(),
+        [format!(
+            "Import cycle: {lib}/math.ln -> {lib}/shared.ln -> {lib}/math.ln"
+        )]
+    );
+    assert_eq!(err.diagnostics()[0].code, codes::IMPORT);
+
+    // the sandbox can't read files by importing them either
+    std::fs::write(dir.join("lib/shared.ln"), "def one: int -> 1\n").unwrap();
+    let mut sandboxed = Pipeline::with_capabilities(Capabilities::sandboxed());
+    let err = sandboxed
+        .run(&program("import \"lib/shared.ln\"\n"))
+        .unwrap_err();
+    assert_eq!(err.stage(), "importing");
+    assert_eq!(
+        err.messages(),
+        [format!(
+            "Could not import '{}/lib/shared.ln': Operation not permitted in sandbox",
+            dir.to_string_lossy()
+        )]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_pipeline_optimize() {
+    let mut pipeline = Pipeline::new();
+    pipeline.set_optimize(true);
+    assert_eq!(
+        pipeline.run(&source("def x: num -> + 1 (/ 2 (* 3 (- 4 5)))\n")),
+        Ok(())
+    );
+    // types are checked before folding, and a failing operation still fails as it runs
+    let err = pipeline.run(&source("+ 1 true\n")).unwrap_err();
+    assert_eq!(err.stage(), "type checking");
+    pipeline.set_type_check(false);
+    let err = pipeline.run(&source("+ 1 true\n")).unwrap_err();
+    assert_eq!(err.stage(), "running");
+}
+
+#[test]
+fn test_debugger() {
+    let output = CapturedOutput::new();
+    let commands = ScriptedInput::new(["", "break double", "continue", "next"]);
+    let mut pipeline = Pipeline::new();
+    pipeline.interpreter().set_max_depth(50);
+    pipeline.interpreter().set_output(Box::new(output.clone()));
+    let debugger = Debugger::new(Box::new(commands), Box::new(output.clone()));
+    pipeline
+        .interpreter()
+        .set_observer(Some(Box::new(debugger)));
+
+    let program = "def double: int x: int -> * 2 x\ndouble 3\n";
+    assert_eq!(pipeline.run(&source(program)), Ok(()));
+    assert_eq!(
+        output.stderr(),
+        [
+            "(def double int ((x int)) (* 2 x))",
+            "(double 3)",
+            "  double = <function double>",
+            "Stopping whenever double is called",
+            "Called double",
+            "  double = <function double>",
+            "  x = 3",
+            "(* 2 x)",
+            "  double = <function double>",
+            "  x = 3",
+        ]
+    );
+    // the commands ran out, so the rest ran without stopping
+    assert_eq!(output.stdout().last().map(String::as_str), Some("6"));
+}
+
+#[test]
+fn test_tracer() {
+    let output = CapturedOutput::new();
+    let mut pipeline = Pipeline::new();
+    pipeline.interpreter().set_max_depth(50);
+    pipeline.interpreter().set_output(Box::new(output.clone()));
+    let tracer = Tracer::new(Box::new(output.clone()));
+    pipeline.interpreter().set_observer(Some(Box::new(tracer)));
+
+    assert_eq!(pipeline.run(&source("+ 1 * 2 3\n")), Ok(()));
+    assert_eq!(
+        output.stderr(),
+        [
+            "  1 => 1",
+            "    2 => 2",
+            "    3 => 3",
+            "  (* 2 3) => 6",
+            "(+ 1 (* 2 3)) => 7",
+        ]
+    );
+
+    let output = CapturedOutput::new();
+    let mut pipeline = Pipeline::new();
+    pipeline.interpreter().set_max_depth(50);
+    let tracer = Tracer::new(Box::new(output.clone()));
+    pipeline.interpreter().set_observer(Some(Box::new(tracer)));
+    assert!(pipeline.run(&source("/ 1 0\n")).is_err());
+    assert_eq!(
+        output.stderr().last().map(String::as_str),
+        Some("(/ 1 0) raised Cannot divide by zero")
+    );
+}
+
+#[test]
+fn test_testing() {
+    let dir = std::env::temp_dir().join(format!("linus-testing-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("nested")).unwrap();
+    for file in ["math_test.ln", "math.ln", "nested/more_test.ln"] {
+        std::fs::write(dir.join(file), "").unwrap();
+    }
+    let found = testing::discover(&[dir.to_string_lossy().into_owned()]).unwrap();
+    let dir = dir.to_string_lossy();
+    assert_eq!(
+        found,
+        vec![
+            format!("{dir}/math_test.ln"),
+            format!("{dir}/nested/more_test.ln")
+        ]
+    );
+    std::fs::remove_dir_all(&*dir).unwrap();
+
+    let program = "def double: int x: int -> * 2 x\n\
+                   def test_double: _ -> assert-eq (double 2) 4\n\
+                   def test_wrong: _ -> assert-eq (double 2) 5\n\
+                   def test_alone: _ -> double test_double\n";
+    let pipeline = || {
+        let mut pipeline = Pipeline::new();
+        pipeline.interpreter().set_max_depth(50);
+        pipeline
+    };
+    let outcomes = testing::run(&source(program), pipeline);
+    let names: Vec<&str> = outcomes.iter().map(|outcome| &*outcome.name).collect();
+    assert_eq!(
+        names,
+        [
+            "test.lin::test_double",
+            "test.lin::test_wrong",
+            "test.lin::test_alone"
+        ]
+    );
+    assert!(outcomes[0].passed());
+    assert!(outcomes[1].failures[0].contains("Assertion failed: expected 5, got 4"));
+    // each test runs without the others
+    assert!(outcomes[2].failures[0].contains("'test_double' is not defined"));
+
+    // a file without tests is a test of its own
+    let outcomes = testing::run(&source("assert (= 1 2) \"one is two\"\n"), pipeline);
+    assert_eq!(outcomes.len(), 1);
+    assert!(outcomes[0].failures[0].contains("Assertion failed: one is two"));
+}
@@ -0,0 +1,792 @@
+//! Tokens and expressions written out for tools to read, as S-expressions or JSON.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::str::FromStr;
+
+use lexer::{Piece, Spanned, Token};
+
+use crate::{Arm, Binding, Expr, Param, Pattern};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `(def x num (+ 1 2))`, with literals written as they are in source.
+    Sexpr,
+    /// An array with an object for each token or expression, which says what kind
+    /// it is.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = &'static str;
+
+    fn from_str(name: &str) -> Result<Format, &'static str> {
+        match name {
+            "sexpr" => Ok(Format::Sexpr),
+            "json" => Ok(Format::Json),
+            _ => Err("Unknown format, expected sexpr or json"),
+        }
+    }
+}
+
+/// Each token, with the line and column it starts at: one per line as
+/// `(Kind value line col)`, or as objects that also have its byte range.
+pub fn tokens(tokens: &[Spanned], format: Format) -> String {
+    let mut written = String::new();
+    match format {
+        Format::Sexpr => {
+            for spanned in tokens {
+                let _ = write!(written, "({}", kind(&spanned.token));
+                if let Some(value) = value(&spanned.token) {
+                    let _ = write!(written, " {}", source_text(value));
+                }
+                let location = &spanned.location;
+                let _ = writeln!(written, " {} {})", location.line, location.col);
+            }
+        }
+        Format::Json => {
+            written.push('[');
+            for (idx, spanned) in tokens.iter().enumerate() {
+                if idx > 0 {
+                    written.push(',');
+                }
+                let _ = write!(written, "{{\"kind\":\"{}\"", kind(&spanned.token));
+                if let Some(value) = value(&spanned.token) {
+                    written.push_str(",\"value\":");
+                    json_literal(&mut written, value);
+                }
+                let location = &spanned.location;
+                let _ = write!(
+                    written,
+                    ",\"line\":{},\"col\":{},\"start\":{},\"end\":{}}}",
+                    location.line, location.col, location.range.start, location.range.end
+                );
+            }
+            written.push_str("]\n");
+        }
+    }
+    written
+}
+
+/// Each top-level expression, as `parse` returns them: one per line as an
+/// S-expression, or as objects. The `EOF` that `parse` ends with is left out.
+pub fn ast(exprs: &[Expr], format: Format) -> String {
+    let exprs = match exprs {
+        [rest @ .., Expr::Literal { token: Token::EOF }] => rest,
+        _ => exprs,
+    };
+    let mut written = String::new();
+    match format {
+        Format::Sexpr => {
+            for expr in exprs {
+                sexpr(&mut written, expr);
+                written.push('\n');
+            }
+        }
+        Format::Json => {
+            written.push('[');
+            for (idx, expr) in exprs.iter().enumerate() {
+                if idx > 0 {
+                    written.push(',');
+                }
+                json(&mut written, expr);
+            }
+            written.push_str("]\n");
+        }
+    }
+    written
+}
+
+/// An expression as an S-expression, as `ast` writes each one.
+pub fn expr_sexpr(expr: &Expr) -> String {
+    let mut written = String::new();
+    sexpr(&mut written, expr);
+    written
+}
+
+/// How a token is written in source, so that it lexes back to the same token.
+pub fn source_text(token: &Token) -> String {
+    match token {
+        Token::Symbol(name) => name.to_string(),
+        Token::TypeDecl(string) => string.clone(),
+        Token::Str(string) => {
+            let mut quoted = String::from("\"");
+            escape(&mut quoted, string);
+            quoted.push('"');
+            quoted
+        }
+        Token::Interpolated(pieces) => {
+            let mut quoted = String::from("\"");
+            for piece in pieces {
+                match piece {
+                    Piece::Text(text) => escape(&mut quoted, text),
+                    Piece::Code(tokens) => {
+                        let code: Vec<String> = tokens
+                            .iter()
+                            .filter(|token| **token != Token::EOF)
+                            .map(source_text)
+                            .collect();
+                        let _ = write!(quoted, "{{{}}}", code.join(" "));
+                    }
+                }
+            }
+            quoted.push('"');
+            quoted
+        }
+        Token::Int(int) => format!("{int}"),
+        // keep the fraction, so a whole num doesn't read back as an int
+        Token::Num(num) => format!("{num:?}"),
+        Token::True => "true".to_string(),
+        Token::False => "false".to_string(),
+        Token::None => "none".to_string(),
+        Token::Add => "+".to_string(),
+        Token::Subtract => "-".to_string(),
+        Token::Multiply => "*".to_string(),
+        Token::Divide => "/".to_string(),
+        Token::GreaterThan => ">".to_string(),
+        Token::LessThan => "<".to_string(),
+        Token::GreaterThanOrEqual => ">=".to_string(),
+        Token::LessThanOrEqual => "<=".to_string(),
+        Token::Equal => "=".to_string(),
+        Token::And => "and".to_string(),
+        Token::Or => "or".to_string(),
+        Token::Not => "not".to_string(),
+        _ => format!("{token:?}"),
+    }
+}
+
+/// Adds `string` as it is written between the quotes of a str.
+pub fn escape(quoted: &mut String, string: &str) {
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '{' => quoted.push_str("\\{"),
+            '}' => quoted.push_str("\\}"),
+            c => quoted.push(c),
+        }
+    }
+}
+
+/// The name of the token's variant, such as `Symbol` or `Add`.
+fn kind(token: &Token) -> String {
+    let debug = format!("{token:?}");
+    match debug.split_once('(') {
+        Some((kind, _)) => kind.to_string(),
+        None => debug,
+    }
+}
+
+/// The token itself when it carries a value, such as a name or a literal.
+fn value(token: &Token) -> Option<&Token> {
+    match token {
+        Token::Symbol(_)
+        | Token::Str(_)
+        | Token::Interpolated(_)
+        | Token::Int(_)
+        | Token::Num(_)
+        | Token::TypeDecl(_) => Some(token),
+        _ => None,
+    }
+}
+
+fn sexpr(written: &mut String, expr: &Expr) {
+    match expr {
+        Expr::Assignment {
+            name,
+            type_decl,
+            expr,
+        } => {
+            let _ = write!(written, "(def {name} {} ", sexpr_type(type_decl));
+            sexpr(written, expr);
+            written.push(')');
+        }
+        Expr::Function {
+            name,
+            type_decl,
+            params,
+            body,
+        } => {
+            let _ = write!(written, "(def {name} {} ", sexpr_type(type_decl));
+            sexpr_params(written, params);
+            written.push(' ');
+            sexpr(written, body);
+            written.push(')');
+        }
+        Expr::Literal { token } | Expr::Variable { name: token } | Expr::Operator { token } => {
+            written.push_str(&source_text(token))
+        }
+        Expr::FunctionCall {
+            operator, operand, ..
+        } => {
+            let _ = write!(written, "({}", source_text(operator));
+            sexpr_items(written, operand);
+            written.push(')');
+        }
+        Expr::Let { bindings, body } => {
+            written.push_str("(let ");
+            sexpr_bindings(written, bindings);
+            written.push(' ');
+            sexpr(written, body);
+            written.push(')');
+        }
+        Expr::Do { exprs } => {
+            written.push_str("(do");
+            sexpr_items(written, exprs);
+            written.push(')');
+        }
+        Expr::Loop {
+            bindings,
+            condition,
+            recur,
+            result,
+        } => {
+            written.push_str("(loop ");
+            sexpr_bindings(written, bindings);
+            written.push_str(" (while ");
+            sexpr(written, condition);
+            written.push_str(") (recur");
+            sexpr_items(written, recur);
+            written.push_str(") ");
+            sexpr(written, result);
+            written.push(')');
+        }
+        Expr::Try {
+            body,
+            catch,
+            finally,
+        } => {
+            written.push_str("(try ");
+            sexpr(written, body);
+            if let Some(catch) = catch {
+                let _ = write!(written, " (catch {} ", catch.name);
+                sexpr(written, &catch.body);
+                written.push(')');
+            }
+            if let Some(finally) = finally {
+                written.push_str(" (finally ");
+                sexpr(written, finally);
+                written.push(')');
+            }
+            written.push(')');
+        }
+        Expr::Interpolation { parts } => {
+            written.push_str("(interpolate");
+            sexpr_items(written, parts);
+            written.push(')');
+        }
+        Expr::Named { name, value } => {
+            let _ = write!(written, "(named {name} ");
+            sexpr(written, value);
+            written.push(')');
+        }
+        Expr::Seq { items } => {
+            written.push('[');
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 {
+                    written.push(' ');
+                }
+                sexpr(written, item);
+            }
+            written.push(']');
+        }
+        Expr::Hash { entries } => {
+            written.push('{');
+            for (idx, (key, value)) in entries.iter().enumerate() {
+                if idx > 0 {
+                    written.push(' ');
+                }
+                sexpr(written, key);
+                written.push(' ');
+                sexpr(written, value);
+            }
+            written.push('}');
+        }
+        Expr::Lambda { params, body } => {
+            written.push_str("(lambda ");
+            sexpr_params(written, params);
+            written.push(' ');
+            sexpr(written, body);
+            written.push(')');
+        }
+        Expr::Set { name, expr } => {
+            let _ = write!(written, "(set {name} ");
+            sexpr(written, expr);
+            written.push(')');
+        }
+        Expr::Throw { value } => {
+            written.push_str("(throw ");
+            sexpr(written, value);
+            written.push(')');
+        }
+        Expr::Match { value, arms } => {
+            written.push_str("(match ");
+            sexpr(written, value);
+            for arm in arms {
+                let _ = write!(written, " ({} ", pattern_text(&arm.pattern));
+                sexpr(written, &arm.body);
+                written.push(')');
+            }
+            written.push(')');
+        }
+        Expr::Import { path } => {
+            let _ = write!(
+                written,
+                "(import {})",
+                source_text(&Token::Str(path.clone()))
+            );
+        }
+    }
+}
+
+/// A pattern as it is written in the source.
+pub fn pattern_text(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Literal(token) => source_text(token),
+        Pattern::Wildcard => "_".to_string(),
+        Pattern::Name(name) => name.to_string(),
+        Pattern::Seq(items) => {
+            let mut written = String::from("[");
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 {
+                    written.push(' ');
+                }
+                written.push_str(&pattern_text(item));
+            }
+            written.push(']');
+            written
+        }
+    }
+}
+
+/// Each expression, with a space before it.
+fn sexpr_items(written: &mut String, exprs: &[Expr]) {
+    for expr in exprs {
+        written.push(' ');
+        sexpr(written, expr);
+    }
+}
+
+fn sexpr_params(written: &mut String, params: &[Param]) {
+    written.push('(');
+    for (idx, param) in params.iter().enumerate() {
+        if idx > 0 {
+            written.push(' ');
+        }
+        let _ = write!(written, "({} {}", param.name, sexpr_type(&param.type_decl));
+        if let Some(default) = &param.default {
+            written.push(' ');
+            sexpr(written, default);
+        }
+        written.push(')');
+    }
+    written.push(')');
+}
+
+fn sexpr_bindings(written: &mut String, bindings: &[Binding]) {
+    written.push('(');
+    for (idx, binding) in bindings.iter().enumerate() {
+        if idx > 0 {
+            written.push(' ');
+        }
+        let _ = write!(
+            written,
+            "({} {} ",
+            binding.name,
+            sexpr_type(&binding.type_decl)
+        );
+        sexpr(written, &binding.expr);
+        written.push(')');
+    }
+    written.push(')');
+}
+
+/// A declared type as one item, so a function type is put in parentheses.
+fn sexpr_type(type_decl: &str) -> String {
+    match type_decl.contains(' ') {
+        true => format!("({type_decl})"),
+        false => type_decl.to_string(),
+    }
+}
+
+fn json(written: &mut String, expr: &Expr) {
+    match expr {
+        Expr::Assignment {
+            name,
+            type_decl,
+            expr,
+        } => {
+            json_start(written, "Assignment");
+            json_field(written, "name", name);
+            json_field(written, "type", type_decl);
+            written.push_str(",\"expr\":");
+            json(written, expr);
+        }
+        Expr::Function {
+            name,
+            type_decl,
+            params,
+            body,
+        } => {
+            json_start(written, "Function");
+            json_field(written, "name", name);
+            json_field(written, "type", type_decl);
+            json_params(written, params);
+            written.push_str(",\"body\":");
+            json(written, body);
+        }
+        Expr::Literal { token } => {
+            json_start(written, "Literal");
+            written.push_str(",\"value\":");
+            json_literal(written, token);
+        }
+        Expr::Variable { name } => {
+            json_start(written, "Variable");
+            json_field(written, "name", &source_text(name));
+        }
+        Expr::Operator { token } => {
+            json_start(written, "Operator");
+            json_field(written, "operator", &source_text(token));
+        }
+        Expr::FunctionCall {
+            operator, operand, ..
+        } => {
+            json_start(written, "FunctionCall");
+            json_field(written, "operator", &source_text(operator));
+            json_exprs(written, "operands", operand);
+        }
+        Expr::Let { bindings, body } => {
+            json_start(written, "Let");
+            json_bindings(written, bindings);
+            written.push_str(",\"body\":");
+            json(written, body);
+        }
+        Expr::Do { exprs } => {
+            json_start(written, "Do");
+            json_exprs(written, "exprs", exprs);
+        }
+        Expr::Loop {
+            bindings,
+            condition,
+            recur,
+            result,
+        } => {
+            json_start(written, "Loop");
+            json_bindings(written, bindings);
+            written.push_str(",\"condition\":");
+            json(written, condition);
+            json_exprs(written, "recur", recur);
+            written.push_str(",\"result\":");
+            json(written, result);
+        }
+        Expr::Try {
+            body,
+            catch,
+            finally,
+        } => {
+            json_start(written, "Try");
+            written.push_str(",\"body\":");
+            json(written, body);
+            written.push_str(",\"catch\":");
+            match catch {
+                Some(catch) => {
+                    written.push('{');
+                    written.push_str("\"name\":");
+                    json_str(written, &catch.name);
+                    written.push_str(",\"body\":");
+                    json(written, &catch.body);
+                    written.push('}');
+                }
+                None => written.push_str("null"),
+            }
+            written.push_str(",\"finally\":");
+            match finally {
+                Some(finally) => json(written, finally),
+                None => written.push_str("null"),
+            }
+        }
+        Expr::Interpolation { parts } => {
+            json_start(written, "Interpolation");
+            json_exprs(written, "parts", parts);
+        }
+        Expr::Named { name, value } => {
+            json_start(written, "Named");
+            json_field(written, "name", name);
+            written.push_str(",\"value\":");
+            json(written, value);
+        }
+        Expr::Seq { items } => {
+            json_start(written, "Seq");
+            json_exprs(written, "items", items);
+        }
+        Expr::Hash { entries } => {
+            json_start(written, "Hash");
+            written.push_str(",\"entries\":[");
+            for (idx, (key, value)) in entries.iter().enumerate() {
+                if idx > 0 {
+                    written.push(',');
+                }
+                written.push('[');
+                json(written, key);
+                written.push(',');
+                json(written, value);
+                written.push(']');
+            }
+            written.push(']');
+        }
+        Expr::Lambda { params, body } => {
+            json_start(written, "Lambda");
+            json_params(written, params);
+            written.push_str(",\"body\":");
+            json(written, body);
+        }
+        Expr::Set { name, expr } => {
+            json_start(written, "Set");
+            json_field(written, "name", name);
+            written.push_str(",\"expr\":");
+            json(written, expr);
+        }
+        Expr::Throw { value } => {
+            json_start(written, "Throw");
+            written.push_str(",\"value\":");
+            json(written, value);
+        }
+        Expr::Match { value, arms } => {
+            json_start(written, "Match");
+            written.push_str(",\"value\":");
+            json(written, value);
+            json_arms(written, arms);
+        }
+        Expr::Import { path } => {
+            json_start(written, "Import");
+            json_field(written, "path", path);
+        }
+    }
+    written.push('}');
+}
+
+fn json_arms(written: &mut String, arms: &[Arm]) {
+    written.push_str(",\"arms\":[");
+    for (idx, arm) in arms.iter().enumerate() {
+        if idx > 0 {
+            written.push(',');
+        }
+        written.push_str("{\"pattern\":");
+        json_pattern(written, &arm.pattern);
+        written.push_str(",\"body\":");
+        json(written, &arm.body);
+        written.push('}');
+    }
+    written.push(']');
+}
+
+fn json_pattern(written: &mut String, pattern: &Pattern) {
+    match pattern {
+        Pattern::Literal(token) => {
+            json_start(written, "Literal");
+            written.push_str(",\"value\":");
+            json_literal(written, token);
+        }
+        Pattern::Wildcard => json_start(written, "Wildcard"),
+        Pattern::Name(name) => {
+            json_start(written, "Name");
+            json_field(written, "name", name);
+        }
+        Pattern::Seq(items) => {
+            json_start(written, "Seq");
+            written.push_str(",\"items\":[");
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 {
+                    written.push(',');
+                }
+                json_pattern(written, item);
+            }
+            written.push(']');
+        }
+    }
+    written.push('}');
+}
+
+/// Opens the object for an expression, with its kind.
+fn json_start(written: &mut String, kind: &str) {
+    let _ = write!(written, "{{\"kind\":\"{kind}\"");
+}
+
+fn json_field(written: &mut String, name: &str, value: &str) {
+    let _ = write!(written, ",\"{name}\":");
+    json_str(written, value);
+}
+
+fn json_exprs(written: &mut String, name: &str, exprs: &[Expr]) {
+    let _ = write!(written, ",\"{name}\":[");
+    for (idx, expr) in exprs.iter().enumerate() {
+        if idx > 0 {
+            written.push(',');
+        }
+        json(written, expr);
+    }
+    written.push(']');
+}
+
+fn json_params(written: &mut String, params: &[Param]) {
+    written.push_str(",\"params\":[");
+    for (idx, param) in params.iter().enumerate() {
+        if idx > 0 {
+            written.push(',');
+        }
+        written.push_str("{\"name\":");
+        json_str(written, &param.name);
+        written.push_str(",\"type\":");
+        json_str(written, &param.type_decl);
+        written.push_str(",\"default\":");
+        match &param.default {
+            Some(default) => json(written, default),
+            None => written.push_str("null"),
+        }
+        written.push('}');
+    }
+    written.push(']');
+}
+
+fn json_bindings(written: &mut String, bindings: &[Binding]) {
+    written.push_str(",\"bindings\":[");
+    for (idx, binding) in bindings.iter().enumerate() {
+        if idx > 0 {
+            written.push(',');
+        }
+        written.push_str("{\"name\":");
+        json_str(written, &binding.name);
+        written.push_str(",\"type\":");
+        json_str(written, &binding.type_decl);
+        written.push_str(",\"expr\":");
+        json(written, &binding.expr);
+        written.push('}');
+    }
+    written.push(']');
+}
+
+/// A literal or name as a JSON value. Nums that JSON can't represent, which are
+/// infinite, are `null`.
+fn json_literal(written: &mut String, token: &Token) {
+    match token {
+        Token::Str(string) | Token::TypeDecl(string) => json_str(written, string),
+        Token::Symbol(name) => json_str(written, name),
+        Token::Interpolated(_) => json_str(written, &source_text(token)),
+        Token::Int(int) => {
+            let _ = write!(written, "{int}");
+        }
+        Token::Num(num) if num.is_finite() => {
+            let _ = write!(written, "{num:?}");
+        }
+        Token::True => written.push_str("true"),
+        Token::False => written.push_str("false"),
+        _ => written.push_str("null"),
+    }
+}
+
+fn json_str(written: &mut String, string: &str) {
+    written.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => written.push_str("\\\""),
+            '\\' => written.push_str("\\\\"),
+            '\n' => written.push_str("\\n"),
+            '\r' => written.push_str("\\r"),
+            '\t' => written.push_str("\\t"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(written, "\\u{:04x}", u32::from(c));
+            }
+            c => written.push(c),
+        }
+    }
+    written.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diagnostics::SourceId;
+
+    fn parse(source: &str) -> alloc::vec::Vec<Expr> {
+        let tokens = lexer::lex(source.to_string()).expect("test source should lex");
+        crate::parse(&tokens).expect("test source should parse")
+    }
+
+    #[test]
+    fn test_ast_sexpr() {
+        let exprs = parse(
+            "def add: num x: num y: num -> + x y\ndef f: int -> int -> \\x: int -> x\nlet a: int -> 2; [a \"b\\n\" 2.0]\ntry throw 1; catch err -> map (+) {1 none}\n",
+        );
+        assert_eq!(
+            ast(&exprs, Format::Sexpr),
+            "(def add num ((x num) (y num)) (+ x y))\n\
+             (def f (int -> int) (lambda ((x int)) x))\n\
+             (let ((a int 2)) [a \"b\\n\" 2.0])\n\
+             (try (throw 1) (catch err (map + {1 none})))\n"
+        );
+        assert_eq!(
+            ast(
+                &parse("match xs\n    [] -> 0\n    [x _] -> x\n    _ -> -1\n"),
+                Format::Sexpr
+            ),
+            "(match xs ([] 0) ([x _] x) (_ -1))\n"
+        );
+        assert_eq!(
+            ast(
+                &parse("def f: num x: num = 1 -> x\nf x: (+ 1 2)\n"),
+                Format::Sexpr
+            ),
+            "(def f num ((x num 1)) x)\n(f (named x (+ 1 2)))\n"
+        );
+        // a pipeline is the calls it stands for
+        assert_eq!(
+            ast(
+                &parse(
+                    "xs |> map inc |> len
+xs
+    |> map f
+    |> - 1
+"
+                ),
+                Format::Sexpr
+            ),
+            "(len (map inc xs))\n(- 1 (map f xs))\n"
+        );
+    }
+
+    #[test]
+    fn test_ast_json() {
+        let exprs = parse("def x: num -> print \"a\\\"\"\n");
+        assert_eq!(
+            ast(&exprs, Format::Json),
+            "[{\"kind\":\"Assignment\",\"name\":\"x\",\"type\":\"num\",\"expr\":\
+             {\"kind\":\"FunctionCall\",\"operator\":\"print\",\"operands\":\
+             [{\"kind\":\"Literal\",\"value\":\"a\\\"\"}]}}]\n"
+        );
+        assert_eq!(
+            ast(&parse("match n; 0 -> true; _ -> false\n"), Format::Json),
+            "[{\"kind\":\"Match\",\"value\":{\"kind\":\"Variable\",\"name\":\"n\"},\"arms\":[\
+             {\"pattern\":{\"kind\":\"Literal\",\"value\":0},\"body\":{\"kind\":\"Literal\",\"value\":true}},\
+             {\"pattern\":{\"kind\":\"Wildcard\"},\"body\":{\"kind\":\"Literal\",\"value\":false}}]}]\n"
+        );
+    }
+
+    #[test]
+    fn test_tokens() {
+        let tokens = lexer::lex_spanned("+ 1\n  x", SourceId(0)).expect("test source should lex");
+        assert_eq!(
+            super::tokens(&tokens, Format::Sexpr),
+            "(Add 1 1)\n(Int 1 1 3)\n(Indent 1 4)\n(Symbol x 2 3)\n(Dedent 2 4)\n(EOF 2 4)\n"
+        );
+        assert!(super::tokens(&tokens, Format::Json).starts_with(
+            "[{\"kind\":\"Add\",\"line\":1,\"col\":1,\"start\":0,\"end\":1},\
+             {\"kind\":\"Int\",\"value\":1,"
+        ));
+        assert_eq!("json".parse(), Ok(Format::Json));
+        assert!("yaml".parse::<Format>().is_err());
+    }
+}
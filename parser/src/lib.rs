@@ -1,19 +1,118 @@
-use lexer::Token;
-use std::cell::Cell;
+#![cfg_attr(not(feature = "std"), no_std)]
 
-#[derive(Debug)]
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use diagnostics::{codes, Diagnostic, SourceId, Span};
+use lexer::{Piece, Spanned, Symbol, Token};
+
+pub mod dump;
+pub mod macros;
+pub mod names;
+mod stream;
+#[cfg(feature = "testing")]
+pub mod testing;
+
+pub use stream::{parse_stream, Exprs};
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Assignment {
-        name: String,
+        name: Symbol,
         type_decl: String,
         expr: Box<Expr>,
     },
+    /// A `def` with parameters, e.g. `def sum: num x: num y: num -> + x y`.
+    Function {
+        name: Symbol,
+        type_decl: String,
+        params: Vec<Param>,
+        body: Box<Expr>,
+    },
     Literal {
         token: Token,
     },
     FunctionCall {
         operator: Token,
         operand: Vec<Expr>,
+        /// Where the function is named, when the tokens were parsed along with
+        /// where they are, so that an error in the call can point at it.
+        span: Option<Span>,
+    },
+    /// `let x: num -> 1; + x 1`, with the bindings only in scope for the body.
+    Let {
+        bindings: Vec<Binding>,
+        body: Box<Expr>,
+    },
+    /// `do print 1; print 2`: evaluates each expression in order, to the last one.
+    Do {
+        exprs: Vec<Expr>,
+    },
+    /// `loop i: num -> 0; while < i 5; recur (+ i 1); i`: while the condition holds,
+    /// the bindings are replaced by the `recur` values. Then the loop evaluates to
+    /// its result.
+    Loop {
+        bindings: Vec<Binding>,
+        condition: Box<Expr>,
+        recur: Vec<Expr>,
+        result: Box<Expr>,
+    },
+    /// `try body; catch err -> handler; finally cleanup`: if the body raises, the
+    /// handler runs with what was raised bound to the name. The cleanup always runs.
+    Try {
+        body: Box<Expr>,
+        catch: Option<Box<Catch>>,
+        finally: Option<Box<Expr>>,
+    },
+    /// `[a b c]`: a seq of the values of its items.
+    Seq {
+        items: Vec<Expr>,
+    },
+    /// `{k v}`: a hash of each key to the value after it.
+    Hash {
+        entries: Vec<(Expr, Expr)>,
+    },
+    /// `\x: num -> + x 1`: a function without a name, evaluated to a function value.
+    Lambda {
+        params: Vec<Param>,
+        body: Box<Expr>,
+    },
+    /// `throw value`: raises the value, to be caught by the nearest `try`.
+    Throw {
+        value: Box<Expr>,
+    },
+    /// `match n; 0 -> "none"; _ -> "some"`: the body of the first arm whose pattern
+    /// fits the value, with the names in the pattern bound to what they fit.
+    Match {
+        value: Box<Expr>,
+        arms: Vec<Arm>,
+    },
+    /// `set x -> + x 1`: gives a binding that already exists, in the innermost scope
+    /// that has it, a new value.
+    Set {
+        name: Symbol,
+        expr: Box<Expr>,
+    },
+    /// `import "lib.ln"`: the definitions of another file, relative to this one. Only
+    /// allowed at the top level, and replaced by what it imports before running.
+    Import {
+        path: String,
+    },
+    /// `"{name} scored {+ a b}"`: a str of the values of its parts, one after the
+    /// other, where the text between the braces is a str literal.
+    Interpolation {
+        parts: Vec<Expr>,
+    },
+    /// `width: 3` among the operands of a call: an argument for the parameter of
+    /// that name, rather than for the next one in order.
+    Named {
+        name: Symbol,
+        value: Box<Expr>,
     },
     Operator {
         token: Token,
@@ -23,31 +122,95 @@ pub enum Expr {
     },
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: Symbol,
+    pub type_decl: String,
+    /// What the parameter is when a call leaves it out, declared as in
+    /// `height: num = 1`.
+    pub default: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub name: Symbol,
+    pub type_decl: String,
+    pub expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Catch {
+    pub name: Symbol,
+    pub body: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub body: Expr,
+    /// Where the pattern is, when the tokens were parsed along with where they are.
+    pub span: Option<Span>,
+}
+
+/// What a value has to be like for a `match` arm to be picked.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    /// `1`, `"a"`, `true`, or `none`: a value equal to the literal, as `=` compares.
+    Literal(Token),
+    /// `_`: any value.
+    Wildcard,
+    /// `x`: any value, bound to the name.
+    Name(Symbol),
+    /// `[a 1 _]`: a seq of exactly as many items, each fitting its pattern.
+    Seq(Vec<Pattern>),
+}
+
 pub struct Parser<'a> {
     tokens: &'a [Token],
+    /// The same tokens with where they are in `source`, when that is known.
+    spanned: &'a [Spanned],
+    source: SourceId,
     idx: Cell<usize>,
+    /// Operands are collected here and then moved into a `Vec` of exactly the right
+    /// size, instead of each call growing its own. Nested calls push above the
+    /// operands of the calls they are inside.
+    scratch: RefCell<Vec<Expr>>,
+    /// How many parenthesized groups are open, so a `try` doesn't take the clauses
+    /// that follow the group it is in.
+    groups: Cell<usize>,
+    /// How many tokens from the start have been looked at, so that a stream of
+    /// tokens knows whether an expression could go on past those it has so far.
+    seen: Cell<usize>,
 }
 
 impl<'a> Parser<'a> {
-    fn new(tokens: &'a [Token]) -> Parser {
+    fn new(tokens: &'a [Token]) -> Parser<'a> {
         Parser {
-            tokens: tokens,
+            tokens,
+            spanned: &[],
+            source: SourceId::default(),
             idx: Cell::new(0),
+            scratch: RefCell::new(vec![]),
+            groups: Cell::new(0),
+            seen: Cell::new(0),
         }
     }
 
-    pub fn parse(&mut self) -> Result<Vec<Expr>, String> {
+    /// Parses every expression, or fails with each error and the index of the token
+    /// it was found at.
+    pub fn parse(&mut self) -> Result<Vec<Expr>, Vec<(usize, &'static str)>> {
         let mut exprs: Vec<Expr> = vec![];
-        let mut errs: Vec<&'static str> = vec![];
+        let mut errs: Vec<(usize, &'static str)> = vec![];
 
         while let Some(tok) = self.peek() {
-            if tok == &Token::Newline {
+            if tok == &Token::Newline || tok == &Token::Semicolon {
                 self.advance();
             } else {
                 match self.declaration() {
                     Ok(expr) => exprs.push(expr),
                     Err(err) => {
-                        errs.push(err);
+                        // the token that was just consumed is the one that didn't fit
+                        errs.push((self.idx.get().saturating_sub(1), err));
                         //self.synchronize();
                     }
                 }
@@ -55,7 +218,7 @@ impl<'a> Parser<'a> {
         }
 
         if !errs.is_empty() {
-            Err(errs.join("\n"))
+            Err(errs)
         } else {
             Ok(exprs)
         }
@@ -67,143 +230,744 @@ impl<'a> Parser<'a> {
                 // advance past def
                 self.advance();
                 // match on variable name
-                match self.advance() {
-                    Some(Token::Symbol(token)) => {
-                        // match on type delimiter ':', type, and assignment symbol '->'
-                        match (self.advance(), self.advance(), self.peek()) {
-                            (
-                                Some(&Token::TypeDelim),
-                                Some(&Token::TypeDecl(ref type_declaration)),
-                                Some(&Token::Assign)
-                            ) => {
-                                // advance past assignment symbol
-                                self.advance();
-                                // match on expression following assignment symbol
-                                match (self.expression()?, self.peek()) {
-                                    (e, Some(&Token::Indent))
-                                    | (e, Some(&Token::LeftParen))
-                                    | (e, Some(&Token::Appl)) 
-                                    | (e, Some(&Token::Newline)) 
-                                    | (e, Some(&Token::EOF)) => {
-                                        self.advance();
-                                        Ok(Expr::Assignment {
-                                            name: token.clone(),
-                                            type_decl: type_declaration.clone(),
-                                            expr: Box::new(e),
-                                        })
-                                    }
-                                    _ => Err("Error in global variable declaration: no expression following variable declaration."),
-                                }
-                            },
-                            _ => Err("Error in global variable declaration: invalid syntax after \"def\""),
-                        }
-                    },
-                    _ => Err("Invalid variable name."),
+                let name = match self.advance() {
+                    Some(Token::Symbol(name)) => *name,
+                    _ => return Err("Invalid variable name."),
+                };
+                // match on type delimiter ':' and type
+                let type_decl = match (self.advance(), self.type_decl()) {
+                    (Some(&Token::TypeDelim), Some(type_decl)) => type_decl,
+                    _ => return Err("Error in global variable declaration: invalid syntax after \"def\""),
+                };
+                let (params, indents) = self.params("Error in global variable declaration: invalid syntax after \"def\"")?;
+                // match on expression following assignment symbol; the
+                // expression parser may already have consumed the newline
+                let e = self.body(indents)?;
+                if let Some(&Token::Newline | &Token::Semicolon) = self.peek() {
+                    self.advance();
+                }
+                match params.is_empty() {
+                    true => Ok(Expr::Assignment {
+                        name,
+                        type_decl,
+                        expr: Box::new(e),
+                    }),
+                    false => Ok(Expr::Function {
+                        name,
+                        type_decl,
+                        params,
+                        body: Box::new(e),
+                    }),
                 }
             },
+            Some(&Token::Import) => {
+                self.advance();
+                let path = match self.advance() {
+                    Some(Token::Str(path)) => path.clone(),
+                    _ => return Err("Expected a path in quotes after \"import\""),
+                };
+                match self.peek() {
+                    Some(&Token::Newline | &Token::Semicolon) => {
+                        self.advance();
+                    }
+                    Some(&Token::EOF) | None => (),
+                    _ => return Err("Expected the end of the line after an import"),
+                }
+                Ok(Expr::Import { path })
+            }
             _ => self.special_expression(),
         }
     }
 
+    /// Parameters, which may be on indented lines of their own, up to the assignment
+    /// symbol '->', and how many indented blocks they started.
+    fn params(&self, invalid: &'static str) -> Result<(Vec<Param>, usize), &'static str> {
+        let mut params = vec![];
+        let mut indents = 0;
+        loop {
+            match self.advance() {
+                Some(Token::Indent) => indents += 1,
+                Some(Token::Newline) if indents > 0 => {}
+                Some(Token::Symbol(param)) => match (self.advance(), self.type_decl()) {
+                    (Some(&Token::TypeDelim), Some(param_type)) => {
+                        let default = match self.peek() {
+                            Some(Token::Equal) => {
+                                self.advance();
+                                Some(self.operand()?)
+                            }
+                            _ => None,
+                        };
+                        params.push(Param {
+                            name: *param,
+                            type_decl: param_type,
+                            default,
+                        })
+                    }
+                    _ => return Err("Error in function declaration: parameters need a type"),
+                },
+                Some(Token::Assign) => break,
+                _ => return Err(invalid),
+            }
+        }
+        Ok((params, indents))
+    }
+
+    /// A declared type: a name such as `num`, or a function's type, with the types of
+    /// its parameters and then of what it returns separated by `->`, such as
+    /// `num -> num -> bool`. A function type that is a parameter or the return type
+    /// of another is put in parentheses. It is written out again with single spaces.
+    fn type_decl(&self) -> Option<String> {
+        let mut type_decl = self.type_atom()?;
+        let mut arrows = false;
+        // a `->` is only part of the type if another type follows it, rather than
+        // the value that is assigned
+        while self.peek() == Some(&Token::Assign) && self.starts_type(self.idx.get() + 1) {
+            self.advance();
+            type_decl.push_str(" -> ");
+            type_decl.push_str(&self.type_atom()?);
+            arrows = true;
+        }
+        // parentheses around the whole type change nothing
+        if !arrows && type_decl.starts_with('(') {
+            type_decl = String::from(&type_decl[1..type_decl.len() - 1]);
+        }
+        Some(type_decl)
+    }
+
+    /// A type name, or a type in parentheses.
+    fn type_atom(&self) -> Option<String> {
+        match self.advance()? {
+            Token::TypeDecl(type_decl) => Some(type_decl.clone()),
+            Token::LeftParen => {
+                let type_decl = self.type_decl()?;
+                match self.advance()? {
+                    Token::RightParen if type_decl.contains(' ') => Some(format!("({type_decl})")),
+                    Token::RightParen => Some(type_decl),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the token at `idx` starts a type, which no expression starts with.
+    fn starts_type(&self, idx: usize) -> bool {
+        match self.token(idx) {
+            Some(Token::TypeDecl(_)) => true,
+            Some(Token::LeftParen) => self.starts_type(idx + 1),
+            _ => false,
+        }
+    }
+
+    /// The expression after a `->`, which may start on an indented line, then the
+    /// end of each of the `indents` blocks that it, or what came before it, started.
+    fn body(&self, mut indents: usize) -> Result<Expr, &'static str> {
+        match self.peek() {
+            Some(Token::Indent) => {
+                self.advance();
+                indents += 1;
+            }
+            // on the next line of the block the parameters started
+            Some(Token::Newline) if indents > 0 => {
+                self.advance();
+            }
+            _ => (),
+        }
+        let body = self.expression()?;
+        for _ in 0..indents {
+            self.dedent()?;
+        }
+        if indents > 0 {
+            self.end_group();
+        }
+        Ok(body)
+    }
+
+    #[allow(clippy::match_single_binding)]
     fn special_expression(&self) -> Result<Expr, &'static str> {
         match self.peek() {
-            // Some(&Token::Let) => {
-            //     self.advance();
-            //     self.let_special_expr()
-            // }
             // Some(&Token::If) => {
             //     self.advance();
             //     self.if_special_expr()
             // }
-            // Some(&Token::Do) => {
-            //     self.advance();
-            //     self.do_special_expr()
-            // }
             // Some(&Token::Loop) => {
             //     self.advance();
             //     self.loop_special_expr()
             // }
-            // Some(&Token::Try) => {
-            //     self.advance();
-            //     self.try_special_expr()
-            // }
-            // Some(&Token::Catch) => {
-            //     self.advance();
-            //     self.catch_special_expr()
-            // }
             _ => self.expression(),
         }
     }
 
     fn expression(&self) -> Result<Expr, &'static str> {
-        self.function_call()
+        match self.peek() {
+            Some(&Token::Let) => {
+                self.advance();
+                self.block(|_| self.let_special_expr())
+            }
+            Some(&Token::Loop) => {
+                self.advance();
+                self.block(|_| self.loop_special_expr())
+            }
+            Some(&Token::Do) => {
+                self.advance();
+                self.block(|indented| self.do_special_expr(indented))
+            }
+            Some(&Token::Try) => {
+                self.advance();
+                self.try_special_expr()
+            }
+            Some(&Token::Match) => {
+                self.advance();
+                self.match_special_expr()
+            }
+            Some(&Token::AnonFn) => {
+                self.advance();
+                let (params, indents) = self.params("Error in anonymous function: invalid syntax after \"\\\"")?;
+                // there is no way to call a function without arguments
+                if params.is_empty() {
+                    return Err("An anonymous function needs at least one parameter");
+                }
+                Ok(Expr::Lambda {
+                    params,
+                    body: Box::new(self.body(indents)?),
+                })
+            }
+            Some(&Token::Set) => {
+                self.advance();
+                let name = match self.advance() {
+                    Some(Token::Symbol(name)) => *name,
+                    _ => return Err("Expected the name of a binding after \"set\""),
+                };
+                if self.advance() != Some(&Token::Assign) {
+                    return Err("Expected '->' after the name in a set");
+                }
+                Ok(Expr::Set {
+                    name,
+                    expr: Box::new(self.body(0)?),
+                })
+            }
+            Some(&Token::Throw) => {
+                self.advance();
+                Ok(Expr::Throw {
+                    value: Box::new(self.expression()?),
+                })
+            }
+            _ => self.pipeline(),
+        }
+    }
+
+    /// A call followed by any number of `|> call`s, each of which is given the value
+    /// before it as its last argument, so that `x |> f a |> g` is `g (f a x)`. The
+    /// `|>`s may instead start the lines of an indented block below the first call.
+    fn pipeline(&self) -> Result<Expr, &'static str> {
+        let mut value = self.function_call()?;
+        let indented = self.pipe_below();
+        if indented {
+            self.advance();
+        }
+        // a `|>` that starts a line of its own is only part of an indented pipeline
+        while self.peek() == Some(&Token::Pipe)
+            && (indented || !matches!(self.previous(), Some(Token::Newline | Token::Semicolon)))
+        {
+            self.advance();
+            let span = self.span(self.idx.get());
+            value = match self.function_call()? {
+                Expr::FunctionCall { operator, mut operand, span } => {
+                    operand.push(value);
+                    Expr::FunctionCall { operator, operand, span }
+                }
+                Expr::Variable { name: operator } | Expr::Operator { token: operator } => {
+                    Expr::FunctionCall { operator, operand: vec![value], span }
+                }
+                _ => return Err("Expected a function to pipe into after '|>'"),
+            };
+        }
+        if indented {
+            self.dedent()?;
+            self.end_group();
+        }
+        Ok(value)
+    }
+
+    /// Whether an indented block of `|>`s comes next.
+    fn pipe_below(&self) -> bool {
+        let idx = self.idx.get();
+        self.token(idx) == Some(&Token::Indent) && self.token(idx + 1) == Some(&Token::Pipe)
+    }
+
+    /// Parses a block, whose parts are either on the lines of an indented block that
+    /// starts on the next line, or on the same line separated by `;`. `parse` is told
+    /// which.
+    fn block(&self, parse: impl FnOnce(bool) -> Result<Expr, &'static str>) -> Result<Expr, &'static str> {
+        let indented = self.peek() == Some(&Token::Indent);
+        if indented {
+            self.advance();
+        }
+        let expr = parse(indented)?;
+        if indented {
+            self.dedent()?;
+            self.end_group();
+        }
+        Ok(expr)
+    }
+
+    /// Consumes the `Dedent` that ends an indented block.
+    fn dedent(&self) -> Result<(), &'static str> {
+        self.separators();
+        match self.advance() {
+            Some(Token::Dedent) => Ok(()),
+            _ => Err("Expected the end of the indented block"),
+        }
+    }
+
+    /// Consumes the `)` of a group that an indented block was the last thing in, as
+    /// the end of a call does. The lexer closes the block first.
+    fn end_group(&self) {
+        if self.groups.get() > 0 && self.peek() == Some(&Token::RightParen) {
+            self.advance();
+            self.close_group();
+        }
+    }
+
+    /// Skips what separates the parts of a block.
+    fn separators(&self) {
+        while let Some(Token::Newline | Token::Semicolon) = self.peek() {
+            self.advance();
+        }
+    }
+
+    /// Bindings of the form `name: type -> expr`, each on an indented line of its
+    /// own or separated by `;`. There must be at least one.
+    /// Whether the next tokens are `name:`, the start of a binding.
+    fn at_binding(&self) -> bool {
+        matches!(
+            (self.peek(), self.token(self.idx.get() + 1)),
+            (Some(Token::Symbol(_)), Some(Token::TypeDelim))
+        )
+    }
+
+    fn bindings(&self) -> Result<Vec<Binding>, &'static str> {
+        let mut bindings = vec![];
+        loop {
+            self.separators();
+            if !self.at_binding() {
+                break;
+            }
+
+            let name = match self.advance() {
+                Some(Token::Symbol(name)) => *name,
+                _ => return Err("Invalid variable name."),
+            };
+            match (self.advance(), self.type_decl(), self.advance()) {
+                (Some(&Token::TypeDelim), Some(type_decl), Some(&Token::Assign)) => {
+                    bindings.push(Binding {
+                        name,
+                        type_decl,
+                        expr: self.expression()?,
+                    })
+                }
+                _ => return Err("Error in binding: invalid syntax after the name"),
+            }
+        }
+
+        if bindings.is_empty() {
+            return Err("Expected at least one binding");
+        }
+        Ok(bindings)
+    }
+
+    /// The bindings, and then the body.
+    fn let_special_expr(&self) -> Result<Expr, &'static str> {
+        let bindings = self.bindings()?;
+        Ok(Expr::Let {
+            bindings,
+            body: Box::new(self.expression()?),
+        })
+    }
+
+    /// Expressions on the lines of an indented block, or separated by `;` up to the
+    /// end of the line or group, or the next binding of an enclosing `let`.
+    fn do_special_expr(&self, indented: bool) -> Result<Expr, &'static str> {
+        let mut exprs = vec![];
+        loop {
+            match indented {
+                true => self.separators(),
+                false => {
+                    while let Some(Token::Semicolon) = self.peek() {
+                        self.advance();
+                    }
+                }
+            }
+            match self.peek() {
+                Some(Token::RightParen) if !indented => {
+                    self.advance();
+                    self.close_group();
+                    break;
+                }
+                None | Some(Token::Newline | Token::Dedent | Token::EOF) => break,
+                // the rest of the bindings of a `let`, or the clauses of a `try`, this
+                // do is part of
+                Some(Token::Catch | Token::Finally) => break,
+                _ if self.at_binding() => break,
+                _ => (),
+            }
+            // a grouped item closes its own `)`
+            let grouped = self.peek() == Some(&Token::LeftParen);
+            if grouped {
+                self.advance();
+                self.open_group();
+            }
+            // a `def` in a do is local to the scope the do runs in
+            exprs.push(self.declaration()?);
+            // an expression that ended its line or group ends the block too, unless
+            // the block is indented
+            match self.previous() {
+                Some(Token::RightParen) if grouped => (),
+                Some(Token::Newline | Token::Dedent | Token::RightParen) if !indented => break,
+                _ => (),
+            }
+        }
+
+        if exprs.is_empty() {
+            return Err("A do block needs at least one expression");
+        }
+        Ok(Expr::Do { exprs })
+    }
+
+    /// The bindings, `while` and the condition, `recur` and a new value for each
+    /// binding, and then the result.
+    fn loop_special_expr(&self) -> Result<Expr, &'static str> {
+        let bindings = self.bindings()?;
+
+        if self.advance() != Some(&Token::Symbol("while".into())) {
+            return Err("Expected 'while' and a condition after a loop's bindings");
+        }
+        let condition = self.expression()?;
+
+        self.separators();
+        if self.advance() != Some(&Token::Symbol("recur".into())) {
+            return Err("Expected 'recur' after a loop's condition");
+        }
+        let recur = self.operands()?;
+        if recur.len() != bindings.len() {
+            return Err("'recur' needs a new value for each of the loop's bindings");
+        }
+
+        self.separators();
+        Ok(Expr::Loop {
+            bindings,
+            condition: Box::new(condition),
+            recur,
+            result: Box::new(self.expression()?),
+        })
+    }
+
+    /// The body, then `catch name ->` and a handler, `finally` and an expression that
+    /// always runs, or both.
+    fn try_special_expr(&self) -> Result<Expr, &'static str> {
+        let groups = self.groups.get();
+        let body = self.body(0)?;
+
+        let catch = match self.clause(&Token::Catch, groups) {
+            true => {
+                let name = match self.advance() {
+                    Some(Token::Symbol(name)) => *name,
+                    _ => return Err("Expected a name after 'catch'"),
+                };
+                if self.advance() != Some(&Token::Assign) {
+                    return Err("Expected '->' after the name in a catch");
+                }
+                Some(Box::new(Catch {
+                    name,
+                    body: self.body(0)?,
+                }))
+            }
+            false => None,
+        };
+        let finally = match self.clause(&Token::Finally, groups) {
+            true => Some(Box::new(self.body(0)?)),
+            false => None,
+        };
+
+        if catch.is_none() && finally.is_none() {
+            return Err("A try needs a catch or a finally");
+        }
+        Ok(Expr::Try {
+            body: Box::new(body),
+            catch,
+            finally,
+        })
+    }
+
+    /// The value, then arms of the form `pattern -> body`, each on a line of an
+    /// indented block or separated by `;`. The value is written like an operand, so
+    /// that the block isn't taken for its operands: a call has to be in parentheses.
+    fn match_special_expr(&self) -> Result<Expr, &'static str> {
+        let value = match self.peek() {
+            Some(Token::LeftParen) => {
+                self.advance();
+                self.open_group();
+                self.expression()?
+            }
+            _ => self.primary()?,
+        };
+        self.block(|indented| {
+            Ok(Expr::Match {
+                value: Box::new(value),
+                arms: self.arms(indented)?,
+            })
+        })
+    }
+
+    fn arms(&self, indented: bool) -> Result<Vec<Arm>, &'static str> {
+        let mut arms = vec![];
+        loop {
+            match indented {
+                true => self.separators(),
+                false => {
+                    while let Some(Token::Semicolon) = self.peek() {
+                        self.advance();
+                    }
+                }
+            }
+            if let None | Some(Token::Newline | Token::Dedent | Token::EOF | Token::RightParen) = self.peek() {
+                break;
+            }
+
+            let start = self.idx.get();
+            let pattern = self.pattern()?;
+            let span = self.span(start).zip(self.span(self.idx.get() - 1));
+            let span = span.map(|(start, end)| start.to(end));
+            if self.advance() != Some(&Token::Assign) {
+                return Err("Expected '->' after the pattern of a match arm");
+            }
+            arms.push(Arm {
+                pattern,
+                body: self.body(0)?,
+                span,
+            });
+            // an arm that ended its line or group ends the match too, unless the
+            // arms are indented
+            if let Some(Token::Newline | Token::Dedent | Token::RightParen) = self.previous() {
+                if !indented {
+                    break;
+                }
+            }
+        }
+
+        if arms.is_empty() {
+            return Err("A match needs at least one arm");
+        }
+        Ok(arms)
+    }
+
+    fn pattern(&self) -> Result<Pattern, &'static str> {
+        match self.advance() {
+            Some(Token::TypeDecl(name)) if name == "_" => Ok(Pattern::Wildcard),
+            Some(Token::Symbol(name)) => Ok(Pattern::Name(*name)),
+            Some(
+                token @ (Token::Int(_)
+                | Token::Num(_)
+                | Token::Str(_)
+                | Token::True
+                | Token::False
+                | Token::None),
+            ) => Ok(Pattern::Literal(token.clone())),
+            Some(Token::Seq) => {
+                let mut items = vec![];
+                loop {
+                    match self.peek() {
+                        Some(Token::RightBracket) => {
+                            self.advance();
+                            return Ok(Pattern::Seq(items));
+                        }
+                        None | Some(Token::EOF) => return Err("Expected ']' to close a seq pattern"),
+                        _ => items.push(self.pattern()?),
+                    }
+                }
+            }
+            _ => Err("Expected a pattern: a literal, '_', a name, or a seq of patterns"),
+        }
+    }
+
+    /// Consumes `token` if it is next after any separators, leaving them in place
+    /// otherwise. A clause can't follow the end of the group its `try` started in,
+    /// when `groups` were open.
+    fn clause(&self, token: &Token, groups: usize) -> bool {
+        if self.groups.get() < groups {
+            return false;
+        }
+        let start = self.idx.get();
+        self.separators();
+        if self.peek() == Some(token) {
+            self.advance();
+            return true;
+        }
+        self.idx.set(start);
+        false
+    }
+
+    fn open_group(&self) {
+        self.groups.set(self.groups.get() + 1);
+    }
+
+    fn close_group(&self) {
+        self.groups.set(self.groups.get().saturating_sub(1));
     }
 
     fn function_call(&self) -> Result<Expr, &'static str> {
         let mut expr = self.primary()?;
+        // a function is named by a single token
+        let span = self.span(self.idx.get() - 1);
         
         loop {
+            if self.pipe_below() {
+                break;
+            }
             match self.peek() {
                 Some(Token::Symbol(_))
                 | Some(Token::Str(_))
+                | Some(Token::Interpolated(_))
+                | Some(Token::Int(_))
                 | Some(Token::Num(_))
                 | Some(Token::True)
                 | Some(Token::False)
                 | Some(Token::None) 
                 | Some(Token::Appl) 
                 | Some(Token::Indent) 
-                | Some(Token::LeftParen) => {
-                    let mut operands: Vec<Expr> = Vec::new();
-                    loop {
-                        match self.peek() {
-                            Some(Token::Appl) | Some(Token::LeftParen) | Some(Token::Indent) => {
-                                self.advance();
-                                operands.push(self.expression()?)
-                            }
-                            Some(Token::Subtract) 
-                            | Some(Token::Add) 
-                            | Some(Token::Multiply) 
-                            | Some(Token::Divide) 
-                            | Some(Token::GreaterThan) 
-                            | Some(Token::LessThan) 
-                            | Some(Token::GreaterThanOrEqual) 
-                            | Some(Token::LessThanOrEqual)
-                            | Some(Token::Equal)
-                            | Some(Token::And) 
-                            | Some(Token::Or)
-                            | Some(Token::Not) => {
-                                operands.push(self.expression()?)
-                            }
-                            Some(Token::RightParen)
-                            | Some(Token::Dedent)
-                            | Some(Token::EOF)
-                            | Some(Token::Newline) => {
-                                break;
-                            }
-                            _ => operands.push(self.primary()?),
-                        }
-                    }
+                | Some(Token::LeftParen)
+                | Some(Token::Seq)
+                | Some(Token::Hash) => {
+                    let operands = self.operands()?;
                     let operator = match expr {
                         Expr::Variable{ name } => name,
                         Expr::Operator{ token } => token,
                         _ => return Err("Invalid function name"),
                     };
                     expr = Expr::FunctionCall {
-                        operator: operator,
+                        operator,
                         operand: operands,
+                        span,
+                    };
+                    // operands on indented lines, or an indented block, end the line
+                    if self.previous() == Some(&Token::Dedent) {
+                        self.end_group();
+                        break;
                     }
                 }
-                Some(Token::RightParen) | Some(Token::Newline) | Some(Token::Dedent) => {self.advance(); break}
+                Some(Token::RightParen) => {
+                    self.advance();
+                    self.close_group();
+                    break
+                }
+                Some(Token::Newline) | Some(Token::Semicolon) => {self.advance(); break}
                 _ => break,
             }
         }
         Ok(expr)
     }
 
+    fn operands(&self) -> Result<Vec<Expr>, &'static str> {
+        let start = self.scratch.borrow().len();
+        let result = self.collect_operands();
+        let mut scratch = self.scratch.borrow_mut();
+        match result {
+            Ok(()) => Ok(scratch.drain(start..).collect()),
+            Err(err) => {
+                scratch.truncate(start);
+                Err(err)
+            }
+        }
+    }
+
+    fn collect_operands(&self) -> Result<(), &'static str> {
+        loop {
+            // an indented block ended the line
+            if self.previous() == Some(&Token::Dedent) || self.pipe_below() {
+                break;
+            }
+            match self.peek() {
+                // each indented line below continues the operands with an expression
+                Some(Token::Indent) => {
+                    self.advance();
+                    loop {
+                        match self.at_binding() {
+                            true => self.push_operand(self.named(Self::expression))?,
+                            false => self.push_operand(self.expression())?,
+                        }
+                        self.separators();
+                        if let None | Some(Token::Dedent | Token::EOF) = self.peek() {
+                            break;
+                        }
+                    }
+                    self.dedent()?;
+                }
+                Some(Token::RightParen)
+                | Some(Token::RightBracket)
+                | Some(Token::RightBrace)
+                | Some(Token::Dedent)
+                | Some(Token::EOF)
+                | Some(Token::Newline)
+                | Some(Token::Semicolon)
+                | Some(Token::Pipe) => {
+                    break;
+                }
+                _ if self.at_binding() => self.push_operand(self.named(Self::operand))?,
+                _ => self.push_operand(self.operand())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// A single operand: an expression after '$', in parentheses, or starting with
+    /// an operator, or else a primary.
+    fn operand(&self) -> Result<Expr, &'static str> {
+        match self.peek() {
+            Some(Token::Appl) | Some(Token::LeftParen) => {
+                if self.advance() == Some(&Token::LeftParen) {
+                    self.open_group();
+                }
+                self.expression()
+            }
+            Some(Token::Subtract) 
+            | Some(Token::Add) 
+            | Some(Token::Multiply) 
+            | Some(Token::Divide) 
+            | Some(Token::GreaterThan) 
+            | Some(Token::LessThan) 
+            | Some(Token::GreaterThanOrEqual) 
+            | Some(Token::LessThanOrEqual)
+            | Some(Token::Equal)
+            | Some(Token::And) 
+            | Some(Token::Or)
+            | Some(Token::Not) => self.expression(),
+            _ => self.primary(),
+        }
+    }
+
+    /// An argument passed by name, `name: value`, with the value parsed by `value`.
+    fn named(&self, value: fn(&Self) -> Result<Expr, &'static str>) -> Result<Expr, &'static str> {
+        let name = match self.advance() {
+            Some(Token::Symbol(name)) => *name,
+            _ => return Err("Invalid argument name."),
+        };
+        self.advance();
+        Ok(Expr::Named {
+            name,
+            value: Box::new(value(self)?),
+        })
+    }
+
+    fn push_operand(&self, operand: Result<Expr, &'static str>) -> Result<(), &'static str> {
+        self.scratch.borrow_mut().push(operand?);
+        Ok(())
+    }
+
     fn primary(&self) -> Result<Expr, &'static str> {
         if let Some(t) = self.advance() {
             match t {
-                Token::Num(_)
+                Token::Int(_)
+                | Token::Num(_)
                 | Token::Str(_)
                 | Token::EOF
                 | Token::True
@@ -222,7 +986,11 @@ impl<'a> Parser<'a> {
                 | Token::Or
                 | Token::Not => Ok(Expr::Operator { token: t.clone() }),
                 Token::Symbol(_) => Ok(Expr::Variable { name: t.clone() }),
+                Token::Interpolated(pieces) => self.interpolation(pieces),
+                Token::Seq => self.seq(),
+                Token::Hash => self.hash(),
                 Token::Appl => Err("Cannot pass an application symbol ($) there."),
+                Token::Pipe => Err("Expected a value to pipe before '|>'"),
                 _ => Err("Problem parsing primary."),
             }
         } else {
@@ -230,110 +998,136 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// The text and the expressions of an interpolated str, each of which is parsed
+    /// on its own.
+    fn interpolation(&self, pieces: &[Piece]) -> Result<Expr, &'static str> {
+        let mut parts = vec![];
+        for piece in pieces {
+            match piece {
+                Piece::Text(text) => parts.push(Expr::Literal { token: Token::Str(text.clone()) }),
+                Piece::Code(tokens) => {
+                    let mut exprs = Parser::new(tokens).parse().map_err(|errs| errs[0].1)?;
+                    exprs.retain(|expr| !matches!(expr, Expr::Literal { token: Token::EOF }));
+                    match (exprs.pop(), exprs.is_empty()) {
+                        (Some(expr), true) => parts.push(expr),
+                        _ => return Err("Expected one expression between '{' and '}' in a str"),
+                    }
+                }
+            }
+        }
+        Ok(Expr::Interpolation { parts })
+    }
+
+    /// The items of a seq literal, which are written like operands, up to its `]`.
+    fn seq(&self) -> Result<Expr, &'static str> {
+        let items = self.operands()?;
+        match self.advance() {
+            Some(Token::RightBracket) => Ok(Expr::Seq { items }),
+            _ => Err("Expected ']' to close a seq"),
+        }
+    }
+
+    /// Keys and values of a hash literal, in turn, up to its `}`.
+    fn hash(&self) -> Result<Expr, &'static str> {
+        let items = self.operands()?;
+        if self.advance() != Some(&Token::RightBrace) {
+            return Err("Expected '}' to close a hash");
+        }
+        if items.len() % 2 != 0 {
+            return Err("A hash needs a value after every key");
+        }
+        let mut items = items.into_iter();
+        let mut entries = vec![];
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            entries.push((key, value));
+        }
+        Ok(Expr::Hash { entries })
+    }
+
     fn advance(&self) -> Option<&Token> {
         let previous_index = self.idx.get();
-        if previous_index >= self.tokens.len() {
-            None
-        } else {
-            self.idx.set(previous_index + 1);
-            Some(&self.tokens[previous_index])
-        }
+        let token = self.token(previous_index)?;
+        self.idx.set(previous_index + 1);
+        Some(token)
+    }
+
+    fn previous(&self) -> Option<&Token> {
+        self.idx.get().checked_sub(1).and_then(|idx| self.token(idx))
     }
 
     fn peek(&self) -> Option<&Token> {
-        if self.idx.get() >= self.tokens.len() {
-            None
-        } else {
-            Some(&self.tokens[self.idx.get()])
-        }
+        self.token(self.idx.get())
+    }
+
+    /// Where the token at `idx` is, if the tokens were given with their spans.
+    fn span(&self, idx: usize) -> Option<Span> {
+        let spanned = self.spanned.get(idx)?;
+        Some(Span::new(self.source, spanned.location.range.clone()))
+    }
+
+    /// The token at `idx`, which counts as having been looked at.
+    fn token(&self, idx: usize) -> Option<&Token> {
+        self.seen.set(self.seen.get().max(idx + 1));
+        self.tokens.get(idx)
     }
 }
 
+/// Every expression in `tokens`, see `parse_stream` for tokens that are still
+/// being lexed.
 pub fn parse(tokens: &[Token]) -> Result<Vec<Expr>, String> {
     let mut parser = Parser::new(tokens);
-    parser.parse()
+    parser.parse().map_err(|errs| {
+        errs.into_iter()
+            .map(|(_, err)| err)
+            .collect::<Vec<_>>()
+            .join("\n")
+    })
 }
 
-pub fn ast_pretty_print(expr: &Expr) {
-    match expr {
-        Expr::Assignment { name, type_decl, expr } => {
-            print!("( ");
-            print!("def ");
-            print!("{}: {} ", name, type_decl);
-            ast_pretty_print(expr);
-            print!(") ");
-        }
-        Expr::Literal { token } => print_literal(token),
-        Expr::FunctionCall { operator, operand } => {
-            print!("( ");
-            print_literal(operator);
-            for op in operand {
-                ast_pretty_print(op);
-            }
-            print!(") ");
-        }
-        Expr::Variable { name } => print_literal(name),
-        Expr::Operator { token } => print_literal(token),
-    }
+/// The declared type that `tokens` start with, written as `parse` writes it in a
+/// `type_decl`, such as `(num -> num) -> bool`.
+pub fn parse_type(tokens: &[Token]) -> Option<String> {
+    Parser::new(tokens).type_decl()
 }
 
-fn print_literal(token: &Token) {
-    match token {
-        Token::Str(string) => {
-            print!("{} ", &string);
-        }
-        Token::Num(num) => {
-            print!("{} ", num);
-        }
-        Token::True => {
-            print!("true ");
-        }
-        Token::False => {
-            print!("false ");
-        }
-        Token::None => {
-            print!("none ");
-        }
-        Token::Add => {
-            print!("+ ");
-        }
-        Token::Subtract => {
-            print!("- ");
-        }
-        Token::Multiply => {
-            print!("* ");
-        }
-        Token::Divide => {
-            print!("/ ");
-        }
-        Token::GreaterThan => {
-            print!("> ");
-        }
-        Token::LessThan => {
-            print!("< ");
-        }
-        Token::GreaterThanOrEqual => {
-            print!(">= ");
-        }
-        Token::LessThanOrEqual => {
-            print!("<= ");
-        }
-        Token::Equal => {
-            print!("= ");
-        }
-        Token::And => {
-            print!("and ");
-        }
-        Token::Or => {
-            print!("or ");
-        }
-        Token::Not => {
-            print!("not ");
-        }
-        Token::Symbol(sym) => {
-            print!("{} ", &sym)
+/// Like `parse`, but each error is a diagnostic pointing at the token in `source`
+/// where it was found, and each call has the span of the function it names.
+pub fn parse_spanned(tokens: &[Spanned], source: SourceId) -> Result<Vec<Expr>, Vec<Diagnostic>> {
+    let plain: Vec<Token> = tokens.iter().map(|spanned| spanned.token.clone()).collect();
+    let mut parser = Parser::new(&plain);
+    parser.spanned = tokens;
+    parser.source = source;
+    parser.parse().map_err(|errs| {
+        errs.into_iter()
+            .map(|(idx, message)| {
+                let location = &tokens[idx.min(tokens.len() - 1)].location;
+                let diagnostic = Diagnostic::error(codes::PARSE, message)
+                    .with_span(Span::new(source, location.range.clone()));
+                match hint(message) {
+                    Some(hint) => diagnostic.with_hint(hint),
+                    None => diagnostic,
+                }
+            })
+            .collect()
+    })
+}
+
+/// How to write what a parse error was expecting, for the errors where that isn't
+/// already clear from the message.
+fn hint(message: &str) -> Option<&'static str> {
+    match message {
+        "Invalid variable name." => Some("a name is a word such as `total`, not a literal or a keyword"),
+        "Error in global variable declaration: invalid syntax after \"def\"" => {
+            Some("a definition is written `def name: type -> expression`")
         }
-        Token::EOF => (),
-        _ => panic!("Shouldn't be printing a literal here!"),
+        "Error in function declaration: parameters need a type" => Some("each parameter is written `name: type`"),
+        "Error in binding: invalid syntax after the name" => Some("a binding is written `name: type -> expression`"),
+        _ => None,
     }
-}
\ No newline at end of file
+}
+
+/// Prints the expression as an S-expression, as `dump::ast` writes it.
+#[cfg(feature = "std")]
+pub fn ast_pretty_print(expr: &Expr) {
+    print!("{}", dump::expr_sexpr(expr));
+}
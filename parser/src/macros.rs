@@ -0,0 +1,413 @@
+//! Macros are expanded in a pass of their own between parsing and interpretation.
+//!
+//! A macro is defined at the top level with `defmacro`, its name, its parameters,
+//! and a `quote`d template. Wherever the macro is called, the call is replaced by
+//! the template with each `unquote param` swapped for the (unevaluated) argument
+//! passed for it:
+//!
+//! ```text
+//! defmacro unless cond then else (quote (if (unquote cond) (unquote else) (unquote then)))
+//! ```
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use lexer::{Symbol, Token};
+
+use crate::{Arm, Binding, Catch, Expr};
+
+/// How many times an expansion may itself expand to another macro call.
+const MAX_EXPANSIONS: usize = 64;
+
+struct Macro {
+    params: Vec<Symbol>,
+    template: Expr,
+}
+
+/// The macros defined so far. Definitions persist between calls to `expand`, so
+/// one REPL input can use a macro defined by an earlier one.
+#[derive(Default)]
+pub struct Macros {
+    macros: BTreeMap<Symbol, Macro>,
+}
+
+impl Macros {
+    pub fn new() -> Macros {
+        Macros::default()
+    }
+
+    /// Takes the macro definitions out of `exprs` and expands every call to a macro
+    /// in what remains.
+    pub fn expand(&mut self, exprs: Vec<Expr>) -> Result<Vec<Expr>, &'static str> {
+        let mut expanded = vec![];
+        for expr in exprs {
+            match expr {
+                Expr::FunctionCall {
+                    operator: Token::Symbol(ref name),
+                    operand,
+                    ..
+                } if name == "defmacro" => self.define(operand)?,
+                expr => expanded.push(self.expand_expr(expr, 0)?),
+            }
+        }
+        Ok(expanded)
+    }
+
+    fn define(&mut self, mut operand: Vec<Expr>) -> Result<(), &'static str> {
+        let template = match operand.pop() {
+            Some(Expr::FunctionCall {
+                operator: Token::Symbol(quote),
+                mut operand,
+                ..
+            }) if quote == "quote" && operand.len() == 1 => operand.remove(0),
+            _ => return Err("A macro's body must be a quoted template"),
+        };
+
+        let mut names = vec![];
+        for expr in operand {
+            match expr {
+                Expr::Variable {
+                    name: Token::Symbol(name),
+                } => names.push(name),
+                _ => return Err("A macro's name and parameters must be symbols"),
+            }
+        }
+        if names.is_empty() {
+            return Err("A macro needs a name");
+        }
+
+        let name = names.remove(0);
+        self.macros.insert(
+            name,
+            Macro {
+                params: names,
+                template,
+            },
+        );
+        Ok(())
+    }
+
+    fn expand_expr(&self, expr: Expr, depth: usize) -> Result<Expr, &'static str> {
+        if depth > MAX_EXPANSIONS {
+            return Err("Macro expansion is too deep");
+        }
+
+        match expr {
+            Expr::FunctionCall {
+                operator: Token::Symbol(name),
+                operand,
+                ..
+            } if self.macros.contains_key(&name) => {
+                let expansion = self.substitute(&name, operand)?;
+                self.expand_expr(expansion, depth + 1)
+            }
+            Expr::Variable {
+                name: Token::Symbol(name),
+            } if self.macros.contains_key(&name) => {
+                let expansion = self.substitute(&name, vec![])?;
+                self.expand_expr(expansion, depth + 1)
+            }
+            Expr::FunctionCall {
+                operator,
+                operand,
+                span,
+            } => Ok(Expr::FunctionCall {
+                operator,
+                operand: operand
+                    .into_iter()
+                    .map(|operand| self.expand_expr(operand, depth))
+                    .collect::<Result<_, _>>()?,
+                span,
+            }),
+            Expr::Assignment {
+                name,
+                type_decl,
+                expr,
+            } => Ok(Expr::Assignment {
+                name,
+                type_decl,
+                expr: Box::new(self.expand_expr(*expr, depth)?),
+            }),
+            Expr::Function {
+                name,
+                type_decl,
+                params,
+                body,
+            } => Ok(Expr::Function {
+                name,
+                type_decl,
+                params,
+                body: Box::new(self.expand_expr(*body, depth)?),
+            }),
+            Expr::Seq { items } => Ok(Expr::Seq {
+                items: items
+                    .into_iter()
+                    .map(|item| self.expand_expr(item, depth))
+                    .collect::<Result<_, _>>()?,
+            }),
+            Expr::Interpolation { parts } => Ok(Expr::Interpolation {
+                parts: parts
+                    .into_iter()
+                    .map(|part| self.expand_expr(part, depth))
+                    .collect::<Result<_, _>>()?,
+            }),
+            Expr::Named { name, value } => Ok(Expr::Named {
+                name,
+                value: Box::new(self.expand_expr(*value, depth)?),
+            }),
+            Expr::Hash { entries } => Ok(Expr::Hash {
+                entries: entries
+                    .into_iter()
+                    .map(|(key, value)| {
+                        Ok((
+                            self.expand_expr(key, depth)?,
+                            self.expand_expr(value, depth)?,
+                        ))
+                    })
+                    .collect::<Result<_, _>>()?,
+            }),
+            Expr::Lambda { params, body } => Ok(Expr::Lambda {
+                params,
+                body: Box::new(self.expand_expr(*body, depth)?),
+            }),
+            Expr::Let { bindings, body } => Ok(Expr::Let {
+                bindings: self.expand_bindings(bindings, depth)?,
+                body: Box::new(self.expand_expr(*body, depth)?),
+            }),
+            Expr::Do { exprs } => Ok(Expr::Do {
+                exprs: exprs
+                    .into_iter()
+                    .map(|expr| self.expand_expr(expr, depth))
+                    .collect::<Result<_, _>>()?,
+            }),
+            Expr::Try {
+                body,
+                catch,
+                finally,
+            } => Ok(Expr::Try {
+                body: Box::new(self.expand_expr(*body, depth)?),
+                catch: match catch {
+                    Some(catch) => Some(Box::new(Catch {
+                        name: catch.name,
+                        body: self.expand_expr(catch.body, depth)?,
+                    })),
+                    None => None,
+                },
+                finally: match finally {
+                    Some(finally) => Some(Box::new(self.expand_expr(*finally, depth)?)),
+                    None => None,
+                },
+            }),
+            Expr::Set { name, expr } => Ok(Expr::Set {
+                name,
+                expr: Box::new(self.expand_expr(*expr, depth)?),
+            }),
+            Expr::Throw { value } => Ok(Expr::Throw {
+                value: Box::new(self.expand_expr(*value, depth)?),
+            }),
+            Expr::Match { value, arms } => Ok(Expr::Match {
+                value: Box::new(self.expand_expr(*value, depth)?),
+                arms: arms
+                    .into_iter()
+                    .map(|arm| {
+                        Ok(Arm {
+                            pattern: arm.pattern,
+                            body: self.expand_expr(arm.body, depth)?,
+                            span: arm.span,
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            }),
+            Expr::Loop {
+                bindings,
+                condition,
+                recur,
+                result,
+            } => Ok(Expr::Loop {
+                bindings: self.expand_bindings(bindings, depth)?,
+                condition: Box::new(self.expand_expr(*condition, depth)?),
+                recur: recur
+                    .into_iter()
+                    .map(|value| self.expand_expr(value, depth))
+                    .collect::<Result<_, _>>()?,
+                result: Box::new(self.expand_expr(*result, depth)?),
+            }),
+            expr => Ok(expr),
+        }
+    }
+
+    fn expand_bindings(
+        &self,
+        bindings: Vec<Binding>,
+        depth: usize,
+    ) -> Result<Vec<Binding>, &'static str> {
+        bindings
+            .into_iter()
+            .map(|binding| {
+                Ok(Binding {
+                    expr: self.expand_expr(binding.expr, depth)?,
+                    ..binding
+                })
+            })
+            .collect()
+    }
+
+    /// The template of the macro `name`, with its parameters bound to `args`.
+    fn substitute(&self, name: &Symbol, args: Vec<Expr>) -> Result<Expr, &'static str> {
+        let found = &self.macros[name];
+        if args.len() != found.params.len() {
+            return Err("Wrong number of arguments to macro");
+        }
+
+        let bindings: BTreeMap<&str, Expr> =
+            found.params.iter().map(Symbol::as_str).zip(args).collect();
+        unquote(&found.template, &bindings)
+    }
+}
+
+fn unquote(template: &Expr, bindings: &BTreeMap<&str, Expr>) -> Result<Expr, &'static str> {
+    match template {
+        Expr::FunctionCall {
+            operator: Token::Symbol(name),
+            operand,
+            ..
+        } if name == "unquote" => match operand.as_slice() {
+            [Expr::Variable {
+                name: Token::Symbol(param),
+            }] => bindings
+                .get(param.as_str())
+                .cloned()
+                .ok_or("Cannot unquote a name that is not a macro parameter"),
+            _ => Err("'unquote' takes a macro parameter"),
+        },
+        Expr::FunctionCall {
+            operator,
+            operand,
+            span,
+        } => Ok(Expr::FunctionCall {
+            operator: operator.clone(),
+            operand: operand
+                .iter()
+                .map(|operand| unquote(operand, bindings))
+                .collect::<Result<_, _>>()?,
+            span: *span,
+        }),
+        Expr::Assignment {
+            name,
+            type_decl,
+            expr,
+        } => Ok(Expr::Assignment {
+            name: *name,
+            type_decl: type_decl.clone(),
+            expr: Box::new(unquote(expr, bindings)?),
+        }),
+        Expr::Let {
+            bindings: lets,
+            body,
+        } => Ok(Expr::Let {
+            bindings: unquote_bindings(lets, bindings)?,
+            body: Box::new(unquote(body, bindings)?),
+        }),
+        Expr::Do { exprs } => Ok(Expr::Do {
+            exprs: exprs
+                .iter()
+                .map(|expr| unquote(expr, bindings))
+                .collect::<Result<_, _>>()?,
+        }),
+        Expr::Try {
+            body,
+            catch,
+            finally,
+        } => Ok(Expr::Try {
+            body: Box::new(unquote(body, bindings)?),
+            catch: match catch {
+                Some(catch) => Some(Box::new(Catch {
+                    name: catch.name,
+                    body: unquote(&catch.body, bindings)?,
+                })),
+                None => None,
+            },
+            finally: match finally {
+                Some(finally) => Some(Box::new(unquote(finally, bindings)?)),
+                None => None,
+            },
+        }),
+        Expr::Set { name, expr } => Ok(Expr::Set {
+            name: *name,
+            expr: Box::new(unquote(expr, bindings)?),
+        }),
+        Expr::Throw { value } => Ok(Expr::Throw {
+            value: Box::new(unquote(value, bindings)?),
+        }),
+        Expr::Match { value, arms } => Ok(Expr::Match {
+            value: Box::new(unquote(value, bindings)?),
+            arms: arms
+                .iter()
+                .map(|arm| {
+                    Ok(Arm {
+                        pattern: arm.pattern.clone(),
+                        body: unquote(&arm.body, bindings)?,
+                        span: arm.span,
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+        }),
+        Expr::Seq { items } => Ok(Expr::Seq {
+            items: items
+                .iter()
+                .map(|item| unquote(item, bindings))
+                .collect::<Result<_, _>>()?,
+        }),
+        Expr::Interpolation { parts } => Ok(Expr::Interpolation {
+            parts: parts
+                .iter()
+                .map(|part| unquote(part, bindings))
+                .collect::<Result<_, _>>()?,
+        }),
+        Expr::Named { name, value } => Ok(Expr::Named {
+            name: *name,
+            value: Box::new(unquote(value, bindings)?),
+        }),
+        Expr::Hash { entries } => Ok(Expr::Hash {
+            entries: entries
+                .iter()
+                .map(|(key, value)| Ok((unquote(key, bindings)?, unquote(value, bindings)?)))
+                .collect::<Result<_, _>>()?,
+        }),
+        Expr::Lambda { params, body } => Ok(Expr::Lambda {
+            params: params.clone(),
+            body: Box::new(unquote(body, bindings)?),
+        }),
+        Expr::Loop {
+            bindings: lets,
+            condition,
+            recur,
+            result,
+        } => Ok(Expr::Loop {
+            bindings: unquote_bindings(lets, bindings)?,
+            condition: Box::new(unquote(condition, bindings)?),
+            recur: recur
+                .iter()
+                .map(|value| unquote(value, bindings))
+                .collect::<Result<_, _>>()?,
+            result: Box::new(unquote(result, bindings)?),
+        }),
+        expr => Ok(expr.clone()),
+    }
+}
+
+fn unquote_bindings(
+    lets: &[Binding],
+    bindings: &BTreeMap<&str, Expr>,
+) -> Result<Vec<Binding>, &'static str> {
+    lets.iter()
+        .map(|binding| {
+            Ok(Binding {
+                expr: unquote(&binding.expr, bindings)?,
+                ..binding.clone()
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,51 @@
+//! The AST has no spans, so the names in it are matched back to the symbol tokens
+//! they were parsed from by walking it in the order it was written.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use lexer::{Spanned, Symbol, Token};
+
+/// The symbol tokens of a source, handed out to the names of its AST as a walk over
+/// it meets them. A name gets the first token of the same name after the last one
+/// handed out, or else, since what a pipe moves into a call comes after the call in
+/// the AST, the closest one before it.
+#[derive(Debug, Default)]
+pub struct NameSpans {
+    /// Each symbol, and whether it has been handed out.
+    symbols: Vec<(Symbol, Range<usize>, bool)>,
+    cursor: usize,
+}
+
+impl NameSpans {
+    /// The symbols in `tokens`, with `offset` added to their spans, e.g. the start of
+    /// the form they were lexed from.
+    pub fn new(tokens: &[Spanned], offset: usize) -> NameSpans {
+        let symbols = tokens
+            .iter()
+            .filter_map(|spanned| match spanned.token {
+                Token::Symbol(name) => {
+                    let range = &spanned.location.range;
+                    Some((name, offset + range.start..offset + range.end, false))
+                }
+                _ => None,
+            })
+            .collect();
+        NameSpans { symbols, cursor: 0 }
+    }
+
+    /// The span of the token `name` was parsed from, which isn't handed out again.
+    pub fn claim(&mut self, name: Symbol) -> Option<Range<usize>> {
+        let unclaimed =
+            |(symbol, _, claimed): &(Symbol, Range<usize>, bool)| *symbol == name && !claimed;
+        let idx = match self.symbols[self.cursor..].iter().position(unclaimed) {
+            Some(idx) => {
+                self.cursor += idx + 1;
+                self.cursor - 1
+            }
+            None => self.symbols[..self.cursor].iter().rposition(unclaimed)?,
+        };
+        self.symbols[idx].2 = true;
+        Some(self.symbols[idx].1.clone())
+    }
+}
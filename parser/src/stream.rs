@@ -0,0 +1,118 @@
+//! Parsing tokens as they are lexed, one top-level expression at a time, so that
+//! only the tokens of the expression being parsed are held rather than those of
+//! the whole source.
+//!
+//! ```
+//! let source = "def x: int -> 1\nprint x";
+//! let exprs: Result<Vec<_>, _> = parser::parse_stream(lexer::Lexer::new(source)).collect();
+//! assert_eq!(exprs.unwrap().len(), 3);
+//! ```
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use lexer::{LexError, Token};
+
+use crate::{Expr, Parser};
+
+/// The expressions parsed from a stream of tokens, see `parse_stream`.
+pub struct Exprs<I> {
+    tokens: I,
+    /// The tokens lexed but not parsed yet.
+    buffer: Vec<Token>,
+    /// Whether `tokens` has ended, or failed to lex.
+    ended: bool,
+}
+
+/// Parses `tokens`, such as a `lexer::Lexer`, as they are asked for. The
+/// expressions are those `parse` gives for the same tokens, except that each
+/// parse error is given on its own, where it was found, and a lex error ends them.
+pub fn parse_stream<I>(tokens: I) -> Exprs<I::IntoIter>
+where
+    I: IntoIterator<Item = Result<Token, LexError>>,
+{
+    Exprs {
+        tokens: tokens.into_iter(),
+        buffer: Vec::new(),
+        ended: false,
+    }
+}
+
+impl<I: Iterator<Item = Result<Token, LexError>>> Exprs<I> {
+    /// Takes tokens up to the next line break, `;`, or end of a block that isn't
+    /// inside any other, where the expression being parsed is likely to end.
+    fn fill(&mut self) -> Result<(), LexError> {
+        let mut depth = self.buffer.iter().fold(0, nest);
+        while !self.ended {
+            match self.tokens.next() {
+                Some(Ok(token)) => {
+                    depth = nest(depth, &token);
+                    let ends = depth == 0
+                        && matches!(
+                            token,
+                            Token::Newline | Token::Semicolon | Token::Dedent | Token::EOF
+                        );
+                    self.buffer.push(token);
+                    if ends {
+                        break;
+                    }
+                }
+                Some(Err(err)) => {
+                    self.ended = true;
+                    self.buffer.clear();
+                    return Err(err);
+                }
+                None => self.ended = true,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<I: Iterator<Item = Result<Token, LexError>>> Iterator for Exprs<I> {
+    type Item = Result<Expr, String>;
+
+    fn next(&mut self) -> Option<Result<Expr, String>> {
+        loop {
+            let separators = self
+                .buffer
+                .iter()
+                .take_while(|token| matches!(token, Token::Newline | Token::Semicolon))
+                .count();
+            self.buffer.drain(..separators);
+            if self.buffer.is_empty() {
+                if self.ended {
+                    return None;
+                }
+                if let Err(err) = self.fill() {
+                    return Some(Err(err.message.to_string()));
+                }
+                continue;
+            }
+
+            let parser = Parser::new(&self.buffer);
+            let parsed = parser.declaration();
+            // the expression may go on in tokens that haven't been lexed yet, such as
+            // a `catch` on the line after a `try`, so it is parsed again with them
+            if parser.seen.get() > self.buffer.len() && !self.ended {
+                if let Err(err) = self.fill() {
+                    return Some(Err(err.message.to_string()));
+                }
+                continue;
+            }
+            let parsed_len = parser.idx.get().max(1);
+            self.buffer.drain(..parsed_len);
+            return Some(parsed.map_err(str::to_string));
+        }
+    }
+}
+
+/// How many brackets and blocks are open after `token`, given how many were before.
+fn nest(depth: usize, token: &Token) -> usize {
+    match token {
+        Token::LeftParen | Token::Seq | Token::Hash | Token::Indent => depth + 1,
+        Token::RightParen | Token::RightBracket | Token::RightBrace | Token::Dedent => {
+            depth.saturating_sub(1)
+        }
+        _ => depth,
+    }
+}
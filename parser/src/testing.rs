@@ -0,0 +1,574 @@
+//! Helpers for property tests: an `Arbitrary` implementation that generates the
+//! expressions the parser can produce, and a printer that turns them back into source
+//! such that `parse(lex(print(exprs)))` gives back `exprs`.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+use lexer::{Symbol, Token};
+
+use crate::dump::{escape, pattern_text, source_text};
+use crate::{Arm, Binding, Catch, Expr, Param, Pattern};
+
+/// How deeply generated function calls may nest.
+const MAX_DEPTH: usize = 4;
+
+const OPERATORS: &[Token] = &[
+    Token::Add,
+    Token::Subtract,
+    Token::Multiply,
+    Token::Divide,
+    Token::GreaterThan,
+    Token::LessThan,
+    Token::GreaterThanOrEqual,
+    Token::LessThanOrEqual,
+    Token::Equal,
+    Token::And,
+    Token::Or,
+    Token::Not,
+];
+
+/// A mix of builtins and names that are never defined, so generated programs
+/// exercise both.
+const NAMES: &[&str] = &[
+    "x",
+    "y",
+    "total",
+    "random",
+    "print",
+    "re-match",
+    "re-split",
+    "csv-parse",
+    "date-add",
+    "date-format",
+    "sha256",
+    "b64-decode",
+    "hex-decode",
+    "path-join",
+    "basename",
+];
+
+const TYPES: &[&str] = &[
+    "num",
+    "str",
+    "bool",
+    "_",
+    "int -> num",
+    "(str -> bool) -> _",
+];
+
+/// A top-level expression: a `def` or an expression on its own line.
+impl<'a> Arbitrary<'a> for Expr {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Expr> {
+        match u.int_in_range(0..=7)? {
+            0 | 1 => Ok(Expr::Assignment {
+                name: Symbol::intern(u.choose(NAMES)?),
+                type_decl: u.choose(TYPES)?.to_string(),
+                expr: Box::new(expression(u, 0)?),
+            }),
+            2 => {
+                let mut params = vec![];
+                for _ in 0..u.int_in_range(1..=3)? {
+                    params.push(Param {
+                        name: Symbol::intern(u.choose(NAMES)?),
+                        type_decl: u.choose(TYPES)?.to_string(),
+                        default: default(u)?,
+                    });
+                }
+                Ok(Expr::Function {
+                    name: Symbol::intern(u.choose(NAMES)?),
+                    type_decl: u.choose(TYPES)?.to_string(),
+                    params,
+                    body: Box::new(expression(u, 0)?),
+                })
+            }
+            _ => expression(u, 0),
+        }
+    }
+}
+
+fn expression(u: &mut Unstructured, depth: usize) -> Result<Expr> {
+    if depth >= MAX_DEPTH || u.ratio(1, 3)? {
+        return atom(u);
+    }
+
+    if u.ratio(1, 10)? {
+        let mut exprs = vec![];
+        for _ in 0..u.int_in_range(1..=3)? {
+            exprs.push(expression(u, depth + 1)?);
+        }
+        return Ok(Expr::Do { exprs });
+    }
+
+    if u.ratio(1, 8)? {
+        let mut bindings = vec![];
+        for _ in 0..u.int_in_range(1..=2)? {
+            bindings.push(Binding {
+                name: Symbol::intern(u.choose(NAMES)?),
+                type_decl: u.choose(TYPES)?.to_string(),
+                expr: closed_expr(u, depth + 1)?,
+            });
+        }
+        return Ok(Expr::Let {
+            bindings,
+            body: Box::new(expression(u, depth + 1)?),
+        });
+    }
+
+    if u.ratio(1, 12)? {
+        let body = Box::new(closed_expr(u, depth + 1)?);
+        let mut catch = None;
+        let mut finally = None;
+        match u.int_in_range(0..=2)? {
+            0 => catch = Some(arbitrary_catch(u, depth)?),
+            1 => finally = Some(Box::new(closed_expr(u, depth + 1)?)),
+            _ => {
+                catch = Some(arbitrary_catch(u, depth)?);
+                finally = Some(Box::new(closed_expr(u, depth + 1)?));
+            }
+        }
+        return Ok(Expr::Try {
+            body,
+            catch,
+            finally,
+        });
+    }
+
+    if u.ratio(1, 12)? {
+        let mut params = vec![];
+        for _ in 0..u.int_in_range(1..=2)? {
+            params.push(Param {
+                name: Symbol::intern(u.choose(NAMES)?),
+                type_decl: u.choose(TYPES)?.to_string(),
+                default: default(u)?,
+            });
+        }
+        return Ok(Expr::Lambda {
+            params,
+            body: Box::new(expression(u, depth + 1)?),
+        });
+    }
+
+    if u.ratio(1, 10)? {
+        let mut items = vec![];
+        for _ in 0..u.int_in_range(0..=3)? {
+            items.push(expression(u, depth + 1)?);
+        }
+        return Ok(Expr::Seq { items });
+    }
+
+    if u.ratio(1, 12)? {
+        let mut entries = vec![];
+        for _ in 0..u.int_in_range(0..=2)? {
+            entries.push((expression(u, depth + 1)?, expression(u, depth + 1)?));
+        }
+        return Ok(Expr::Hash { entries });
+    }
+
+    if u.ratio(1, 12)? {
+        let mut arms = vec![];
+        for _ in 0..u.int_in_range(1..=3)? {
+            arms.push(Arm {
+                pattern: pattern(u, 0)?,
+                body: closed_expr(u, depth + 1)?,
+                span: None,
+            });
+        }
+        return Ok(Expr::Match {
+            value: Box::new(atom(u)?),
+            arms,
+        });
+    }
+
+    if u.ratio(1, 16)? {
+        return Ok(Expr::Set {
+            name: Symbol::intern(u.choose(NAMES)?),
+            expr: Box::new(expression(u, depth + 1)?),
+        });
+    }
+
+    if u.ratio(1, 16)? {
+        return interpolation(u, depth);
+    }
+
+    if u.ratio(1, 16)? {
+        return Ok(Expr::Throw {
+            value: Box::new(expression(u, depth + 1)?),
+        });
+    }
+
+    let operator = match u.arbitrary()? {
+        true => u.choose(OPERATORS)?.clone(),
+        false => Token::Symbol(Symbol::intern(u.choose(NAMES)?)),
+    };
+    let mut operand = vec![expression(u, depth + 1)?];
+    for _ in 0..u.int_in_range(0..=3)? {
+        operand.push(match u.ratio(1, 6)? {
+            true => Expr::Named {
+                name: Symbol::intern(u.choose(NAMES)?),
+                value: Box::new(expression(u, depth + 1)?),
+            },
+            false => expression(u, depth + 1)?,
+        });
+    }
+    Ok(Expr::FunctionCall {
+        operator,
+        operand,
+        span: None,
+    })
+}
+
+/// A parameter's default, for some parameters.
+fn default(u: &mut Unstructured) -> Result<Option<Expr>> {
+    match u.ratio(1, 4)? {
+        true => Ok(Some(atom(u)?)),
+        false => Ok(None),
+    }
+}
+
+/// Text and expressions in turn, with at least one expression, since the text is
+/// read back as one str literal for every run of it and without an expression the
+/// whole is a plain str. An expression that is a str literal would read back as text.
+fn interpolation(u: &mut Unstructured, depth: usize) -> Result<Expr> {
+    let mut parts = vec![];
+    let mut text = u.arbitrary()?;
+    for _ in 0..u.int_in_range(1..=4)? {
+        let part = match text {
+            true => atom(u)?,
+            false => closed_expr(u, depth + 1)?,
+        };
+        match (text, part) {
+            (
+                true,
+                Expr::Literal {
+                    token: Token::Str(string),
+                },
+            ) if !string.is_empty() => parts.push(Expr::Literal {
+                token: Token::Str(string),
+            }),
+            (true, _) => {}
+            (
+                false,
+                Expr::Literal {
+                    token: Token::Str(_),
+                },
+            ) => parts.push(Expr::Literal { token: Token::True }),
+            (false, part) => parts.push(part),
+        }
+        text = !text;
+    }
+    if parts.iter().all(|part| {
+        matches!(
+            part,
+            Expr::Literal {
+                token: Token::Str(_)
+            }
+        )
+    }) {
+        parts.push(Expr::Literal { token: Token::None });
+    }
+    Ok(Expr::Interpolation { parts })
+}
+
+fn atom(u: &mut Unstructured) -> Result<Expr> {
+    Ok(match u.int_in_range(0..=7)? {
+        0 => Expr::Operator {
+            token: u.choose(OPERATORS)?.clone(),
+        },
+        1 => Expr::Variable {
+            name: Token::Symbol(Symbol::intern(u.choose(NAMES)?)),
+        },
+        2 => Expr::Literal { token: Token::True },
+        3 => Expr::Literal {
+            token: Token::False,
+        },
+        4 => Expr::Literal { token: Token::None },
+        5 => {
+            let len = u.int_in_range(0..=8)?;
+            let mut string = String::new();
+            for _ in 0..len {
+                string.push(*u.choose(&[
+                    'a', 'z', '0', ' ', '#', ':', '(', '$', 'é', '"', '\\', '\n', '\t', '{', '}',
+                ])?);
+            }
+            Expr::Literal {
+                token: Token::Str(string),
+            }
+        }
+        6 => Expr::Literal {
+            token: Token::Int(u.arbitrary()?),
+        },
+        _ => {
+            // NaN never equals itself, so it can't round trip
+            let num = f64::from(u.arbitrary::<i32>()?) / f64::from(u.int_in_range(1..=1000u32)?);
+            Expr::Literal {
+                token: Token::Num(num),
+            }
+        }
+    })
+}
+
+fn pattern(u: &mut Unstructured, depth: usize) -> Result<Pattern> {
+    Ok(match u.int_in_range(0..=3)? {
+        0 => Pattern::Wildcard,
+        1 => Pattern::Name(Symbol::intern(u.choose(NAMES)?)),
+        2 if depth < 2 => {
+            let mut items = vec![];
+            for _ in 0..u.int_in_range(0..=2)? {
+                items.push(pattern(u, depth + 1)?);
+            }
+            Pattern::Seq(items)
+        }
+        _ => match atom(u)? {
+            Expr::Literal { token } => Pattern::Literal(token),
+            _ => Pattern::Wildcard,
+        },
+    })
+}
+
+/// Prints `exprs` as source, one top-level expression per line. A trailing `EOF`
+/// literal, as `parse` returns, is skipped.
+pub fn print(exprs: &[Expr]) -> String {
+    let mut source = String::new();
+    for expr in exprs {
+        if let Expr::Literal { token: Token::EOF } = expr {
+            continue;
+        }
+        match expr {
+            Expr::Assignment {
+                name,
+                type_decl,
+                expr,
+            } => source.push_str(&format!(
+                "def {name}: {type_decl} -> {}",
+                print_expr(expr, false)
+            )),
+            Expr::Function {
+                name,
+                type_decl,
+                params,
+                body,
+            } => {
+                source.push_str(&format!("def {name}: {type_decl}"));
+                for param in params {
+                    source.push_str(&format!(" {}", print_param(param)));
+                }
+                source.push_str(&format!(" -> {}", print_expr(body, false)));
+            }
+            _ => source.push_str(&print_expr(expr, false)),
+        }
+        source.push('\n');
+    }
+    source
+}
+
+fn print_param(param: &Param) -> String {
+    match &param.default {
+        Some(default) => format!(
+            "{}: {} = {}",
+            param.name,
+            param.type_decl,
+            print_expr(default, true)
+        ),
+        None => format!("{}: {}", param.name, param.type_decl),
+    }
+}
+
+/// Calls and bare operators are parenthesized when they are operands, since they
+/// would otherwise take everything after them as their own operands.
+fn print_expr(expr: &Expr, nested: bool) -> String {
+    match expr {
+        Expr::FunctionCall {
+            operator, operand, ..
+        } => {
+            let mut call = source_text(operator);
+            for operand in operand {
+                call.push(' ');
+                call.push_str(&print_expr(operand, true));
+            }
+            match nested {
+                true => format!("({call})"),
+                false => call,
+            }
+        }
+        Expr::Operator { token: operator } if nested => format!("({})", source_text(operator)),
+        Expr::Operator { token: operator } => source_text(operator),
+        Expr::Literal { token: literal } | Expr::Variable { name: literal } => source_text(literal),
+        Expr::Let { bindings, body } => {
+            let mut block = String::from("let");
+            for binding in bindings {
+                block.push_str(&format!(
+                    " {}: {} -> {};",
+                    binding.name,
+                    binding.type_decl,
+                    print_expr(&binding.expr, false)
+                ));
+            }
+            block.push_str(&format!(" {}", print_expr(body, false)));
+            match nested {
+                true => format!("({block})"),
+                false => block,
+            }
+        }
+        Expr::Try {
+            body,
+            catch,
+            finally,
+        } => {
+            let mut block = format!("try {}", print_expr(body, false));
+            if let Some(catch) = catch {
+                block.push_str(&format!(
+                    "; catch {} -> {}",
+                    catch.name,
+                    print_expr(&catch.body, false)
+                ));
+            }
+            if let Some(finally) = finally {
+                block.push_str(&format!("; finally {}", print_expr(finally, false)));
+            }
+            match nested {
+                true => format!("({block})"),
+                false => block,
+            }
+        }
+        Expr::Lambda { params, body } => {
+            let mut lambda = String::from("\\");
+            for param in params {
+                lambda.push_str(&format!("{} ", print_param(param)));
+            }
+            lambda.push_str(&format!("-> {}", print_expr(body, false)));
+            match nested {
+                true => format!("({lambda})"),
+                false => lambda,
+            }
+        }
+        Expr::Named { name, value } => format!("{name}: {}", print_expr(value, true)),
+        Expr::Interpolation { parts } => {
+            let mut quoted = String::from("\"");
+            for part in parts {
+                match part {
+                    Expr::Literal {
+                        token: Token::Str(text),
+                    } => escape(&mut quoted, text),
+                    part => quoted.push_str(&format!("{{{}}}", print_expr(part, false))),
+                }
+            }
+            quoted.push('"');
+            quoted
+        }
+        Expr::Seq { items } => {
+            let items: Vec<String> = items.iter().map(|item| print_expr(item, true)).collect();
+            format!("[{}]", items.join(" "))
+        }
+        Expr::Hash { entries } => {
+            let entries: Vec<String> = entries
+                .iter()
+                .map(|(key, value)| {
+                    format!("{} {}", print_expr(key, true), print_expr(value, true))
+                })
+                .collect();
+            format!("{{{}}}", entries.join(" "))
+        }
+        Expr::Throw { value } => match nested {
+            true => format!("(throw {})", print_expr(value, false)),
+            false => format!("throw {}", print_expr(value, false)),
+        },
+        Expr::Set { name, expr } => match nested {
+            true => format!("(set {name} -> {})", print_expr(expr, false)),
+            false => format!("set {name} -> {}", print_expr(expr, false)),
+        },
+        Expr::Do { exprs } => match nested {
+            true => format!("({})", print_do(exprs)),
+            false => print_do(exprs),
+        },
+        Expr::Loop {
+            bindings,
+            condition,
+            recur,
+            result,
+        } => {
+            let mut block = String::from("loop");
+            for binding in bindings {
+                block.push_str(&format!(
+                    " {}: {} -> {};",
+                    binding.name,
+                    binding.type_decl,
+                    print_expr(&binding.expr, false)
+                ));
+            }
+            block.push_str(&format!(" while {}; recur", print_expr(condition, false)));
+            for value in recur {
+                block.push_str(&format!(" {}", print_expr(value, true)));
+            }
+            block.push_str(&format!("; {}", print_expr(result, false)));
+            match nested {
+                true => format!("({block})"),
+                false => block,
+            }
+        }
+        Expr::Match { value, arms } => {
+            let mut block = format!("match {}", print_expr(value, true));
+            for arm in arms {
+                block.push_str(&format!(
+                    "; {} -> {}",
+                    pattern_text(&arm.pattern),
+                    print_expr(&arm.body, false)
+                ));
+            }
+            match nested {
+                true => format!("({block})"),
+                false => block,
+            }
+        }
+        Expr::Assignment { .. } | Expr::Function { .. } | Expr::Import { .. } => {
+            String::from("<def>")
+        }
+    }
+}
+
+fn arbitrary_catch(u: &mut Unstructured, depth: usize) -> Result<Box<Catch>> {
+    Ok(Box::new(Catch {
+        name: Symbol::intern(u.choose(NAMES)?),
+        body: closed_expr(u, depth + 1)?,
+    }))
+}
+
+/// An expression for a binding or a part of a `try`, which can't be grouped, so it
+/// is never open ended.
+fn closed_expr(u: &mut Unstructured, depth: usize) -> Result<Expr> {
+    let expr = expression(u, depth)?;
+    match open_ended(&expr) {
+        true => atom(u),
+        false => Ok(expr),
+    }
+}
+
+/// Whether an expression would take what follows it on the line as part of itself:
+/// more items of a do, or a `finally` for a try that has none.
+fn open_ended(expr: &Expr) -> bool {
+    match expr {
+        Expr::Do { .. } | Expr::Match { .. } => true,
+        Expr::Try { finally: None, .. } => true,
+        Expr::Try {
+            finally: Some(finally),
+            ..
+        } => open_ended(finally),
+        Expr::Let { body, .. } => open_ended(body),
+        Expr::Throw { value }
+        | Expr::Set { expr: value, .. }
+        | Expr::Lambda { body: value, .. } => open_ended(value),
+        _ => false,
+    }
+}
+
+fn print_do(exprs: &[Expr]) -> String {
+    // an open ended item would take the items after it
+    let exprs: Vec<String> = exprs
+        .iter()
+        .map(|expr| print_expr(expr, open_ended(expr)))
+        .collect();
+    format!("do {}", exprs.join("; "))
+}
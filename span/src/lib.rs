@@ -0,0 +1,221 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Identifies a file (or other chunk of source text) registered with a `SourceMap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct SourceId(pub u32);
+
+/// A byte range within one source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub source: SourceId,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(source: SourceId, range: Range<usize>) -> Span {
+        Span {
+            source,
+            start: range.start,
+            end: range.end,
+        }
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        self.range().contains(&offset)
+    }
+
+    /// The smallest span covering both `self` and `other`, which must be in the same source.
+    pub fn to(&self, other: Span) -> Span {
+        debug_assert_eq!(self.source, other.source);
+        Span {
+            source: self.source,
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
+/// A 1-based line and column, with columns counted in chars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: usize,
+    pub col: usize,
+}
+
+/// The byte offsets at which each line of a text starts, for converting between
+/// offsets and line/column positions.
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> LineIndex {
+        let line_starts = core::iter::once(0)
+            .chain(text.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+        LineIndex { line_starts }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The line and column of byte `offset` into `text`, which must be the text the index
+    /// was built from.
+    pub fn line_col(&self, text: &str, offset: usize) -> LineCol {
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[line];
+        let col = text[start..offset.min(text.len())].chars().count();
+        LineCol {
+            line: line + 1,
+            col: col + 1,
+        }
+    }
+
+    /// The byte offset of a 1-based `line` and `col`, if it falls within the text.
+    pub fn offset(&self, text: &str, line: usize, col: usize) -> Option<usize> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let line_text = text[start..].split('\n').next()?;
+
+        line_text
+            .char_indices()
+            .nth(col.checked_sub(1)?)
+            .map(|(idx, _)| start + idx)
+    }
+
+    /// The byte range of a 1-based `line`, without its line ending.
+    pub fn line_range(&self, text: &str, line: usize) -> Option<Range<usize>> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(text.len(), |next| next - 1);
+        Some(start..end)
+    }
+}
+
+struct File {
+    name: String,
+    text: String,
+    lines: LineIndex,
+}
+
+/// Every source the pipeline has seen, so that a `Span` from any stage can be turned
+/// back into a file name, a line and column, or the text it covers.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<File>,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    pub fn add(&mut self, name: &str, text: String) -> SourceId {
+        self.files.push(File {
+            name: name.to_string(),
+            lines: LineIndex::new(&text),
+            text,
+        });
+        SourceId(self.files.len() as u32 - 1)
+    }
+
+    pub fn name(&self, id: SourceId) -> &str {
+        &self.file(id).name
+    }
+
+    pub fn text(&self, id: SourceId) -> &str {
+        &self.file(id).text
+    }
+
+    pub fn lines(&self, id: SourceId) -> &LineIndex {
+        &self.file(id).lines
+    }
+
+    /// The text `span` covers.
+    pub fn snippet(&self, span: Span) -> &str {
+        &self.text(span.source)[span.range()]
+    }
+
+    /// Where `span` starts.
+    pub fn line_col(&self, span: Span) -> LineCol {
+        let file = self.file(span.source);
+        file.lines.line_col(&file.text, span.start)
+    }
+
+    /// `file:line:col` for the start of `span`.
+    pub fn describe(&self, span: Span) -> String {
+        let LineCol { line, col } = self.line_col(span);
+        format!("{}:{}:{}", self.name(span.source), line, col)
+    }
+
+    fn file(&self, id: SourceId) -> &File {
+        &self.files[id.0 as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_col() {
+        let text = "def x: num -> 1\nx\n\ndéf y";
+        let lines = LineIndex::new(text);
+
+        assert_eq!(lines.line_count(), 4);
+        assert_eq!(lines.line_col(text, 0), LineCol { line: 1, col: 1 });
+        assert_eq!(lines.line_col(text, 16), LineCol { line: 2, col: 1 });
+        assert_eq!(lines.line_col(text, 18), LineCol { line: 3, col: 1 });
+        // columns count chars, so the two-byte 'é' is one column
+        assert_eq!(lines.line_col(text, 23), LineCol { line: 4, col: 4 });
+    }
+
+    #[test]
+    fn test_offset_round_trip() {
+        let text = "a\nbé c\n";
+        let lines = LineIndex::new(text);
+
+        for (offset, _) in text.char_indices().filter(|(_, c)| *c != '\n') {
+            let LineCol { line, col } = lines.line_col(text, offset);
+            assert_eq!(lines.offset(text, line, col), Some(offset));
+        }
+        assert_eq!(lines.offset(text, 0, 1), None);
+        assert_eq!(lines.offset(text, 2, 9), None);
+        assert_eq!(lines.line_range(text, 2), Some(2..7));
+    }
+
+    #[test]
+    fn test_source_map() {
+        let mut map = SourceMap::new();
+        map.add("a.lin", "1\n".to_string());
+        let id = map.add("b.lin", "def x: num -> 1\n  + x 2".to_string());
+
+        let span = Span::new(id, 20..21);
+        assert_eq!(map.snippet(span), "x");
+        assert_eq!(map.describe(span), "b.lin:2:5");
+        assert_eq!(span.to(Span::new(id, 4..5)).range(), 4..21);
+    }
+}
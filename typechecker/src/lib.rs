@@ -0,0 +1,958 @@
+//! Checks the types a program declares against the types of its expressions before
+//! it runs. A type is worked out from literals, operators, and the declarations of
+//! the names an expression uses; anything else, such as what a builtin returns or a
+//! name declared `_`, is `_`, which matches every type, so only mismatches that are
+//! certain are reported.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::ops::Range;
+
+use diagnostics::{codes, Diagnostic};
+use lexer::{Spanned, Symbol, Token};
+use parser::dump::{pattern_text, source_text};
+use parser::names::NameSpans;
+use parser::{Binding, Expr, Param, Pattern};
+
+/// The static type of an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    /// `_`: not known until the program runs.
+    Any,
+    Int,
+    Num,
+    Str,
+    Bool,
+    None,
+    Seq,
+    Hash,
+    /// A function from its parameters' types to the type it returns.
+    Function(Vec<Type>, Box<Type>),
+}
+
+impl Type {
+    /// The type a declaration such as `x: num` or `f: num -> num -> bool` names, as
+    /// the parser writes it.
+    pub fn from_decl(type_decl: &str) -> Type {
+        let mut parts = arrow_parts(type_decl);
+        let returned = parts.pop().unwrap_or_default();
+        if !parts.is_empty() {
+            return Type::Function(
+                parts.into_iter().map(Type::from_decl).collect(),
+                Box::new(Type::from_decl(returned)),
+            );
+        }
+        match returned {
+            "int" => Type::Int,
+            "num" => Type::Num,
+            "str" => Type::Str,
+            "bool" => Type::Bool,
+            _ => match returned
+                .strip_prefix('(')
+                .and_then(|inner| inner.strip_suffix(')'))
+            {
+                Some(inner) => Type::from_decl(inner),
+                None => Type::Any,
+            },
+        }
+    }
+
+    /// Whether a value of type `value` can be used where this type is declared. An
+    /// int can be used as a num, as arithmetic promotes it.
+    pub fn accepts(&self, value: &Type) -> bool {
+        match (self, value) {
+            (Type::Any, _) | (_, Type::Any) | (Type::Num, Type::Int) => true,
+            (Type::Function(params, returned), Type::Function(value_params, value_returned)) => {
+                params.len() == value_params.len()
+                    && params
+                        .iter()
+                        .zip(value_params)
+                        .all(|(param, value_param)| value_param.accepts(param))
+                    && returned.accepts(value_returned)
+            }
+            (declared, value) => declared == value,
+        }
+    }
+
+    /// A type for a value that is one of `self` or `other`.
+    fn join(self, other: Type) -> Type {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Type::Int | Type::Num, Type::Int | Type::Num) => Type::Num,
+            _ => Type::Any,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Type::Any | Type::Int | Type::Num)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "_"),
+            Type::Int => write!(f, "int"),
+            Type::Num => write!(f, "num"),
+            Type::Str => write!(f, "str"),
+            Type::Bool => write!(f, "bool"),
+            Type::None => write!(f, "none"),
+            Type::Seq => write!(f, "seq"),
+            Type::Hash => write!(f, "hash"),
+            Type::Function(params, returned) => {
+                for param in params {
+                    match param {
+                        Type::Function(..) => write!(f, "({param}) -> ")?,
+                        _ => write!(f, "{param} -> ")?,
+                    }
+                }
+                match (params.is_empty(), returned.as_ref()) {
+                    (true, _) => write!(f, "-> {returned}"),
+                    // otherwise its parameters would look like more of this one's
+                    (false, Type::Function(..)) => write!(f, "({returned})"),
+                    (false, _) => write!(f, "{returned}"),
+                }
+            }
+        }
+    }
+}
+
+/// Checks sources one after another, so that the globals one defines are known to
+/// the next, as they are when an interpreter runs them in turn.
+#[derive(Debug, Default)]
+pub struct Checker {
+    globals: BTreeMap<String, Type>,
+    /// The local bindings in scope, innermost last.
+    scopes: Vec<Vec<(String, Type)>>,
+    errors: Vec<Diagnostic>,
+    /// The symbol tokens of the source being checked by `check_spanned`.
+    names: Option<NameSpans>,
+    /// The type of each name in the source last checked by `check_spanned`, at the
+    /// span of its token.
+    types: Vec<(Range<usize>, Type)>,
+}
+
+impl Checker {
+    pub fn new() -> Checker {
+        Checker::default()
+    }
+
+    /// Checks the expressions in order, returning every type error found. The
+    /// globals they define are only remembered when there are none.
+    pub fn check(&mut self, exprs: &[Expr]) -> Result<(), Vec<Diagnostic>> {
+        let globals = self.globals.clone();
+        for expr in exprs {
+            self.infer(expr);
+        }
+        self.scopes.clear();
+
+        let errors = mem::take(&mut self.errors);
+        match errors.is_empty() {
+            true => Ok(()),
+            false => {
+                self.globals = globals;
+                Err(errors)
+            }
+        }
+    }
+
+    /// Checks the expressions like `check`, also recording the type of each name in
+    /// them, as found where it is written, for `type_at`. `tokens` are those the
+    /// expressions were parsed from.
+    pub fn check_spanned(
+        &mut self,
+        exprs: &[Expr],
+        tokens: &[Spanned],
+    ) -> Result<(), Vec<Diagnostic>> {
+        self.names = Some(NameSpans::new(tokens, 0));
+        self.types.clear();
+        let result = self.check(exprs);
+        self.names = None;
+        result
+    }
+
+    /// The type of the name at byte `offset` in the source last checked by
+    /// `check_spanned`.
+    pub fn type_at(&self, offset: usize) -> Option<&Type> {
+        self.types
+            .iter()
+            .find(|(span, _)| span.contains(&offset))
+            .map(|(_, value)| value)
+    }
+
+    /// The type `expr` would evaluate to, leaving out its type errors and forgetting
+    /// any globals it defines.
+    pub fn type_of(&mut self, expr: &Expr) -> Type {
+        let globals = self.globals.clone();
+        let value = self.infer(expr);
+        self.scopes.clear();
+        self.errors.clear();
+        self.globals = globals;
+        value
+    }
+
+    /// The type of the global `name`, if it has been defined.
+    pub fn global(&self, name: &str) -> Option<&Type> {
+        self.globals.get(name)
+    }
+
+    /// Forgets the global `name`, e.g. when the file that defined it is reloaded
+    /// without it.
+    pub fn undefine(&mut self, name: &str) {
+        self.globals.remove(name);
+    }
+
+    fn infer(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Literal { token } => match token {
+                Token::Int(_) => Type::Int,
+                Token::Num(_) => Type::Num,
+                Token::Str(_) | Token::Symbol(_) => Type::Str,
+                Token::True | Token::False => Type::Bool,
+                Token::None | Token::EOF => Type::None,
+                _ => Type::Any,
+            },
+            Expr::Variable {
+                name: Token::Symbol(name),
+            } => {
+                let span = self.claim(*name);
+                let value = self.lookup(name);
+                self.record(span, &value);
+                value
+            }
+            Expr::Variable { .. } | Expr::Operator { .. } => Type::Any,
+            // what a file imports is checked along with it once the import is resolved
+            Expr::Import { .. } => Type::None,
+            Expr::FunctionCall {
+                operator, operand, ..
+            } => {
+                if let Token::Symbol(name) = operator {
+                    let span = self.claim(*name);
+                    let value = self.lookup(name);
+                    self.record(span, &value);
+                }
+                let args: Vec<Type> = operand.iter().map(|expr| self.infer(expr)).collect();
+                if operand
+                    .iter()
+                    .any(|expr| matches!(expr, Expr::Named { .. }))
+                {
+                    // which parameter each argument is for is only known once it runs
+                    return match (operator, self.lookup(&source_text(operator))) {
+                        (Token::Symbol(_), Type::Function(_, returned)) => *returned,
+                        _ => Type::Any,
+                    };
+                }
+                match operator {
+                    Token::Symbol(name) => self.call(name, args),
+                    _ => self.operate(operator, &args),
+                }
+            }
+            Expr::Assignment {
+                name,
+                type_decl,
+                expr,
+            } => {
+                let span = self.claim(*name);
+                let value = self.infer(expr);
+                let declared = Type::from_decl(type_decl);
+                self.record(span, &declared);
+                if !declared.accepts(&value) {
+                    self.mismatch(format!(
+                        "'{name}' is declared {declared} but its value is {value}"
+                    ));
+                }
+                self.define(name, declared);
+                Type::None
+            }
+            Expr::Function {
+                name,
+                type_decl,
+                params,
+                body,
+            } => {
+                let declared = Type::from_decl(type_decl);
+                let span = self.claim(*name);
+                // one without parameters is just the value its body gives
+                match params.is_empty() {
+                    true => self.record(span, &declared),
+                    false => self.record(span, &function_type(params, declared.clone())),
+                }
+                // defined before the body is checked, so that it can call itself
+                self.define(name, function_type(params, declared.clone()));
+                let returned = self.function_body(params, body);
+                if !declared.accepts(&returned) {
+                    self.mismatch(format!(
+                        "'{name}' is declared to return {declared} but its body is {returned}"
+                    ));
+                }
+                Type::None
+            }
+            Expr::Let { bindings, body } => {
+                self.scopes.push(vec![]);
+                for binding in bindings {
+                    self.bind(binding);
+                }
+                let value = self.infer(body);
+                self.scopes.pop();
+                value
+            }
+            Expr::Do { exprs } => exprs
+                .iter()
+                .map(|expr| self.infer(expr))
+                .last()
+                .unwrap_or(Type::None),
+            Expr::Loop {
+                bindings,
+                condition,
+                recur,
+                result,
+            } => {
+                self.scopes.push(vec![]);
+                for binding in bindings {
+                    self.bind(binding);
+                }
+                let condition = self.infer(condition);
+                if !Type::Bool.accepts(&condition) {
+                    self.error(format!(
+                        "A loop's condition must be a bool, but it is {condition}"
+                    ));
+                }
+                for (idx, value) in recur.iter().enumerate() {
+                    let value = self.infer(value);
+                    let Some(binding) = bindings.get(idx) else {
+                        continue;
+                    };
+                    let declared = Type::from_decl(&binding.type_decl);
+                    if !declared.accepts(&value) {
+                        self.mismatch(format!(
+                            "'{}' is declared {declared} but recur gives it {value}",
+                            binding.name
+                        ));
+                    }
+                }
+                let value = self.infer(result);
+                self.scopes.pop();
+                value
+            }
+            Expr::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                let mut value = self.infer(body);
+                if let Some(catch) = catch {
+                    let span = self.claim(catch.name);
+                    self.record(span, &Type::Any);
+                    self.scopes.push(vec![(catch.name.to_string(), Type::Any)]);
+                    value = value.join(self.infer(&catch.body));
+                    self.scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    self.infer(finally);
+                }
+                value
+            }
+            Expr::Seq { items } => {
+                for item in items {
+                    self.infer(item);
+                }
+                Type::Seq
+            }
+            // any value can be spliced into a str
+            Expr::Interpolation { parts } => {
+                // the names in it have no tokens of their own
+                let names = self.names.take();
+                for part in parts {
+                    self.infer(part);
+                }
+                self.names = names;
+                Type::Str
+            }
+            Expr::Hash { entries } => {
+                for (key, value) in entries {
+                    let key = self.infer(key);
+                    if !matches!(
+                        key,
+                        Type::Any | Type::Int | Type::Num | Type::Str | Type::Bool
+                    ) {
+                        self.error(format!(
+                            "A hash key must be a str, num, or bool, but it is {key}"
+                        ));
+                    }
+                    self.infer(value);
+                }
+                Type::Hash
+            }
+            Expr::Lambda { params, body } => {
+                let returned = self.function_body(params, body);
+                function_type(params, returned)
+            }
+            Expr::Named { name, value } => {
+                self.claim(*name);
+                self.infer(value)
+            }
+            Expr::Set { name, expr } => {
+                let span = self.claim(*name);
+                let value = self.infer(expr);
+                let declared = self.lookup(name);
+                self.record(span, &declared);
+                if !declared.accepts(&value) {
+                    self.mismatch(format!(
+                        "'{name}' is declared {declared} but set gives it {value}"
+                    ));
+                }
+                Type::None
+            }
+            Expr::Throw { value } => {
+                self.infer(value);
+                Type::Any
+            }
+            Expr::Match { value, arms } => {
+                let value = self.infer(value);
+                let mut result: Option<Type> = None;
+                for arm in arms {
+                    if !fits(&arm.pattern, &value) {
+                        self.error(format!(
+                            "No {value} fits the pattern {}",
+                            pattern_text(&arm.pattern)
+                        ));
+                    }
+                    let mut names = vec![];
+                    bound_names(&arm.pattern, &value, &mut names);
+                    for (name, value) in &names {
+                        let span = self.claim(Symbol::intern(name));
+                        self.record(span, value);
+                    }
+                    self.scopes.push(names);
+                    let body = self.infer(&arm.body);
+                    self.scopes.pop();
+                    result = Some(match result {
+                        Some(result) => result.join(body),
+                        None => body,
+                    });
+                }
+                result.unwrap_or(Type::None)
+            }
+        }
+    }
+
+    /// The type of a function's body, with its parameters in scope.
+    fn function_body(&mut self, params: &[Param], body: &Expr) -> Type {
+        self.scopes.push(vec![]);
+        // each default is checked with the parameters before it in scope, as it runs
+        for param in params {
+            let span = self.claim(param.name);
+            let declared = Type::from_decl(&param.type_decl);
+            self.record(span, &declared);
+            if let Some(default) = &param.default {
+                let value = self.infer(default);
+                if !declared.accepts(&value) {
+                    self.mismatch(format!(
+                        "'{}' is declared {declared} but its default is {value}",
+                        param.name
+                    ));
+                }
+            }
+            self.define(&param.name, declared);
+        }
+        let returned = self.infer(body);
+        self.scopes.pop();
+        returned
+    }
+
+    /// Checks a `let` or `loop` binding's value against its declared type, then binds
+    /// it in the innermost scope.
+    fn bind(&mut self, binding: &Binding) {
+        let span = self.claim(binding.name);
+        let value = self.infer(&binding.expr);
+        let declared = Type::from_decl(&binding.type_decl);
+        self.record(span, &declared);
+        if !declared.accepts(&value) {
+            self.mismatch(format!(
+                "'{}' is declared {declared} but its value is {value}",
+                binding.name
+            ));
+        }
+        self.define(&binding.name, declared);
+    }
+
+    /// Checks a call's arguments against the parameters of the function it calls,
+    /// when that function's type is known, to work out the type of the call.
+    fn call(&mut self, name: &str, args: Vec<Type>) -> Type {
+        match self.lookup(name) {
+            Type::Function(params, returned) => {
+                // a call with fewer arguments is the function of the ones left
+                let returned = match params.get(args.len()..) {
+                    Some(rest) if !args.is_empty() && !rest.is_empty() => {
+                        Box::new(Type::Function(rest.to_vec(), returned))
+                    }
+                    _ => returned,
+                };
+                if params.len() < args.len() {
+                    self.error(format!(
+                        "'{name}' takes {} argument(s) but is given {}",
+                        params.len(),
+                        args.len()
+                    ));
+                }
+                for (idx, (param, arg)) in params.iter().zip(&args).enumerate() {
+                    if !param.accepts(arg) {
+                        self.mismatch(format!(
+                            "Argument {} to '{name}' is {arg}, but it expects {param}",
+                            idx + 1
+                        ));
+                    }
+                }
+                *returned
+            }
+            Type::Any => Type::Any,
+            // with nothing to apply it to, this is just the value
+            value if args.is_empty() => value,
+            value => {
+                self.error(format!("'{name}' is {value}, which cannot be called"));
+                Type::Any
+            }
+        }
+    }
+
+    /// Checks an operator's operands, to work out the type of what it gives.
+    fn operate(&mut self, operator: &Token, args: &[Type]) -> Type {
+        let name = source_text(operator);
+        match operator {
+            // strs are added to and ordered against other strs only
+            Token::Add
+            | Token::GreaterThan
+            | Token::LessThan
+            | Token::GreaterThanOrEqual
+            | Token::LessThanOrEqual
+                if args.contains(&Type::Str) =>
+            {
+                if let Some(arg) = args
+                    .iter()
+                    .find(|arg| !matches!(arg, Type::Any | Type::Str))
+                {
+                    self.error(format!("'{name}' cannot combine str and {arg}"));
+                }
+                match operator {
+                    Token::Add => Type::Str,
+                    _ => Type::Bool,
+                }
+            }
+            Token::Add => {
+                self.operands(&name, args, "int, num, or str", Type::is_numeric);
+                numeric_result(operator, args)
+            }
+            Token::Subtract | Token::Multiply | Token::Divide => {
+                self.operands(&name, args, "int or num", Type::is_numeric);
+                numeric_result(operator, args)
+            }
+            Token::GreaterThan
+            | Token::LessThan
+            | Token::GreaterThanOrEqual
+            | Token::LessThanOrEqual => {
+                self.operands(&name, args, "int, num, or str", Type::is_numeric);
+                Type::Bool
+            }
+            Token::Equal => {
+                self.operands(&name, args, "int, num, str, bool, or none", |arg| {
+                    arg.is_numeric() || matches!(arg, Type::Str | Type::Bool | Type::None)
+                });
+                // values of different types are never equal, which is almost always a
+                // mistake
+                let mut known = args.iter().filter(|arg| **arg != Type::Any);
+                if let Some(first) = known.next() {
+                    let comparable =
+                        |arg: &&Type| arg == &first || (first.is_numeric() && arg.is_numeric());
+                    if let Some(other) = known.find(|arg| !comparable(arg)) {
+                        self.error(format!("'=' cannot compare {first} and {other}"));
+                    }
+                }
+                Type::Bool
+            }
+            Token::And | Token::Or => {
+                self.operands(&name, args, "bool", |arg| {
+                    matches!(arg, Type::Any | Type::Bool)
+                });
+                Type::Bool
+            }
+            Token::Not => {
+                self.operands(&name, args, "bool", |arg| {
+                    matches!(arg, Type::Any | Type::Bool | Type::None)
+                });
+                Type::Bool
+            }
+            _ => Type::Any,
+        }
+    }
+
+    /// Reports the first operand that `expected` doesn't hold for.
+    fn operands(
+        &mut self,
+        operator: &str,
+        args: &[Type],
+        description: &str,
+        expected: impl Fn(&Type) -> bool,
+    ) {
+        if let Some(arg) = args.iter().find(|arg| !expected(arg)) {
+            self.error(format!(
+                "'{operator}' expects {description} operands, but one is {arg}"
+            ));
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Type {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(bound, _)| bound == name)
+            .map(|(_, value)| value)
+            .or_else(|| self.globals.get(name))
+            .cloned()
+            .unwrap_or(Type::Any)
+    }
+
+    /// Defines `name` in the innermost local scope, or as a global at the top level,
+    /// as the interpreter does.
+    fn define(&mut self, name: &str, value: Type) {
+        match self.scopes.last_mut() {
+            Some(scope) => scope.push((name.into(), value)),
+            None => {
+                self.globals.insert(name.into(), value);
+            }
+        }
+    }
+
+    /// The span of the token `name` was parsed from, when there are tokens to find
+    /// it in.
+    fn claim(&mut self, name: Symbol) -> Option<Range<usize>> {
+        self.names.as_mut()?.claim(name)
+    }
+
+    fn record(&mut self, span: Option<Range<usize>>, value: &Type) {
+        if let Some(span) = span {
+            self.types.push((span, value.clone()));
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors.push(Diagnostic::error(codes::TYPE, message));
+    }
+
+    /// An error for a value that doesn't match its declared type.
+    fn mismatch(&mut self, message: String) {
+        self.errors.push(
+            Diagnostic::error(codes::TYPE, message)
+                .with_hint("change the declared type, or declare it `_` to leave it unchecked"),
+        );
+    }
+}
+
+/// The types on either side of each `->` of a declared type that isn't inside
+/// parentheses.
+fn arrow_parts(type_decl: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (idx, c) in type_decl.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            '-' if depth == 0 && type_decl[idx..].starts_with("->") => {
+                parts.push(type_decl[start..idx].trim());
+                start = idx + 2;
+            }
+            _ => (),
+        }
+    }
+    parts.push(type_decl[start..].trim());
+    parts
+}
+
+/// The type of a function with `params`. One with defaults can be called with fewer
+/// arguments than it takes, so what a call to it gives is not certain.
+fn function_type(params: &[Param], returned: Type) -> Type {
+    if params.iter().any(|param| param.default.is_some()) {
+        return Type::Any;
+    }
+    Type::Function(
+        params
+            .iter()
+            .map(|param| Type::from_decl(&param.type_decl))
+            .collect(),
+        Box::new(returned),
+    )
+}
+
+/// Checks the expressions of a single program.
+pub fn check(exprs: &[Expr]) -> Result<(), Vec<Diagnostic>> {
+    Checker::new().check(exprs)
+}
+
+/// The type of an arithmetic operation: a quotient or anything with a num is a num,
+/// and ints make an int.
+fn numeric_result(operator: &Token, args: &[Type]) -> Type {
+    if *operator == Token::Divide || args.contains(&Type::Num) {
+        Type::Num
+    } else if args.iter().all(|arg| *arg == Type::Int) {
+        Type::Int
+    } else {
+        Type::Any
+    }
+}
+
+/// Whether a value of type `value` could fit `pattern`. A literal fits a value of
+/// its own kind, where ints and nums are one kind, as `=` compares them.
+fn fits(pattern: &Pattern, value: &Type) -> bool {
+    let kind = match pattern {
+        Pattern::Wildcard | Pattern::Name(_) => return true,
+        Pattern::Literal(Token::Int(_) | Token::Num(_)) => return value.is_numeric(),
+        Pattern::Literal(Token::Str(_)) => Type::Str,
+        Pattern::Literal(Token::True | Token::False) => Type::Bool,
+        Pattern::Literal(_) => Type::None,
+        Pattern::Seq(_) => Type::Seq,
+    };
+    matches!(value, Type::Any) || *value == kind
+}
+
+/// The names `pattern` binds, with their types: the whole value's type, or `_` for
+/// the items of a seq, whose types aren't known.
+fn bound_names(pattern: &Pattern, value: &Type, names: &mut Vec<(String, Type)>) {
+    match pattern {
+        Pattern::Name(name) => names.push((name.to_string(), value.clone())),
+        Pattern::Seq(items) => {
+            for item in items {
+                bound_names(item, &Type::Any, names);
+            }
+        }
+        Pattern::Literal(_) | Pattern::Wildcard => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn errors(source: &str) -> Vec<String> {
+        let tokens = lexer::lex(source.to_string()).expect("test source should lex");
+        let exprs = parser::parse(&tokens).expect("test source should parse");
+        match check(&exprs) {
+            Ok(()) => vec![],
+            Err(errors) => errors.into_iter().map(|error| error.message).collect(),
+        }
+    }
+
+    #[test]
+    fn test_declarations() {
+        assert_eq!(
+            errors("def x: num -> \"hello\"\n"),
+            ["'x' is declared num but its value is str"]
+        );
+        assert_eq!(
+            errors("def x: int -> 1.5\ndef y: num -> 1\ndef z: _ -> \"a\"\n"),
+            ["'x' is declared int but its value is num"]
+        );
+        assert_eq!(
+            errors("def f: str x: num -> + x 1\n"),
+            ["'f' is declared to return str but its body is num"]
+        );
+        assert_eq!(
+            errors("let a: bool -> 1; a\n"),
+            ["'a' is declared bool but its value is int"]
+        );
+        // what builtins return isn't known
+        assert!(errors("def x: num -> len \"abc\"\n").is_empty());
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(errors("+ 1 \"a\"\n"), ["'+' cannot combine str and int"]);
+        assert_eq!(
+            errors("+ 1 true\n"),
+            ["'+' expects int, num, or str operands, but one is bool"]
+        );
+        assert_eq!(
+            errors("def x: int -> + \"a\" \"b\"\n"),
+            ["'x' is declared int but its value is str"]
+        );
+        assert_eq!(errors("= \"a\" 1\n"), ["'=' cannot compare str and int"]);
+        assert!(errors("def x: bool -> < \"a\" \"b\"\ndef y: bool -> = 1 2.5\n").is_empty());
+        assert_eq!(
+            errors("and true 1\n"),
+            ["'and' expects bool operands, but one is int"]
+        );
+        assert_eq!(errors("= 1 true\n"), ["'=' cannot compare int and bool"]);
+        assert_eq!(
+            errors("def x: int -> / 4 2\n"),
+            ["'x' is declared int but its value is num"]
+        );
+        assert!(errors("def x: int -> * (+ 1 2) 3\ndef y: bool -> not (< 1 2.5)\n").is_empty());
+    }
+
+    #[test]
+    fn test_calls() {
+        assert_eq!(
+            errors("def f: num x: num -> x\nf \"a\"\nf 1 2\n"),
+            [
+                "Argument 1 to 'f' is str, but it expects num",
+                "'f' takes 1 argument(s) but is given 2"
+            ]
+        );
+        assert_eq!(
+            errors("def n: num -> \\x: num -> x\n"),
+            ["'n' is declared num but its value is num -> num"]
+        );
+        // a call with fewer arguments gives a function of the rest
+        assert_eq!(
+            errors("def add: int a: int b: int -> + a b\nlet f: str -> add 1; f\n"),
+            ["'f' is declared str but its value is int -> int"]
+        );
+        assert!(errors("def add: int a: int b: int -> + a b\nmap (add 1) [1 2]\n").is_empty());
+        assert_eq!(
+            errors("def n: int -> 1\nset n -> \"a\"\nset n -> 2\nset m -> true\n"),
+            ["'n' is declared int but set gives it str"]
+        );
+        // nothing is known about what is declared `_`
+        assert!(errors("def g: _ -> \\x: str -> x\ng 1 2\n").is_empty());
+        // a default is checked against its parameter's type, and a call by name gives
+        // what the function returns
+        assert_eq!(
+            errors("def f: num x: num = \"a\" -> x\ndef g: str x: num y: num -> \"\"\n+ 1 (g y: 1 x: 2)\n"),
+            [
+                "'x' is declared num but its default is str",
+                "'+' cannot combine str and int"
+            ]
+        );
+        // a function can call itself, and parameters shadow globals
+        assert!(errors(
+            "def x: str -> \"a\"\ndef fact: int x: int -> * x (fact (- x 1))\nfact 3\n"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_scopes() {
+        assert_eq!(
+            errors("loop i: int -> 0; while i; recur 1.5; i\n"),
+            [
+                "A loop's condition must be a bool, but it is int",
+                "'i' is declared int but recur gives it num"
+            ]
+        );
+        assert!(errors("def x: _ -> 1\nlet x: str -> \"a\"; + 1 (len x)\n").is_empty());
+        assert_eq!(
+            errors("try throw 1; catch err -> - err \"a\"\n"),
+            ["'-' expects int or num operands, but one is str"]
+        );
+    }
+
+    #[test]
+    fn test_match() {
+        assert!(errors("def f: str n: int -> match n; 0 -> \"none\"; _ -> \"some\"\n").is_empty());
+        assert_eq!(
+            errors("def n: int -> 1\nmatch n; \"one\" -> 1; [x] -> x; m -> + m 1\n"),
+            [
+                "No int fits the pattern \"one\"",
+                "No int fits the pattern [x]"
+            ]
+        );
+        assert_eq!(
+            errors("def x: int -> match 1.5; 1 -> 2; _ -> 2.5\n"),
+            ["'x' is declared int but its value is num"]
+        );
+        // a bound name has the type of the value it is bound to
+        assert_eq!(
+            errors("match \"a\"; s -> + s 1\n"),
+            ["'+' cannot combine str and int"]
+        );
+    }
+
+    #[test]
+    fn test_checker_keeps_globals_between_checks() {
+        let mut checker = Checker::new();
+        let parse = |source: &str| parser::parse(&lexer::lex(source.to_string()).unwrap()).unwrap();
+
+        assert!(checker.check(&parse("def x: str -> \"a\"\n")).is_ok());
+        assert_eq!(checker.global("x"), Some(&Type::Str));
+        assert!(checker.check(&parse("+ x 1\n")).is_err());
+
+        // a source with errors defines nothing
+        assert!(checker.check(&parse("def y: num -> true\n")).is_err());
+        assert_eq!(checker.global("y"), None);
+        checker.undefine("x");
+        assert!(checker.check(&parse("+ x 1\n")).is_ok());
+    }
+
+    #[test]
+    fn test_type_at() {
+        let source = "match [1 \"a\"]; [n s] -> s; x -> \"{x}\"\ntry throw 1; catch err -> err\n";
+        let tokens = lexer::lex_spanned(source, Default::default()).unwrap();
+        let exprs = parser::parse_spanned(&tokens, Default::default()).unwrap();
+        let mut checker = Checker::new();
+        assert!(checker.check_spanned(&exprs, &tokens).is_ok());
+
+        let at = |name: &str| checker.type_at(source.find(name).unwrap());
+        assert_eq!(at("n s"), Some(&Type::Any));
+        assert_eq!(at("x ->"), Some(&Type::Seq));
+        assert_eq!(at("err"), Some(&Type::Any));
+        // a name in an interpolated str has no token
+        assert_eq!(checker.type_at(source.find("{x}").unwrap() + 1), None);
+    }
+
+    #[test]
+    fn test_display() {
+        let function = Type::Function(
+            vec![
+                Type::Function(vec![Type::Int], Box::new(Type::Num)),
+                Type::Str,
+            ],
+            Box::new(Type::Bool),
+        );
+        assert_eq!(function.to_string(), "(int -> num) -> str -> bool");
+
+        let curried = Type::Function(
+            vec![Type::Int],
+            Box::new(Type::Function(vec![Type::Int], Box::new(Type::Int))),
+        );
+        assert_eq!(curried.to_string(), "int -> (int -> int)");
+        assert_eq!(Type::from_decl(&curried.to_string()), curried);
+        assert_eq!(Type::from_decl(&function.to_string()), function);
+    }
+
+    #[test]
+    fn test_function_types() {
+        assert!(errors(
+            "def add: num -> num -> num -> \\x: num y: num -> + x y\n\
+             def apply: num f: num -> num x: num -> f x\n\
+             def adder: (num -> num) n: int -> \\x: num -> + x n\n\
+             apply (adder 1) 2\n"
+        )
+        .is_empty());
+        assert_eq!(
+            errors("def add: num -> num -> num -> \\x: num -> x\n"),
+            ["'add' is declared num -> num -> num but its value is num -> num"]
+        );
+        assert_eq!(
+            errors("def apply: num f: num -> num x: num -> f x \"a\"\n"),
+            ["'f' takes 1 argument(s) but is given 2"]
+        );
+        assert_eq!(
+            errors(
+                "def apply: num f: num -> num x: num -> f x\n\
+                 apply (\\s: str -> 1) 2\n"
+            ),
+            ["Argument 1 to 'apply' is str -> int, but it expects num -> num"]
+        );
+    }
+}